@@ -0,0 +1,311 @@
+//! Checks that every entity has one arch, and that each arch drives the ports of its entity and of the
+//! entities it instantiates in the directions they were declared with
+//!
+//! The types of the values connected to ports are checked along with every other expression by
+//! [`typeck`](crate::typeck), so only directions and drivers are checked here.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::ast::*;
+use crate::diagnostics::Diagnostic;
+use crate::loader::Crate;
+use crate::resolve::{DefId, DefKind, PathResolution, Res, Resolutions};
+use crate::source_map::FileId;
+use crate::typeck::{is_place, TypeContext};
+use crate::visit::Visit;
+
+/// Checks the entities and archs of `krate`
+pub fn check(krate: &Crate, resolutions: &Resolutions) -> Result<(), Vec<Diagnostic>> {
+    let cx = TypeContext::new(krate, resolutions);
+    let mut diagnostics = vec![];
+
+    let mut archs: HashMap<DefId, Vec<(FileId, &ItemArch)>> = HashMap::new();
+    for module in krate.modules() {
+        for item in &module.items {
+            let item_arch = match item {
+                Item::Arch(item_arch) => item_arch,
+                _ => continue,
+            };
+            // Archs of anything but an entity have been reported by the resolver
+            if let Some(entity) = arch_entity(resolutions, module.file, item_arch) {
+                archs
+                    .entry(entity)
+                    .or_default()
+                    .push((module.file, item_arch));
+            }
+        }
+    }
+
+    for (id, def) in resolutions.defs.iter().enumerate() {
+        let id = DefId(id);
+        if def.kind != DefKind::Entity {
+            continue;
+        }
+        let item = match cx.item(id) {
+            Item::Entity(item) => item,
+            _ => continue,
+        };
+        let entity_archs = archs.get(&id).map(Vec::as_slice).unwrap_or_default();
+        if entity_archs.is_empty() {
+            let message = format!("entity `{}` has no arch", item.ident);
+            diagnostics
+                .push(Diagnostic::new(message, item.ident.span.clone()).in_file(cx.file(id)));
+        }
+        for (file, item_arch) in entity_archs.iter().skip(1) {
+            let message = format!("entity `{}` has more than one arch", item.ident);
+            let span = item_arch.entity.segments.span();
+            diagnostics.push(Diagnostic::new(message, span).in_file(*file));
+        }
+
+        // Ports left undriven by any of the archs, which are reported once even if an entity
+        // has more than one arch
+        let mut undriven = HashSet::new();
+        for (file, item_arch) in entity_archs {
+            let mut checker = ArchChecker {
+                cx: &cx,
+                file: *file,
+                ports: &item.ports,
+                driven: HashSet::new(),
+                diagnostics: vec![],
+            };
+            checker.check_arch(item_arch);
+            for (index, port) in item.ports.iter().enumerate() {
+                if let PortType::Out(_) = &port.port_type {
+                    if !checker.driven.contains(&index) {
+                        undriven.insert(index);
+                    }
+                }
+            }
+            diagnostics.extend(checker.diagnostics);
+        }
+        for (index, port) in item.ports.iter().enumerate() {
+            if undriven.contains(&index) {
+                let message = format!(
+                    "`out` port `{}` of `{}` is never driven",
+                    port.ident, item.ident
+                );
+                diagnostics
+                    .push(Diagnostic::new(message, port.ident.span.clone()).in_file(cx.file(id)));
+            }
+        }
+    }
+
+    if diagnostics.is_empty() {
+        Ok(())
+    } else {
+        Err(diagnostics)
+    }
+}
+
+fn arch_entity(resolutions: &Resolutions, file: FileId, item: &ItemArch) -> Option<DefId> {
+    match resolutions.type_path(file, &item.entity) {
+        Some(PathResolution {
+            res: Res::Def(id),
+            unresolved_segments: 0,
+        }) if resolutions.def(*id).kind == DefKind::Entity => Some(*id),
+        _ => None,
+    }
+}
+
+struct ArchChecker<'a, 'cx> {
+    cx: &'cx TypeContext<'a>,
+    file: FileId,
+    /// Ports of the entity that the arch implements
+    ports: &'a Punctuated<Port, token::Comma>,
+    /// Indices of the ports that the arch drives
+    driven: HashSet<usize>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a, 'cx> ArchChecker<'a, 'cx> {
+    fn error(&mut self, message: impl Into<String>, span: Span) {
+        self.diagnostics
+            .push(Diagnostic::new(message, span).in_file(self.file));
+    }
+
+    fn check_arch(&mut self, item: &'a ItemArch) {
+        for arch_item in &item.items {
+            match arch_item {
+                ArchItem::Const(item_const) => self.visit_expr(&item_const.expr),
+                ArchItem::Let(local) => {
+                    if let Some((_, init)) = &local.init {
+                        self.visit_expr(init);
+                    }
+                }
+                ArchItem::When(when) => {
+                    self.visit_expr(&when.expr);
+                    self.visit_block(&when.block);
+                }
+                ArchItem::EntityExpression(expr) => self.check_instance(expr),
+            }
+        }
+    }
+
+    /// Checks the connections made to the ports of an instantiated entity
+    fn check_instance(&mut self, expr: &'a ArchItemEntityExpression) {
+        let resolutions = self.cx.resolutions();
+        let entity = match resolutions.expr_path(self.file, &expr.path) {
+            Some(PathResolution {
+                res: Res::Def(id),
+                unresolved_segments: 0,
+            }) if resolutions.def(*id).kind == DefKind::Entity => *id,
+            // Anything but an entity has been reported by the type checker
+            _ => return,
+        };
+        let item = match self.cx.item(entity) {
+            Item::Entity(item) => item,
+            _ => return,
+        };
+
+        let mut connected = HashSet::new();
+        for field in expr.fields.iter() {
+            let port = match item.ports.iter().find(|port| port.ident == field.ident) {
+                Some(port) => port,
+                // Unknown ports have been reported by the type checker
+                None => continue,
+            };
+            if !connected.insert(&port.ident.inner) {
+                let message = format!("port `{}` is connected more than once", field.ident);
+                self.error(message, field.ident.span.clone());
+                continue;
+            }
+            let writes = matches!(port.port_type, PortType::Out(_) | PortType::InOut(_));
+            match &field.expr {
+                Some((_, value)) => {
+                    if writes {
+                        if is_place(value) {
+                            self.write(value);
+                        } else {
+                            let message = format!(
+                                "`{}` port `{}` of `{}` must be connected to a signal or port",
+                                direction(&port.port_type),
+                                port.ident,
+                                item.ident
+                            );
+                            self.error(message, value.span());
+                        }
+                    }
+                    self.visit_expr(value);
+                }
+                None if writes => {
+                    let resolution = resolutions.ident(self.file, &field.ident).cloned();
+                    self.write_res(resolution, &field.ident);
+                }
+                None => {}
+            }
+        }
+
+        let missing = item
+            .ports
+            .iter()
+            .filter(|port| {
+                matches!(port.port_type, PortType::In(_) | PortType::InOut(_))
+                    && port.expr.is_none()
+                    && !connected.contains(&port.ident.inner)
+            })
+            .map(|port| format!("`{}`", port.ident))
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            let message = format!(
+                "missing {} in instantiation of `{}`",
+                missing.join(", "),
+                item.ident
+            );
+            self.error(message, expr.path.segments.span());
+        }
+    }
+
+    /// Records a write to the place `lhs`, which may only be a port of this arch's entity through `self.port`
+    fn write(&mut self, lhs: &Expr) {
+        match lhs {
+            Expr::Field(field) if is_self(self.cx.resolutions(), self.file, &field.on) => {
+                if let Member::Named(ident) = &field.member {
+                    let index = self.ports.iter().position(|port| port.ident == *ident);
+                    if let Some(index) = index {
+                        self.drive(index, &field.member.span());
+                    }
+                }
+            }
+            Expr::Field(field) => self.write(&field.on),
+            Expr::Index(index) => self.write(&index.on),
+            Expr::Grouped(grouped) => self.write(&grouped.expr),
+            Expr::Tuple(tuple) => tuple
+                .elements
+                .iter()
+                .for_each(|element| self.write(element)),
+            Expr::Path(path) => {
+                let resolution = self.cx.resolutions().expr_path(self.file, path).cloned();
+                if let Some(ident) = path.segments.last().map(|segment| &segment.ident) {
+                    self.write_res(resolution, ident);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Records a write to a place named without `self.`, which must not be a port
+    fn write_res(&mut self, resolution: Option<PathResolution>, ident: &Ident) {
+        if let Some(PathResolution {
+            res: Res::Port { index, .. },
+            unresolved_segments: 0,
+        }) = resolution
+        {
+            let port = self.ports.iter().nth(index);
+            match port.map(|port| &port.port_type) {
+                Some(PortType::In(_)) => self.drive(index, &ident.span),
+                Some(port_type) => {
+                    let message = format!(
+                        "`{}` port `{}` can only be written through `self.{}`",
+                        direction(port_type),
+                        ident,
+                        ident
+                    );
+                    self.error(message, ident.span.clone());
+                }
+                None => {}
+            }
+        }
+    }
+
+    fn drive(&mut self, index: usize, span: &Span) {
+        let port = match self.ports.iter().nth(index) {
+            Some(port) => port,
+            None => return,
+        };
+        if let PortType::In(_) = port.port_type {
+            let message = format!("cannot assign to `in` port `{}`", port.ident);
+            self.error(message, span.clone());
+        } else {
+            self.driven.insert(index);
+        }
+    }
+}
+
+impl<'ast, 'a, 'cx> Visit<'ast> for ArchChecker<'a, 'cx> {
+    fn visit_expr_assign(&mut self, assign: &'ast ExprAssign) {
+        self.write(&assign.lhs);
+        visit_expr_assign(self, assign);
+    }
+}
+
+fn is_self(resolutions: &Resolutions, file: FileId, expr: &Expr) -> bool {
+    match expr {
+        Expr::Path(path) => matches!(
+            resolutions.expr_path(file, path),
+            Some(PathResolution {
+                res: Res::SelfValue,
+                ..
+            })
+        ),
+        Expr::Grouped(grouped) => is_self(resolutions, file, &grouped.expr),
+        _ => false,
+    }
+}
+
+fn direction(port_type: &PortType) -> &'static str {
+    match port_type {
+        PortType::In(_) => "in",
+        PortType::Out(_) => "out",
+        PortType::InOut(_) => "inout",
+    }
+}
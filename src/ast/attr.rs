@@ -0,0 +1,80 @@
+use paste::paste;
+
+use super::*;
+
+crate::insts_from_tokens! {
+    Attribute {
+        pound: Pound,
+        bracket_open: BracketOpen,
+        meta: Meta,
+        bracket_close: BracketClose
+    }
+}
+
+crate::class_from_tokens! {
+    OuterAttr {
+        Doc {
+            inner: DocComment
+        },
+        Attr {
+            inner: Attribute
+        }
+    }
+}
+
+crate::class_from_tokens! {
+    Meta {
+        Path {
+            inner: SimplePath
+        },
+        NameValue {
+            path: SimplePath,
+            eq: Eq,
+            lit: Lit
+        },
+        List {
+            path: SimplePath,
+            paren_open: ParenOpen,
+            nested: Punctuated<NestedMeta, Comma>,
+            paren_close: ParenClose
+        }
+    }
+}
+
+crate::class_from_tokens! {
+    NestedMeta {
+        Meta {
+            inner: Meta
+        },
+        Lit {
+            inner: Lit
+        }
+    }
+}
+
+impl Meta {
+    pub fn path(&self) -> &SimplePath {
+        use Meta::*;
+        match self {
+            Path(p) => p,
+            NameValue(nv) => &nv.path,
+            List(l) => &l.path,
+        }
+    }
+}
+
+impl OuterAttr {
+    pub fn doc(&self) -> Option<&DocComment> {
+        match self {
+            OuterAttr::Doc(doc) => Some(doc),
+            OuterAttr::Attr(_) => None,
+        }
+    }
+
+    pub fn attr(&self) -> Option<&Attribute> {
+        match self {
+            OuterAttr::Doc(_) => None,
+            OuterAttr::Attr(attr) => Some(attr),
+        }
+    }
+}
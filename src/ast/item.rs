@@ -2,7 +2,10 @@ use std::boxed::Box;
 
 use paste::paste;
 
+use crate::diagnostics::Diagnostic;
+
 use super::{
+    attr::OuterAttr,
     expr::{Expr, ExprPath},
     pat::PatType,
     token::*,
@@ -19,18 +22,21 @@ crate::inst_from_tokens! {
 crate::class_from_tokens! {
     Item {
         Mod {
+                        attrs: Vec<OuterAttr>,
             vis: Option<Vis>,
             mod_token: Mod,
             ident: Ident,
             content: ModContent
         },
         Use {
+                        attrs: Vec<OuterAttr>,
             vis: Option<Vis>,
             use_token: Use,
             tree: UseTree,
             semi: Semi
         },
         Const {
+                        attrs: Vec<OuterAttr>,
             vis: Option<Vis>,
             const_token: Const,
             ident: Ident,
@@ -41,61 +47,75 @@ crate::class_from_tokens! {
             semi: Semi
         },
         Fn {
+                        attrs: Vec<OuterAttr>,
             vis: Option<Vis>,
             fn_token: Fn,
             sig: Sig,
             block: Block
         },
         Type {
+                        attrs: Vec<OuterAttr>,
             vis: Option<Vis>,
             type_token: TokenType,
             ident: Ident,
             generics: Option<Generics>,
+            where_clause: Option<WhereClause>,
             eq: Eq,
             ty: Type,
             semi: Semi
         },
         Struct {
+                        attrs: Vec<OuterAttr>,
             vis: Option<Vis>,
             struct_token: Struct,
             ident: Ident,
             generics: Option<Generics>,
+            where_clause: Option<WhereClause>,
             fields: Fields,
+            tuple_where_clause: Option<WhereClause>,
             semi: Option<Semi>
         },
         Enum {
+                        attrs: Vec<OuterAttr>,
             vis: Option<Vis>,
             enum_token: Enum,
             ident: Ident,
             generics: Option<Generics>,
+            where_clause: Option<WhereClause>,
             brace_open: BraceOpen,
             variants: Punctuated<Variant, Comma>,
             brace_close: BraceClose
         },
         Trait {
+                        attrs: Vec<OuterAttr>,
             vis: Option<Vis>,
             trait_token: Trait,
             ident: Ident,
             generics: Option<Generics>,
             super_traits: Option<(Colon, Punctuated<TypePath, Plus>)>,
+            where_clause: Option<WhereClause>,
             brace_open: BraceOpen,
             items: Vec<TraitItem>,
             brace_close: BraceClose
         },
         TraitAlias {
+                        attrs: Vec<OuterAttr>,
             vis: Option<Vis>,
             trait_token: Trait,
             ident: Ident,
             generics: Option<Generics>,
+            where_clause: Option<WhereClause>,
             eq: Eq,
             bounds: Punctuated<TypePath, Plus>,
             semi: Semi
         },
         Impl {
+                        attrs: Vec<OuterAttr>,
             impl_token: Impl,
             generics: Option<Generics>,
             of: Option<(TypePath, For)>,
             ty: Box<Type>,
+            where_clause: Option<WhereClause>,
             brace_open: BraceOpen,
             items: Vec<ImplItem>,
             brace_close: BraceClose
@@ -108,10 +128,12 @@ crate::class_from_tokens! {
 
         // }
         Entity {
+                        attrs: Vec<OuterAttr>,
             vis: Option<Vis>,
             entity: Entity,
             ident: Ident,
             generics: Option<Generics>,
+            where_clause: Option<WhereClause>,
             brace_open: BraceOpen,
             ports: Punctuated<Port, Comma>,
             brace_close: BraceClose
@@ -133,12 +155,28 @@ crate::class_from_tokens! {
         //     semi: Semi
         // },
         Arch {
+                        attrs: Vec<OuterAttr>,
             arch: Arch,
             generics: Option<Generics>,
             entity: TypePath,
+            where_clause: Option<WhereClause>,
             brace_open: BraceOpen,
             items: Vec<ArchItem>,
             brace_close: BraceClose
+        },
+        Test {
+                        attrs: Vec<OuterAttr>,
+            test: Test,
+            ident: Ident,
+            for_token: For,
+            entity: TypePath,
+            brace_open: BraceOpen,
+            stmts: Vec<TestStmt>,
+            brace_close: BraceClose
+        },
+        // Placeholder left by the parser where it recovered from a syntax error
+        Error {
+            diagnostic: Diagnostic
         }
     }
 }
@@ -234,7 +272,8 @@ crate::insts_from_tokens! {
         paren_open: ParenOpen,
         inputs: Punctuated<FnArg, Comma>,
         paren_close: ParenClose,
-        output: Option<(RArrow, Type)>
+        output: Option<(RArrow, Type)>,
+        where_clause: Option<WhereClause>
     }
 }
 
@@ -279,7 +318,7 @@ crate::class_from_tokens! {
 }
 
 impl GenericParam {
-    pub fn ident<'ast>(&'ast self) -> &'ast Ident {
+    pub fn ident(&self) -> &Ident {
         use GenericParam::*;
         match self {
             Type(t) => &t.ident,
@@ -315,6 +354,7 @@ crate::class_from_tokens! {
 
 crate::insts_from_tokens! {
     NamedField {
+                attrs: Vec<OuterAttr>,
         vis: Option<Vis>,
         ident: Ident,
         colon: Colon,
@@ -325,6 +365,7 @@ crate::insts_from_tokens! {
         ty: Type
     },
     Variant {
+                attrs: Vec<OuterAttr>,
         ident: Ident,
         variant_type: VariantType
     }
@@ -346,6 +387,7 @@ crate::class_from_tokens! {
 crate::class_from_tokens! {
     TraitItem {
         Const {
+                        attrs: Vec<OuterAttr>,
             vis: Option<Vis>,
             const_token: Const,
             ident: Ident,
@@ -355,6 +397,7 @@ crate::class_from_tokens! {
             semi: Semi
         },
         Fn {
+                        attrs: Vec<OuterAttr>,
             vis: Option<Vis>,
             fn_token: Fn,
             sig: Sig,
@@ -362,6 +405,7 @@ crate::class_from_tokens! {
             semi: Option<Semi>
         },
         Type {
+                        attrs: Vec<OuterAttr>,
             vis: Option<Vis>,
             type_token: TokenType,
             ident: Ident,
@@ -396,6 +440,7 @@ crate::class_only_from_tokens! {
 
 crate::inst_from_tokens! {
     Port {
+                attrs: Vec<OuterAttr>,
         port_type: PortType,
         ident: Ident,
         colon: Colon,
@@ -413,11 +458,13 @@ crate::class_from_tokens! {
             inner: StmtLocal
         },
         When {
+                        attrs: Vec<OuterAttr>,
             when: When,
             expr: Expr,
             block: Block
         },
         EntityExpression {
+                        attrs: Vec<OuterAttr>,
             path: ExprPath,
             brace_open: BraceOpen,
             fields: Punctuated<EntityFieldValue, Comma>,
@@ -432,3 +479,32 @@ crate::inst_from_tokens! {
         expr: Option<(Colon, Expr)>
     }
 }
+
+crate::class_from_tokens! {
+    TestStmt {
+        Drive {
+            port: Ident,
+            eq: Eq,
+            value: Expr,
+            semi: Semi
+        },
+        Tick {
+            tick: Tick,
+            clock: Ident,
+            count: Option<(Star, Expr)>,
+            semi: Semi
+        },
+        Assert {
+            assert: Assert,
+            cond: Expr,
+            semi: Semi
+        },
+        AssertEq {
+            assert_eq: AssertEq,
+            left: Expr,
+            comma: Comma,
+            right: Expr,
+            semi: Semi
+        }
+    }
+}
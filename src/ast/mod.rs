@@ -3,6 +3,7 @@ use std::fmt::Debug;
 
 use paste::paste;
 
+mod attr;
 mod expr;
 mod item;
 // pub mod macaroni;
@@ -10,11 +11,14 @@ mod pat;
 pub mod token;
 mod types;
 
+pub use attr::*;
 pub use expr::*;
 pub use item::*;
 pub use pat::*;
 use token::*;
-pub use token::{Ident, Lit, LitBool, LitFloat, LitInt, PathSep, Span, Spanned, ToTokens, Tok};
+pub use token::{
+    DocComment, Ident, Lit, LitBool, LitFloat, LitInt, PathSep, Span, Spanned, ToTokens, Tok,
+};
 pub use types::*;
 
 #[derive(Debug, Clone, PartialEq)]
@@ -55,6 +59,7 @@ where
 
     fn first(&self) -> Option<Tok> {
         self.inner
+            .as_slice()
             .first()
             .map(|(t, _)| t)
             .and_then(ToTokens::first)
@@ -70,7 +75,12 @@ where
             .as_ref()
             .map(Box::as_ref)
             .and_then(ToTokens::last)
-            .or(self.inner.last().map(|(t, _)| t).and_then(ToTokens::last))
+            .or(self
+                .inner
+                .as_slice()
+                .last()
+                .map(|(t, _)| t)
+                .and_then(ToTokens::last))
     }
 
     fn len(&self) -> usize {
@@ -102,7 +112,7 @@ where
         self.last
             .as_ref()
             .map(|last| last.as_ref())
-            .or(self.inner.last().map(|(t, _)| t))
+            .or(self.inner.as_slice().last().map(|(t, _)| t))
     }
 
     pub fn len(&'ast self) -> usize {
@@ -170,106 +180,34 @@ macro_rules! from_tokens_to_tokens {
 
 #[macro_export]
 macro_rules! call_visitors {
-    ($v: expr, $field: expr => Option<($a: ty, $b: ty, Punctuated<$d: ty, $e: ty>)>) => {
-        if let Some((a, b, c)) = $field.as_ref() {
-            crate::call_visitors!($v, a => $a);
-            crate::call_visitors!($v, b => $b);
-            c.inner.iter().for_each(|(d, e)| {
-                crate::call_visitors!($v, d => $d);
-                crate::call_visitors!($v, e => $e);
-            });
-            if let Some(last) = c.last.as_ref() {
-                crate::call_visitors!($v, last.as_ref() => $d);
-            }
-        }
-    };
-
-    ($v: expr, $field: expr => Option<($a: ty, Punctuated<$c: ty, $d: ty>, $e: ty)>) => {
-        if let Some((a, b, e)) = $field.as_ref() {
-            crate::call_visitors!($v, a => $a);
-            b.inner.iter().for_each(|(c, d)| {
-                crate::call_visitors!($v, c => $c);
-                crate::call_visitors!($v, d => $d);
-            });
-            if let Some(last) = b.last.as_ref() {
-                crate::call_visitors!($v, last.as_ref() => $c);
-            }
-            crate::call_visitors!($v, e => $e);
-        }
-    };
-
-    ($v: expr, $field: expr => Option<($a: ty, $b: ty, $c: ty)>) => {
-        if let Some((a, b, c)) = $field.as_ref() {
-            crate::call_visitors!($v, a => $a);
-            crate::call_visitors!($v, b => $b);
-            crate::call_visitors!($v, c => $c);
-        }
-    };
-
-    ($v: expr, $field: expr => Option<($a: ty, Punctuated<$c: ty, $d: ty>)>) => {
-        if let Some((a, b)) = $field.as_ref() {
-            crate::call_visitors!($v, a => $a);
-            b.inner.iter().for_each(|(c, d)| {
-                crate::call_visitors!($v, c => $c);
-                crate::call_visitors!($v, d => $d);
-            });
-            if let Some(last) = b.last.as_ref() {
-                crate::call_visitors!($v, last.as_ref() => $c);
-            }
-        }
-    };
-
-
-    ($v: expr, $field: expr => Option<($a: ty, Box<$b: ty>)>) => {
-        if let Some((a, b)) = $field.as_ref() {
-            crate::call_visitors!($v, a => $a);
-            crate::call_visitors!($v, b.as_ref() => $b);
-        }
-    };
-
-    ($v: expr, $field: expr => Option<($a: ty, $b: ty)>) => {
-        if let Some((a, b)) = $field.as_ref() {
-            crate::call_visitors!($v, a => $a);
-            crate::call_visitors!($v, b => $b);
-        }
-    };
-
-    ($v: expr, $field: expr => Vec<$ty: ty>) => {
-        for field in $field.iter() {
-            crate::call_visitors!($v, field => $ty)
-        }
-    };
-
-    ($v: expr, $field: expr => Option<Box<$ty: ty>>) => {
-        if let Some(field) = $field.as_ref() {
-            crate::call_visitors!($v, field.as_ref() => $ty);
-        }
-    };
-
-    ($v: expr, $field: expr => Option<$ty: ty>) => {
-        if let Some(field) = $field.as_ref() {
-            crate::call_visitors!($v, field => $ty);
-        }
+    ($v: expr, $field: expr => $ty: ty) => {
+        crate::visit::Visitable::visit(&$field, $v)
     };
+}
 
-    ($v: expr, $field: expr => Punctuated<$a: ty, $b: ty>) => {
-        $field.inner.iter().for_each(|(a, b)| {
-            crate::call_visitors!($v, a => $a);
-            crate::call_visitors!($v, b => $b);
-        });
-        if let Some(last) = $field.last.as_ref() {
-            crate::call_visitors!($v, last.as_ref() => $a);
-        }
+#[macro_export]
+macro_rules! call_visitors_mut {
+    ($v: expr, $field: expr => $ty: ty) => {
+        crate::visit::VisitableMut::visit_mut(&mut $field, $v)
     };
+}
 
-    ($v: expr, $field: expr => Box<$ty: ty>) => {
-        crate::call_visitors!($v, $field.as_ref() => $ty);
+#[macro_export]
+macro_rules! call_folders {
+    ($f: expr, $field: expr => $ty: ty) => {
+        crate::fold::Foldable::fold($field, $f)
     };
+}
 
-    ($v: expr, $field: expr => $ty: ty) => {
-        paste! {
-            $v.[<visit_ $ty:snake>](&$field)
-        }
+/// JSON of the variant `$kind` of a class, which is a node of its own kind when it has no members
+#[macro_export]
+macro_rules! variant_to_json {
+    ($kind: ident, $variant: expr, ) => {{
+        let _ = $variant;
+        crate::json::Json::node(stringify!($kind), vec![])
+    }};
+    ($kind: ident, $variant: expr, $($member_ident: ident),+) => {
+        crate::json::ToJson::to_json($variant)
     };
 }
 
@@ -278,7 +216,13 @@ macro_rules! inst_from_tokens {
     ($inst: ident { }) => {
         pub type $inst = ();
         paste! {
-            pub(crate) fn [<visit_ $inst:snake>]<'ast, V>(v: &mut V, inst: &'ast $inst) where V: crate::visit::Visit<'ast> + ?Sized { }
+            pub fn [<visit_ $inst:snake>]<'ast, V>(v: &mut V, inst: &'ast $inst) where V: crate::visit::Visit<'ast> + ?Sized { }
+
+            pub fn [<visit_ $inst:snake _mut>]<V>(_v: &mut V, _inst: &mut $inst) where V: crate::visit::VisitMut + ?Sized { }
+
+            pub fn [<fold_ $inst:snake>]<F>(_f: &mut F, inst: $inst) -> $inst where F: crate::fold::Fold + ?Sized {
+                inst
+            }
         }
 
         impl ToTokens for $inst {
@@ -298,8 +242,16 @@ macro_rules! inst_from_tokens {
 
 
         paste! {
-            pub(crate) fn [<visit_ $inst:snake>]<'ast, V>(v: &mut V, inst: &'ast $inst) where V: crate::visit::Visit<'ast> + ?Sized {
-                crate::call_visitors!(v, inst => $member_ty)
+            pub fn [<visit_ $inst:snake>]<'ast, V>(v: &mut V, inst: &'ast $inst) where V: crate::visit::Visit<'ast> + ?Sized {
+                crate::call_visitors!(v, *inst => $member_ty)
+            }
+
+            pub fn [<visit_ $inst:snake _mut>]<V>(v: &mut V, inst: &mut $inst) where V: crate::visit::VisitMut + ?Sized {
+                crate::call_visitors_mut!(v, *inst => $member_ty)
+            }
+
+            pub fn [<fold_ $inst:snake>]<F>(f: &mut F, inst: $inst) -> $inst where F: crate::fold::Fold + ?Sized {
+                crate::call_folders!(f, inst => $member_ty)
             }
         }
     };
@@ -316,11 +268,53 @@ macro_rules! inst_from_tokens {
         }
 
         paste! {
-            pub(crate) fn [<visit_ $inst:snake>]<'ast, V>(v: &mut V, inst: &'ast $inst) where V: crate::visit::Visit<'ast> + ?Sized {
+            pub fn [<visit_ $inst:snake>]<'ast, V>(v: &mut V, inst: &'ast $inst) where V: crate::visit::Visit<'ast> + ?Sized {
                 $(
                     crate::call_visitors!(v, inst.$member_ident => $member_ty);
                 )*
             }
+
+            pub fn [<visit_ $inst:snake _mut>]<V>(v: &mut V, inst: &mut $inst) where V: crate::visit::VisitMut + ?Sized {
+                $(
+                    crate::call_visitors_mut!(v, inst.$member_ident => $member_ty);
+                )*
+            }
+
+            pub fn [<fold_ $inst:snake>]<F>(f: &mut F, inst: $inst) -> $inst where F: crate::fold::Fold + ?Sized {
+                $inst {
+                    $(
+                        $member_ident: crate::call_folders!(f, inst.$member_ident => $member_ty)
+                    ),*
+                }
+            }
+
+            impl<'ast> crate::visit::Visitable<'ast> for $inst {
+                fn visit<V>(&'ast self, v: &mut V) where V: crate::visit::Visit<'ast> + ?Sized {
+                    v.[<visit_ $inst:snake>](self)
+                }
+            }
+
+            impl crate::visit::VisitableMut for $inst {
+                fn visit_mut<V>(&mut self, v: &mut V) where V: crate::visit::VisitMut + ?Sized {
+                    v.[<visit_ $inst:snake _mut>](self)
+                }
+            }
+
+            impl crate::fold::Foldable for $inst {
+                fn fold<F>(self, f: &mut F) -> Self where F: crate::fold::Fold + ?Sized {
+                    f.[<fold_ $inst:snake>](self)
+                }
+            }
+        }
+
+        impl crate::json::ToJson for $inst {
+            fn to_json(&self) -> crate::json::Json {
+                crate::json::Json::node(stringify!($inst), vec![
+                    $(
+                        (stringify!($member_ident), crate::json::ToJson::to_json(&self.$member_ident))
+                    ),*
+                ])
+            }
         }
 
         impl ToTokens for $inst {
@@ -384,11 +378,49 @@ macro_rules! class_only_from_tokens {
                 ),*
             }
 
-            pub(crate) fn [<visit_ $class:snake>]<'ast, V>(v: &mut V, inst: &'ast $class) where V: crate::visit::Visit<'ast> + ?Sized {
+            pub fn [<visit_ $class:snake>]<'ast, V>(v: &mut V, inst: &'ast $class) where V: crate::visit::Visit<'ast> + ?Sized {
                 match inst {
                     $( $class::$variant(variant) => v.[<visit _ $variant:snake>](variant) ),*
                 }
             }
+
+            pub fn [<visit_ $class:snake _mut>]<V>(v: &mut V, inst: &mut $class) where V: crate::visit::VisitMut + ?Sized {
+                match inst {
+                    $( $class::$variant(variant) => v.[<visit _ $variant:snake _mut>](variant) ),*
+                }
+            }
+
+            pub fn [<fold_ $class:snake>]<F>(f: &mut F, inst: $class) -> $class where F: crate::fold::Fold + ?Sized {
+                match inst {
+                    $( $class::$variant(variant) => $class::$variant(f.[<fold _ $variant:snake>](variant)) ),*
+                }
+            }
+
+            impl<'ast> crate::visit::Visitable<'ast> for $class {
+                fn visit<V>(&'ast self, v: &mut V) where V: crate::visit::Visit<'ast> + ?Sized {
+                    v.[<visit_ $class:snake>](self)
+                }
+            }
+
+            impl crate::visit::VisitableMut for $class {
+                fn visit_mut<V>(&mut self, v: &mut V) where V: crate::visit::VisitMut + ?Sized {
+                    v.[<visit_ $class:snake _mut>](self)
+                }
+            }
+
+            impl crate::fold::Foldable for $class {
+                fn fold<F>(self, f: &mut F) -> Self where F: crate::fold::Fold + ?Sized {
+                    f.[<fold_ $class:snake>](self)
+                }
+            }
+
+            impl crate::json::ToJson for $class {
+                fn to_json(&self) -> crate::json::Json {
+                    match self {
+                        $( Self::$variant(x) => crate::json::ToJson::to_json(x) ),*
+                    }
+                }
+            }
         }
 
         impl ToTokens for $class {
@@ -445,12 +477,50 @@ macro_rules! class_from_tokens {
                 }
             })*
 
-            pub(crate) fn [<visit_ $class:snake>]<'ast, V>(v: &mut V, inst: &'ast $class) where V: crate::visit::Visit<'ast> + ?Sized {
+            pub fn [<visit_ $class:snake>]<'ast, V>(v: &mut V, inst: &'ast $class) where V: crate::visit::Visit<'ast> + ?Sized {
                 match inst {
                     $( $class::$variant(variant) => v.[<visit_ $class:snake _ $variant:snake>](variant) ),*
                 }
             }
 
+            pub fn [<visit_ $class:snake _mut>]<V>(v: &mut V, inst: &mut $class) where V: crate::visit::VisitMut + ?Sized {
+                match inst {
+                    $( $class::$variant(variant) => v.[<visit_ $class:snake _ $variant:snake _mut>](variant) ),*
+                }
+            }
+
+            pub fn [<fold_ $class:snake>]<F>(f: &mut F, inst: $class) -> $class where F: crate::fold::Fold + ?Sized {
+                match inst {
+                    $( $class::$variant(variant) => $class::$variant(f.[<fold_ $class:snake _ $variant:snake>](variant)) ),*
+                }
+            }
+
+            impl<'ast> crate::visit::Visitable<'ast> for $class {
+                fn visit<V>(&'ast self, v: &mut V) where V: crate::visit::Visit<'ast> + ?Sized {
+                    v.[<visit_ $class:snake>](self)
+                }
+            }
+
+            impl crate::visit::VisitableMut for $class {
+                fn visit_mut<V>(&mut self, v: &mut V) where V: crate::visit::VisitMut + ?Sized {
+                    v.[<visit_ $class:snake _mut>](self)
+                }
+            }
+
+            impl crate::fold::Foldable for $class {
+                fn fold<F>(self, f: &mut F) -> Self where F: crate::fold::Fold + ?Sized {
+                    f.[<fold_ $class:snake>](self)
+                }
+            }
+
+            impl crate::json::ToJson for $class {
+                fn to_json(&self) -> crate::json::Json {
+                    match self {
+                        $( Self::$variant(x) => crate::variant_to_json!([<$class $variant>], x, $($member_ident),*) ),*
+                    }
+                }
+            }
+
             #[derive(Clone, Debug, PartialEq)]
             pub enum $class {
                 $(
@@ -488,6 +558,7 @@ macro_rules! class_from_tokens {
 crate::class_from_tokens! {
     Stmt {
         Local {
+                        attrs: Vec<OuterAttr>,
             let_token: Let,
             pat: Pat,
             ty: Option<(Colon, Type)>,
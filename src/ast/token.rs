@@ -55,6 +55,63 @@ impl<T: ToTokens> ToTokens for Vec<T> {
     }
 }
 
+impl<T: ToTokens> ToTokens for Option<T> {
+    fn to_tokens(&self) -> Vec<Tok> {
+        self.as_ref().map(ToTokens::to_tokens).unwrap_or_default()
+    }
+
+    /// Number of contained tokens
+    fn len(&self) -> usize {
+        self.as_ref().map(ToTokens::len).unwrap_or_default()
+    }
+}
+
+impl<T: ToTokens> ToTokens for Box<T> {
+    fn to_tokens(&self) -> Vec<Tok> {
+        self.as_ref().to_tokens()
+    }
+
+    fn first(&self) -> Option<Tok> {
+        self.as_ref().first()
+    }
+
+    fn last(&self) -> Option<Tok> {
+        self.as_ref().last()
+    }
+
+    /// Number of contained tokens
+    fn len(&self) -> usize {
+        self.as_ref().len()
+    }
+}
+
+impl<A: ToTokens, B: ToTokens> ToTokens for (A, B) {
+    fn to_tokens(&self) -> Vec<Tok> {
+        let mut acc = self.0.to_tokens();
+        acc.append(&mut self.1.to_tokens());
+        acc
+    }
+
+    /// Number of contained tokens
+    fn len(&self) -> usize {
+        self.0.len() + self.1.len()
+    }
+}
+
+impl<A: ToTokens, B: ToTokens, C: ToTokens> ToTokens for (A, B, C) {
+    fn to_tokens(&self) -> Vec<Tok> {
+        let mut acc = self.0.to_tokens();
+        acc.append(&mut self.1.to_tokens());
+        acc.append(&mut self.2.to_tokens());
+        acc
+    }
+
+    /// Number of contained tokens
+    fn len(&self) -> usize {
+        self.0.len() + self.1.len() + self.2.len()
+    }
+}
+
 pub trait Spanned {
     fn span(&self) -> Span;
 }
@@ -118,12 +175,145 @@ impl ToTokens for Ident {
     }
 }
 
-pub(crate) fn visit_ident<'ast, V>(v: &mut V, inst: &'ast Ident)
+pub fn visit_ident<'ast, V>(v: &mut V, inst: &'ast Ident)
+where
+    V: crate::visit::Visit<'ast> + ?Sized,
+{
+}
+
+impl<'ast> crate::visit::Visitable<'ast> for Ident {
+    fn visit<V>(&'ast self, v: &mut V)
+    where
+        V: crate::visit::Visit<'ast> + ?Sized,
+    {
+        v.visit_ident(self)
+    }
+}
+
+pub fn visit_ident_mut<V>(_v: &mut V, _inst: &mut Ident)
+where
+    V: crate::visit::VisitMut + ?Sized,
+{
+}
+
+impl crate::visit::VisitableMut for Ident {
+    fn visit_mut<V>(&mut self, v: &mut V)
+    where
+        V: crate::visit::VisitMut + ?Sized,
+    {
+        v.visit_ident_mut(self)
+    }
+}
+
+pub fn fold_ident<F>(_f: &mut F, inst: Ident) -> Ident
+where
+    F: crate::fold::Fold + ?Sized,
+{
+    inst
+}
+
+impl crate::fold::Foldable for Ident {
+    fn fold<F>(self, f: &mut F) -> Self
+    where
+        F: crate::fold::Fold + ?Sized,
+    {
+        f.fold_ident(self)
+    }
+}
+
+impl crate::json::ToJson for Ident {
+    fn to_json(&self) -> crate::json::Json {
+        crate::json::Json::node(
+            "Ident",
+            vec![
+                ("name", crate::json::Json::String(self.inner.clone())),
+                ("span", crate::json::ToJson::to_json(&self.span)),
+            ],
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+/// A `///` doc comment, where inner is the text following the slashes
+pub struct DocComment {
+    pub inner: String,
+    pub span: Span,
+}
+
+impl fmt::Display for DocComment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "///{}", self.inner)
+    }
+}
+
+impl ToTokens for DocComment {
+    fn to_tokens(&self) -> Vec<Tok> {
+        vec![Tok::DocComment(self.clone())]
+    }
+
+    fn len(&self) -> usize {
+        1
+    }
+}
+
+pub fn visit_doc_comment<'ast, V>(_v: &mut V, _inst: &'ast DocComment)
 where
     V: crate::visit::Visit<'ast> + ?Sized,
 {
 }
 
+impl<'ast> crate::visit::Visitable<'ast> for DocComment {
+    fn visit<V>(&'ast self, v: &mut V)
+    where
+        V: crate::visit::Visit<'ast> + ?Sized,
+    {
+        v.visit_doc_comment(self)
+    }
+}
+
+pub fn visit_doc_comment_mut<V>(_v: &mut V, _inst: &mut DocComment)
+where
+    V: crate::visit::VisitMut + ?Sized,
+{
+}
+
+impl crate::visit::VisitableMut for DocComment {
+    fn visit_mut<V>(&mut self, v: &mut V)
+    where
+        V: crate::visit::VisitMut + ?Sized,
+    {
+        v.visit_doc_comment_mut(self)
+    }
+}
+
+pub fn fold_doc_comment<F>(_f: &mut F, inst: DocComment) -> DocComment
+where
+    F: crate::fold::Fold + ?Sized,
+{
+    inst
+}
+
+impl crate::fold::Foldable for DocComment {
+    fn fold<F>(self, f: &mut F) -> Self
+    where
+        F: crate::fold::Fold + ?Sized,
+    {
+        f.fold_doc_comment(self)
+    }
+}
+
+impl crate::json::ToJson for DocComment {
+    fn to_json(&self) -> crate::json::Json {
+        crate::json::Json::node(
+            "DocComment",
+            vec![
+                ("text", crate::json::Json::String(self.inner.clone())),
+                ("span", crate::json::ToJson::to_json(&self.span)),
+            ],
+        )
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum Lit {
     Int(LitInt),
@@ -151,7 +341,7 @@ impl ToTokens for Lit {
     }
 }
 
-pub(crate) fn visit_lit<'ast, V>(v: &mut V, inst: &'ast Lit)
+pub fn visit_lit<'ast, V>(v: &mut V, inst: &'ast Lit)
 where
     V: crate::visit::Visit<'ast> + ?Sized,
 {
@@ -162,6 +352,65 @@ where
     }
 }
 
+impl<'ast> crate::visit::Visitable<'ast> for Lit {
+    fn visit<V>(&'ast self, v: &mut V)
+    where
+        V: crate::visit::Visit<'ast> + ?Sized,
+    {
+        v.visit_lit(self)
+    }
+}
+
+pub fn visit_lit_mut<V>(v: &mut V, inst: &mut Lit)
+where
+    V: crate::visit::VisitMut + ?Sized,
+{
+    match inst {
+        Lit::Int(lit_int) => v.visit_lit_int_mut(lit_int),
+        Lit::Float(lit_float) => v.visit_lit_float_mut(lit_float),
+        Lit::Bool(lit_bool) => v.visit_lit_bool_mut(lit_bool),
+    }
+}
+
+impl crate::visit::VisitableMut for Lit {
+    fn visit_mut<V>(&mut self, v: &mut V)
+    where
+        V: crate::visit::VisitMut + ?Sized,
+    {
+        v.visit_lit_mut(self)
+    }
+}
+
+pub fn fold_lit<F>(f: &mut F, inst: Lit) -> Lit
+where
+    F: crate::fold::Fold + ?Sized,
+{
+    match inst {
+        Lit::Int(lit_int) => Lit::Int(f.fold_lit_int(lit_int)),
+        Lit::Float(lit_float) => Lit::Float(f.fold_lit_float(lit_float)),
+        Lit::Bool(lit_bool) => Lit::Bool(f.fold_lit_bool(lit_bool)),
+    }
+}
+
+impl crate::fold::Foldable for Lit {
+    fn fold<F>(self, f: &mut F) -> Self
+    where
+        F: crate::fold::Fold + ?Sized,
+    {
+        f.fold_lit(self)
+    }
+}
+
+impl crate::json::ToJson for Lit {
+    fn to_json(&self) -> crate::json::Json {
+        match self {
+            Lit::Int(lit_int) => crate::json::ToJson::to_json(lit_int),
+            Lit::Float(lit_float) => crate::json::ToJson::to_json(lit_float),
+            Lit::Bool(lit_bool) => crate::json::ToJson::to_json(lit_bool),
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct LitInt {
     pub val: Int,
@@ -186,12 +435,66 @@ impl ToTokens for LitInt {
     }
 }
 
-pub(crate) fn visit_lit_int<'ast, V>(_v: &mut V, _inst: &'ast LitInt)
+pub fn visit_lit_int<'ast, V>(_v: &mut V, _inst: &'ast LitInt)
 where
     V: crate::visit::Visit<'ast> + ?Sized,
 {
 }
 
+impl<'ast> crate::visit::Visitable<'ast> for LitInt {
+    fn visit<V>(&'ast self, v: &mut V)
+    where
+        V: crate::visit::Visit<'ast> + ?Sized,
+    {
+        v.visit_lit_int(self)
+    }
+}
+
+pub fn visit_lit_int_mut<V>(_v: &mut V, _inst: &mut LitInt)
+where
+    V: crate::visit::VisitMut + ?Sized,
+{
+}
+
+impl crate::visit::VisitableMut for LitInt {
+    fn visit_mut<V>(&mut self, v: &mut V)
+    where
+        V: crate::visit::VisitMut + ?Sized,
+    {
+        v.visit_lit_int_mut(self)
+    }
+}
+
+pub fn fold_lit_int<F>(_f: &mut F, inst: LitInt) -> LitInt
+where
+    F: crate::fold::Fold + ?Sized,
+{
+    inst
+}
+
+impl crate::fold::Foldable for LitInt {
+    fn fold<F>(self, f: &mut F) -> Self
+    where
+        F: crate::fold::Fold + ?Sized,
+    {
+        f.fold_lit_int(self)
+    }
+}
+
+impl crate::json::ToJson for LitInt {
+    fn to_json(&self) -> crate::json::Json {
+        crate::json::Json::node(
+            "LitInt",
+            vec![
+                ("raw", crate::json::Json::String(self.raw.clone())),
+                ("value", crate::json::Json::String(self.val.to_string())),
+                ("suffix", crate::json::ToJson::to_json(&self.suffix)),
+                ("span", crate::json::ToJson::to_json(&self.span)),
+            ],
+        )
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct LitFloat {
     pub val: Float,
@@ -216,12 +519,65 @@ impl ToTokens for LitFloat {
     }
 }
 
-pub(crate) fn visit_lit_float<'ast, V>(_v: &mut V, _inst: &'ast LitFloat)
+pub fn visit_lit_float<'ast, V>(_v: &mut V, _inst: &'ast LitFloat)
 where
     V: crate::visit::Visit<'ast> + ?Sized,
 {
 }
 
+impl<'ast> crate::visit::Visitable<'ast> for LitFloat {
+    fn visit<V>(&'ast self, v: &mut V)
+    where
+        V: crate::visit::Visit<'ast> + ?Sized,
+    {
+        v.visit_lit_float(self)
+    }
+}
+
+pub fn visit_lit_float_mut<V>(_v: &mut V, _inst: &mut LitFloat)
+where
+    V: crate::visit::VisitMut + ?Sized,
+{
+}
+
+impl crate::visit::VisitableMut for LitFloat {
+    fn visit_mut<V>(&mut self, v: &mut V)
+    where
+        V: crate::visit::VisitMut + ?Sized,
+    {
+        v.visit_lit_float_mut(self)
+    }
+}
+
+pub fn fold_lit_float<F>(_f: &mut F, inst: LitFloat) -> LitFloat
+where
+    F: crate::fold::Fold + ?Sized,
+{
+    inst
+}
+
+impl crate::fold::Foldable for LitFloat {
+    fn fold<F>(self, f: &mut F) -> Self
+    where
+        F: crate::fold::Fold + ?Sized,
+    {
+        f.fold_lit_float(self)
+    }
+}
+
+impl crate::json::ToJson for LitFloat {
+    fn to_json(&self) -> crate::json::Json {
+        crate::json::Json::node(
+            "LitFloat",
+            vec![
+                ("raw", crate::json::Json::String(self.raw.clone())),
+                ("suffix", crate::json::ToJson::to_json(&self.suffix)),
+                ("span", crate::json::ToJson::to_json(&self.span)),
+            ],
+        )
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct LitBool {
     pub inner: bool,
@@ -244,12 +600,64 @@ impl ToTokens for LitBool {
     }
 }
 
-pub(crate) fn visit_lit_bool<'ast, V>(_v: &mut V, _inst: &'ast LitBool)
+pub fn visit_lit_bool<'ast, V>(_v: &mut V, _inst: &'ast LitBool)
 where
     V: crate::visit::Visit<'ast> + ?Sized,
 {
 }
 
+impl<'ast> crate::visit::Visitable<'ast> for LitBool {
+    fn visit<V>(&'ast self, v: &mut V)
+    where
+        V: crate::visit::Visit<'ast> + ?Sized,
+    {
+        v.visit_lit_bool(self)
+    }
+}
+
+pub fn visit_lit_bool_mut<V>(_v: &mut V, _inst: &mut LitBool)
+where
+    V: crate::visit::VisitMut + ?Sized,
+{
+}
+
+impl crate::visit::VisitableMut for LitBool {
+    fn visit_mut<V>(&mut self, v: &mut V)
+    where
+        V: crate::visit::VisitMut + ?Sized,
+    {
+        v.visit_lit_bool_mut(self)
+    }
+}
+
+pub fn fold_lit_bool<F>(_f: &mut F, inst: LitBool) -> LitBool
+where
+    F: crate::fold::Fold + ?Sized,
+{
+    inst
+}
+
+impl crate::fold::Foldable for LitBool {
+    fn fold<F>(self, f: &mut F) -> Self
+    where
+        F: crate::fold::Fold + ?Sized,
+    {
+        f.fold_lit_bool(self)
+    }
+}
+
+impl crate::json::ToJson for LitBool {
+    fn to_json(&self) -> crate::json::Json {
+        crate::json::Json::node(
+            "LitBool",
+            vec![
+                ("value", crate::json::Json::Bool(self.inner)),
+                ("span", crate::json::ToJson::to_json(&self.span)),
+            ],
+        )
+    }
+}
+
 macro_rules! token {
     ($format: literal => $variant: ident) => {
         #[derive(Debug, Hash, Clone, PartialEq)]
@@ -258,12 +666,44 @@ macro_rules! token {
         }
 
         paste! {
-            pub(crate) fn [<visit_ $variant:snake>]<'ast, V>(v: &mut V, inst: &'ast $variant) where V: crate::visit::Visit<'ast> + ?Sized { }
+            pub fn [<visit_ $variant:snake>]<'ast, V>(v: &mut V, inst: &'ast $variant) where V: crate::visit::Visit<'ast> + ?Sized { }
+
+            impl<'ast> crate::visit::Visitable<'ast> for $variant {
+                fn visit<V>(&'ast self, v: &mut V) where V: crate::visit::Visit<'ast> + ?Sized {
+                    v.[<visit_ $variant:snake>](self)
+                }
+            }
+
+            pub fn [<visit_ $variant:snake _mut>]<V>(_v: &mut V, _inst: &mut $variant) where V: crate::visit::VisitMut + ?Sized { }
+
+            impl crate::visit::VisitableMut for $variant {
+                fn visit_mut<V>(&mut self, v: &mut V) where V: crate::visit::VisitMut + ?Sized {
+                    v.[<visit_ $variant:snake _mut>](self)
+                }
+            }
+
+            pub fn [<fold_ $variant:snake>]<F>(_f: &mut F, inst: $variant) -> $variant where F: crate::fold::Fold + ?Sized {
+                inst
+            }
+
+            impl crate::fold::Foldable for $variant {
+                fn fold<F>(self, f: &mut F) -> Self where F: crate::fold::Fold + ?Sized {
+                    f.[<fold_ $variant:snake>](self)
+                }
+            }
+        }
+
+        impl crate::json::ToJson for $variant {
+            fn to_json(&self) -> crate::json::Json {
+                let span = Span(self.left, self.left + $variant::len());
+                crate::json::Json::node(stringify!($variant), vec![("span", crate::json::ToJson::to_json(&span))])
+            }
         }
 
         impl $variant {
+            /// Length of the token in source, where a brace is escaped as `{{` or `}}` in its format
             fn len() -> usize {
-                $format.len()
+                $format.replace("{{", "{").replace("}}", "}").len()
             }
         }
 
@@ -290,7 +730,38 @@ macro_rules! token {
         }
 
         paste! {
-            pub(crate) fn [<visit_ $variant:snake>]<'ast, V>(v: &mut V, inst: &'ast $variant) where V: crate::visit::Visit<'ast> + ?Sized { }
+            pub fn [<visit_ $variant:snake>]<'ast, V>(v: &mut V, inst: &'ast $variant) where V: crate::visit::Visit<'ast> + ?Sized { }
+
+            impl<'ast> crate::visit::Visitable<'ast> for $variant {
+                fn visit<V>(&'ast self, v: &mut V) where V: crate::visit::Visit<'ast> + ?Sized {
+                    v.[<visit_ $variant:snake>](self)
+                }
+            }
+
+            pub fn [<visit_ $variant:snake _mut>]<V>(_v: &mut V, _inst: &mut $variant) where V: crate::visit::VisitMut + ?Sized { }
+
+            impl crate::visit::VisitableMut for $variant {
+                fn visit_mut<V>(&mut self, v: &mut V) where V: crate::visit::VisitMut + ?Sized {
+                    v.[<visit_ $variant:snake _mut>](self)
+                }
+            }
+
+            pub fn [<fold_ $variant:snake>]<F>(_f: &mut F, inst: $variant) -> $variant where F: crate::fold::Fold + ?Sized {
+                inst
+            }
+
+            impl crate::fold::Foldable for $variant {
+                fn fold<F>(self, f: &mut F) -> Self where F: crate::fold::Fold + ?Sized {
+                    f.[<fold_ $variant:snake>](self)
+                }
+            }
+        }
+
+        impl crate::json::ToJson for $variant {
+            fn to_json(&self) -> crate::json::Json {
+                let span = Span(self.left, self.left + $variant::len());
+                crate::json::Json::node(stringify!($variant), vec![("span", crate::json::ToJson::to_json(&span))])
+            }
         }
 
         paste::paste! {
@@ -329,6 +800,7 @@ macro_rules! tokens {
             $( $variant($variant) ),*,
             Ident(Ident),
             Lit(Lit),
+            DocComment(DocComment),
         }
 
         impl fmt::Display for Tok {
@@ -336,7 +808,8 @@ macro_rules! tokens {
                 match self {
                     $(Self::$variant(v) => write!(f, "{}", v)),*,
                     Self::Ident(i) => write!(f, "{}", i),
-                    Self::Lit(l) => write!(f, "{}", l)
+                    Self::Lit(l) => write!(f, "{}", l),
+                    Self::DocComment(d) => write!(f, "{}", d)
                 }
             }
         }
@@ -349,6 +822,7 @@ macro_rules! tokens {
                     Self::Lit(lit) => match lit {
                         Lit::Int(LitInt { span, .. }) | Lit::Float(LitFloat { span, .. }) | Lit::Bool(LitBool { span, .. }) => span.clone(),
                     },
+                    Self::DocComment(DocComment { span, .. }) => span.clone(),
                 }
             }
         }
@@ -417,6 +891,10 @@ tokens! {
     When,
     Out,
     InOut,
+    Test,
+    Tick,
+    Assert,
+    "assert_eq" => AssertEq,
 
     "+" => Plus,
     "-" => Minus,
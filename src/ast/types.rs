@@ -28,7 +28,7 @@ crate::class_from_tokens! {
             bracket_open: BracketOpen,
             ty: Box<Type>,
             semi: Semi,
-            lit: LitInt,
+            len: Box<Expr>,
             bracket_close: BracketClose
         },
         Slice {
@@ -0,0 +1,1686 @@
+//! Evaluates constant expressions at compile time, using integers of arbitrary precision
+//!
+//! Calls are evaluated by running the body of the called `fn`, so any `fn` can be called from a
+//! constant as long as its body only uses what can be known at compile time.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use rug::ops::Pow;
+use rug::Integer;
+
+use crate::ast::*;
+use crate::diagnostics::Diagnostic;
+use crate::loader::Crate;
+use crate::resolve::{DefId, DefKind, PathResolution, Res, Resolutions};
+use crate::source_map::FileId;
+use crate::ty::Ty;
+use crate::typeck::{self, Method, Subst, TypeContext, Types};
+use crate::visit::Visit;
+
+/// How deeply calls may nest before evaluation gives up
+const MAX_CALL_DEPTH: usize = 128;
+
+/// How many loop iterations a single evaluation may take
+const MAX_STEPS: usize = 1_000_000;
+
+/// Largest number of bits an integer of unbounded width may grow to
+const MAX_BITS: u64 = 1 << 20;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    /// An integer or bit, which is always in the range of its type
+    Int {
+        val: Integer,
+        ty: Ty,
+    },
+    Bool(bool),
+    Array(Vec<Value>),
+    Tuple(Vec<Value>),
+    /// A struct, or an enum whose variant is `variant`
+    Adt {
+        def: DefId,
+        name: String,
+        variant: Option<DefId>,
+        fields: Vec<(String, Value)>,
+    },
+    /// The half open range `start..end`
+    Range(Integer, Integer),
+}
+
+impl Value {
+    pub fn unit() -> Self {
+        Self::Tuple(vec![])
+    }
+
+    pub fn as_int(&self) -> Option<&Integer> {
+        match self {
+            Self::Int { val, .. } => Some(val),
+            _ => None,
+        }
+    }
+
+    /// Whether two values are equal, regardless of the types of the integers within them
+    pub fn same(&self, other: &Self) -> bool {
+        fn all_same(a: &[Value], b: &[Value]) -> bool {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.same(b))
+        }
+        match (self, other) {
+            (Self::Int { val: a, .. }, Self::Int { val: b, .. }) => a == b,
+            (Self::Array(a), Self::Array(b)) | (Self::Tuple(a), Self::Tuple(b)) => all_same(a, b),
+            (
+                Self::Adt {
+                    def: a,
+                    variant: a_variant,
+                    fields: a_fields,
+                    ..
+                },
+                Self::Adt {
+                    def: b,
+                    variant: b_variant,
+                    fields: b_fields,
+                    ..
+                },
+            ) => {
+                a == b
+                    && a_variant == b_variant
+                    && a_fields.len() == b_fields.len()
+                    && a_fields
+                        .iter()
+                        .zip(b_fields)
+                        .all(|((a_name, a), (b_name, b))| a_name == b_name && a.same(b))
+            }
+            (a, b) => a == b,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn list(f: &mut fmt::Formatter<'_>, values: &[Value]) -> fmt::Result {
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{}", value)?;
+            }
+            Ok(())
+        }
+
+        match self {
+            Self::Int { val, .. } => write!(f, "{}", val),
+            Self::Bool(b) => write!(f, "{}", b),
+            Self::Array(values) => {
+                write!(f, "[")?;
+                list(f, values)?;
+                write!(f, "]")
+            }
+            Self::Tuple(values) if values.len() == 1 => write!(f, "({},)", values[0]),
+            Self::Tuple(values) => {
+                write!(f, "(")?;
+                list(f, values)?;
+                write!(f, ")")
+            }
+            Self::Adt { name, fields, .. } => {
+                write!(f, "{}", name)?;
+                if fields.is_empty() {
+                    return Ok(());
+                }
+                if fields[0].0 == "0" {
+                    write!(f, "(")?;
+                    for (i, (_, value)) in fields.iter().enumerate() {
+                        if i > 0 {
+                            write!(f, ", ")?;
+                        }
+                        write!(f, "{}", value)?;
+                    }
+                    write!(f, ")")
+                } else {
+                    write!(f, " {{ ")?;
+                    for (i, (name, value)) in fields.iter().enumerate() {
+                        if i > 0 {
+                            write!(f, ", ")?;
+                        }
+                        write!(f, "{}: {}", name, value)?;
+                    }
+                    write!(f, " }}")
+                }
+            }
+            Self::Range(start, end) => write!(f, "{}..{}", start, end),
+        }
+    }
+}
+
+/// Values of every `const` item of a crate, and of the discriminants given to enum variants
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Consts {
+    values: HashMap<DefId, Value>,
+}
+
+impl Consts {
+    pub fn get(&self, def: DefId) -> Option<&Value> {
+        self.values.get(&def)
+    }
+}
+
+/// Evaluates every constant of `krate`, whose types have been checked
+pub fn eval(
+    krate: &Crate,
+    resolutions: &Resolutions,
+    types: &Types,
+) -> Result<Consts, Vec<Diagnostic>> {
+    let mut eval = ConstEval::new(krate, resolutions, types);
+    let mut consts = Consts::default();
+    for (id, def) in resolutions.defs.iter().enumerate() {
+        let id = DefId(id);
+        let value = match def.kind {
+            DefKind::Const => eval.eval_const(id),
+            DefKind::Variant => eval.discriminant(id),
+            _ => continue,
+        };
+        if let Some(value) = value {
+            consts.values.insert(id, value);
+        }
+    }
+    if eval.diagnostics.is_empty() {
+        Ok(consts)
+    } else {
+        Err(eval.diagnostics)
+    }
+}
+
+/// Evaluates the lengths of the array types and repeat expressions of `krate`, which its types are
+/// lowered with, so this runs before they are checked
+///
+/// Lengths that name a generic parameter are left to be worked out for each instance.
+pub fn lens<'a>(
+    krate: &'a Crate,
+    resolutions: &'a Resolutions,
+) -> (TypeContext<'a>, Vec<Diagnostic>) {
+    // Nothing has been checked yet, so integers only take a width from their suffix or a cast
+    let types = Types::default();
+    let mut eval = ConstEval::unevaluated(krate, resolutions, &types);
+    eval.eval_lens(krate);
+    let mut cx = TypeContext::new(krate, resolutions);
+    for ((file, span), len) in eval.cx.lens() {
+        cx.set_len(*file, span.clone(), *len);
+    }
+    (cx, eval.diagnostics)
+}
+
+/// Values known while evaluating a body, along with the file its spans are offsets into
+#[derive(Clone, Debug)]
+pub struct Env {
+    pub file: FileId,
+    /// Values of const generic parameters, by the span of their name
+    pub params: HashMap<Span, Value>,
+    /// Values of `let` bindings and constants that are local to the body, by the span of their name
+    pub locals: HashMap<Span, Value>,
+    pub self_value: Option<Value>,
+}
+
+impl Env {
+    pub fn new(file: FileId) -> Self {
+        Self {
+            file,
+            params: HashMap::new(),
+            locals: HashMap::new(),
+            self_value: None,
+        }
+    }
+}
+
+/// Why evaluation of an expression stopped early
+enum Flow {
+    Return(Value),
+    /// An error that has already been reported
+    Error,
+}
+
+type Eval = Result<Value, Flow>;
+
+pub struct ConstEval<'a> {
+    cx: TypeContext<'a>,
+    /// Types that the checker inferred, which give unsuffixed literals their width
+    types: &'a Types,
+    methods: HashMap<DefId, Vec<Method<'a>>>,
+    /// Values of the constants evaluated so far, which are `None` if evaluation failed
+    values: HashMap<DefId, Option<Value>>,
+    /// Discriminants of the variants of each enum, along with whether each was given by `= expr`
+    tags: HashMap<DefId, Vec<(Integer, bool)>>,
+    /// Constants currently being evaluated, innermost last
+    stack: Vec<DefId>,
+    depth: usize,
+    steps: usize,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> ConstEval<'a> {
+    pub fn new(krate: &'a Crate, resolutions: &'a Resolutions, types: &'a Types) -> Self {
+        let mut eval = Self::unevaluated(krate, resolutions, types);
+        eval.eval_lens(krate);
+        // Tags are sized to hold the largest discriminant, so widths of enums depend on them
+        for (id, def) in resolutions.defs.iter().enumerate() {
+            if def.kind == DefKind::Enum {
+                eval.eval_tags(DefId(id));
+            }
+        }
+        eval
+    }
+
+    fn unevaluated(krate: &'a Crate, resolutions: &'a Resolutions, types: &'a Types) -> Self {
+        let cx = TypeContext::new(krate, resolutions);
+        Self {
+            types,
+            methods: typeck::methods(&cx),
+            cx,
+            values: HashMap::new(),
+            tags: HashMap::new(),
+            stack: vec![],
+            depth: 0,
+            steps: 0,
+            diagnostics: vec![],
+        }
+    }
+
+    pub fn cx(&self) -> &TypeContext<'a> {
+        &self.cx
+    }
+
+    /// Errors reported since the last call, which are cleared
+    pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.diagnostics)
+    }
+
+    /// Value of the `const` item `def`, or `None` if it could not be evaluated
+    pub fn eval_const(&mut self, def: DefId) -> Option<Value> {
+        if let Some(value) = self.values.get(&def) {
+            return value.clone();
+        }
+        let item = match self.cx.item(def) {
+            Item::Const(item) => item,
+            _ => return None,
+        };
+        let file = self.cx.file(def);
+        if let Some(start) = self.stack.iter().position(|id| *id == def) {
+            let cycle = self.stack[start..]
+                .iter()
+                .chain(std::iter::once(&def))
+                .map(|id| format!("`{}`", self.cx.resolutions().def(*id).name))
+                .collect::<Vec<_>>()
+                .join(" -> ");
+            let message = format!("cycle detected when evaluating `{}`: {}", item.ident, cycle);
+            self.diagnostics
+                .push(Diagnostic::new(message, item.ident.span.clone()).in_file(file));
+            return None;
+        }
+
+        self.stack.push(def);
+        let mut env = Env::new(file);
+        let ty = self.cx.lower(file, &item.ty, &Subst::default());
+        let value = self
+            .eval_body(&item.expr, &mut env)
+            .and_then(|value| self.coerce(value, &ty, item.expr.span(), file));
+        self.stack.pop();
+        let value = value.ok();
+        self.values.insert(def, value.clone());
+        value
+    }
+
+    /// Value given to an enum variant by `= expr`
+    fn discriminant(&mut self, variant: DefId) -> Option<Value> {
+        if let Some(value) = self.values.get(&variant) {
+            return value.clone();
+        }
+        let index = self.cx.resolutions().def(variant).variant?;
+        let item = match self.cx.item(variant) {
+            Item::Enum(item) => item,
+            _ => return None,
+        };
+        let value = match &item.variants.iter().nth(index)?.variant_type {
+            VariantType::Discrim(discrim) => {
+                let mut env = Env::new(self.cx.file(variant));
+                self.eval_body(&discrim.expr, &mut env).ok()
+            }
+            VariantType::Fields(_) | VariantType::Unit(_) => None,
+        };
+        self.values.insert(variant, value.clone());
+        value
+    }
+
+    /// Discriminants of the variants of an enum, along with whether each was given by `= expr`,
+    /// which are the tags its values are laid out with
+    pub fn tags(&self, def: DefId) -> &[(Integer, bool)] {
+        self.tags.get(&def).map_or(&[], Vec::as_slice)
+    }
+
+    /// Works out the discriminants of an enum, where a variant without `= expr` takes one more
+    /// than the variant before it, and sizes its tag to hold the largest of them
+    fn eval_tags(&mut self, def: DefId) {
+        let ids = self
+            .cx
+            .resolutions()
+            .variants
+            .get(&def)
+            .cloned()
+            .unwrap_or_default();
+        let mut tags: Vec<(Integer, bool)> = vec![];
+        let mut next = Integer::new();
+        for (index, id) in ids.iter().enumerate() {
+            let (discriminant, explicit) = match self.discriminant(*id) {
+                Some(Value::Int { val, .. }) => (val, true),
+                _ => (next.clone(), false),
+            };
+            next = Integer::from(&discriminant + 1);
+            let resolutions = self.cx.resolutions();
+            let variant = resolutions.def(*id);
+            let message = if discriminant < 0 {
+                Some(format!(
+                    "the discriminant `{}` of `{}` is negative, so it cannot be used as a tag",
+                    discriminant, variant.name
+                ))
+            } else {
+                tags.iter()
+                    .position(|(tag, _)| *tag == discriminant)
+                    .map(|other| {
+                        format!(
+                            "`{}` has the same discriminant `{}` as `{}`",
+                            variant.name,
+                            discriminant,
+                            resolutions.def(ids[other]).name
+                        )
+                    })
+            };
+            match message {
+                Some(message) => {
+                    let span = variant.ident.span.clone();
+                    let file = self.cx.file(*id);
+                    self.error(message, span, file);
+                    tags.push((Integer::from(index), explicit));
+                }
+                None => tags.push((discriminant, explicit)),
+            }
+        }
+        let width = tags
+            .iter()
+            .map(|(tag, _)| u64::from(tag.significant_bits()))
+            .max()
+            .unwrap_or(0);
+        self.cx.set_tag_width(def, width);
+        self.tags.insert(def, tags);
+    }
+
+    /// Evaluates the length of every array type and repeat expression of `krate` that does not name
+    /// a generic parameter
+    fn eval_lens(&mut self, krate: &'a Crate) {
+        struct Lens<'ast>(Vec<&'ast Expr>);
+
+        impl<'ast> Visit<'ast> for Lens<'ast> {
+            fn visit_type_array(&mut self, array: &'ast TypeArray) {
+                self.0.push(&array.len);
+                visit_type_array(self, array);
+            }
+
+            fn visit_expr_repeat(&mut self, repeat: &'ast ExprRepeat) {
+                self.0.push(&repeat.repeat);
+                visit_expr_repeat(self, repeat);
+            }
+        }
+
+        struct Generic<'r> {
+            resolutions: &'r Resolutions,
+            file: FileId,
+            found: bool,
+        }
+
+        impl<'ast> Visit<'ast> for Generic<'_> {
+            fn visit_expr_path(&mut self, path: &'ast ExprPath) {
+                if let Some(PathResolution {
+                    res: Res::GenericParam(_),
+                    ..
+                }) = self.resolutions.expr_path(self.file, path)
+                {
+                    self.found = true;
+                }
+            }
+        }
+
+        for module in krate.modules() {
+            let mut lens = Lens(vec![]);
+            for item in &module.items {
+                lens.visit_item(item);
+            }
+            for expr in lens.0 {
+                let mut generic = Generic {
+                    resolutions: self.cx.resolutions(),
+                    file: module.file,
+                    found: false,
+                };
+                generic.visit_expr(expr);
+                if generic.found {
+                    continue;
+                }
+                let mut env = Env::new(module.file);
+                let len = self
+                    .eval_expr(expr, &mut env)
+                    .and_then(|value| self.length(&value, expr.span(), module.file).ok());
+                if let Some(len) = len {
+                    self.cx.set_len(module.file, expr.span(), len as u64);
+                }
+            }
+        }
+    }
+
+    /// Evaluates `expr` with the values in `env`, or returns `None` once its errors have been reported
+    pub fn eval_expr(&mut self, expr: &Expr, env: &mut Env) -> Option<Value> {
+        self.steps = 0;
+        self.eval_body(expr, env).ok()
+    }
+
+    fn eval_body(&mut self, expr: &Expr, env: &mut Env) -> Eval {
+        match self.eval(expr, env) {
+            Err(Flow::Return(_)) => {
+                Err(self.error("`return` outside of a function", expr.span(), env.file))
+            }
+            result => result,
+        }
+    }
+
+    fn error(&mut self, message: impl Into<String>, span: Span, file: FileId) -> Flow {
+        self.diagnostics
+            .push(Diagnostic::new(message, span).in_file(file));
+        Flow::Error
+    }
+
+    fn unsupported(&mut self, what: &str, span: Span, file: FileId) -> Flow {
+        let message = format!("{} cannot be evaluated at compile time", what);
+        self.error(message, span, file)
+    }
+
+    fn step(&mut self, span: Span, file: FileId) -> Result<(), Flow> {
+        self.steps += 1;
+        if self.steps > MAX_STEPS {
+            return Err(self.error(
+                format!("constant evaluation took more than {} steps", MAX_STEPS),
+                span,
+                file,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Converts a value to `ty`, reporting integers that do not fit in it
+    fn coerce(&mut self, value: Value, ty: &Ty, span: Span, file: FileId) -> Eval {
+        match (value, ty) {
+            (Value::Int { val, ty: from }, to) if to.is_integer() || *to == Ty::Bit => {
+                if !fits(&val, to) {
+                    let message = format!("value `{}` does not fit in `{}`", val, to);
+                    return Err(self.error(message, span, file));
+                }
+                // Integers of unbounded width keep the type they had, if it was narrower
+                let ty = match to {
+                    Ty::Unsigned(None) | Ty::Signed(None) | Ty::Integer if from != Ty::Integer => {
+                        from
+                    }
+                    to => to.clone(),
+                };
+                Ok(Value::Int { val, ty })
+            }
+            (Value::Array(values), Ty::Array(element, _)) => Ok(Value::Array(
+                values
+                    .into_iter()
+                    .map(|value| self.coerce(value, element, span.clone(), file))
+                    .collect::<Result<_, _>>()?,
+            )),
+            (Value::Tuple(values), Ty::Tuple(tys)) if values.len() == tys.len() => {
+                Ok(Value::Tuple(
+                    values
+                        .into_iter()
+                        .zip(tys)
+                        .map(|(value, ty)| self.coerce(value, ty, span.clone(), file))
+                        .collect::<Result<_, _>>()?,
+                ))
+            }
+            (value, _) => Ok(value),
+        }
+    }
+
+    fn eval(&mut self, expr: &Expr, env: &mut Env) -> Eval {
+        let value = self.eval_untyped(expr, env)?;
+        match (&value, self.types.expr(env.file, expr)) {
+            (
+                Value::Int {
+                    ty: Ty::Integer, ..
+                },
+                Some(ty),
+            ) if *ty != Ty::Integer => {
+                let ty = ty.clone();
+                self.coerce(value, &ty, expr.span(), env.file)
+            }
+            _ => Ok(value),
+        }
+    }
+
+    /// Evaluates `expr`, where integers whose type is not yet known are [`Ty::Integer`]
+    fn eval_untyped(&mut self, expr: &Expr, env: &mut Env) -> Eval {
+        let file = env.file;
+        match expr {
+            Expr::Lit(lit) => self.eval_lit(lit, file),
+            Expr::Unary(unary) => {
+                // A negated literal is only typed once negated, as `128` alone does not fit in `i8`
+                let value = match (&unary.op, unary.expr.as_ref()) {
+                    (UnOp::Minus(_), Expr::Lit(lit)) => self.eval_lit(lit, file)?,
+                    _ => self.eval(&unary.expr, env)?,
+                };
+                self.unary(&unary.op, value, expr.span(), file)
+            }
+            Expr::Binary(binary) => {
+                let left = self.eval(&binary.left, env)?;
+                let right = self.eval(&binary.right, env)?;
+                self.binary(&binary.op, left, right, expr.span(), file)
+            }
+            Expr::Assign(assign) => {
+                let value = self.eval(&assign.rhs, env)?;
+                let value = match typeck::compound(&assign.op) {
+                    Some(op) => {
+                        let current = self.eval(&assign.lhs, env)?;
+                        self.binary(&op, current, value, expr.span(), file)?
+                    }
+                    None => value,
+                };
+                self.assign(&assign.lhs, value, env)?;
+                Ok(Value::unit())
+            }
+            Expr::Repeat(repeat) => {
+                let value = self.eval(&repeat.init, env)?;
+                let count = self.eval(&repeat.repeat, env)?;
+                let count = self.length(&count, repeat.repeat.span(), file)?;
+                Ok(Value::Array(vec![value; count]))
+            }
+            Expr::Range(range) => {
+                let (left, right) = match (&range.left, &range.right) {
+                    (Some(left), Some(right)) => (left, right),
+                    _ => {
+                        return Err(self.unsupported(
+                            "a range without both bounds",
+                            expr.span(),
+                            file,
+                        ))
+                    }
+                };
+                let start = self.eval(left, env)?;
+                let end = self.eval(right, env)?;
+                match (start, end) {
+                    (Value::Int { val: start, .. }, Value::Int { val: end, .. }) => {
+                        let end = match range.range_type {
+                            RangeType::Closed(_) => end + 1u32,
+                            RangeType::HalfOpen(_) => end,
+                        };
+                        Ok(Value::Range(start, end))
+                    }
+                    _ => Err(self.error("range bounds must be integers", expr.span(), file)),
+                }
+            }
+            Expr::Path(path) => self.eval_path(path, env),
+            Expr::QPath(_) => Err(self.unsupported("an associated item", expr.span(), file)),
+            Expr::Field(field) => {
+                let on = self.eval(&field.on, env)?;
+                let name = member_name(&field.member);
+                match project_field(on, &name) {
+                    Some(value) => Ok(value),
+                    None => {
+                        let message = format!("no field `{}` on this value", name);
+                        Err(self.error(message, field.member.span(), file))
+                    }
+                }
+            }
+            Expr::Call(call) => self.eval_call(call, env),
+            Expr::MethodCall(call) => self.eval_method_call(call, env),
+            Expr::Index(index) => {
+                let on = self.eval(&index.on, env)?;
+                let by = self.eval(&index.index, env)?;
+                self.index(on, by, expr.span(), file)
+            }
+            Expr::Array(array) => Ok(Value::Array(
+                array
+                    .elements
+                    .iter()
+                    .map(|element| self.eval(element, env))
+                    .collect::<Result<_, _>>()?,
+            )),
+            Expr::Tuple(tuple) => Ok(Value::Tuple(
+                tuple
+                    .elements
+                    .iter()
+                    .map(|element| self.eval(element, env))
+                    .collect::<Result<_, _>>()?,
+            )),
+            Expr::Cast(cast) => {
+                let value = self.eval(&cast.expr, env)?;
+                let ty = self.cx.lower(file, &cast.ty, &Subst::default());
+                self.cast(value, &ty, expr.span(), file)
+            }
+            Expr::For(expr_for) => {
+                let values = match self.eval(&expr_for.expr, env)? {
+                    Value::Range(start, end) => {
+                        let mut values = vec![];
+                        let mut i = start;
+                        while i < end {
+                            self.step(expr.span(), file)?;
+                            values.push(Value::Int {
+                                val: i.clone(),
+                                ty: Ty::Integer,
+                            });
+                            i += 1u32;
+                        }
+                        values
+                    }
+                    Value::Array(values) => values,
+                    value => {
+                        let message = format!("`{}` is not a range or array", value);
+                        return Err(self.error(message, expr_for.expr.span(), file));
+                    }
+                };
+                for value in values {
+                    self.step(expr.span(), file)?;
+                    self.bind(&expr_for.pat, value, env)?;
+                    self.eval_block(&expr_for.block, env)?;
+                }
+                Ok(Value::unit())
+            }
+            Expr::If(expr_if) => {
+                if self.condition(&expr_if.expr, env)? {
+                    self.eval_block(&expr_if.block, env)
+                } else {
+                    match &expr_if.else_token {
+                        Some((_, else_expr)) => self.eval(else_expr, env),
+                        None => Ok(Value::unit()),
+                    }
+                }
+            }
+            Expr::Match(expr_match) => {
+                let scrutinee = self.eval(&expr_match.expr, env)?;
+                for arm in expr_match.arms.iter() {
+                    if !self.matches(&arm.pat, &scrutinee, env)? {
+                        continue;
+                    }
+                    if let Some((_, guard)) = &arm.guard {
+                        if !self.condition(guard, env)? {
+                            continue;
+                        }
+                    }
+                    return self.eval(&arm.body, env);
+                }
+                let message = format!("no arm matches the value `{}`", scrutinee);
+                Err(self.error(message, expr_match.expr.span(), file))
+            }
+            Expr::Block(block) => self.eval_block(block, env),
+            Expr::Return(expr_return) => {
+                let value = match &expr_return.expr {
+                    Some(value) => self.eval(value, env)?,
+                    None => Value::unit(),
+                };
+                Err(Flow::Return(value))
+            }
+            Expr::Struct(expr_struct) => self.eval_struct(expr_struct, env),
+            Expr::Grouped(grouped) => self.eval(&grouped.expr, env),
+        }
+    }
+
+    fn eval_lit(&mut self, lit: &Lit, file: FileId) -> Eval {
+        match lit {
+            Lit::Int(lit) => {
+                let ty = match &lit.suffix {
+                    Some(suffix) => typeck::primitive(&suffix.inner).unwrap_or(Ty::Integer),
+                    None => Ty::Integer,
+                };
+                self.coerce(
+                    Value::Int {
+                        val: lit.val.clone(),
+                        ty: Ty::Integer,
+                    },
+                    &ty,
+                    lit.span.clone(),
+                    file,
+                )
+            }
+            Lit::Bool(lit) => Ok(Value::Bool(lit.inner)),
+            Lit::Float(lit) => {
+                Err(self.unsupported("a floating point number", lit.span.clone(), file))
+            }
+        }
+    }
+
+    fn condition(&mut self, expr: &Expr, env: &mut Env) -> Result<bool, Flow> {
+        match self.eval(expr, env)? {
+            Value::Bool(b) => Ok(b),
+            Value::Int { val, ty: Ty::Bit } => Ok(val != 0),
+            value => {
+                let message = format!("expected a `bool` or `bit`, found `{}`", value);
+                Err(self.error(message, expr.span(), env.file))
+            }
+        }
+    }
+
+    fn eval_block(&mut self, block: &Block, env: &mut Env) -> Eval {
+        let mut value = Value::unit();
+        for stmt in &block.statements {
+            value = Value::unit();
+            match stmt {
+                Stmt::Local(local) => {
+                    let init = match &local.init {
+                        Some((_, init)) => self.eval(init, env)?,
+                        None => {
+                            return Err(self.unsupported(
+                                "a `let` without a value",
+                                local.pat.span(),
+                                env.file,
+                            ))
+                        }
+                    };
+                    let init = match &local.ty {
+                        Some((_, ty)) => {
+                            let ty = self.cx.lower(env.file, ty, &Subst::default());
+                            self.coerce(init, &ty, local.pat.span(), env.file)?
+                        }
+                        None => init,
+                    };
+                    self.bind(&local.pat, init, env)?;
+                }
+                Stmt::Item(_) => {}
+                Stmt::Expr(stmt) => {
+                    let result = self.eval(&stmt.expr, env)?;
+                    if stmt.semi.is_none() {
+                        value = result;
+                    }
+                }
+            }
+        }
+        Ok(value)
+    }
+
+    /// Binds an irrefutable pattern
+    fn bind(&mut self, pat: &Pat, value: Value, env: &mut Env) -> Result<(), Flow> {
+        if self.matches(pat, &value, env)? {
+            Ok(())
+        } else {
+            let message = format!("the value `{}` does not match this pattern", value);
+            Err(self.error(message, pat.span(), env.file))
+        }
+    }
+
+    /// Whether `value` matches `pat`, binding the names within it if so
+    fn matches(&mut self, pat: &Pat, value: &Value, env: &mut Env) -> Result<bool, Flow> {
+        let file = env.file;
+        match pat {
+            Pat::Ident(ident) => {
+                env.locals.insert(ident.span.clone(), value.clone());
+                Ok(true)
+            }
+            Pat::Wildcard(_) => Ok(true),
+            Pat::Lit(lit) => {
+                let lit = self.eval_lit(lit, file)?;
+                Ok(lit.same(value))
+            }
+            Pat::Range(range) => {
+                let low = self.eval(&range.left, env)?;
+                let high = self.eval(&range.right, env)?;
+                match (low.as_int(), high.as_int(), value.as_int()) {
+                    (Some(low), Some(high), Some(val)) => Ok(val >= low
+                        && match range.range_type {
+                            RangeType::Closed(_) => val <= high,
+                            RangeType::HalfOpen(_) => val < high,
+                        }),
+                    _ => Err(self.error("range patterns must be integers", pat.span(), file)),
+                }
+            }
+            Pat::Path(path) => {
+                let expected = self.eval_path(path, env)?;
+                Ok(expected.same(value))
+            }
+            Pat::Struct(pat_struct) => {
+                let fields = match self.pat_fields(&pat_struct.path, value, file)? {
+                    Some(fields) => fields,
+                    None => return Ok(false),
+                };
+                for field in pat_struct.fields.iter() {
+                    let (name, subpat, ident) = match field {
+                        StructPatternField::TuplePat(field) => {
+                            (field.index.val.to_string(), Some(field.pat.as_ref()), None)
+                        }
+                        StructPatternField::IdentPat(field) => {
+                            (field.ident.inner.clone(), Some(field.pat.as_ref()), None)
+                        }
+                        StructPatternField::Ident(ident) => {
+                            (ident.inner.clone(), None, Some(ident))
+                        }
+                    };
+                    let value = match fields.iter().find(|(field, _)| *field == name) {
+                        Some((_, value)) => value,
+                        None => {
+                            let message = format!("no field `{}` on this value", name);
+                            return Err(self.error(message, field.span(), file));
+                        }
+                    };
+                    match (subpat, ident) {
+                        (Some(subpat), _) => {
+                            if !self.matches(subpat, value, env)? {
+                                return Ok(false);
+                            }
+                        }
+                        (None, Some(ident)) => {
+                            env.locals.insert(ident.span.clone(), value.clone());
+                        }
+                        (None, None) => {}
+                    }
+                }
+                Ok(true)
+            }
+            Pat::TupleStruct(pat_tuple) => {
+                let values: Vec<Value> = match self.pat_fields(&pat_tuple.path, value, file)? {
+                    Some(fields) => fields.into_iter().map(|(_, value)| value).collect(),
+                    None => return Ok(false),
+                };
+                self.matches_all(
+                    &pat_tuple.subpats,
+                    pat_tuple.rest_subpats.as_ref(),
+                    &values,
+                    pat.span(),
+                    env,
+                )
+            }
+            Pat::Tuple(tuple) => match value {
+                Value::Tuple(values) => self.matches_all(
+                    &tuple.subpats,
+                    tuple.rest_subpats.as_ref(),
+                    values,
+                    pat.span(),
+                    env,
+                ),
+                value => {
+                    let message = format!("expected a tuple, found `{}`", value);
+                    Err(self.error(message, pat.span(), file))
+                }
+            },
+            Pat::Slice(slice) => match value {
+                Value::Array(values) if values.len() == slice.subpats.len() => {
+                    for (subpat, value) in slice.subpats.iter().zip(values) {
+                        if !self.matches(subpat, value, env)? {
+                            return Ok(false);
+                        }
+                    }
+                    Ok(true)
+                }
+                value => {
+                    let message = format!(
+                        "expected an array of {} elements, found `{}`",
+                        slice.subpats.len(),
+                        value
+                    );
+                    Err(self.error(message, pat.span(), file))
+                }
+            },
+        }
+    }
+
+    fn matches_all(
+        &mut self,
+        subpats: &Punctuated<Pat, token::Comma>,
+        rest: Option<&(token::DotDot, token::Comma, Punctuated<Pat, token::Comma>)>,
+        values: &[Value],
+        span: Span,
+        env: &mut Env,
+    ) -> Result<bool, Flow> {
+        let rest = rest.map(|(_, _, pats)| pats.iter().collect::<Vec<_>>());
+        let count = subpats.len() + rest.as_ref().map_or(0, Vec::len);
+        if count > values.len() || (rest.is_none() && count != values.len()) {
+            let message = format!(
+                "this pattern has {} fields, but the value has {}",
+                count,
+                values.len()
+            );
+            return Err(self.error(message, span, env.file));
+        }
+        for (subpat, value) in subpats.iter().zip(values) {
+            if !self.matches(subpat, value, env)? {
+                return Ok(false);
+            }
+        }
+        let rest = rest.unwrap_or_default();
+        for (subpat, value) in rest.iter().zip(&values[values.len() - rest.len()..]) {
+            if !self.matches(subpat, value, env)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Fields of `value` if it is the struct or variant named by a pattern's path
+    fn pat_fields(
+        &mut self,
+        path: &ExprPath,
+        value: &Value,
+        file: FileId,
+    ) -> Result<Option<Vec<(String, Value)>>, Flow> {
+        let id = match self.cx.resolutions().expr_path(file, path) {
+            Some(PathResolution {
+                res: Res::Def(id),
+                unresolved_segments: 0,
+            }) => *id,
+            _ => return Err(self.unsupported("this pattern", path.segments.span(), file)),
+        };
+        match value {
+            Value::Adt {
+                def,
+                variant,
+                fields,
+                ..
+            } if *variant == Some(id) || (variant.is_none() && *def == id) => {
+                Ok(Some(fields.clone()))
+            }
+            Value::Adt { .. } => Ok(None),
+            value => {
+                let message = format!("expected a struct or enum, found `{}`", value);
+                Err(self.error(message, path.segments.span(), file))
+            }
+        }
+    }
+
+    fn eval_path(&mut self, path: &ExprPath, env: &mut Env) -> Eval {
+        let file = env.file;
+        let span = path.segments.span();
+        let name = path
+            .segments
+            .last()
+            .map(|segment| segment.ident.inner.clone())
+            .unwrap_or_default();
+        let resolution = match self.cx.resolutions().expr_path(file, path) {
+            Some(resolution) if resolution.unresolved_segments == 0 => resolution.res.clone(),
+            Some(_) => return Err(self.unsupported("an associated item", span, file)),
+            None => return Err(Flow::Error),
+        };
+        match resolution {
+            Res::Local(local) => match env.locals.get(&local) {
+                Some(value) => Ok(value.clone()),
+                None => Err(self.unsupported(&format!("`{}`", name), span, file)),
+            },
+            Res::GenericParam(param) => match env.params.get(&param) {
+                Some(value) => Ok(value.clone()),
+                None => {
+                    let message = format!("the generic parameter `{}` has no value here", name);
+                    Err(self.error(message, span, file))
+                }
+            },
+            Res::SelfValue => match &env.self_value {
+                Some(value) => Ok(value.clone()),
+                None => Err(self.unsupported("`self`", span, file)),
+            },
+            Res::Def(id) => match self.cx.resolutions().def(id).kind {
+                DefKind::Const => self.eval_const(id).ok_or(Flow::Error),
+                DefKind::Variant => {
+                    let parent = self.cx.parent_enum(id);
+                    Ok(Value::Adt {
+                        def: parent,
+                        name: self.cx.resolutions().def(parent).name.clone(),
+                        variant: Some(id),
+                        fields: vec![],
+                    })
+                }
+                kind => {
+                    let message = format!(
+                        "expected a value, found {} `{}`",
+                        typeck::describe(kind),
+                        name
+                    );
+                    Err(self.error(message, span, file))
+                }
+            },
+            Res::Port { .. } | Res::Primitive(_) | Res::SelfType => {
+                Err(self.unsupported(&format!("`{}`", name), span, file))
+            }
+        }
+    }
+
+    /// Values of the const generic params of `def` for a use of it named by `segment`, where
+    /// the args of `segment` are evaluated in `env` and params that are not given take their default
+    pub fn const_params(
+        &mut self,
+        def: DefId,
+        segment: Option<&PathSegment>,
+        env: &mut Env,
+    ) -> Option<HashMap<Span, Value>> {
+        self.steps = 0;
+        let generics = self.cx.generics(def);
+        self.const_args(def, generics, segment, env).ok()
+    }
+
+    /// Values of the generic params of `def` given by the args of `segment`, evaluated in `env`
+    fn const_args(
+        &mut self,
+        def: DefId,
+        generics: Option<&Generics>,
+        segment: Option<&PathSegment>,
+        env: &mut Env,
+    ) -> Result<HashMap<Span, Value>, Flow> {
+        let args = segment
+            .and_then(|segment| segment.generic_args.as_ref())
+            .map(|args| args.args.iter().collect::<Vec<_>>())
+            .unwrap_or_default();
+        let mut params = HashMap::new();
+        let callee_file = self.cx.file(def);
+        for (i, param) in generics
+            .iter()
+            .flat_map(|generics| generics.params.iter())
+            .enumerate()
+        {
+            let param = match param {
+                GenericParam::Const(param) => param,
+                GenericParam::Type(_) => continue,
+            };
+            let value = match args.get(i) {
+                Some(GenericArg::Expr(expr)) => self.eval(expr, env)?,
+                // A bare name as an argument parses as a type, but may name a constant
+                Some(GenericArg::Type(Type::Path(path))) => {
+                    let expr = Expr::Path(ExprPath {
+                        leading_sep: path.leading_sep.clone(),
+                        segments: path.segments.clone(),
+                    });
+                    self.eval(&expr, env)?
+                }
+                Some(arg) => {
+                    let message =
+                        format!("expected a value for the const parameter `{}`", param.ident);
+                    return Err(self.error(message, arg.span(), env.file));
+                }
+                None => match &param.default {
+                    Some((_, default)) => {
+                        let mut defaults = Env::new(callee_file);
+                        defaults.params = params.clone();
+                        self.eval(default, &mut defaults)?
+                    }
+                    None => {
+                        let message = format!(
+                            "cannot infer the value of the const parameter `{}`",
+                            param.ident
+                        );
+                        let span = segment.map_or(param.ident.span.clone(), |segment| {
+                            segment.ident.span.clone()
+                        });
+                        return Err(self.error(message, span, env.file));
+                    }
+                },
+            };
+            let ty = self.cx.lower(callee_file, &param.ty, &Subst::default());
+            let value = self.coerce(value, &ty, param.ident.span.clone(), callee_file)?;
+            params.insert(param.ident.span.clone(), value);
+        }
+        Ok(params)
+    }
+
+    fn eval_call(&mut self, call: &ExprCall, env: &mut Env) -> Eval {
+        let file = env.file;
+        let path = match call.on.as_ref() {
+            Expr::Path(path) => path,
+            on => return Err(self.unsupported("calling this expression", on.span(), file)),
+        };
+        let id = match self.cx.resolutions().expr_path(file, path) {
+            Some(PathResolution {
+                res: Res::Def(id),
+                unresolved_segments: 0,
+            }) => *id,
+            _ => return Err(self.unsupported("calling this expression", path.span(), file)),
+        };
+        let args = call
+            .args
+            .iter()
+            .map(|arg| self.eval(arg, env))
+            .collect::<Result<Vec<_>, _>>()?;
+        let def = self.cx.resolutions().def(id);
+        match (def.kind, self.cx.item(id)) {
+            (DefKind::Fn, Item::Fn(item)) => {
+                let params =
+                    self.const_args(id, item.sig.generics.as_ref(), path.segments.last(), env)?;
+                let mut callee = Env::new(self.cx.file(id));
+                callee.params = params;
+                self.call(item, args, callee, call.on.span(), file)
+            }
+            (DefKind::Struct, _) | (DefKind::Variant, _) => {
+                let (owner, variant) = match def.kind {
+                    DefKind::Variant => (self.cx.parent_enum(id), Some(id)),
+                    _ => (id, None),
+                };
+                let (_, fields) = self.cx.fields(id, &[]);
+                let fields = fields
+                    .into_iter()
+                    .zip(args)
+                    .map(|((name, ty), value)| {
+                        let value = self.coerce(value, &ty, call.on.span(), file)?;
+                        Ok((name, value))
+                    })
+                    .collect::<Result<_, _>>()?;
+                Ok(Value::Adt {
+                    def: owner,
+                    name: self.cx.resolutions().def(owner).name.clone(),
+                    variant,
+                    fields,
+                })
+            }
+            _ => Err(self.unsupported("calling this expression", path.span(), file)),
+        }
+    }
+
+    fn eval_method_call(&mut self, call: &ExprMethodCall, env: &mut Env) -> Eval {
+        let file = env.file;
+        let on = self.eval(&call.on, env)?;
+        let def = match &on {
+            Value::Adt { def, .. } => Some(*def),
+            _ => None,
+        };
+        let method = def
+            .and_then(|def| self.methods.get(&def))
+            .and_then(|methods| {
+                methods
+                    .iter()
+                    .find(|method| method.item.sig.ident == call.method)
+            })
+            .map(|method| (method.file, method.item));
+        let (method_file, item) = match method {
+            Some(method) => method,
+            None => {
+                let message = format!("no method named `{}` found for `{}`", call.method, on);
+                return Err(self.error(message, call.method.span.clone(), file));
+            }
+        };
+        let args = call
+            .args
+            .iter()
+            .map(|arg| self.eval(arg, env))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut callee = Env::new(method_file);
+        callee.self_value = Some(on);
+        self.call(item, args, callee, call.method.span.clone(), file)
+    }
+
+    /// Runs the body of `item` in `callee` once its arguments are bound
+    fn call(
+        &mut self,
+        item: &ItemFn,
+        args: Vec<Value>,
+        mut callee: Env,
+        span: Span,
+        file: FileId,
+    ) -> Eval {
+        if self.depth >= MAX_CALL_DEPTH {
+            let message = format!(
+                "reached the recursion limit of {} while calling `{}`",
+                MAX_CALL_DEPTH, item.sig.ident
+            );
+            return Err(self.error(message, span, file));
+        }
+        let inputs = item
+            .sig
+            .inputs
+            .iter()
+            .filter_map(|input| match input {
+                FnArg::Typed(typed) => Some(typed),
+                FnArg::Receiver(_) => None,
+            })
+            .collect::<Vec<_>>();
+        if inputs.len() != args.len() {
+            let message = format!(
+                "this takes {} arguments but {} were supplied",
+                inputs.len(),
+                args.len()
+            );
+            return Err(self.error(message, span, file));
+        }
+        for (input, arg) in inputs.into_iter().zip(args) {
+            let ty = self.cx.lower(callee.file, &input.ty, &Subst::default());
+            let arg = self.coerce(arg, &ty, span.clone(), file)?;
+            self.bind(&input.pat, arg, &mut callee)?;
+        }
+
+        self.depth += 1;
+        let result = match self.eval_block(&item.block, &mut callee) {
+            Err(Flow::Return(value)) => Ok(value),
+            result => result,
+        };
+        self.depth -= 1;
+        let ty = match &item.sig.output {
+            Some((_, ty)) => self.cx.lower(callee.file, ty, &Subst::default()),
+            None => Ty::unit(),
+        };
+        self.coerce(result?, &ty, span, file)
+    }
+
+    fn eval_struct(&mut self, expr: &ExprStruct, env: &mut Env) -> Eval {
+        let file = env.file;
+        let span = expr.path.segments.span();
+        let id = match self.cx.resolutions().expr_path(file, &expr.path) {
+            Some(PathResolution {
+                res: Res::Def(id),
+                unresolved_segments: 0,
+            }) => *id,
+            _ => return Err(self.unsupported("this struct", span, file)),
+        };
+        let (owner, variant) = match self.cx.resolutions().def(id).kind {
+            DefKind::Variant => (self.cx.parent_enum(id), Some(id)),
+            _ => (id, None),
+        };
+        let mut base = match &expr.base {
+            Some((_, base)) => match self.eval(base, env)? {
+                Value::Adt { fields, .. } => Some(fields),
+                value => {
+                    let message = format!("expected a struct, found `{}`", value);
+                    return Err(self.error(message, base.span(), file));
+                }
+            },
+            None => None,
+        };
+        let (_, declared) = self.cx.fields(id, &[]);
+        let mut fields = vec![];
+        for (name, ty) in declared {
+            let given = expr
+                .fields
+                .iter()
+                .find(|field| member_name(&field.member) == name);
+            let value = match given {
+                Some(field) => {
+                    let value = match (&field.expr, &field.member) {
+                        (Some((_, value)), _) => self.eval(value, env)?,
+                        (None, Member::Named(ident)) => {
+                            let path = ExprPath {
+                                leading_sep: None,
+                                segments: Punctuated {
+                                    inner: vec![],
+                                    last: Some(Box::new(PathSegment {
+                                        ident: ident.clone(),
+                                        generic_args: None,
+                                    })),
+                                },
+                            };
+                            self.eval_path(&path, env)?
+                        }
+                        (None, Member::Unnamed(_)) => return Err(Flow::Error),
+                    };
+                    self.coerce(value, &ty, field.span(), file)?
+                }
+                None => match base.as_mut().and_then(|base| {
+                    base.iter()
+                        .position(|(field, _)| *field == name)
+                        .map(|i| base.remove(i).1)
+                }) {
+                    Some(value) => value,
+                    None => {
+                        let message = format!("missing `{}` in initializer", name);
+                        return Err(self.error(message, span, file));
+                    }
+                },
+            };
+            fields.push((name, value));
+        }
+        Ok(Value::Adt {
+            def: owner,
+            name: self.cx.resolutions().def(owner).name.clone(),
+            variant,
+            fields,
+        })
+    }
+
+    /// Stores `value` in the place named by `lhs`
+    fn assign(&mut self, lhs: &Expr, value: Value, env: &mut Env) -> Result<(), Flow> {
+        let file = env.file;
+        // Indices are evaluated before the place is borrowed
+        let mut projections = vec![];
+        let mut place = lhs;
+        let local = loop {
+            match place {
+                Expr::Grouped(grouped) => place = &grouped.expr,
+                Expr::Field(field) => {
+                    projections.push(Projection::Field(member_name(&field.member)));
+                    place = &field.on;
+                }
+                Expr::Index(index) => {
+                    let by = self.eval(&index.index, env)?;
+                    let by = self.length(&by, index.index.span(), file)?;
+                    projections.push(Projection::Index(by));
+                    place = &index.on;
+                }
+                Expr::Path(path) => match self.cx.resolutions().expr_path(file, path) {
+                    Some(PathResolution {
+                        res: Res::Local(local),
+                        unresolved_segments: 0,
+                    }) if env.locals.contains_key(local) => break local.clone(),
+                    _ => return Err(self.unsupported("assigning to this", path.span(), file)),
+                },
+                _ => return Err(self.unsupported("assigning to this", place.span(), file)),
+            }
+        };
+
+        let mut target = env.locals.get_mut(&local).unwrap();
+        for projection in projections.into_iter().rev() {
+            let next = match (target, &projection) {
+                (Value::Array(values), Projection::Index(i)) => values.get_mut(*i),
+                (Value::Tuple(values), Projection::Field(name)) => match name.parse::<usize>() {
+                    Ok(i) => values.get_mut(i),
+                    Err(_) => None,
+                },
+                (Value::Adt { fields, .. }, Projection::Field(name)) => fields
+                    .iter_mut()
+                    .find(|(field, _)| field == name)
+                    .map(|(_, value)| value),
+                _ => None,
+            };
+            target = match next {
+                Some(next) => next,
+                None => return Err(self.unsupported("assigning to this", lhs.span(), file)),
+            };
+        }
+        *target = value;
+        Ok(())
+    }
+
+    /// An integer that can be used as a length or index
+    fn length(&mut self, value: &Value, span: Span, file: FileId) -> Result<usize, Flow> {
+        match value.as_int().and_then(Integer::to_usize) {
+            Some(len) if (len as u64) < MAX_BITS => Ok(len),
+            _ => {
+                let message = format!("`{}` is not a valid length or index", value);
+                Err(self.error(message, span, file))
+            }
+        }
+    }
+
+    fn index(&mut self, on: Value, by: Value, span: Span, file: FileId) -> Eval {
+        match (on, by) {
+            (Value::Array(values), Value::Range(start, end)) => {
+                let (start, end) = (start.to_usize(), end.to_usize());
+                match (start, end) {
+                    (Some(start), Some(end)) if start <= end && end <= values.len() => {
+                        Ok(Value::Array(values[start..end].to_vec()))
+                    }
+                    _ => Err(self.error("slice out of range", span, file)),
+                }
+            }
+            (Value::Array(values), by) => {
+                let i = self.length(&by, span.clone(), file)?;
+                match values.get(i) {
+                    Some(value) => Ok(value.clone()),
+                    None => {
+                        let message = format!(
+                            "index out of bounds: the length is {} but the index is {}",
+                            values.len(),
+                            i
+                        );
+                        Err(self.error(message, span, file))
+                    }
+                }
+            }
+            (Value::Int { val, ty }, by) => {
+                let width = match ty.width() {
+                    Some(width) => width,
+                    None => {
+                        return Err(self.error(
+                            "cannot index into an integer of unbounded width",
+                            span,
+                            file,
+                        ))
+                    }
+                };
+                let (start, end) = match by {
+                    Value::Range(start, end) => (start, end),
+                    by => {
+                        let i = Integer::from(self.length(&by, span.clone(), file)?);
+                        let end = Integer::from(&i + 1u32);
+                        (i, end)
+                    }
+                };
+                match (start.to_u32(), end.to_u32()) {
+                    (Some(start), Some(end)) if start < end && u64::from(end) <= width => {
+                        let bits = Integer::from(val.keep_bits_ref(end)) >> start;
+                        let ty = if end - start == 1 {
+                            Ty::Bit
+                        } else {
+                            Ty::Unsigned(Some(end - start))
+                        };
+                        Ok(Value::Int { val: bits, ty })
+                    }
+                    _ => {
+                        let message = format!("bit index out of range for `{}`", ty);
+                        Err(self.error(message, span, file))
+                    }
+                }
+            }
+            (on, _) => {
+                let message = format!("cannot index into `{}`", on);
+                Err(self.error(message, span, file))
+            }
+        }
+    }
+
+    fn cast(&mut self, value: Value, ty: &Ty, span: Span, file: FileId) -> Eval {
+        let val = match value {
+            Value::Int { val, .. } => val,
+            Value::Bool(b) => Integer::from(b as u32),
+            value => {
+                let message = format!("cannot cast `{}` to `{}`", value, ty);
+                return Err(self.error(message, span, file));
+            }
+        };
+        match ty {
+            Ty::Bool => Ok(Value::Bool(val != 0)),
+            Ty::Unsigned(None) if val < 0 => {
+                let message = format!("cannot cast the negative value `{}` to `uint`", val);
+                Err(self.error(message, span, file))
+            }
+            ty if ty.is_integer() || *ty == Ty::Bit => Ok(Value::Int {
+                val: wrap(val, ty),
+                ty: ty.clone(),
+            }),
+            ty => {
+                let message = format!("cannot cast `{}` to `{}`", val, ty);
+                Err(self.error(message, span, file))
+            }
+        }
+    }
+
+    fn unary(&mut self, op: &UnOp, value: Value, span: Span, file: FileId) -> Eval {
+        match (op, value) {
+            (UnOp::Not(_), Value::Bool(b)) => Ok(Value::Bool(!b)),
+            (UnOp::Not(_), Value::Int { val, ty }) => {
+                let val = match &ty {
+                    Ty::Unsigned(None) => {
+                        return Err(self.error(
+                            "cannot apply `!` to a `uint`, whose width is unbounded",
+                            span,
+                            file,
+                        ))
+                    }
+                    ty => wrap(Integer::from(!&val), ty),
+                };
+                Ok(Value::Int { val, ty })
+            }
+            (UnOp::Minus(_), Value::Int { val, ty }) => {
+                let negated = Integer::from(-&val);
+                if !fits(&negated, &ty) {
+                    let message =
+                        format!("attempt to negate `{}`, which would overflow `{}`", val, ty);
+                    return Err(self.error(message, span, file));
+                }
+                Ok(Value::Int { val: negated, ty })
+            }
+            (op, value) => {
+                let message = format!("cannot apply unary `{}` to `{}`", op.to_tokens()[0], value);
+                Err(self.error(message, span, file))
+            }
+        }
+    }
+
+    fn binary(&mut self, op: &BinOp, left: Value, right: Value, span: Span, file: FileId) -> Eval {
+        let token = op.to_tokens()[0].to_string();
+        let (a, a_ty, b, b_ty) = match (left, right) {
+            (Value::Int { val: a, ty: a_ty }, Value::Int { val: b, ty: b_ty }) => {
+                (a, a_ty, b, b_ty)
+            }
+            (Value::Bool(a), Value::Bool(b)) => {
+                let result = match op {
+                    BinOp::And(_) => a & b,
+                    BinOp::Or(_) => a | b,
+                    BinOp::Caret(_) | BinOp::Ne(_) => a ^ b,
+                    BinOp::EqEq(_) => a == b,
+                    BinOp::Lt(_) => !a & b,
+                    BinOp::Le(_) => a <= b,
+                    BinOp::Gt(_) => a & !b,
+                    BinOp::Ge(_) => a >= b,
+                    _ => {
+                        let message = format!("cannot apply `{}` to `bool`", token);
+                        return Err(self.error(message, span, file));
+                    }
+                };
+                return Ok(Value::Bool(result));
+            }
+            (left, right) => {
+                return match op {
+                    BinOp::EqEq(_) => Ok(Value::Bool(left.same(&right))),
+                    BinOp::Ne(_) => Ok(Value::Bool(!left.same(&right))),
+                    _ => {
+                        let message =
+                            format!("cannot apply `{}` to `{}` and `{}`", token, left, right);
+                        Err(self.error(message, span, file))
+                    }
+                };
+            }
+        };
+
+        // Shift amounts and exponents keep their own type, while other operands meet at the type of the typed side
+        let shift = matches!(op, BinOp::Shl(_) | BinOp::Shr(_) | BinOp::StarStar(_));
+        let ty = if a_ty == Ty::Integer && !shift {
+            b_ty.clone()
+        } else {
+            a_ty.clone()
+        };
+        if !shift {
+            for val in [&a, &b] {
+                if !fits(val, &ty) {
+                    let message = format!("value `{}` does not fit in `{}`", val, ty);
+                    return Err(self.error(message, span, file));
+                }
+            }
+        }
+        let overflow = |this: &mut Self| {
+            let message = format!(
+                "attempt to compute `{} {} {}`, which would overflow `{}`",
+                a, token, b, ty
+            );
+            Err(this.error(message, span.clone(), file))
+        };
+
+        let val = match op {
+            BinOp::Plus(_) => Integer::from(&a + &b),
+            BinOp::Minus(_) => Integer::from(&a - &b),
+            BinOp::Star(_) => Integer::from(&a * &b),
+            BinOp::Slash(_) | BinOp::Percent(_) if b == 0 => {
+                let message = format!(
+                    "attempt to compute `{} {} {}`, which divides by zero",
+                    a, token, b
+                );
+                return Err(self.error(message, span, file));
+            }
+            BinOp::Slash(_) => Integer::from(&a / &b),
+            BinOp::Percent(_) => Integer::from(&a % &b),
+            BinOp::StarStar(_) => {
+                let exponent = match b.to_u32() {
+                    Some(exponent) => exponent,
+                    None => return overflow(self),
+                };
+                if u64::from(a.significant_bits()) * u64::from(exponent) > MAX_BITS {
+                    return overflow(self);
+                }
+                Integer::from((&a).pow(exponent))
+            }
+            BinOp::Caret(_) => Integer::from(&a ^ &b),
+            BinOp::And(_) => Integer::from(&a & &b),
+            BinOp::Or(_) => Integer::from(&a | &b),
+            BinOp::Shl(_) | BinOp::Shr(_) => {
+                let amount = match b.to_u32() {
+                    Some(amount) if u64::from(amount) < ty.width().unwrap_or(MAX_BITS) => amount,
+                    _ => {
+                        let direction = if let BinOp::Shl(_) = op {
+                            "left"
+                        } else {
+                            "right"
+                        };
+                        let message = format!(
+                            "attempt to shift {} by `{}`, which would overflow `{}`",
+                            direction, b, ty
+                        );
+                        return Err(self.error(message, span, file));
+                    }
+                };
+                match op {
+                    // Bits shifted out of a fixed width are discarded
+                    BinOp::Shl(_) if ty.width().is_some() => wrap(a.clone() << amount, &ty),
+                    BinOp::Shl(_) => a.clone() << amount,
+                    _ => a.clone() >> amount,
+                }
+            }
+            BinOp::EqEq(_) => return Ok(Value::Bool(a == b)),
+            BinOp::Ne(_) => return Ok(Value::Bool(a != b)),
+            BinOp::Lt(_) => return Ok(Value::Bool(a < b)),
+            BinOp::Le(_) => return Ok(Value::Bool(a <= b)),
+            BinOp::Gt(_) => return Ok(Value::Bool(a > b)),
+            BinOp::Ge(_) => return Ok(Value::Bool(a >= b)),
+        };
+        if !fits(&val, &ty) {
+            return overflow(self);
+        }
+        Ok(Value::Int { val, ty })
+    }
+}
+
+enum Projection {
+    Field(String),
+    Index(usize),
+}
+
+/// Whether `val` is in the range of the integer type `ty`
+pub fn fits(val: &Integer, ty: &Ty) -> bool {
+    match ty {
+        Ty::Bit => *val == 0 || *val == 1,
+        Ty::Unsigned(None) => *val >= 0,
+        Ty::Unsigned(Some(width)) => *val >= 0 && val.significant_bits() <= *width,
+        Ty::Signed(Some(width)) => val.signed_bits() <= *width,
+        _ => true,
+    }
+}
+
+/// Truncates `val` to the width of `ty`, as two's complement for signed types
+pub fn wrap(val: Integer, ty: &Ty) -> Integer {
+    match ty {
+        Ty::Bit => val.keep_bits(1),
+        Ty::Unsigned(Some(width)) => val.keep_bits(*width),
+        Ty::Signed(Some(width)) => val.keep_signed_bits(*width),
+        _ => val,
+    }
+}
+
+fn member_name(member: &Member) -> String {
+    match member {
+        Member::Named(ident) => ident.inner.clone(),
+        Member::Unnamed(index) => index.val.to_string(),
+    }
+}
+
+fn project_field(value: Value, name: &str) -> Option<Value> {
+    match value {
+        Value::Tuple(mut values) => {
+            let i = name.parse::<usize>().ok().filter(|i| *i < values.len())?;
+            Some(values.swap_remove(i))
+        }
+        Value::Adt { fields, .. } => fields
+            .into_iter()
+            .find(|(field, _)| field == name)
+            .map(|(_, value)| value),
+        _ => None,
+    }
+}
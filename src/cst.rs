@@ -0,0 +1,364 @@
+//! Lossless view of parsed source, where every token carries the comments and whitespace around it
+//!
+//! [`lex`] splits source with the lexer of the parser, but keeps the whitespace and `//` comments
+//! that the parser skips as [`Trivia`]. A [`Cst`] attaches that trivia to the tokens of a node
+//! parsed from the source: a token trails the trivia after it up to and including the end of its
+//! line, and leads with the rest. Writing each token's leading trivia, the token itself and its
+//! trailing trivia, in order, gives back the source byte for byte.
+//!
+//! Tokens are matched with what was lexed by the offset they start at, which edits to a tree leave
+//! alone, so [`Cst::update`] carries the trivia of each token over to an edited tree.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use lalrpop_util::lexer::Token;
+use lalrpop_util::ParseError;
+
+use crate::ast::token::{Spanned, ToTokens, Tok};
+use crate::ast::{visit_generic_args, visit_generics, visit_qualifier};
+use crate::ast::{GenericArgs, Generics, Qualifier, Span};
+use crate::visit::{Visit, Visitable};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TriviaKind {
+    /// Spaces, tabs and line breaks
+    Whitespace,
+    /// A `//` comment, without the line break that ends it
+    Comment,
+    /// Source that no token was parsed from, such as what the parser skipped to recover from a
+    /// syntax error
+    Skipped,
+}
+
+/// A run of source between two tokens
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub text: String,
+    pub span: Span,
+}
+
+impl fmt::Display for Trivia {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+/// What the lexer splits source into
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Lexeme {
+    Token(Span),
+    Trivia(Trivia),
+}
+
+/// Splits `source` into the tokens of the lexer of the parser and the trivia between them
+///
+/// A doc comment ends before its line break, like the span the parser gives it. A character that
+/// starts no token is skipped on its own.
+pub fn lex(source: &str) -> Vec<Lexeme> {
+    let builder = crate::parser::lexer();
+
+    let mut acc = vec![];
+    let mut offset = 0;
+    while offset < source.len() {
+        let rest = &source[offset..];
+        let mut matcher = builder.matcher::<()>(rest);
+        let mut end = 0;
+        offset = loop {
+            match matcher.next() {
+                Some(Ok((start, Token(_, text), _))) => {
+                    trivia(&mut acc, source, offset + end, offset + start);
+                    end = start + text.strip_suffix('\n').unwrap_or(text).len();
+                    acc.push(Lexeme::Token(Span(offset + start, offset + end)));
+                }
+                Some(Err(ParseError::InvalidToken { location })) => {
+                    trivia(&mut acc, source, offset + end, offset + location);
+                    let len = rest[location..].chars().next().map_or(1, char::len_utf8);
+                    let span = Span(offset + location, offset + location + len);
+                    acc.push(Lexeme::Trivia(Trivia {
+                        kind: TriviaKind::Skipped,
+                        text: rest[location..location + len].to_string(),
+                        span: span.clone(),
+                    }));
+                    break span.1;
+                }
+                Some(Err(_)) | None => {
+                    trivia(&mut acc, source, offset + end, source.len());
+                    break source.len();
+                }
+            }
+        };
+    }
+    acc
+}
+
+/// Pushes the whitespace and `//` comments that the lexer of the parser skipped from `start` to
+/// `end` of `source`
+fn trivia(acc: &mut Vec<Lexeme>, source: &str, mut start: usize, end: usize) {
+    while start < end {
+        let rest = &source[start..end];
+        let (kind, len) = match rest.strip_prefix("//") {
+            Some(comment) => (
+                TriviaKind::Comment,
+                "//".len() + comment.find('\n').unwrap_or(comment.len()),
+            ),
+            None => (
+                TriviaKind::Whitespace,
+                rest.find("//").unwrap_or(rest.len()),
+            ),
+        };
+        acc.push(Lexeme::Trivia(Trivia {
+            kind,
+            text: rest[..len].to_string(),
+            span: Span(start, start + len),
+        }));
+        start += len;
+    }
+}
+
+/// A token along with the trivia around it
+#[derive(Clone, Debug, PartialEq)]
+pub struct TriviaTok {
+    pub leading: Vec<Trivia>,
+    pub tok: Tok,
+    /// Trivia on the rest of the token's line, ending with its line break if there is one
+    pub trailing: Vec<Trivia>,
+}
+
+impl fmt::Display for TriviaTok {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for trivia in &self.leading {
+            write!(f, "{}", trivia)?;
+        }
+        write!(f, "{}", self.tok)?;
+        for trivia in &self.trailing {
+            write!(f, "{}", trivia)?;
+        }
+        Ok(())
+    }
+}
+
+/// The tokens of a node with every byte of the source around them
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cst {
+    pub tokens: Vec<TriviaTok>,
+    /// Trivia after the trailing trivia of the last token, or all of it if there are no tokens
+    pub eof: Vec<Trivia>,
+    /// Offsets of the `<` and `>` tokens that delimit generics, rather than compare
+    pub angle_brackets: HashSet<usize>,
+}
+
+/// A token as lexed, with the trivia that [`Cst::new`] attaches to it
+struct Lexed {
+    span: Span,
+    leading: Vec<Trivia>,
+    trailing: Vec<Trivia>,
+}
+
+impl Cst {
+    /// The tokens of `node` with the trivia that was lexed around them from `source`, which is
+    /// where `node` was parsed from
+    ///
+    /// Lexed tokens that are not in `node`, such as those the parser skipped to recover from a
+    /// syntax error, become [`TriviaKind::Skipped`] trivia that leads the next token, so the tree
+    /// renders as the whole of `source` when `node` is the file parsed from it.
+    pub fn new<'ast>(source: &str, node: &'ast (impl ToTokens + Visitable<'ast>)) -> Self {
+        let mut lexed: Vec<Lexed> = vec![];
+        let mut pending = vec![];
+        for lexeme in lex(source) {
+            let span = match lexeme {
+                Lexeme::Trivia(trivia) => {
+                    pending.push(trivia);
+                    continue;
+                }
+                Lexeme::Token(span) => span,
+            };
+            let trivia = std::mem::take(&mut pending);
+            let leading = match lexed.last_mut() {
+                Some(prev) => {
+                    let (trailing, leading) = split_line(trivia);
+                    prev.trailing = trailing;
+                    leading
+                }
+                None => trivia,
+            };
+            lexed.push(Lexed {
+                span,
+                leading,
+                trailing: vec![],
+            });
+        }
+        let mut eof = match lexed.last_mut() {
+            Some(last) => {
+                let (trailing, eof) = split_line(pending);
+                last.trailing = trailing;
+                eof
+            }
+            None => pending,
+        };
+
+        let tokens = node.to_tokens();
+        let starts = tokens
+            .iter()
+            .map(|tok| tok.span().0)
+            .collect::<HashSet<_>>();
+        let mut claimed = HashMap::new();
+        let mut skipped = vec![];
+        for lexed in lexed {
+            if starts.contains(&lexed.span.0) {
+                let mut leading = std::mem::take(&mut skipped);
+                leading.extend(lexed.leading);
+                claimed.insert(lexed.span.0, (leading, lexed.trailing));
+            } else {
+                skipped.extend(lexed.leading);
+                skipped.push(Trivia {
+                    kind: TriviaKind::Skipped,
+                    text: source[lexed.span.0..lexed.span.1].to_string(),
+                    span: lexed.span,
+                });
+                skipped.extend(lexed.trailing);
+            }
+        }
+        skipped.append(&mut eof);
+
+        let tokens = tokens
+            .into_iter()
+            .map(|tok| {
+                // Tokens that the parser duplicated have no trivia of their own
+                let (leading, trailing) = claimed.remove(&tok.span().0).unwrap_or_default();
+                TriviaTok {
+                    leading,
+                    tok,
+                    trailing,
+                }
+            })
+            .collect();
+        Self {
+            tokens,
+            eof: skipped,
+            angle_brackets: angle_brackets(node),
+        }
+    }
+
+    /// The tokens of `node`, which is the tree that this was built from after it has been edited,
+    /// where each token keeps the trivia that it had here and a new token is set apart from the
+    /// one before it by a space
+    ///
+    /// Trivia goes along with the tokens it is attached to, so a comment stays with the item or
+    /// statement it was next to wherever that ends up, and is gone along with it.
+    pub fn update<'ast>(&self, node: &'ast (impl ToTokens + Visitable<'ast>)) -> Self {
+        let mut trivia = self
+            .tokens
+            .iter()
+            .map(|tok| (tok.tok.span().0, (&tok.leading, &tok.trailing)))
+            .collect::<HashMap<_, _>>();
+        let tokens = node
+            .to_tokens()
+            .into_iter()
+            .enumerate()
+            .map(|(i, tok)| {
+                let (leading, trailing) = match trivia.remove(&tok.span().0) {
+                    Some((leading, trailing)) => (leading.clone(), trailing.clone()),
+                    None if i == 0 => (vec![], vec![]),
+                    None => {
+                        let space = Trivia {
+                            kind: TriviaKind::Whitespace,
+                            text: " ".to_string(),
+                            span: Span(tok.span().0, tok.span().0),
+                        };
+                        (vec![space], vec![])
+                    }
+                };
+                TriviaTok {
+                    leading,
+                    tok,
+                    trailing,
+                }
+            })
+            .collect();
+        Self {
+            tokens,
+            eof: self.eof.clone(),
+            angle_brackets: angle_brackets(node),
+        }
+    }
+
+    /// Every `//` comment, in source order
+    pub fn comments(&self) -> impl Iterator<Item = &Trivia> {
+        self.tokens
+            .iter()
+            .flat_map(|tok| tok.leading.iter().chain(&tok.trailing))
+            .chain(&self.eof)
+            .filter(|trivia| trivia.kind == TriviaKind::Comment)
+    }
+}
+
+/// Offsets of the `<` and `>` tokens of `node` that delimit generic parameters and arguments, which
+/// the tokens alone cannot tell apart from comparisons
+pub(crate) fn angle_brackets<'ast>(node: &'ast impl Visitable<'ast>) -> HashSet<usize> {
+    struct AngleBrackets(HashSet<usize>);
+
+    impl<'ast> Visit<'ast> for AngleBrackets {
+        fn visit_generics(&mut self, generics: &'ast Generics) {
+            self.0.extend([generics.lt.left, generics.gt.left]);
+            visit_generics(self, generics);
+        }
+
+        fn visit_generic_args(&mut self, args: &'ast GenericArgs) {
+            self.0.extend([args.lt.left, args.gt.left]);
+            visit_generic_args(self, args);
+        }
+
+        fn visit_qualifier(&mut self, qualifier: &'ast Qualifier) {
+            self.0.extend([qualifier.lt.left, qualifier.gt.left]);
+            visit_qualifier(self, qualifier);
+        }
+    }
+
+    let mut angle_brackets = AngleBrackets(HashSet::new());
+    node.visit(&mut angle_brackets);
+    angle_brackets.0
+}
+
+impl fmt::Display for Cst {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for tok in &self.tokens {
+            write!(f, "{}", tok)?;
+        }
+        for trivia in &self.eof {
+            write!(f, "{}", trivia)?;
+        }
+        Ok(())
+    }
+}
+
+/// Splits trivia after the first line break into what trails the token before it and what leads
+/// the token after
+fn split_line(mut trivia: Vec<Trivia>) -> (Vec<Trivia>, Vec<Trivia>) {
+    let line_break = trivia.iter().enumerate().find_map(|(i, trivia)| {
+        match (trivia.kind, trivia.text.find('\n')) {
+            (TriviaKind::Whitespace, Some(at)) => Some((i, at + 1)),
+            _ => None,
+        }
+    });
+    let (i, at) = match line_break {
+        Some(line_break) => line_break,
+        None => return (trivia, vec![]),
+    };
+    let mut leading = trivia.split_off(i + 1);
+    let last = trivia.last_mut().expect("the line break is in this trivia");
+    if at < last.text.len() {
+        let start = last.span.0 + at;
+        leading.insert(
+            0,
+            Trivia {
+                kind: TriviaKind::Whitespace,
+                text: last.text.split_off(at),
+                span: Span(start, last.span.1),
+            },
+        );
+        last.span.1 = start;
+    }
+    (trivia, leading)
+}
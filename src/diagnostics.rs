@@ -0,0 +1,360 @@
+//! Human-readable diagnostics for errors found in RHDL source
+
+use std::fmt;
+
+use lalrpop_util::{lexer::Token, ParseError};
+
+use crate::ast::{token::Tok, File, Span, ToTokens};
+use crate::source_map::{FileId, SourceMap};
+use crate::visit::Visit;
+
+/// An error located at a span of the source it was found in
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub message: String,
+    pub span: Span,
+    pub file: Option<FileId>,
+    /// Tokens that would have been accepted instead, as they are written in source
+    pub expected: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(message: impl Into<String>, span: Span) -> Self {
+        Self {
+            message: message.into(),
+            span,
+            file: None,
+            expected: vec![],
+        }
+    }
+
+    /// Attributes the diagnostic to `file`, which its span is an offset into
+    pub fn in_file(mut self, file: FileId) -> Self {
+        self.file = Some(file);
+        self
+    }
+
+    /// Renders the diagnostic with the offending line of its file underlined
+    ///
+    /// ```text
+    /// error: unexpected `,`, expected one of `}`, identifier
+    ///  --> top.rhdl:1:12
+    ///   |
+    /// 1 | arch Top { , }
+    ///   |            ^
+    /// ```
+    ///
+    /// Only the message is rendered if the diagnostic is not attributed to a file.
+    pub fn render(&self, source_map: &SourceMap) -> String {
+        let file = match self.file {
+            Some(file) => source_map.get(file),
+            None => return format!("error: {}\n", self),
+        };
+        let (start, end) = file.resolve(&self.span);
+        let line_text = file.line(start.line);
+        let underline = if end.line == start.line {
+            end.column - start.column
+        } else {
+            line_text.chars().count() + 1 - start.column
+        };
+
+        let gutter = " ".repeat(start.line.to_string().len());
+        format!(
+            "error: {}\n{}--> {}:{}\n{} |\n{} | {}\n{} | {}{}\n",
+            self,
+            gutter,
+            file.name().display(),
+            start,
+            gutter,
+            start.line,
+            line_text,
+            gutter,
+            " ".repeat(start.column - 1),
+            "^".repeat(underline.max(1))
+        )
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        match self.expected.as_slice() {
+            [] => Ok(()),
+            [only] => write!(f, ", expected {}", only),
+            expected => write!(f, ", expected one of {}", expected.join(", ")),
+        }
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+/// Diagnostics are kept in the AST for errors the parser recovered from, but have no tokens of their own
+impl ToTokens for Diagnostic {
+    fn to_tokens(&self) -> Vec<Tok> {
+        vec![]
+    }
+
+    fn len(&self) -> usize {
+        0
+    }
+}
+
+pub fn visit_diagnostic<'ast, V>(_v: &mut V, _inst: &'ast Diagnostic)
+where
+    V: crate::visit::Visit<'ast> + ?Sized,
+{
+}
+
+impl<'ast> crate::visit::Visitable<'ast> for Diagnostic {
+    fn visit<V>(&'ast self, v: &mut V)
+    where
+        V: crate::visit::Visit<'ast> + ?Sized,
+    {
+        v.visit_diagnostic(self)
+    }
+}
+
+pub fn visit_diagnostic_mut<V>(_v: &mut V, _inst: &mut Diagnostic)
+where
+    V: crate::visit::VisitMut + ?Sized,
+{
+}
+
+impl crate::visit::VisitableMut for Diagnostic {
+    fn visit_mut<V>(&mut self, v: &mut V)
+    where
+        V: crate::visit::VisitMut + ?Sized,
+    {
+        v.visit_diagnostic_mut(self)
+    }
+}
+
+pub fn fold_diagnostic<F>(_f: &mut F, inst: Diagnostic) -> Diagnostic
+where
+    F: crate::fold::Fold + ?Sized,
+{
+    inst
+}
+
+impl crate::fold::Foldable for Diagnostic {
+    fn fold<F>(self, f: &mut F) -> Self
+    where
+        F: crate::fold::Fold + ?Sized,
+    {
+        f.fold_diagnostic(self)
+    }
+}
+
+impl crate::json::ToJson for Diagnostic {
+    fn to_json(&self) -> crate::json::Json {
+        crate::json::Json::node(
+            "Diagnostic",
+            vec![
+                ("message", crate::json::Json::String(self.message.clone())),
+                ("span", crate::json::ToJson::to_json(&self.span)),
+            ],
+        )
+    }
+}
+
+/// Every syntax error the parser recovered from while parsing `file`, in source order
+pub fn recovered(file: &File) -> Vec<&Diagnostic> {
+    struct Recovered<'ast>(Vec<&'ast Diagnostic>);
+
+    impl<'ast> Visit<'ast> for Recovered<'ast> {
+        fn visit_diagnostic(&mut self, diagnostic: &'ast Diagnostic) {
+            self.0.push(diagnostic);
+        }
+    }
+
+    let mut recovered = Recovered(vec![]);
+    recovered.visit_file(file);
+    recovered.0
+}
+
+impl<'input> From<ParseError<usize, Token<'input>, &'static str>> for Diagnostic {
+    fn from(err: ParseError<usize, Token<'input>, &'static str>) -> Self {
+        let (message, span, expected) = match err {
+            ParseError::InvalidToken { location } => (
+                "invalid token".to_string(),
+                Span(location, location),
+                vec![],
+            ),
+            ParseError::UnrecognizedEOF { location, expected } => (
+                "unexpected end of file".to_string(),
+                Span(location, location),
+                expected,
+            ),
+            ParseError::UnrecognizedToken {
+                token: (left, token, right),
+                expected,
+            } => (
+                format!("unexpected `{}`", token.1),
+                Span(left, right),
+                expected,
+            ),
+            ParseError::ExtraToken {
+                token: (left, token, right),
+            } => (
+                format!("unexpected extra token `{}`", token.1),
+                Span(left, right),
+                vec![],
+            ),
+            ParseError::User { error } => (error.to_string(), Span(0, 0), vec![]),
+        };
+        let mut expected: Vec<String> = expected
+            .iter()
+            .map(|name| describe_terminal(name))
+            .collect();
+        expected.sort();
+        expected.dedup();
+        Self {
+            message,
+            span,
+            file: None,
+            expected,
+        }
+    }
+}
+
+/// Describes a terminal named in the grammar (i.e. `TokEntity`) the way it is written in source (i.e. `entity`)
+pub fn describe_terminal(name: &str) -> String {
+    match spelling(name) {
+        Some(spelling) => format!("`{}`", spelling),
+        None => match name {
+            "NonKeywordIdentifier" | "RawIdentifier" => "identifier",
+            "Integer" | "IntegerForLiteral" | "DecimalInteger" | "HexInteger" | "OctalInteger"
+            | "BinaryInteger" | "BaseInteger" => "integer literal",
+            "FloatingPoint" => "float literal",
+            "Boolean" => "boolean literal",
+            "Docstring" => "doc comment",
+            other => other,
+        }
+        .to_string(),
+    }
+}
+
+/// Keywords and punctuation, as they appear in the grammar's `match` block
+fn spelling(name: &str) -> Option<&'static str> {
+    let spelling = match name {
+        "TokAs" => "as",
+        "TokBreak" => "break",
+        "TokConst" => "const",
+        "TokContinue" => "continue",
+        "TokCrate" => "crate",
+        "TokElse" => "else",
+        "TokEnum" => "enum",
+        "TokExtern" => "extern",
+        "TokFn" => "fn",
+        "TokFor" => "for",
+        "TokIf" => "if",
+        "TokImpl" => "impl",
+        "TokIn" => "in",
+        "TokLet" => "let",
+        "TokLoop" => "loop",
+        "TokMatch" => "match",
+        "TokMod" => "mod",
+        "TokMove" => "move",
+        "TokMut" => "mut",
+        "TokPub" => "pub",
+        "TokRef" => "ref",
+        "TokReturn" => "return",
+        "TokLowerSelf" => "self",
+        "TokUpperSelf" => "Self",
+        "TokStatic" => "static",
+        "TokStruct" => "struct",
+        "TokSuper" => "super",
+        "TokTrait" => "trait",
+        "TokTokenType" => "type",
+        "TokUnsafe" => "unsafe",
+        "TokUse" => "use",
+        "TokWhere" => "where",
+        "TokWhile" => "while",
+        "TokAsync" => "async",
+        "TokAwait" => "await",
+        "TokDyn" => "dyn",
+        "TokAbstract" => "abstract",
+        "TokBecome" => "become",
+        "TokBox" => "box",
+        "TokDo" => "do",
+        "TokFinal" => "final",
+        "TokMacro" => "macro",
+        "TokOverride" => "override",
+        "TokPriv" => "priv",
+        "TokTypeof" => "typeof",
+        "TokUnsized" => "unsized",
+        "TokVirtual" => "virtual",
+        "TokYield" => "yield",
+        "TokTry" => "try",
+        "TokUnion" => "union",
+        "TokEntity" => "entity",
+        "TokBag" => "bag",
+        "TokRing" => "ring",
+        "TokArch" => "arch",
+        "TokWhen" => "when",
+        "TokOut" => "out",
+        "TokInOut" => "inout",
+        "TokTest" => "test",
+        "TokTick" => "tick",
+        "TokAssert" => "assert",
+        "TokAssertEq" => "assert_eq",
+        "TokPlus" => "+",
+        "TokMinus" => "-",
+        "TokStar" => "*",
+        "TokStarStar" => "**",
+        "TokSlash" => "/",
+        "TokPercent" => "%",
+        "TokCaret" => "^",
+        "TokNot" => "!",
+        "TokAnd" => "&",
+        "TokOr" => "|",
+        "TokAndAnd" => "&&",
+        "TokOrOr" => "||",
+        "TokShl" => "<<",
+        "TokShr" => ">>",
+        "TokPlusEq" => "+=",
+        "TokMinusEq" => "-=",
+        "TokStarEq" => "*=",
+        "TokStarStarEq" => "**=",
+        "TokSlashEq" => "/=",
+        "TokPercentEq" => "%=",
+        "TokCaretEq" => "^=",
+        "TokAndEq" => "&=",
+        "TokOrEq" => "|=",
+        "TokShlEq" => "<<=",
+        "TokShrEq" => ">>=",
+        "TokEq" => "=",
+        "TokEqEq" => "==",
+        "TokNe" => "!=",
+        "TokGt" => ">",
+        "TokLt" => "<",
+        "TokGe" => ">=",
+        "TokLe" => "<=",
+        "TokAt" => "@",
+        "TokUnderscore" => "_",
+        "TokDot" => ".",
+        "TokDotDot" => "..",
+        "TokDotDotEq" => "..=",
+        "TokComma" => ",",
+        "TokSemi" => ";",
+        "TokColon" => ":",
+        "TokPathSep" => "::",
+        "TokRArrow" => "->",
+        "TokFatArrow" => "=>",
+        "TokPound" => "#",
+        "TokDollar" => "$",
+        "TokQuestion" => "?",
+        "TokBracketOpen" => "[",
+        "TokBracketClose" => "]",
+        "TokParenOpen" => "(",
+        "TokParenClose" => ")",
+        "TokBraceOpen" => "{",
+        "TokBraceClose" => "}",
+        "RawCrate" => "r#crate",
+        "RawLowerSelf" => "r#self",
+        "RawUpperSelf" => "r#Self",
+        "RawSuper" => "r#super",
+        _ => return None,
+    };
+    Some(spelling)
+}
@@ -0,0 +1,541 @@
+//! Elaborates a design by instantiating its top entity and, recursively, every entity that its arch
+//! instantiates, with the generic arguments of each instance known
+
+use std::collections::HashMap;
+use std::fmt;
+
+use rug::Integer;
+
+use crate::ast::*;
+use crate::const_eval::{ConstEval, Env, Value};
+use crate::diagnostics::Diagnostic;
+use crate::loader::Crate;
+use crate::resolve::{DefId, DefKind, PathResolution, Res, Resolutions};
+use crate::source_map::FileId;
+use crate::ty::Ty;
+use crate::typeck::{Subst, Types};
+
+/// The value given to a generic param of an entity
+#[derive(Clone, Debug, PartialEq)]
+pub enum GenericValue {
+    Type(Ty),
+    Const(Value),
+}
+
+impl fmt::Display for GenericValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Type(ty) => write!(f, "{}", ty),
+            Self::Const(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+/// A port of an instance, with its type and width known
+#[derive(Clone, Debug, PartialEq)]
+pub struct InstancePort<'a> {
+    pub port: &'a Port,
+    pub ty: Ty,
+    pub width: u64,
+    /// Value of the port when it is left unconnected
+    pub default: Option<Value>,
+}
+
+impl InstancePort<'_> {
+    pub fn name(&self) -> &str {
+        &self.port.ident.inner
+    }
+}
+
+/// An entity instantiated with known generic arguments, along with the arch that implements it
+#[derive(Clone, Debug)]
+pub struct Instance<'a> {
+    /// Name of the instance within its parent
+    pub name: String,
+    /// Names of the instances from the top down to this one, joined by `.`
+    pub path: String,
+    pub entity: DefId,
+    pub entity_name: String,
+    /// Values of the entity's generic params, in the order they were declared
+    pub generics: Vec<GenericValue>,
+    pub ports: Vec<InstancePort<'a>>,
+    pub arch: &'a ItemArch,
+    /// What the arch's type params stand for
+    pub subst: Subst,
+    /// Values of the arch's const params and constants, where the file is the one the arch is in
+    pub env: Env,
+    /// Where the parent arch instantiates this one, which is `None` for the top
+    pub instantiation: Option<&'a ArchItemEntityExpression>,
+    pub children: Vec<Instance<'a>>,
+}
+
+impl<'a> Instance<'a> {
+    /// File that the spans of `arch` are offsets into
+    pub fn file(&self) -> FileId {
+        self.env.file
+    }
+
+    /// Type args of the entity, which are those of its generics that are types
+    pub fn type_args(&self) -> Vec<Ty> {
+        type_args(&self.generics)
+    }
+
+    pub fn port(&self, name: &str) -> Option<&InstancePort<'a>> {
+        self.ports.iter().find(|port| port.name() == name)
+    }
+
+    /// This instance and every instance below it in pre-order
+    pub fn instances(&self) -> Vec<&Instance<'a>> {
+        fn pre_order<'i, 'a>(instance: &'i Instance<'a>, acc: &mut Vec<&'i Instance<'a>>) {
+            acc.push(instance);
+            instance
+                .children
+                .iter()
+                .for_each(|child| pre_order(child, acc));
+        }
+        let mut acc = vec![];
+        pre_order(self, &mut acc);
+        acc
+    }
+
+    fn fmt_indented(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        write!(
+            f,
+            "{:indent$}{}: {}",
+            "",
+            self.name,
+            self.entity_name,
+            indent = depth * 2
+        )?;
+        if !self.generics.is_empty() {
+            let generics = self
+                .generics
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>();
+            write!(f, "<{}>", generics.join(", "))?;
+        }
+        writeln!(f)?;
+        for child in &self.children {
+            child.fmt_indented(f, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+/// Prints the instance tree, one instance per line indented by its depth
+impl fmt::Display for Instance<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_indented(f, 0)
+    }
+}
+
+/// Finds the entity named by a path such as `Top` or `crate::cpu::Core`
+pub fn find_entity(resolutions: &Resolutions, name: &str) -> Result<DefId, String> {
+    let name = name.strip_prefix("crate::").unwrap_or(name);
+    let found = resolutions
+        .defs
+        .iter()
+        .enumerate()
+        .filter(|(_, def)| def.kind == DefKind::Entity)
+        .filter(|(_, def)| {
+            let path = format!("{}::{}", resolutions.module_path(def.module), def.name);
+            path == format!("crate::{}", name) || def.name == name
+        })
+        .map(|(id, _)| DefId(id))
+        .collect::<Vec<_>>();
+    match found.as_slice() {
+        [id] => Ok(*id),
+        [] => Err(format!("cannot find an entity named `{}`", name)),
+        _ => Err(format!(
+            "`{}` is ambiguous, more than one entity has that name",
+            name
+        )),
+    }
+}
+
+/// Elaborates the design whose top is the entity named `top`
+pub fn elaborate<'a>(
+    krate: &'a Crate,
+    resolutions: &'a Resolutions,
+    types: &'a Types,
+    top: &str,
+) -> Result<Instance<'a>, Vec<Diagnostic>> {
+    let top = find_entity(resolutions, top)
+        .map_err(|message| vec![Diagnostic::new(message, Span(0, 0))])?;
+    let mut elaborator = Elaborator::new(krate, resolutions, types);
+    let name = snake_case(&resolutions.def(top).name);
+    let instance = elaborator.instantiate(top, name.clone(), name, None, None);
+    let mut diagnostics = elaborator.diagnostics;
+    diagnostics.extend(elaborator.eval.take_diagnostics());
+    match instance {
+        Some(instance) if diagnostics.is_empty() => Ok(instance),
+        _ => Err(diagnostics),
+    }
+}
+
+struct Elaborator<'a> {
+    eval: ConstEval<'a>,
+    types: &'a Types,
+    /// Archs of each entity
+    archs: HashMap<DefId, (FileId, &'a ItemArch)>,
+    /// Entities being instantiated, from the top down
+    stack: Vec<DefId>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Elaborator<'a> {
+    fn new(krate: &'a Crate, resolutions: &'a Resolutions, types: &'a Types) -> Self {
+        let mut archs = HashMap::new();
+        for module in krate.modules() {
+            for item in &module.items {
+                let item_arch = match item {
+                    Item::Arch(item_arch) => item_arch,
+                    _ => continue,
+                };
+                if let Some(PathResolution {
+                    res: Res::Def(id),
+                    unresolved_segments: 0,
+                }) = resolutions.type_path(module.file, &item_arch.entity)
+                {
+                    // More than one arch for an entity is reported by `arch_check`
+                    archs.entry(*id).or_insert((module.file, item_arch));
+                }
+            }
+        }
+        Self {
+            eval: ConstEval::new(krate, resolutions, types),
+            types,
+            archs,
+            stack: vec![],
+            diagnostics: vec![],
+        }
+    }
+
+    fn error(&mut self, message: impl Into<String>, span: Span, file: FileId) {
+        self.diagnostics
+            .push(Diagnostic::new(message, span).in_file(file));
+    }
+
+    /// Instantiates `entity`, where `parent` is the instance whose arch names it in `instantiation`
+    fn instantiate(
+        &mut self,
+        entity: DefId,
+        name: String,
+        path: String,
+        parent: Option<&Instance<'a>>,
+        instantiation: Option<&'a ArchItemEntityExpression>,
+    ) -> Option<Instance<'a>> {
+        let resolutions = self.eval.cx().resolutions();
+        let entity_name = resolutions.def(entity).name.clone();
+        let entity_file = self.eval.cx().file(entity);
+        let item = match self.eval.cx().item(entity) {
+            Item::Entity(item) => item,
+            _ => return None,
+        };
+        let (use_file, use_span) = match (parent, instantiation) {
+            (Some(parent), Some(instantiation)) => {
+                (parent.file(), instantiation.path.segments.span())
+            }
+            _ => (entity_file, item.ident.span.clone()),
+        };
+        // Instantiation is unconditional, so an entity that contains itself would never end
+        if self.stack.contains(&entity) {
+            let message = format!("entity `{}` instantiates itself", entity_name);
+            self.error(message, use_span, use_file);
+            return None;
+        }
+        let (file, arch) = match self.archs.get(&entity) {
+            Some(arch) => *arch,
+            None => {
+                let message = format!("entity `{}` has no arch", entity_name);
+                self.error(message, use_span, use_file);
+                return None;
+            }
+        };
+
+        let generics = self.generics(
+            entity,
+            item,
+            parent,
+            instantiation,
+            use_file,
+            use_span.clone(),
+        )?;
+        let (entity_subst, mut entity_env) = self.bind_entity(entity, item, &generics);
+        let mut ports = vec![];
+        for (port, ty) in self.eval.cx().ports_with(entity, &entity_subst) {
+            let width = match self.eval.cx().width(&ty) {
+                Some(width) => width,
+                None => {
+                    let message = format!(
+                        "port `{}` of `{}` has type `{}`, which has no fixed width",
+                        port.ident, entity_name, ty
+                    );
+                    self.error(message, use_span.clone(), use_file);
+                    return None;
+                }
+            };
+            let default = match &port.expr {
+                Some((_, expr)) => Some(self.eval.eval_expr(expr, &mut entity_env)?),
+                None => None,
+            };
+            ports.push(InstancePort {
+                port,
+                ty,
+                width,
+                default,
+            });
+        }
+
+        let (subst, mut env) = self.bind_arch(arch, file, &generics);
+        for arch_item in &arch.items {
+            if let ArchItem::Const(item_const) = arch_item {
+                let value = self.eval.eval_expr(&item_const.expr, &mut env)?;
+                env.locals.insert(item_const.ident.span.clone(), value);
+            }
+        }
+
+        let mut instance = Instance {
+            name,
+            path,
+            entity,
+            entity_name,
+            generics,
+            ports,
+            arch,
+            subst,
+            env,
+            instantiation,
+            children: vec![],
+        };
+
+        self.stack.push(entity);
+        let mut counts: HashMap<DefId, usize> = HashMap::new();
+        let mut children = vec![];
+        for arch_item in &arch.items {
+            let expr = match arch_item {
+                ArchItem::EntityExpression(expr) => expr,
+                _ => continue,
+            };
+            let child = match resolutions.expr_path(file, &expr.path) {
+                Some(PathResolution {
+                    res: Res::Def(id),
+                    unresolved_segments: 0,
+                }) if resolutions.def(*id).kind == DefKind::Entity => *id,
+                _ => continue,
+            };
+            let count = counts.entry(child).or_default();
+            let name = format!("{}_{}", snake_case(&resolutions.def(child).name), count);
+            *count += 1;
+            let path = format!("{}.{}", instance.path, name);
+            if let Some(child) = self.instantiate(child, name, path, Some(&instance), Some(expr)) {
+                children.push(child);
+            }
+        }
+        self.stack.pop();
+        instance.children = children;
+        Some(instance)
+    }
+
+    /// Values of the generic params of an entity, given by where it is instantiated or by their defaults
+    fn generics(
+        &mut self,
+        entity: DefId,
+        item: &'a ItemEntity,
+        parent: Option<&Instance<'a>>,
+        instantiation: Option<&'a ArchItemEntityExpression>,
+        use_file: FileId,
+        use_span: Span,
+    ) -> Option<Vec<GenericValue>> {
+        let segment = instantiation.and_then(|expr| expr.path.segments.last());
+        let mut env = match parent {
+            Some(parent) => parent.env.clone(),
+            None => Env::new(use_file),
+        };
+        let consts = self.eval.const_params(entity, segment, &mut env)?;
+        let entity_file = self.eval.cx().file(entity);
+
+        let args = segment
+            .and_then(|segment| segment.generic_args.as_ref())
+            .map(|args| args.args.iter().collect::<Vec<_>>())
+            .unwrap_or_default();
+        let mut generics = vec![];
+        let mut subst = Subst::default();
+        for (i, param) in item
+            .generics
+            .iter()
+            .flat_map(|generics| generics.params.iter())
+            .enumerate()
+        {
+            match param {
+                GenericParam::Type(param) => {
+                    let inferred = match (parent, instantiation) {
+                        (Some(parent), Some(instantiation)) => {
+                            self.infer(entity_file, item, &param.ident, parent, instantiation)
+                        }
+                        _ => None,
+                    };
+                    let ty = match (args.get(i), inferred, &param.eq, parent) {
+                        (Some(GenericArg::Type(ty)), _, _, Some(parent)) => {
+                            self.eval.cx().lower(parent.file(), ty, &parent.subst)
+                        }
+                        (None, Some(ty), _, _) => ty,
+                        (None, None, Some((_, ty)), _) => {
+                            self.eval.cx().lower(entity_file, ty, &subst)
+                        }
+                        _ => {
+                            let message = format!(
+                                "cannot infer the type parameter `{}` of `{}`",
+                                param.ident, item.ident
+                            );
+                            self.error(message, use_span, use_file);
+                            return None;
+                        }
+                    };
+                    subst.params.insert(param.ident.span.clone(), ty.clone());
+                    generics.push(GenericValue::Type(ty));
+                }
+                GenericParam::Const(param) => {
+                    let value = consts.get(&param.ident.span)?.clone();
+                    if let Some(len) = value.as_int().and_then(Integer::to_u64) {
+                        subst.consts.insert(param.ident.span.clone(), len);
+                    }
+                    generics.push(GenericValue::Const(value));
+                }
+            }
+        }
+        Some(generics)
+    }
+
+    /// Type of a type param that is left out of an instantiation, taken from a value connected to a port
+    /// declared with exactly that type, as the type checker inferred it
+    fn infer(
+        &self,
+        entity_file: FileId,
+        item: &ItemEntity,
+        param: &Ident,
+        parent: &Instance<'a>,
+        instantiation: &ArchItemEntityExpression,
+    ) -> Option<Ty> {
+        let resolutions = self.eval.cx().resolutions();
+        instantiation.fields.iter().find_map(|field| {
+            let port = item.ports.iter().find(|port| port.ident == field.ident)?;
+            match &port.ty {
+                Type::Path(path) => match resolutions.type_path(entity_file, path) {
+                    Some(PathResolution {
+                        res: Res::GenericParam(span),
+                        unresolved_segments: 0,
+                    }) if *span == param.span => {}
+                    _ => return None,
+                },
+                _ => return None,
+            }
+            let value = match &field.expr {
+                Some((_, value)) => self.types.expr(parent.file(), value)?,
+                None => match resolutions.ident(parent.file(), &field.ident)?.res {
+                    Res::Local(ref span) => self.types.local(parent.file(), span)?,
+                    Res::Port { index, .. } => &parent.ports.get(index)?.ty,
+                    _ => return None,
+                },
+            };
+            match value {
+                Ty::Param { span, .. } => parent.subst.params.get(span).cloned(),
+                Ty::Var(_) | Ty::Error => None,
+                ty => Some(ty.clone()),
+            }
+        })
+    }
+
+    /// What the generic params of an entity stand for in the types of its ports, and the values
+    /// known to their defaults
+    fn bind_entity(
+        &self,
+        entity: DefId,
+        item: &ItemEntity,
+        generics: &[GenericValue],
+    ) -> (Subst, Env) {
+        let mut subst = self.eval.cx().subst(entity, &type_args(generics));
+        let mut env = Env::new(self.eval.cx().file(entity));
+        for (param, value) in item
+            .generics
+            .iter()
+            .flat_map(|generics| generics.params.iter())
+            .zip(generics)
+        {
+            if let (GenericParam::Const(param), GenericValue::Const(value)) = (param, value) {
+                if let Some(len) = value.as_int().and_then(Integer::to_u64) {
+                    subst.consts.insert(param.ident.span.clone(), len);
+                }
+                env.params.insert(param.ident.span.clone(), value.clone());
+            }
+        }
+        (subst, env)
+    }
+
+    /// Binds the generic params of an arch to the values of its entity's params that the arch names as args
+    fn bind_arch(&self, arch: &ItemArch, file: FileId, generics: &[GenericValue]) -> (Subst, Env) {
+        let resolutions = self.eval.cx().resolutions();
+        let mut subst = Subst::default();
+        let mut env = Env::new(file);
+        let args = arch
+            .entity
+            .segments
+            .last()
+            .and_then(|segment| segment.generic_args.as_ref())
+            .map(|args| args.args.iter().collect::<Vec<_>>())
+            .unwrap_or_default();
+        for (arg, value) in args.into_iter().zip(generics) {
+            let resolution = match arg {
+                GenericArg::Type(Type::Path(path)) => resolutions.type_path(file, path),
+                GenericArg::Expr(Expr::Path(path)) => resolutions.expr_path(file, path),
+                _ => None,
+            };
+            let span = match resolution {
+                Some(PathResolution {
+                    res: Res::GenericParam(span),
+                    unresolved_segments: 0,
+                }) => span.clone(),
+                _ => continue,
+            };
+            match value {
+                GenericValue::Type(ty) => {
+                    subst.params.insert(span, ty.clone());
+                }
+                GenericValue::Const(value) => {
+                    if let Some(len) = value.as_int().and_then(Integer::to_u64) {
+                        subst.consts.insert(span.clone(), len);
+                    }
+                    env.params.insert(span, value.clone());
+                }
+            }
+        }
+        (subst, env)
+    }
+}
+
+fn type_args(generics: &[GenericValue]) -> Vec<Ty> {
+    generics
+        .iter()
+        .filter_map(|generic| match generic {
+            GenericValue::Type(ty) => Some(ty.clone()),
+            GenericValue::Const(_) => None,
+        })
+        .collect()
+}
+
+/// Converts a name such as `SawTooth` to `saw_tooth`
+fn snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() {
+            if i > 0 && !snake.ends_with('_') {
+                snake.push('_');
+            }
+            snake.extend(c.to_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
+}
@@ -0,0 +1,1107 @@
+//! Emits a [`Netlist`] as a FIRRTL circuit, for tools such as `firtool`
+//!
+//! Ports, wires and registers keep the shape of their types: structs are bundles declared as type
+//! aliases, tuples are anonymous bundles and arrays are vectors, while enums are `UInt`s that hold
+//! their tags. Nodes are the ground `UInt`s and `SInt`s of the netlist, so fields and elements
+//! read from aggregates are concatenated into bits and values written to them are split back into
+//! fields. A net that triggers a process, or that is connected to a port that does, is a `Clock`,
+//! unless it is a register, which is cast to a clock where it is used as one. Every net a clocked
+//! process updates is a `reg` of its clock, declared after the wires, instances and registers that
+//! clock comes from, and updates whose value picks between others by a condition become `when`
+//! blocks, in which a register that is not updated holds its value. FIRRTL has no way to give a
+//! register a value before its first clock edge, so a register with an initial value is reset to
+//! it by a `preset` port of type `AsyncReset`, which modules pass down to their instances and which
+//! the top marks with a `PresetAnnotation` for tools to apply when the design powers up rather than
+//! as a reset.
+
+use std::collections::HashSet;
+use std::fmt::Write;
+
+use rug::Integer;
+
+use crate::netlist::{
+    self, BinaryOp, Direction, Module, NetId, NetKind, Netlist, NodeId, NodeKind, Trigger,
+    TypeDeclKind, UnaryOp,
+};
+use crate::ty::Ty;
+
+/// Version of the FIRRTL specification that the output follows
+const VERSION: &str = "3.3.0";
+
+/// FIRRTL source for a circuit of every module of `netlist`, whose main module is the top
+pub fn emit(netlist: &Netlist) -> String {
+    let types = Types { netlist };
+    let clocks = clocks(netlist);
+    let presets = presets(netlist);
+    let mut out = String::new();
+    writeln!(out, "FIRRTL version {}", VERSION).unwrap();
+    let top = &netlist.module(netlist.top).name;
+    match &presets[netlist.top.0] {
+        Some(preset) => writeln!(
+            out,
+            "circuit {} :%[[{{\"class\":\"firrtl.annotations.PresetAnnotation\",\"target\":\"~{}|{}>{}\"}}]]",
+            ident(top),
+            top,
+            top,
+            preset
+        ),
+        None => writeln!(out, "circuit {} :", ident(top)),
+    }
+    .unwrap();
+    let mut aliases = false;
+    for decl in &netlist.types {
+        if let TypeDeclKind::Struct(fields) = &decl.kind {
+            if types.netlist.width(&decl.ty) > 0 {
+                writeln!(
+                    out,
+                    "  type {} = {}",
+                    ident(&decl.name),
+                    types.bundle(fields)
+                )
+                .unwrap();
+                aliases = true;
+            }
+        }
+    }
+    for i in 0..netlist.modules.len() {
+        if aliases || i > 0 {
+            out.push('\n');
+        }
+        Emitter::new(&types, &clocks, &presets, i).emit(&mut out);
+    }
+    out
+}
+
+/// Which nets of each module are clocks: those that trigger a process, and those connected to a
+/// port of an instance that is one, except for registers, which keep their type and are cast to
+/// clocks where they are used as one
+fn clocks(netlist: &Netlist) -> Vec<Vec<bool>> {
+    let regs = netlist
+        .modules
+        .iter()
+        .map(|module| {
+            let mut regs = vec![false; module.nets.len()];
+            for process in &module.processes {
+                if !matches!(process.trigger, Trigger::Always) {
+                    for update in &process.updates {
+                        regs[update.net.0] = true;
+                    }
+                }
+            }
+            regs
+        })
+        .collect::<Vec<_>>();
+    let mut clocks = netlist
+        .modules
+        .iter()
+        .enumerate()
+        .map(|(i, module)| {
+            let mut clocks = vec![false; module.nets.len()];
+            for process in &module.processes {
+                if let Trigger::Posedge(clock) | Trigger::Negedge(clock) = process.trigger {
+                    clocks[clock.0] = !regs[i][clock.0];
+                }
+            }
+            clocks
+        })
+        .collect::<Vec<_>>();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (i, module) in netlist.modules.iter().enumerate() {
+            for instance in &module.instances {
+                let child = netlist.module(instance.module);
+                for (j, (_, net)) in instance.connections.iter().enumerate() {
+                    let port = child.ports[j];
+                    if clocks[instance.module.0][port.0] && !clocks[i][net.0] && !regs[i][net.0] {
+                        clocks[i][net.0] = true;
+                        changed = true;
+                    }
+                }
+            }
+        }
+    }
+    clocks
+}
+
+/// Name of the `preset` port of each module that has one: those with a register that has an
+/// initial value, and those with an instance of a module that has one
+fn presets(netlist: &Netlist) -> Vec<Option<String>> {
+    let mut needed = netlist
+        .modules
+        .iter()
+        .map(|module| {
+            module.processes.iter().any(|process| {
+                !matches!(process.trigger, Trigger::Always)
+                    && process.updates.iter().any(|update| {
+                        let net = module.net(update.net);
+                        net.init.is_some() && net.width > 0
+                    })
+            })
+        })
+        .collect::<Vec<_>>();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (i, module) in netlist.modules.iter().enumerate() {
+            if !needed[i]
+                && module
+                    .instances
+                    .iter()
+                    .any(|instance| needed[instance.module.0])
+            {
+                needed[i] = true;
+                changed = true;
+            }
+        }
+    }
+    netlist
+        .modules
+        .iter()
+        .zip(needed)
+        .map(|(module, needed)| {
+            needed.then(|| {
+                let mut name = "preset".to_string();
+                while module.nets.iter().any(|net| net.name == name)
+                    || module
+                        .instances
+                        .iter()
+                        .any(|instance| instance.name == name)
+                {
+                    name.push('_');
+                }
+                name
+            })
+        })
+        .collect()
+}
+
+/// How the types of a netlist are written
+struct Types<'n> {
+    netlist: &'n Netlist,
+}
+
+impl<'n> Types<'n> {
+    /// Whether values of type `ty` are bundles or vectors rather than ground values
+    fn is_aggregate(&self, ty: &Ty) -> bool {
+        matches!(ty, Ty::Array(..)) || self.netlist.fields(ty).is_some()
+    }
+
+    /// The type that a value of type `ty` is declared as, or a `UInt` if `ty` is not `width` bits
+    /// wide
+    fn name(&self, ty: &Ty, width: u64) -> String {
+        if self.netlist.width(ty) != width {
+            return ground(false, width);
+        }
+        match ty {
+            Ty::Array(elem, len) => {
+                format!("{}[{}]", self.name(elem, self.netlist.width(elem)), len)
+            }
+            Ty::Tuple(_) => self.bundle(&self.netlist.fields(ty).unwrap_or_default()),
+            Ty::Adt { .. } => match self.netlist.type_decl(ty) {
+                Some(decl) if matches!(decl.kind, TypeDeclKind::Struct(_)) => ident(&decl.name),
+                _ => ground(false, width),
+            },
+            _ => ground(is_signed(ty), width),
+        }
+    }
+
+    /// A bundle type with the given fields, leaving out those that have no bits
+    fn bundle(&self, fields: &[(String, Ty)]) -> String {
+        let fields = fields
+            .iter()
+            .filter(|(_, ty)| self.netlist.width(ty) > 0)
+            .map(|(name, ty)| {
+                format!(
+                    "{} : {}",
+                    member(name),
+                    self.name(ty, self.netlist.width(ty))
+                )
+            })
+            .collect::<Vec<_>>();
+        format!("{{ {} }}", fields.join(", "))
+    }
+
+    /// Ground fields and elements of a value `base` of type `ty`, from the least significant up,
+    /// along with the bit each starts at
+    fn leaves(&self, base: String, ty: &Ty, lo: u64, leaves: &mut Vec<Leaf>) {
+        if let Ty::Array(elem, len) = ty {
+            let width = self.netlist.width(elem);
+            for i in 0..*len {
+                self.leaves(format!("{}[{}]", base, i), elem, lo + i * width, leaves);
+            }
+        } else if let Some(fields) = self.netlist.fields(ty) {
+            let mut start = lo;
+            for (name, ty) in &fields {
+                self.leaves(format!("{}.{}", base, member(name)), ty, start, leaves);
+                start += self.netlist.width(ty);
+            }
+        } else if self.netlist.width(ty) > 0 {
+            leaves.push(Leaf {
+                path: base,
+                lo,
+                width: self.netlist.width(ty),
+                signed: is_signed(ty),
+            });
+        }
+    }
+
+    /// The field or element of a value `base` of type `ty` that is exactly the `width` bits from
+    /// bit `lo`, along with its type
+    fn select(&self, base: String, ty: &Ty, lo: u64, width: u64) -> Option<(String, Ty)> {
+        if lo == 0 && width == self.netlist.width(ty) {
+            return Some((base, ty.clone()));
+        }
+        if let Ty::Array(elem, _) = ty {
+            let elem_width = self.netlist.width(elem);
+            if elem_width == 0 {
+                return None;
+            }
+            let index = lo / elem_width;
+            let start = index * elem_width;
+            if lo + width > start + elem_width {
+                return None;
+            }
+            return self.select(format!("{}[{}]", base, index), elem, lo - start, width);
+        }
+        let mut start = 0;
+        for (name, ty) in &self.netlist.fields(ty)? {
+            let field_width = self.netlist.width(ty);
+            if lo >= start && lo + width <= start + field_width {
+                let base = format!("{}.{}", base, member(name));
+                return self.select(base, ty, lo - start, width);
+            }
+            start += field_width;
+        }
+        None
+    }
+}
+
+/// A ground field or element of an aggregate
+struct Leaf {
+    path: String,
+    lo: u64,
+    width: u64,
+    signed: bool,
+}
+
+struct Emitter<'t, 'n> {
+    types: &'t Types<'n>,
+    module: &'n Module,
+    /// Which nets of each module are clocks
+    clocks: &'t [Vec<bool>],
+    /// Name of the `preset` port of each module that has one
+    presets: &'t [Option<String>],
+    /// Index of the module
+    index: usize,
+    /// Name by which each net is read and written, which for a port that is a register is that of
+    /// the register rather than the port
+    names: Vec<String>,
+    /// Name of the wire that holds the initial value of each net that is an aggregate with one
+    inits: Vec<Option<String>>,
+    /// Name of the node that holds each node
+    wires: Vec<String>,
+    /// The field or element of an aggregate net that each node is, if it is one
+    paths: Vec<Option<(String, Ty)>>,
+    /// How many times each node is used
+    uses: Vec<usize>,
+    /// Whether each node is used as bits, rather than only as a field or element of a net or as
+    /// the whole of one
+    flat: Vec<bool>,
+    /// Whether each node picks between the values of an update in `when` blocks
+    unfolded: Vec<bool>,
+}
+
+impl<'t, 'n> Emitter<'t, 'n> {
+    fn new(
+        types: &'t Types<'n>,
+        clocks: &'t [Vec<bool>],
+        presets: &'t [Option<String>],
+        index: usize,
+    ) -> Self {
+        let module = &types.netlist.modules[index];
+        let mut taken = module
+            .nets
+            .iter()
+            .map(|net| net.name.clone())
+            .collect::<HashSet<_>>();
+        taken.extend(presets[index].clone());
+        taken.extend(
+            module
+                .instances
+                .iter()
+                .map(|instance| instance.name.clone()),
+        );
+        let mut fresh = |mut name: String| {
+            while taken.contains(&name) {
+                name.push('_');
+            }
+            taken.insert(name.clone());
+            name
+        };
+        let names = module
+            .nets
+            .iter()
+            .map(|net| match (net.port, net.kind) {
+                (Some(_), NetKind::Reg) => ident(&fresh(format!("{}_reg", net.name))),
+                _ => ident(&net.name),
+            })
+            .collect::<Vec<_>>();
+        let inits = module
+            .nets
+            .iter()
+            .map(|net| {
+                (net.init.is_some() && types.is_aggregate(&net.ty))
+                    .then(|| ident(&fresh(format!("{}_init", net.name))))
+            })
+            .collect::<Vec<_>>();
+        let wires = (0..module.nodes.len())
+            .map(|i| fresh(format!("_n{}", i)))
+            .collect::<Vec<_>>();
+        let mut emitter = Self {
+            types,
+            module,
+            clocks,
+            presets,
+            index,
+            names,
+            inits,
+            wires,
+            paths: vec![None; module.nodes.len()],
+            uses: vec![0; module.nodes.len()],
+            flat: vec![false; module.nodes.len()],
+            unfolded: vec![false; module.nodes.len()],
+        };
+
+        for (i, node) in module.nodes.iter().enumerate() {
+            let path = match &node.kind {
+                NodeKind::Net(id) => {
+                    let net = module.net(*id);
+                    (types.is_aggregate(&net.ty) && types.netlist.width(&net.ty) == net.width)
+                        .then(|| (emitter.names[id.0].clone(), net.ty.clone()))
+                }
+                NodeKind::Slice(arg, lo) => emitter.paths[arg.0]
+                    .clone()
+                    .and_then(|(base, ty)| types.select(base, &ty, *lo, node.width)),
+                NodeKind::Index(arg, index) => match &emitter.paths[arg.0] {
+                    Some((base, Ty::Array(elem, _))) if types.netlist.width(elem) == node.width => {
+                        Some((
+                            format!("{}[{}]", base, emitter.unsigned(*index)),
+                            elem.as_ref().clone(),
+                        ))
+                    }
+                    _ => None,
+                },
+                _ => None,
+            };
+            emitter.paths[i] = path;
+        }
+
+        for node in &module.nodes {
+            for arg in netlist::operands(&node.kind) {
+                emitter.uses[arg.0] += 1;
+            }
+        }
+        for assign in &module.assigns {
+            emitter.uses[assign.value.0] += 1;
+        }
+        for process in &module.processes {
+            for update in &process.updates {
+                emitter.uses[update.value.0] += 1;
+            }
+        }
+        for process in &module.processes {
+            for update in &process.updates {
+                emitter.unfold(update.value);
+            }
+        }
+
+        for (i, node) in module.nodes.iter().enumerate() {
+            let args = match node.kind {
+                NodeKind::Mux(cond, ..) if emitter.unfolded[i] => vec![cond],
+                NodeKind::Slice(..) | NodeKind::Index(..) if emitter.paths[i].is_some() => {
+                    netlist::operands(&node.kind).split_off(1)
+                }
+                _ => netlist::operands(&node.kind),
+            };
+            for arg in args {
+                emitter.flat[arg.0] = true;
+            }
+        }
+        for assign in &module.assigns {
+            emitter.connected(assign.net, assign.value);
+        }
+        for process in &module.processes {
+            for update in &process.updates {
+                emitter.connected_update(update.net, update.value);
+            }
+        }
+        emitter
+    }
+
+    /// Marks the muxes that pick the value of an update as unfolded into `when` blocks, where
+    /// nothing else uses them
+    fn unfold(&mut self, value: NodeId) {
+        if let NodeKind::Mux(_, then, otherwise) = self.module.node(value).kind {
+            if self.uses[value.0] == 1 {
+                self.unfolded[value.0] = true;
+                self.unfold(then);
+                self.unfold(otherwise);
+            }
+        }
+    }
+
+    /// Records how the values of an update are connected to its net
+    fn connected_update(&mut self, net: NetId, value: NodeId) {
+        match self.module.node(value).kind {
+            NodeKind::Mux(_, then, otherwise) if self.unfolded[value.0] => {
+                self.connected_update(net, then);
+                self.connected_update(net, otherwise);
+            }
+            _ => self.connected(net, value),
+        }
+    }
+
+    /// Records whether connecting a node to a net uses it as bits
+    fn connected(&mut self, net: NetId, value: NodeId) {
+        if !self.is_whole(net, value) {
+            self.flat[value.0] = true;
+        }
+    }
+
+    /// Whether a node is a whole aggregate of the same type as a net, which it is connected to as
+    /// it is
+    fn is_whole(&self, net: NetId, value: NodeId) -> bool {
+        match &self.paths[value.0] {
+            Some((_, ty)) => self.types.is_aggregate(ty) && *ty == self.module.net(net).ty,
+            None => false,
+        }
+    }
+
+    fn is_clock(&self, net: NetId) -> bool {
+        self.clocks[self.index][net.0]
+    }
+
+    fn emit(&self, out: &mut String) {
+        writeln!(out, "  module {} :", ident(&self.module.name)).unwrap();
+        for port in &self.module.ports {
+            if self.module.net(*port).width == 0 {
+                continue;
+            }
+            let direction = match self.module.net(*port).port {
+                Some(Direction::Out) => "output",
+                _ => "input",
+            };
+            writeln!(
+                out,
+                "    {} {} : {}",
+                direction,
+                ident(&self.module.net(*port).name),
+                self.net_type(*port)
+            )
+            .unwrap();
+        }
+        if let Some(preset) = &self.presets[self.index] {
+            writeln!(out, "    input {} : AsyncReset", ident(preset)).unwrap();
+        }
+        let mut body = vec![];
+
+        let mut clocks = vec![None; self.module.nets.len()];
+        for process in &self.module.processes {
+            let clock = match process.trigger {
+                Trigger::Posedge(clock) => (
+                    clock,
+                    clock_cast(self.names[clock.0].clone(), self.is_clock(clock), true),
+                ),
+                Trigger::Negedge(clock) => {
+                    let bit = clock_cast(self.names[clock.0].clone(), self.is_clock(clock), false);
+                    (clock, format!("asClock(not({}))", bit))
+                }
+                Trigger::Always => continue,
+            };
+            for update in &process.updates {
+                clocks[update.net.0] = Some(clock.clone());
+            }
+        }
+        // Registers are declared after the wires and instances that their clocks may come from
+        let mut regs = vec![];
+        for (i, net) in self.module.nets.iter().enumerate() {
+            if net.width == 0 {
+                continue;
+            }
+            match (&clocks[i], &net.init, &self.presets[self.index]) {
+                (Some((clock_net, clock)), Some(init), Some(preset)) => {
+                    let init = match &self.inits[i] {
+                        Some(name) => {
+                            body.push(format!("wire {} : {}", name, self.net_type(NetId(i))));
+                            self.connect_bits(name.clone(), &net.ty, init, &mut body);
+                            name.clone()
+                        }
+                        None => literal(init, is_signed(&net.ty), net.width),
+                    };
+                    let decl = format!(
+                        "regreset {} : {}, {}, {}, {}",
+                        self.names[i],
+                        self.net_type(NetId(i)),
+                        clock,
+                        ident(preset),
+                        init
+                    );
+                    regs.push((NetId(i), *clock_net, decl));
+                }
+                (Some((clock_net, clock)), ..) => {
+                    let decl = format!(
+                        "reg {} : {}, {}",
+                        self.names[i],
+                        self.net_type(NetId(i)),
+                        clock
+                    );
+                    regs.push((NetId(i), *clock_net, decl));
+                }
+                (None, ..) if net.port.is_none() => body.push(format!(
+                    "wire {} : {}",
+                    self.names[i],
+                    self.net_type(NetId(i))
+                )),
+                (None, ..) => {}
+            }
+        }
+
+        for instance in &self.module.instances {
+            let child = self.types.netlist.module(instance.module);
+            body.push(format!(
+                "inst {} of {}",
+                ident(&instance.name),
+                ident(&child.name)
+            ));
+        }
+
+        // A register clocked by another register comes after it
+        while !regs.is_empty() {
+            let waiting = regs.iter().map(|(net, ..)| *net).collect::<HashSet<_>>();
+            let (ready, rest): (Vec<_>, Vec<_>) = regs
+                .into_iter()
+                .partition(|(net, clock, _)| clock == net || !waiting.contains(clock));
+            if ready.is_empty() {
+                body.extend(rest.into_iter().map(|(.., decl)| decl));
+                break;
+            }
+            body.extend(ready.into_iter().map(|(.., decl)| decl));
+            regs = rest;
+        }
+
+        for instance in &self.module.instances {
+            let child = self.types.netlist.module(instance.module);
+            if let (Some(port), Some(preset)) =
+                (&self.presets[instance.module.0], &self.presets[self.index])
+            {
+                body.push(format!(
+                    "connect {}.{}, {}",
+                    ident(&instance.name),
+                    ident(port),
+                    ident(preset)
+                ));
+            }
+            for (j, (name, net)) in instance.connections.iter().enumerate() {
+                let port = child.ports[j];
+                if child.net(port).width == 0 {
+                    continue;
+                }
+                let port_name = format!("{}.{}", ident(&instance.name), ident(name));
+                let port_clock = self.clocks[instance.module.0][port.0];
+                let net_name = self.names[net.0].clone();
+                let net_clock = self.is_clock(*net);
+                body.push(match child.net(port).port {
+                    Some(Direction::Out) => format!(
+                        "connect {}, {}",
+                        net_name,
+                        clock_cast(port_name, port_clock, net_clock)
+                    ),
+                    _ => format!(
+                        "connect {}, {}",
+                        port_name,
+                        clock_cast(net_name, net_clock, port_clock)
+                    ),
+                });
+            }
+        }
+
+        for (i, node) in self.module.nodes.iter().enumerate() {
+            if node.width == 0 || self.unfolded[i] {
+                continue;
+            }
+            let expr = match (&node.kind, &self.paths[i]) {
+                (NodeKind::Const(_), _) => continue,
+                (_, Some((path, ty))) if self.types.is_aggregate(ty) => {
+                    if !self.flat[i] {
+                        continue;
+                    }
+                    self.flatten(path.clone(), ty)
+                }
+                (_, Some(_)) | (NodeKind::Net(_), None) => continue,
+                (kind, None) => self.expr(NodeId(i), kind, &mut body),
+            };
+            body.push(format!("node {} = {}", self.wires[i], expr));
+        }
+
+        for assign in &self.module.assigns {
+            self.connect(assign.net, assign.value, &mut body);
+        }
+        for process in &self.module.processes {
+            let hold = !matches!(process.trigger, Trigger::Always);
+            for update in &process.updates {
+                self.update(update.net, update.value, hold, 0, false, &mut body);
+            }
+        }
+        for port in &self.module.ports {
+            let net = self.module.net(*port);
+            if net.kind == NetKind::Reg && net.width > 0 {
+                body.push(format!(
+                    "connect {}, {}",
+                    ident(&net.name),
+                    self.names[port.0]
+                ));
+            }
+        }
+
+        if !body.is_empty() {
+            writeln!(out).unwrap();
+        }
+        for line in body {
+            writeln!(out, "    {}", line).unwrap();
+        }
+    }
+
+    /// The type of a net, which is a `Clock` if it is a clock
+    fn net_type(&self, id: NetId) -> String {
+        let net = self.module.net(id);
+        if self.is_clock(id) && net.width == 1 {
+            "Clock".to_string()
+        } else {
+            self.types.name(&net.ty, net.width)
+        }
+    }
+
+    /// Statements that update a net to a node, in `when` blocks if the node is unfolded, where a
+    /// register that keeps its value is not connected
+    fn update(
+        &self,
+        net: NetId,
+        value: NodeId,
+        hold: bool,
+        depth: usize,
+        chained: bool,
+        body: &mut Vec<String>,
+    ) {
+        let indent = "  ".repeat(depth);
+        let (cond, then, otherwise) = match self.module.node(value).kind {
+            NodeKind::Mux(cond, then, otherwise) if self.unfolded[value.0] => {
+                (cond, then, otherwise)
+            }
+            _ if chained => {
+                body.push(format!("{}else :", indent));
+                return self.update(net, value, hold, depth + 1, false, body);
+            }
+            _ if hold && self.holds(net, value) => return,
+            _ => {
+                let start = body.len();
+                self.connect(net, value, body);
+                for line in &mut body[start..] {
+                    line.insert_str(0, &indent);
+                }
+                return;
+            }
+        };
+        let prefix = if chained { "else " } else { "" };
+        let cond = self.operand(cond);
+        if hold && self.holds(net, then) {
+            body.push(format!("{}{}when not({}) :", indent, prefix, cond));
+            let start = body.len();
+            self.update(net, otherwise, hold, depth + 1, false, body);
+            if body.len() == start {
+                body.push(format!("{}  skip", indent));
+            }
+            return;
+        }
+        body.push(format!("{}{}when {} :", indent, prefix, cond));
+        self.update(net, then, hold, depth + 1, false, body);
+        if !(hold && self.holds(net, otherwise)) {
+            self.update(net, otherwise, hold, depth, true, body);
+        }
+    }
+
+    /// Whether a node is the value that a net already holds
+    fn holds(&self, net: NetId, value: NodeId) -> bool {
+        self.module.node(value).kind == NodeKind::Net(net)
+    }
+
+    /// Statements that connect a node to a net, field by field if the net is an aggregate
+    fn connect(&self, net: NetId, value: NodeId, body: &mut Vec<String>) {
+        let target = self.module.net(net);
+        let name = self.names[net.0].clone();
+        if let (true, Some((path, _))) = (self.is_whole(net, value), &self.paths[value.0]) {
+            body.push(format!("connect {}, {}", name, path));
+            return;
+        }
+        let aggregate = self.types.is_aggregate(&target.ty)
+            && self.types.netlist.width(&target.ty) == target.width;
+        let signed = is_signed(&target.ty) && !aggregate && !self.is_clock(net);
+        let constant = match &self.module.node(value).kind {
+            NodeKind::Const(bits) => Some(bits.clone()),
+            _ => None,
+        };
+        let value = self.fit(
+            self.operand(value),
+            self.shape(value),
+            (target.width, signed),
+        );
+        if self.is_clock(net) {
+            body.push(format!("connect {}, asClock({})", name, value));
+            return;
+        }
+        if !aggregate {
+            body.push(format!("connect {}, {}", name, value));
+            return;
+        }
+        if let Some(constant) = &constant {
+            return self.connect_bits(name, &target.ty, constant, body);
+        }
+        let mut leaves = vec![];
+        self.types.leaves(name, &target.ty, 0, &mut leaves);
+        for leaf in leaves {
+            let mut bits = format!("bits({}, {}, {})", value, leaf.lo + leaf.width - 1, leaf.lo);
+            if leaf.signed {
+                bits = format!("asSInt({})", bits);
+            }
+            body.push(format!("connect {}, {}", leaf.path, bits));
+        }
+    }
+
+    /// Statements that connect the bits of a constant to an aggregate `base` of type `ty`, field by
+    /// field
+    fn connect_bits(&self, base: String, ty: &Ty, constant: &Integer, body: &mut Vec<String>) {
+        let mut leaves = vec![];
+        self.types.leaves(base, ty, 0, &mut leaves);
+        for leaf in leaves {
+            let bits = Integer::from(constant >> leaf.lo as u32).keep_bits(leaf.width as u32);
+            let bits = literal(&bits, leaf.signed, leaf.width);
+            body.push(format!("connect {}, {}", leaf.path, bits));
+        }
+    }
+
+    /// The bits of an aggregate, with its first field or element as the least significant
+    fn flatten(&self, base: String, ty: &Ty) -> String {
+        let mut leaves = vec![];
+        self.types.leaves(base, ty, 0, &mut leaves);
+        let mut leaves = leaves.into_iter().map(|leaf| {
+            if leaf.signed {
+                format!("asUInt({})", leaf.path)
+            } else {
+                leaf.path
+            }
+        });
+        let first = leaves.next().unwrap_or_default();
+        leaves.fold(first, |low, high| format!("cat({}, {})", high, low))
+    }
+
+    /// Expression for the value of a node, pushing any nodes that it needs to `body`
+    fn expr(&self, id: NodeId, kind: &NodeKind, body: &mut Vec<String>) -> String {
+        let node = self.module.node(id);
+        let to = (node.width, is_signed(&node.ty));
+        match kind {
+            NodeKind::Const(_) | NodeKind::Net(_) => self.operand(id),
+            NodeKind::Unary(UnaryOp::Not, arg) => {
+                let (width, _) = self.shape(*arg);
+                let expr = format!("not({})", self.operand(*arg));
+                self.fit(expr, (width, false), to)
+            }
+            NodeKind::Unary(UnaryOp::Neg, arg) => {
+                let (width, _) = self.shape(*arg);
+                let expr = format!("neg({})", self.operand(*arg));
+                self.fit(expr, (width + 1, true), to)
+            }
+            NodeKind::Binary(op, lhs, rhs) => self.binary(id, *op, *lhs, *rhs, body),
+            NodeKind::Cast(arg) => self.fit(self.operand(*arg), self.shape(*arg), to),
+            NodeKind::Slice(arg, lo) => {
+                let expr = format!(
+                    "bits({}, {}, {})",
+                    self.operand(*arg),
+                    lo + node.width - 1,
+                    lo
+                );
+                self.fit(expr, (node.width, false), to)
+            }
+            NodeKind::Index(arg, index) => {
+                let index = self.unsigned(*index);
+                let shift = if node.width == 1 {
+                    index
+                } else {
+                    format!(
+                        "mul({}, {})",
+                        index,
+                        literal(&Integer::from(node.width), false, width_of(node.width))
+                    )
+                };
+                let expr = format!(
+                    "bits(dshr({}, {}), {}, 0)",
+                    self.operand(*arg),
+                    shift,
+                    node.width - 1
+                );
+                self.fit(expr, (node.width, false), to)
+            }
+            NodeKind::Concat(args) => {
+                let mut args = args
+                    .iter()
+                    .filter(|arg| self.module.node(**arg).width > 0)
+                    .map(|arg| self.unsigned(*arg));
+                let first = args.next().unwrap_or_default();
+                let expr = args.fold(first, |low, high| format!("cat({}, {})", high, low));
+                self.fit(expr, (node.width, false), to)
+            }
+            NodeKind::Mux(cond, then, otherwise) => format!(
+                "mux({}, {}, {})",
+                self.operand(*cond),
+                self.fit(self.operand(*then), self.shape(*then), to),
+                self.fit(self.operand(*otherwise), self.shape(*otherwise), to)
+            ),
+        }
+    }
+
+    fn binary(
+        &self,
+        id: NodeId,
+        op: BinaryOp,
+        lhs: NodeId,
+        rhs: NodeId,
+        body: &mut Vec<String>,
+    ) -> String {
+        let node = self.module.node(id);
+        let to = (node.width, is_signed(&node.ty));
+        let (wa, signed) = self.shape(lhs);
+        let (wb, _) = self.shape(rhs);
+        let a = self.operand(lhs);
+        // Both operands take the signedness of the first
+        let b = self.fit(self.operand(rhs), self.shape(rhs), (wb, signed));
+        let (expr, from) = match op {
+            BinaryOp::Add => (format!("add({}, {})", a, b), (wa.max(wb) + 1, signed)),
+            BinaryOp::Sub => (format!("sub({}, {})", a, b), (wa.max(wb) + 1, signed)),
+            BinaryOp::Mul => (format!("mul({}, {})", a, b), (wa + wb, signed)),
+            BinaryOp::Div => (
+                format!("div({}, {})", a, b),
+                (if signed { wa + 1 } else { wa }, signed),
+            ),
+            BinaryOp::Rem => (format!("rem({}, {})", a, b), (wa.min(wb), signed)),
+            BinaryOp::Pow => return self.pow(id, lhs, rhs, body),
+            BinaryOp::Xor => (format!("xor({}, {})", a, b), (wa.max(wb), false)),
+            BinaryOp::And => (format!("and({}, {})", a, b), (wa.max(wb), false)),
+            BinaryOp::Or => (format!("or({}, {})", a, b), (wa.max(wb), false)),
+            BinaryOp::Shl => match self.constant(rhs) {
+                Some(amount) if amount < node.width => {
+                    (format!("shl({}, {})", a, amount), (wa + amount, signed))
+                }
+                Some(_) => return literal(&Integer::new(), to.1, to.0),
+                None => {
+                    // Shifting by the width or more leaves no bits, so the amount is cut down to
+                    // the bits that can shift by less
+                    let (amount, bits) = self.amount(rhs, node.width);
+                    let shifted = format!("dshl({}, {})", a, amount);
+                    let shifted = self.fit(shifted, (wa + (1 << bits) - 1, signed), to);
+                    return self.in_range(rhs, node.width, shifted, to);
+                }
+            },
+            BinaryOp::Shr => match self.constant(rhs) {
+                Some(amount) => (
+                    format!("shr({}, {})", a, amount),
+                    (wa.saturating_sub(amount).max(1), signed),
+                ),
+                None => (format!("dshr({}, {})", a, self.unsigned(rhs)), (wa, signed)),
+            },
+            BinaryOp::Eq => (format!("eq({}, {})", a, b), (1, false)),
+            BinaryOp::Ne => (format!("neq({}, {})", a, b), (1, false)),
+            BinaryOp::Lt => (format!("lt({}, {})", a, b), (1, false)),
+            BinaryOp::Le => (format!("leq({}, {})", a, b), (1, false)),
+            BinaryOp::Gt => (format!("gt({}, {})", a, b), (1, false)),
+            BinaryOp::Ge => (format!("geq({}, {})", a, b), (1, false)),
+        };
+        self.fit(expr, from, to)
+    }
+
+    /// A shift amount cut down to the bits needed to shift by less than `width`, along with its
+    /// width
+    fn amount(&self, amount: NodeId, width: u64) -> (String, u64) {
+        let bits = width_of(width.saturating_sub(1));
+        let (amount_width, _) = self.shape(amount);
+        let amount = self.unsigned(amount);
+        if amount_width <= bits {
+            (amount, amount_width)
+        } else {
+            (format!("bits({}, {}, 0)", amount, bits - 1), bits)
+        }
+    }
+
+    /// `value`, or zero where a shift amount is not less than `width`
+    fn in_range(&self, amount: NodeId, width: u64, value: String, to: (u64, bool)) -> String {
+        let (amount_width, _) = self.shape(amount);
+        if amount_width <= width_of(width.saturating_sub(1)) {
+            return value;
+        }
+        let limit = literal(&Integer::from(width), false, width_of(width));
+        format!(
+            "mux(lt({}, {}), {}, {})",
+            self.unsigned(amount),
+            limit,
+            value,
+            literal(&Integer::new(), to.1, to.0)
+        )
+    }
+
+    /// A power, by squaring the base for each bit of the exponent and multiplying together the
+    /// squares whose bits are set
+    fn pow(&self, id: NodeId, base: NodeId, exponent: NodeId, body: &mut Vec<String>) -> String {
+        let node = self.module.node(id);
+        let to = (node.width, is_signed(&node.ty));
+        let one = literal(&Integer::from(1), to.1, to.0);
+        let mut square = self.fit(self.operand(base), self.shape(base), to);
+        let mut product = one.clone();
+        let (bits, _) = self.shape(exponent);
+        let exponent = self.unsigned(exponent);
+        for bit in 0..bits {
+            if bit > 0 {
+                let name = format!("{}_{}", self.wires[id.0], bit);
+                let squared = format!("mul({}, {})", square, square);
+                body.push(format!(
+                    "node {} = {}",
+                    name,
+                    self.fit(squared, (to.0 * 2, to.1), to)
+                ));
+                square = name;
+            }
+            let factor = format!(
+                "mux(bits({}, {}, {}), {}, {})",
+                exponent, bit, bit, square, one
+            );
+            product = if bit == 0 {
+                factor
+            } else {
+                let name = format!("{}_p{}", self.wires[id.0], bit);
+                let multiplied = format!("mul({}, {})", product, factor);
+                body.push(format!(
+                    "node {} = {}",
+                    name,
+                    self.fit(multiplied, (to.0 * 2, to.1), to)
+                ));
+                name
+            };
+        }
+        product
+    }
+
+    /// Converts an expression of the given width and signedness to another, truncating it or
+    /// extending it by its own signedness
+    fn fit(&self, expr: String, from: (u64, bool), to: (u64, bool)) -> String {
+        let (mut expr, mut signed) = (expr, from.1);
+        if to.0 > from.0 {
+            expr = format!("pad({}, {})", expr, to.0);
+        } else if to.0 < from.0 {
+            expr = format!("bits({}, {}, 0)", expr, to.0.max(1) - 1);
+            signed = false;
+        }
+        match (signed, to.1) {
+            (false, true) => format!("asSInt({})", expr),
+            (true, false) => format!("asUInt({})", expr),
+            _ => expr,
+        }
+    }
+
+    /// Width and signedness of a node's value
+    fn shape(&self, id: NodeId) -> (u64, bool) {
+        let node = self.module.node(id);
+        (node.width, is_signed(&node.ty))
+    }
+
+    /// The value of a node if it is a constant that fits in a `u64`
+    fn constant(&self, id: NodeId) -> Option<u64> {
+        match &self.module.node(id).kind {
+            NodeKind::Const(bits) => bits.to_u64(),
+            _ => None,
+        }
+    }
+
+    /// A node as a `UInt`, for shift amounts, indices and the parts of a concatenation
+    fn unsigned(&self, id: NodeId) -> String {
+        let (width, signed) = self.shape(id);
+        self.fit(self.operand(id), (width, signed), (width, false))
+    }
+
+    /// How a node is referred to where it is used
+    fn operand(&self, id: NodeId) -> String {
+        let node = self.module.node(id);
+        match (&node.kind, &self.paths[id.0]) {
+            (NodeKind::Const(bits), _) => literal(bits, is_signed(&node.ty), node.width),
+            (_, Some((path, ty))) if !self.types.is_aggregate(ty) => path.clone(),
+            (NodeKind::Net(net), None) if self.is_clock(*net) => {
+                format!("asUInt({})", self.names[net.0])
+            }
+            (NodeKind::Net(net), None) => self.names[net.0].clone(),
+            _ => self.wires[id.0].clone(),
+        }
+    }
+}
+
+/// Converts a value between a `Clock` and a `UInt<1>`
+fn clock_cast(expr: String, from: bool, to: bool) -> String {
+    match (from, to) {
+        (true, false) => format!("asUInt({})", expr),
+        (false, true) => format!("asClock({})", expr),
+        _ => expr,
+    }
+}
+
+fn is_signed(ty: &Ty) -> bool {
+    matches!(ty, Ty::Signed(_))
+}
+
+/// The ground type `UInt<N>` or `SInt<N>`
+fn ground(signed: bool, width: u64) -> String {
+    format!("{}<{}>", if signed { "SInt" } else { "UInt" }, width)
+}
+
+/// Bits needed to hold `value`, which is at least one
+fn width_of(value: u64) -> u64 {
+    u64::from(u64::BITS - value.leading_zeros()).max(1)
+}
+
+/// Literal with the bits of a constant, such as `UInt<8>(255)` or `SInt<8>(-1)` for an `i8` of
+/// `-1`
+fn literal(bits: &Integer, signed: bool, width: u64) -> String {
+    if signed && width > 0 && bits.get_bit((width - 1) as u32) {
+        let value = bits - (Integer::from(1) << width as u32);
+        format!("{}({})", ground(true, width), value)
+    } else {
+        format!("{}({})", ground(signed, width), bits)
+    }
+}
+
+/// Name of the field of a bundle for a field, where those of tuples are `_0`, `_1` and so on
+fn member(name: &str) -> String {
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("_{}", name)
+    } else {
+        ident(name)
+    }
+}
+
+/// A name, quoted in backticks if FIRRTL would not read it as an identifier
+fn ident(name: &str) -> String {
+    let simple = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if simple {
+        name.to_string()
+    } else {
+        format!("`{}`", name)
+    }
+}
@@ -0,0 +1,768 @@
+//! Pretty-prints any AST node as RHDL source
+//!
+//! The tokens of a node are grouped into trees by their delimiters, which is all the structure the
+//! printer needs: the items of a file and the statements of a block each go on their own lines,
+//! the bodies of declarations such as a `fn`, `arch` or `when` always span lines, and any other
+//! group stays on one line if it fits within the configured width, together with whatever follows
+//! it on that line up to where the line can next break, or else puts each of its comma-separated
+//! elements or statements on a line of their own. The only other thing the printer takes from the
+//! tree is which `<` and `>` delimit generics rather than compare. Printing
+//! depends only on these and the [`Config`], so the output parses back to the same tokens, apart
+//! from the trailing commas of braced lists, and printing it again changes nothing.
+//!
+//! [`format_cst`] also keeps the `//` comments of a [`Cst`]: a comment on a line of its own stays
+//! on a line of its own before the token it led, and a comment after a token stays after it on
+//! its line. Any group with a comment in it spans lines.
+
+use std::collections::HashSet;
+
+use crate::ast::token::{Spanned, Tok};
+use crate::ast::ToTokens;
+use crate::cst::{angle_brackets, Cst, Trivia, TriviaKind};
+use crate::visit::Visitable;
+
+/// Whether the last element of a braced list, such as the ports of an entity, is followed by a
+/// comma
+///
+/// Parenthesized and bracketed lists keep their trailing commas as they are, since removing one
+/// can change what a tuple means and some of them, such as tuple patterns, cannot have one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrailingComma {
+    /// Only when the list is split across lines
+    Vertical,
+    Always,
+    Never,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// Spaces per level of indentation
+    pub indent: usize,
+    /// Column that lines are kept within, unless a line has nowhere to be broken
+    pub width: usize,
+    pub trailing_comma: TrailingComma,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            indent: 4,
+            width: 100,
+            trailing_comma: TrailingComma::Vertical,
+        }
+    }
+}
+
+/// Prints `node` as formatted source, without a final line break
+pub fn format<'ast>(node: &'ast (impl ToTokens + Visitable<'ast>), config: &Config) -> String {
+    let tokens = node
+        .to_tokens()
+        .into_iter()
+        .map(|tok| (tok, Comments::default()))
+        .collect();
+    print(tokens, &angle_brackets(node), config)
+}
+
+/// Prints the tokens of `cst` as formatted source along with its comments, without a final line
+/// break
+pub fn format_cst(cst: &Cst, config: &Config) -> String {
+    let tokens = cst
+        .tokens
+        .iter()
+        .map(|tok| {
+            let comments = Comments {
+                leading: comments(&tok.leading).collect(),
+                trailing: comments(&tok.trailing).next(),
+            };
+            (tok.tok.clone(), comments)
+        })
+        .collect();
+    let mut out = print(tokens, &cst.angle_brackets, config);
+    for comment in comments(&cst.eof) {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out += &comment;
+    }
+    out
+}
+
+/// Text of the `//` comments among `trivia`
+fn comments(trivia: &[Trivia]) -> impl Iterator<Item = String> + '_ {
+    trivia
+        .iter()
+        .filter(|trivia| trivia.kind == TriviaKind::Comment)
+        .map(|comment| comment.text.trim_end().to_string())
+}
+
+/// Prints tokens along with the comments around each, where the `<` and `>` that start at
+/// `angle_brackets` delimit generics
+fn print(tokens: Vec<(Tok, Comments)>, angle_brackets: &HashSet<usize>, config: &Config) -> String {
+    let mut trees = trees(&mut roles(tokens, angle_brackets).into_iter());
+    mark_bodies(&mut trees);
+    let mut printer = Printer {
+        config,
+        out: String::new(),
+        depth: 0,
+        column: 0,
+        broken: false,
+    };
+    printer.lines(&trees, true, true);
+    printer.out
+}
+
+/// The comments around a token
+#[derive(Clone, Debug, Default)]
+struct Comments {
+    /// Comments on lines of their own before the token
+    leading: Vec<String>,
+    /// A comment after the token on its line
+    trailing: Option<String>,
+}
+
+impl Comments {
+    fn is_empty(&self) -> bool {
+        self.leading.is_empty() && self.trailing.is_none()
+    }
+}
+
+/// How a token that can be written in more than one way is used
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Role {
+    Plain,
+    /// A `-` that negates the operand after it
+    Unary,
+    /// A `<` that opens generics, such as in `Fifo<T>`
+    GenericOpen,
+    /// The `>` that closes generics
+    GenericClose,
+}
+
+enum Tree {
+    Leaf(Tok, Role, Comments),
+    Group(Group),
+}
+
+struct Group {
+    open: (Tok, Comments),
+    trees: Vec<Tree>,
+    close: Option<(Tok, Comments)>,
+    /// Whether this is the body of a declaration, such as a `fn` or `arch`
+    body: bool,
+}
+
+impl Tree {
+    fn first(&self) -> (&Tok, Role) {
+        match self {
+            Self::Leaf(tok, role, _) => (tok, *role),
+            Self::Group(group) => (&group.open.0, Role::Plain),
+        }
+    }
+
+    fn last(&self) -> (&Tok, Role) {
+        match self {
+            Self::Leaf(tok, role, _) => (tok, *role),
+            Self::Group(group) => (&group.close.as_ref().unwrap_or(&group.open).0, Role::Plain),
+        }
+    }
+
+    fn is(&self, f: impl Fn(&Tok) -> bool) -> bool {
+        matches!(self, Self::Leaf(tok, ..) if f(tok))
+    }
+}
+
+impl Group {
+    fn is_brace(&self) -> bool {
+        matches!(self.open.0, Tok::BraceOpen(_))
+    }
+
+    /// Whether the group holds comma-separated elements, rather than statements or an expression
+    fn is_list(&self) -> bool {
+        let comma = self.trees.iter().any(|tree| tree.is(is_comma));
+        let semi = self.trees.iter().any(|tree| tree.is(is_semi));
+        comma && !(self.is_brace() && (self.body || semi))
+    }
+
+    /// Whether the group spans lines wherever it is
+    fn forced(&self) -> bool {
+        let commented = !self.open.1.is_empty()
+            || self
+                .close
+                .as_ref()
+                .is_some_and(|(_, comments)| !comments.is_empty());
+        if self.trees.is_empty() {
+            return commented;
+        }
+        let block = self.is_brace() && !self.is_list();
+        commented
+            || self.body
+            || (block && statements(&self.trees).len() > 1)
+            || (block && self.trees.iter().any(|tree| tree.is(is_semi)))
+            || self.trees.iter().any(|tree| match tree {
+                Tree::Leaf(tok, _, comments) => {
+                    matches!(tok, Tok::DocComment(_)) || !comments.is_empty()
+                }
+                Tree::Group(group) => group.forced(),
+            })
+    }
+}
+
+fn is_comma(tok: &Tok) -> bool {
+    matches!(tok, Tok::Comma(_))
+}
+
+fn is_semi(tok: &Tok) -> bool {
+    matches!(tok, Tok::Semi(_))
+}
+
+/// Gives each token its role, from the tokens before it and the offsets of the `<` and `>` that
+/// delimit generics
+fn roles(
+    tokens: Vec<(Tok, Comments)>,
+    angle_brackets: &HashSet<usize>,
+) -> Vec<(Tok, Role, Comments)> {
+    use Tok::*;
+
+    let mut acc: Vec<(Tok, Role, Comments)> = Vec::with_capacity(tokens.len());
+    for (tok, comments) in tokens {
+        let prev = acc.last().map(|(tok, role, _)| (tok, *role));
+        let role = match &tok {
+            Lt(_) if angle_brackets.contains(&tok.span().0) => Role::GenericOpen,
+            Gt(_) if angle_brackets.contains(&tok.span().0) => Role::GenericClose,
+            Minus(_) => match prev {
+                Some((Ident(_), _))
+                | Some((Lit(_), _))
+                | Some((ParenClose(_), _))
+                | Some((BracketClose(_), _))
+                | Some((BraceClose(_), _))
+                | Some((Question(_), _))
+                | Some((LowerSelf(_), _))
+                | Some((_, Role::GenericClose)) => Role::Plain,
+                _ => Role::Unary,
+            },
+            _ => Role::Plain,
+        };
+        acc.push((tok, role, comments));
+    }
+    acc
+}
+
+/// Groups tokens by their delimiters, up to the close of the group that they are in
+fn trees(tokens: &mut impl Iterator<Item = (Tok, Role, Comments)>) -> Vec<Tree> {
+    let mut acc = vec![];
+    while let Some((tok, role, comments)) = tokens.next() {
+        match tok {
+            Tok::ParenOpen(_) | Tok::BracketOpen(_) | Tok::BraceOpen(_) => {
+                let mut inner = vec![];
+                let mut close = None;
+                for tree in trees(tokens) {
+                    match tree {
+                        Tree::Leaf(tok, _, comments) if closes(&tok) && close.is_none() => {
+                            close = Some((tok, comments))
+                        }
+                        tree => inner.push(tree),
+                    }
+                }
+                acc.push(Tree::Group(Group {
+                    open: (tok, comments),
+                    trees: inner,
+                    close,
+                    body: false,
+                }));
+            }
+            tok if closes(&tok) => {
+                acc.push(Tree::Leaf(tok, role, comments));
+                return acc;
+            }
+            tok => acc.push(Tree::Leaf(tok, role, comments)),
+        }
+    }
+    acc
+}
+
+fn closes(tok: &Tok) -> bool {
+    matches!(
+        tok,
+        Tok::ParenClose(_) | Tok::BracketClose(_) | Tok::BraceClose(_)
+    )
+}
+
+/// Marks the first braces of each statement that declares a `fn`, `impl`, `trait`, `mod`,
+/// `arch`, `when` or `test` as its body
+fn mark_bodies(trees: &mut [Tree]) {
+    let mut start = 0;
+    let lengths: Vec<_> = statements(trees)
+        .iter()
+        .map(|statement| statement.len())
+        .collect();
+    for statement in lengths {
+        let trees = &mut trees[start..start + statement];
+        start += statement;
+        let declares = trees
+            .iter()
+            .find(|tree| match tree {
+                Tree::Leaf(tok, ..) => {
+                    !matches!(tok, Tok::DocComment(_) | Tok::Pound(_) | Tok::Pub(_))
+                }
+                Tree::Group(group) => group.is_brace(),
+            })
+            .is_some_and(|tree| {
+                tree.is(|tok| {
+                    matches!(
+                        tok,
+                        Tok::Fn(_)
+                            | Tok::Impl(_)
+                            | Tok::Trait(_)
+                            | Tok::Mod(_)
+                            | Tok::Arch(_)
+                            | Tok::When(_)
+                            | Tok::Test(_)
+                    )
+                })
+            });
+        let body = trees.iter_mut().find_map(|tree| match tree {
+            Tree::Group(group) if group.is_brace() => Some(group),
+            _ => None,
+        });
+        if let (true, Some(body)) = (declares, body) {
+            body.body = true;
+        }
+    }
+    for tree in trees {
+        if let Tree::Group(group) = tree {
+            mark_bodies(&mut group.trees);
+        }
+    }
+}
+
+/// Splits a sequence of items or statements after each `;` and each `}` that ends one
+fn statements(trees: &[Tree]) -> Vec<&[Tree]> {
+    use Tok::*;
+
+    let mut acc = vec![];
+    let mut start = 0;
+    for (i, tree) in trees.iter().enumerate() {
+        let ends = match tree {
+            Tree::Leaf(tok, ..) => matches!(tok, Semi(_)),
+            Tree::Group(group) if group.is_brace() => match trees.get(i + 1) {
+                Some(next) => !next.is(|tok| {
+                    matches!(
+                        tok,
+                        Else(_)
+                            | Semi(_)
+                            | Comma(_)
+                            | Dot(_)
+                            | Question(_)
+                            | As(_)
+                            | FatArrow(_)
+                            | Colon(_)
+                            | PathSep(_)
+                            | DotDot(_)
+                            | DotDotEq(_)
+                            | Plus(_)
+                            | Minus(_)
+                            | Star(_)
+                            | StarStar(_)
+                            | Slash(_)
+                            | Percent(_)
+                            | Caret(_)
+                            | And(_)
+                            | Or(_)
+                            | AndAnd(_)
+                            | OrOr(_)
+                            | Shl(_)
+                            | Shr(_)
+                            | Eq(_)
+                            | EqEq(_)
+                            | Ne(_)
+                            | Gt(_)
+                            | Lt(_)
+                            | Ge(_)
+                            | Le(_)
+                            | PlusEq(_)
+                            | MinusEq(_)
+                            | StarEq(_)
+                            | StarStarEq(_)
+                            | SlashEq(_)
+                            | PercentEq(_)
+                            | CaretEq(_)
+                            | AndEq(_)
+                            | OrEq(_)
+                            | ShlEq(_)
+                            | ShrEq(_)
+                    )
+                }),
+                None => true,
+            },
+            Tree::Group(_) => false,
+        };
+        if ends {
+            acc.push(&trees[start..=i]);
+            start = i + 1;
+        }
+    }
+    if start < trees.len() {
+        acc.push(&trees[start..]);
+    }
+    acc
+}
+
+/// Splits a list at its commas, along with whether it ends with one
+fn elements(trees: &[Tree]) -> (Vec<&[Tree]>, bool) {
+    let mut acc: Vec<&[Tree]> = trees.split(|tree| tree.is(is_comma)).collect();
+    let trailing = acc.len() > 1 && acc.last().is_some_and(|last| last.is_empty());
+    if trailing {
+        acc.pop();
+    }
+    (acc, trailing)
+}
+
+/// Whether a space goes between two tokens on the same line
+fn space(prev: (&Tok, Role), next: (&Tok, Role)) -> bool {
+    use Tok::*;
+
+    let after = !matches!(
+        prev,
+        (_, Role::Unary)
+            | (_, Role::GenericOpen)
+            | (ParenOpen(_), _)
+            | (BracketOpen(_), _)
+            | (PathSep(_), _)
+            | (Dot(_), _)
+            | (Pound(_), _)
+            | (Not(_), _)
+            | (DotDot(_), _)
+            | (DotDotEq(_), _)
+    );
+    // `<<` and `>>` would lex as shifts
+    if matches!(
+        (prev.1, next.1),
+        (Role::GenericOpen, Role::GenericOpen) | (Role::GenericClose, Role::GenericClose)
+    ) {
+        return true;
+    }
+    after
+        && match next {
+            (_, Role::GenericOpen) | (_, Role::GenericClose) => false,
+            (ParenClose(_), _)
+            | (BracketClose(_), _)
+            | (Comma(_), _)
+            | (Semi(_), _)
+            | (Dot(_), _)
+            | (PathSep(_), _)
+            | (Question(_), _)
+            | (Colon(_), _) => false,
+            (DotDot(_), _) | (DotDotEq(_), _) => matches!(prev.0, Comma(_)),
+            (ParenOpen(_), _) => !matches!(
+                prev,
+                (Ident(_), _)
+                    | (ParenClose(_), _)
+                    | (BracketClose(_), _)
+                    | (_, Role::GenericClose)
+                    | (Pub(_), _)
+                    | (Fn(_), _)
+                    | (UpperSelf(_), _)
+                    | (LowerSelf(_), _)
+                    | (Super(_), _)
+                    | (Crate(_), _)
+            ),
+            (BracketOpen(_), _) => !matches!(
+                prev.0,
+                Ident(_) | ParenClose(_) | BracketClose(_) | LowerSelf(_)
+            ),
+            _ => true,
+        }
+}
+
+struct Printer<'c> {
+    config: &'c Config,
+    out: String,
+    depth: usize,
+    column: usize,
+    /// Whether the line ends with a comment, so nothing more can go on it
+    broken: bool,
+}
+
+impl Printer<'_> {
+    fn write(&mut self, text: &str) {
+        match text.rfind('\n') {
+            Some(i) => self.column = text[i + 1..].chars().count(),
+            None => self.column += text.chars().count(),
+        }
+        self.out += text;
+    }
+
+    fn newline(&mut self) {
+        let trimmed = self.out.trim_end_matches(' ').len();
+        self.out.truncate(trimmed);
+        self.out.push('\n');
+        self.column = 0;
+        self.broken = false;
+        self.write(&" ".repeat(self.depth * self.config.indent));
+    }
+
+    /// Writes a token, or just its comments if `text` is empty, after ending a line that a comment
+    /// ends
+    fn token(&mut self, text: &str, comments: &Comments) {
+        if self.broken {
+            self.newline();
+        }
+        for comment in &comments.leading {
+            if !self.out.trim().is_empty() && !self.out.trim_end_matches(' ').ends_with('\n') {
+                self.newline();
+            }
+            self.write(comment);
+            self.newline();
+        }
+        self.write(text);
+        if let Some(comment) = &comments.trailing {
+            if !text.is_empty() || !self.out.trim_end_matches(' ').ends_with('\n') {
+                self.write(" ");
+            }
+            self.write(comment);
+            self.broken = true;
+        }
+    }
+
+    /// Prints each statement on its own line, separating those of a file by blank lines where
+    /// either spans more than one
+    fn lines(&mut self, trees: &[Tree], file: bool, declarations: bool) {
+        let mut spans_lines = false;
+        for (i, statement) in statements(trees).into_iter().enumerate() {
+            let mut printer = Printer {
+                config: self.config,
+                out: String::new(),
+                depth: self.depth,
+                column: match i {
+                    0 => self.column,
+                    _ => self.depth * self.config.indent,
+                },
+                broken: false,
+            };
+            printer.statement(statement, declarations);
+            let lines = printer.out.contains('\n');
+            if i > 0 {
+                if file && (lines || spans_lines) {
+                    self.out.push('\n');
+                }
+                self.newline();
+            }
+            spans_lines = lines;
+            self.write(&printer.out);
+            self.broken = printer.broken;
+        }
+    }
+
+    /// Prints a statement, with its doc comments and, if it is in a file or the body of a
+    /// declaration, its attributes on lines of their own
+    fn statement(&mut self, trees: &[Tree], declaration: bool) {
+        let mut rest = trees;
+        loop {
+            match rest {
+                [Tree::Leaf(Tok::DocComment(_), ..), tail @ ..] => {
+                    self.inline(&rest[..1], 0);
+                    rest = tail;
+                }
+                [Tree::Leaf(Tok::Pound(_), ..), Tree::Group(_), tail @ ..] if declaration => {
+                    self.inline(&rest[..2], 0);
+                    self.newline();
+                    rest = tail;
+                }
+                _ => return self.inline(rest, 0),
+            }
+        }
+    }
+
+    /// Prints trees one after another, letting the groups among them span lines if they must,
+    /// given the width of what follows them on their last line
+    fn inline(&mut self, trees: &[Tree], after: usize) {
+        let mut prev: Option<(&Tok, Role)> = None;
+        for (i, tree) in trees.iter().enumerate() {
+            if let Some(prev) = prev {
+                if space(prev, tree.first()) {
+                    self.write(" ");
+                }
+            }
+            match tree {
+                Tree::Leaf(tok, _, comments) => self.token(&tok.to_string(), comments),
+                Tree::Group(group) => {
+                    let after = self.run(Some(tree.last()), &trees[i + 1..], after);
+                    self.group(group, after);
+                }
+            }
+            prev = Some(tree.last());
+            if tree.is(|tok| matches!(tok, Tok::DocComment(_))) {
+                self.newline();
+                prev = None;
+            }
+        }
+    }
+
+    /// Prints a group, on one line if it fits there along with the `after` columns that follow it
+    /// up to where the line can next break
+    fn group(&mut self, group: &Group, after: usize) {
+        if self.broken {
+            self.newline();
+        }
+        let flat = self.flat_group(group);
+        if !group.forced()
+            && (group.trees.is_empty()
+                || self.column + flat.chars().count() + after <= self.config.width)
+        {
+            return self.write(&flat);
+        }
+
+        self.token(&group.open.0.to_string(), &group.open.1);
+        let mut close = group.close.clone();
+        let vertical = group.is_list() || group.is_brace();
+        if vertical {
+            self.depth += 1;
+        }
+        if group.is_list() {
+            let (elements, trailing) = elements(&group.trees);
+            let trailing = self.trailing_comma(group, &elements, trailing, true);
+            let mut commas = group.trees.iter().filter_map(|tree| match tree {
+                Tree::Leaf(Tok::Comma(_), _, comments) => Some(comments),
+                _ => None,
+            });
+            for (i, element) in elements.iter().enumerate() {
+                self.newline();
+                let comma = if i + 1 < elements.len() || trailing {
+                    ","
+                } else {
+                    ""
+                };
+                self.inline(element, comma.len());
+                // A comma that is dropped leaves its comments behind
+                self.token(comma, commas.next().unwrap_or(&Comments::default()));
+            }
+        } else if group.is_brace() && !group.trees.is_empty() {
+            self.newline();
+            self.lines(&group.trees, false, group.body);
+        } else if !group.is_brace() {
+            let end = close
+                .as_ref()
+                .map_or(0, |(close, _)| close.to_string().len());
+            self.inline(&group.trees, end + after);
+        }
+        if vertical {
+            // Comments before the close stay inside the group
+            if let Some((_, comments)) = &mut close {
+                for comment in comments.leading.drain(..) {
+                    self.newline();
+                    self.write(&comment);
+                }
+            }
+            self.depth -= 1;
+            self.newline();
+        }
+        if let Some((close, comments)) = &close {
+            self.token(&close.to_string(), comments);
+        }
+    }
+
+    /// Whether a list ends with a comma, given whether it did and whether it spans lines
+    fn trailing_comma(
+        &self,
+        group: &Group,
+        elements: &[&[Tree]],
+        trailing: bool,
+        vertical: bool,
+    ) -> bool {
+        if !group.is_brace() {
+            return trailing;
+        }
+        // Nothing can follow the rest of a struct pattern or the base of a struct expression
+        let rest = elements
+            .last()
+            .and_then(|last| last.first())
+            .is_some_and(|first| first.is(|tok| matches!(tok, Tok::DotDot(_) | Tok::DotDotEq(_))));
+        !rest
+            && match self.config.trailing_comma {
+                TrailingComma::Vertical => vertical,
+                TrailingComma::Always => true,
+                TrailingComma::Never => false,
+            }
+    }
+
+    /// How many columns trees printed after `prev` take up to the first place the line breaks or
+    /// may break, which is at a comment or inside a group that spans lines wherever it is, plus
+    /// `after` if there is no such place among them
+    fn run<'t>(&self, mut prev: Option<(&'t Tok, Role)>, trees: &'t [Tree], after: usize) -> usize {
+        let mut width = 0;
+        for tree in trees {
+            if let Some(prev) = prev {
+                if space(prev, tree.first()) {
+                    width += 1;
+                }
+            }
+            match tree {
+                Tree::Leaf(Tok::DocComment(_), ..) => return width,
+                Tree::Leaf(_, _, comments) if !comments.leading.is_empty() => return width,
+                Tree::Leaf(tok, _, comments) => {
+                    width += tok.to_string().chars().count();
+                    if comments.trailing.is_some() {
+                        return width;
+                    }
+                }
+                Tree::Group(group) if group.forced() => {
+                    return width + group.open.0.to_string().chars().count();
+                }
+                Tree::Group(group) => width += self.flat_group(group).chars().count(),
+            }
+            prev = Some(tree.last());
+        }
+        width + after
+    }
+
+    /// Trees printed one after another on a single line
+    fn flat(&self, trees: &[Tree]) -> String {
+        let mut acc = String::new();
+        let mut prev: Option<(&Tok, Role)> = None;
+        for tree in trees {
+            if let Some(prev) = prev {
+                if space(prev, tree.first()) {
+                    acc.push(' ');
+                }
+            }
+            match tree {
+                Tree::Leaf(tok, ..) => acc += &tok.to_string(),
+                Tree::Group(group) => acc += &self.flat_group(group),
+            }
+            prev = Some(tree.last());
+        }
+        acc
+    }
+
+    fn flat_group(&self, group: &Group) -> String {
+        let open = &group.open.0;
+        let close = group
+            .close
+            .as_ref()
+            .map(|(close, _)| close.to_string())
+            .unwrap_or_default();
+        if group.trees.is_empty() {
+            return format!("{}{}", open, close);
+        }
+        let inner = if group.is_list() {
+            let (elements, trailing) = elements(&group.trees);
+            let trailing = self.trailing_comma(group, &elements, trailing, false);
+            let mut inner = elements
+                .iter()
+                .map(|element| self.flat(element))
+                .collect::<Vec<_>>()
+                .join(", ");
+            if trailing {
+                inner.push(',');
+            }
+            inner
+        } else {
+            self.flat(&group.trees)
+        };
+        if group.is_brace() {
+            format!("{} {} {}", open, inner, close)
+        } else {
+            format!("{}{}{}", open, inner, close)
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use paste::paste;
+
+use crate::ast::token::*;
+use crate::ast::*;
+use crate::diagnostics::Diagnostic;
+
+/// Where the walkers that the default methods of [`Fold`] call are defined
+mod walkers {
+    pub use crate::ast::token::*;
+    pub use crate::ast::*;
+    pub use crate::diagnostics::fold_diagnostic;
+}
+
+macro_rules! fold {
+    ($($token_class: ident),*) => {
+        paste! {
+            // An overriding method calls these to keep folding the children of its node
+            pub use walkers::{$( [<fold_ $token_class:snake>] ),*};
+
+            pub trait Fold {
+                $(
+                    fn [<fold_ $token_class:snake>](&mut self, inst: $token_class) -> $token_class {
+                        [<fold_ $token_class:snake>](self, inst)
+                    }
+                )*
+            }
+        }
+    };
+}
+
+/// Dispatches an owned node to its [`Fold`] method, looking through the containers that wrap AST fields
+pub trait Foldable: Sized {
+    fn fold<F>(self, f: &mut F) -> Self
+    where
+        F: Fold + ?Sized;
+}
+
+impl<T: Foldable> Foldable for Option<T> {
+    fn fold<F>(self, f: &mut F) -> Self
+    where
+        F: Fold + ?Sized,
+    {
+        self.map(|inner| inner.fold(f))
+    }
+}
+
+impl<T: Foldable> Foldable for Box<T> {
+    fn fold<F>(self, f: &mut F) -> Self
+    where
+        F: Fold + ?Sized,
+    {
+        Box::new((*self).fold(f))
+    }
+}
+
+impl<T: Foldable> Foldable for Vec<T> {
+    fn fold<F>(self, f: &mut F) -> Self
+    where
+        F: Fold + ?Sized,
+    {
+        self.into_iter().map(|inner| inner.fold(f)).collect()
+    }
+}
+
+impl<A: Foldable, B: Foldable> Foldable for (A, B) {
+    fn fold<F>(self, f: &mut F) -> Self
+    where
+        F: Fold + ?Sized,
+    {
+        (self.0.fold(f), self.1.fold(f))
+    }
+}
+
+impl<A: Foldable, B: Foldable, C: Foldable> Foldable for (A, B, C) {
+    fn fold<F>(self, f: &mut F) -> Self
+    where
+        F: Fold + ?Sized,
+    {
+        (self.0.fold(f), self.1.fold(f), self.2.fold(f))
+    }
+}
+
+impl<T, P> Foldable for Punctuated<T, P>
+where
+    T: Foldable + ToTokens + Clone + std::fmt::Debug + PartialEq,
+    P: Foldable + ToTokens + Clone + std::fmt::Debug + PartialEq,
+{
+    fn fold<F>(self, f: &mut F) -> Self
+    where
+        F: Fold + ?Sized,
+    {
+        Punctuated {
+            inner: self
+                .inner
+                .into_iter()
+                .map(|(t, p)| (t.fold(f), p.fold(f)))
+                .collect(),
+            last: self.last.fold(f),
+        }
+    }
+}
+
+crate::visit::nodes!(fold);
@@ -0,0 +1,143 @@
+//! Writes any AST node as JSON, for tools that work on RHDL source without linking to this crate
+//!
+//! Every node becomes an object whose `kind` is the name of its type, such as `ItemConst` or
+//! `ExprBinary`, with one member per field. Enums of nodes write the node they hold, and a variant
+//! that holds nothing, such as `VariantTypeUnit`, is a node with only its `kind`. Tokens and
+//! identifiers carry the `[start, end]` span of source they were parsed from, an absent optional
+//! field is `null`, and punctuated lists drop their separators. Integer literals keep both their
+//! source text and their value, which is written as a string since it may not fit a JSON number.
+
+use std::fmt;
+
+use crate::ast::token::Span;
+use crate::ast::{Punctuated, ToTokens};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(usize),
+    String(String),
+    Array(Vec<Json>),
+    /// Members in the order they are written
+    Object(Vec<(&'static str, Json)>),
+}
+
+impl Json {
+    /// An object for a node of type `kind` with the given fields
+    pub fn node(kind: &str, fields: Vec<(&'static str, Json)>) -> Self {
+        let mut members = vec![("kind", Self::String(kind.to_string()))];
+        members.extend(fields);
+        Self::Object(members)
+    }
+
+    fn write(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        // The alternate flag puts each element and member on its own line
+        let pretty = f.alternate();
+        let (open, close, len) = match self {
+            Self::Null => return write!(f, "null"),
+            Self::Bool(b) => return write!(f, "{}", b),
+            Self::Number(n) => return write!(f, "{}", n),
+            Self::String(s) => return write_string(f, s),
+            Self::Array(elements) => ('[', ']', elements.len()),
+            Self::Object(members) => ('{', '}', members.len()),
+        };
+        write!(f, "{}", open)?;
+        for i in 0..len {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            if pretty {
+                write!(f, "\n{}", "  ".repeat(depth + 1))?;
+            }
+            match self {
+                Self::Array(elements) => elements[i].write(f, depth + 1)?,
+                Self::Object(members) => {
+                    let (name, value) = &members[i];
+                    write_string(f, name)?;
+                    write!(f, "{}", if pretty { ": " } else { ":" })?;
+                    value.write(f, depth + 1)?;
+                }
+                _ => unreachable!("only arrays and objects have elements"),
+            }
+        }
+        if pretty && len > 0 {
+            write!(f, "\n{}", "  ".repeat(depth))?;
+        }
+        write!(f, "{}", close)
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, 0)
+    }
+}
+
+/// Writes `s` as a JSON string, escaping quotes, backslashes and control characters
+fn write_string(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+/// Converts a node, or one of the containers that wrap AST fields, to [`Json`]
+pub trait ToJson {
+    fn to_json(&self) -> Json;
+}
+
+impl<T: ToJson> ToJson for Option<T> {
+    fn to_json(&self) -> Json {
+        self.as_ref().map_or(Json::Null, ToJson::to_json)
+    }
+}
+
+impl<T: ToJson> ToJson for Box<T> {
+    fn to_json(&self) -> Json {
+        self.as_ref().to_json()
+    }
+}
+
+impl<T: ToJson> ToJson for Vec<T> {
+    fn to_json(&self) -> Json {
+        Json::Array(self.iter().map(ToJson::to_json).collect())
+    }
+}
+
+impl<A: ToJson, B: ToJson> ToJson for (A, B) {
+    fn to_json(&self) -> Json {
+        Json::Array(vec![self.0.to_json(), self.1.to_json()])
+    }
+}
+
+impl<A: ToJson, B: ToJson, C: ToJson> ToJson for (A, B, C) {
+    fn to_json(&self) -> Json {
+        Json::Array(vec![self.0.to_json(), self.1.to_json(), self.2.to_json()])
+    }
+}
+
+impl<T, P> ToJson for Punctuated<T, P>
+where
+    T: ToJson + ToTokens + Clone + std::fmt::Debug + PartialEq,
+    P: ToTokens + Clone + std::fmt::Debug + PartialEq,
+{
+    fn to_json(&self) -> Json {
+        Json::Array(self.iter().map(ToJson::to_json).collect())
+    }
+}
+
+impl ToJson for Span {
+    fn to_json(&self) -> Json {
+        Json::Array(vec![Json::Number(self.0), Json::Number(self.1)])
+    }
+}
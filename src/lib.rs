@@ -3,10 +3,56 @@ pub mod ast;
 
 pub mod visit;
 
-#[cfg(test)]
-mod display;
+pub mod fold;
+
+pub mod diagnostics;
+
+pub mod source_map;
+
+pub mod loader;
+
+pub mod resolve;
+
+pub mod ty;
+
+pub mod typeck;
+
+pub mod const_eval;
+
+pub mod arch_check;
+
+pub mod elaborate;
+
+pub mod netlist;
+
+pub mod verilog;
+
+pub mod vhdl;
+
+pub mod systemverilog;
+
+pub mod firrtl;
+
+pub mod sim;
+
+pub mod vcd;
+
+pub mod testbench;
+
+pub mod fmt;
 
-pub mod parser;
+pub mod cst;
+
+pub mod json;
+
+pub mod parser {
+    include!("parser.rs");
+
+    /// Matches the tokens of the `match` block of the grammar, skipping whitespace and comments
+    pub(crate) fn lexer() -> lalrpop_util::lexer::MatcherBuilder {
+        __intern_token::new_builder()
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -15,11 +61,22 @@ mod tests {
             token::{LitFloat, LitInt, ToTokens},
             *,
         },
-        display::format,
+        diagnostics,
+        fmt::{format, Config, TrailingComma},
         parser::*,
+        source_map::*,
     };
     use pretty_assertions::assert_eq;
 
+    /// Asserts that each file parses and formats back to itself
+    macro_rules! parse_file {
+        ($($input: expr),+) => {
+            $(
+                assert_eq!(FileParser::new().parse($input).map(|output| format(&output, &Config::default())), Ok($input.to_string()));
+            )+
+        };
+    }
+
     #[test]
     fn int_parser() {
         macro_rules! parse {
@@ -83,7 +140,7 @@ mod tests {
         macro_rules! parse {
                 ($($input: expr),+) => {
                     $(
-                        assert_eq!(ExprParser::new().parse(&$input).map(|output| format(output.to_tokens())), Ok($input.to_string()));
+                        assert_eq!(ExprParser::new().parse(&$input).map(|output| format(&output, &Config::default())), Ok($input.to_string()));
                     )+
                 };
             }
@@ -103,9 +160,9 @@ mod tests {
         }
         for op in AssOp::variants() {
             parse!(
-                format!("{{ a {} 0; }}", op),
-                format!("{{ a {} b; }}", op),
-                format!("{{ a {} {{ b }}; }}", op)
+                format!("{{\n    a {} 0;\n}}", op),
+                format!("{{\n    a {} b;\n}}", op),
+                format!("{{\n    a {} {{ b }};\n}}", op)
             );
         }
     }
@@ -115,12 +172,12 @@ mod tests {
         macro_rules! parse {
                 ($($input: expr),+) => {
                     $(
-                        assert_eq!(ExprParser::new().parse($input).map(|output| format(output.to_tokens())), Ok($input.to_string()));
+                        assert_eq!(ExprParser::new().parse($input).map(|output| format(&output, &Config::default())), Ok($input.to_string()));
                     )+
                 };
             }
         parse!(
-            "{ }",
+            "{}",
             "a",
             "4",
             "{ a }",
@@ -133,16 +190,16 @@ mod tests {
             "[0, 1, 2, 3, 4, 5]",
             "(0, 1, 2, 3, 4, 4.5)",
             "x as y",
-            "if a >= 4 { }",
-            "if a >= 4 { }\nelse { }",
-            "if a >= 4 { }\nelse if a < 0 { }\nelse if a > 0 { }\nelse { }",
-            "match x { 0 => { }, 1 => { y }, _ if x != 2 => { }, 2 => { } }",
-            "{ return a; }",
+            "if a >= 4 {}",
+            "if a >= 4 {} else {}",
+            "if a >= 4 {} else if a < 0 {} else if a > 0 {} else {}",
+            "match x { 0 => {}, 1 => { y }, _ if x != 2 => {}, 2 => {} }",
+            "{\n    return a;\n}",
             "Struct { x, y, z }",
             "Struct { x: a, y: b, z: c }",
-            "Struct { x, .. z }",
-            "Struct { .. z }",
-            "for x in 0..16 { }"
+            "Struct { x, ..z }",
+            "Struct { ..z }",
+            "for x in 0..16 {}"
         );
     }
 
@@ -151,7 +208,7 @@ mod tests {
         macro_rules! parse {
                 ($($input: expr),+) => {
                     $(
-                        assert_eq!(PatParser::new().parse($input).map(|output| format(output.to_tokens())), Ok($input.to_string()));
+                        assert_eq!(PatParser::new().parse($input).map(|output| format(&output, &Config::default())), Ok($input.to_string()));
                     )+
                 };
             }
@@ -178,14 +235,7 @@ mod tests {
 
     #[test]
     fn file_parser() {
-        macro_rules! parse {
-                ($($input: expr),+) => {
-                    $(
-                        assert_eq!(FileParser::new().parse($input).map(|output| format(output.to_tokens())), Ok($input.to_string()));
-                    )+
-                };
-            }
-        parse!(
+        parse_file!(
             r#"use super::X;
 use crate::Y;
 use crate::{ first::{ self, Type }, second::Type as AnotherType };
@@ -193,18 +243,1363 @@ pub const ROM_SIZE: uint = 64 * 1024 * 1024;
 const TRUE: bool = true;
 const FALSE: bool = false;
 mod in_another_file;
-mod in_this_file { }
-fn x(x: X) { }
+mod in_this_file {}
+fn x(x: X) {}
 type AliasForX = X;
 pub(super) struct NamedWrapper { x: X }
 pub(crate) struct UnnamedWrapper(X);
 pub(in super::super) enum Z { A(X), B(Y), C(u12) }
 enum GrayU2 { Zero = 0b00, One = 0b01, Two = 0b11, Three = 0b10 }
 enum States { Uninitialized, Ready, Busy, Error }
-fn everything(parenthesized: (Type), typePath: Type, tuple1: (), tuple2: (u9, i9), array: [u4; 32], slice: [u8], infer: _, function: fn(u8), function_ret: fn(u10) -> u16) { }
+
+fn everything(
+    parenthesized: (Type),
+    typePath: Type,
+    tuple1: (),
+    tuple2: (u9, i9),
+    array: [u4; 32],
+    slice: [u8],
+    infer: _,
+    function: fn(u8),
+    function_ret: fn(u10) -> u16
+) {}
+
 entity Top { in clk: bit, out audio: u24 = 0 }
-arch Top { let level; Sawtooth { clk: self.clk, level }
-when clk.posedge { self.audio = level >> 8 } }"#
+
+arch Top {
+    let level;
+    Sawtooth { clk: self.clk, level }
+    when clk.posedge {
+        self.audio = level >> 8
+    }
+}"#
+        );
+    }
+
+    #[test]
+    fn file_parser_where_clauses() {
+        parse_file!(
+            "fn f<T>(x: T) -> T where T: Bits {\n    x\n}",
+            "type Word<T> where T: Bits = [T; 4];",
+            "struct Named<T> where T: Bits + Default { x: T }",
+            "struct Unnamed<T>(T) where T: Bits;",
+            "enum E<T> where T: Bits { A(T), B }",
+            "trait Tr<T>: Base where T: Bits {}",
+            "trait Alias<T> where T: Bits = Base + Other;",
+            "impl<T> Tr for X<T> where T: Bits, X<T>: Default {}",
+            "entity Fifo<T> where T: Bits { in clk: bit, out data: T }",
+            "arch<T> Fifo<T> where T: Bits {}"
+        );
+    }
+
+    #[test]
+    fn file_parser_doc_comments() {
+        parse_file!(
+            "/// Produces a sawtooth wave\n/// at the audio sample rate\nentity Sawtooth {\n    /// Sample clock\n    in clk: bit,\n    /// Current level\n    out level: u24,\n}",
+            "/// Registers are reset to zero\nstruct Regs {\n    /// Control register\n    ctrl: u8,\n    status: u8,\n}",
+            "/// Gray coded counter\nenum GrayU2 {\n    /// Reset state\n    Zero = 0b00,\n    One = 0b01,\n}",
+            "/// Constant\nconst X: u8 = 0;\n\n/// Function\nfn f() {}",
+            "trait Tr {\n    /// Associated constant\n    const X: u8;\n}",
+            "////// Slashes\nconst X: u8 = 0;"
+        );
+
+        let file = FileParser::new()
+            .parse("/// Sample clock\nentity Top { in clk: bit }")
+            .unwrap();
+        if let Item::Entity(entity) = &file.items[0] {
+            let doc = entity.attrs[0].doc().unwrap();
+            assert_eq!(doc.inner, " Sample clock");
+            assert_eq!(doc.span, Span(0, 16));
+        } else {
+            panic!("expected an entity");
+        }
+    }
+
+    #[test]
+    fn file_parser_attributes() {
+        parse_file!(
+            "#[keep]\nentity Top { #[clock] in clk: bit, #[reset_value = 0] out audio: u24 = 0 }",
+            "#[synthesis(retime, effort = 3, 0)]\narch Top {\n    #[keep]\n    let level;\n    #[clock_domain(clk)]\n    when clk.posedge {}\n    #[instance::name = 1]\n    Sawtooth { clk }\n}",
+            "/// Registers\n#[packed]\nstruct Regs { #[reset_value = 0xFF] ctrl: u8 }",
+            "enum States { #[default] Idle, Busy }",
+            "fn f() {\n    #[keep]\n    let x = 0;\n}",
+            "#[keep]\n/// doc\nentity E {}",
+            "/// A\n#[keep]\n/// B\nconst X: u8 = 0;"
+        );
+
+        let file = FileParser::new()
+            .parse("#[keep]\n/// doc\nentity E {}")
+            .unwrap();
+        if let Item::Entity(entity) = &file.items[0] {
+            let attr = entity.attrs[0].attr().unwrap();
+            assert_eq!(format(attr.meta.path(), &Config::default()), "keep");
+            assert_eq!(entity.attrs[1].doc().unwrap().inner, " doc");
+        } else {
+            panic!("expected an entity");
+        }
+
+        // Printing keeps the order of the list rather than that of the spans
+        let mut file = FileParser::new()
+            .parse("/// A\n#[keep]\nconst X: u8 = 0;")
+            .unwrap();
+        if let Item::Const(item) = &mut file.items[0] {
+            item.attrs.reverse();
+        }
+        assert_eq!(
+            format(&file, &Config::default()),
+            "#[keep]\n/// A\nconst X: u8 = 0;"
+        );
+    }
+
+    #[test]
+    fn file_parser_contextual_keywords() {
+        parse_file!(
+            "entity Top { in test: bit, in tick: bit, out assert: bit }",
+            "fn f(assert_eq: u8) -> u8 {\n    let tick = assert_eq;\n    tick\n}",
+            "test tick for Top {\n    tick = 1;\n    tick tick * 2;\n    assert assert;\n    assert_eq test, tick;\n}"
+        );
+    }
+
+    #[test]
+    fn visit_mut_rewrites_in_place() {
+        use super::visit::{visit_expr_mut, VisitMut};
+
+        struct Rename;
+
+        impl VisitMut for Rename {
+            fn visit_expr_mut(&mut self, expr: &mut Expr) {
+                visit_expr_mut(self, expr);
+            }
+
+            fn visit_ident_mut(&mut self, ident: &mut Ident) {
+                if ident == "level" {
+                    ident.inner = "amplitude".to_string();
+                }
+            }
+        }
+
+        let mut file = FileParser::new()
+            .parse("arch Top { let level; when clk.posedge { self.audio = level >> 8 } }")
+            .unwrap();
+        Rename.visit_file_mut(&mut file);
+        assert_eq!(
+            format(&file, &Config::default()),
+            "arch Top {\n    let amplitude;\n    when clk.posedge {\n        self.audio = amplitude >> 8\n    }\n}"
+        );
+    }
+
+    #[test]
+    fn fold_rebuilds_owned_tree() {
+        use super::fold::{fold_expr, Fold};
+
+        struct Ungroup;
+
+        impl Fold for Ungroup {
+            fn fold_expr(&mut self, expr: Expr) -> Expr {
+                match fold_expr(self, expr) {
+                    Expr::Grouped(grouped) => *grouped.expr,
+                    expr => expr,
+                }
+            }
+        }
+
+        let file = FileParser::new()
+            .parse("fn f(a: u8, b: u8) -> u8 { ((a) + (b)) }")
+            .unwrap();
+        assert_eq!(
+            format(&Ungroup.fold_file(file), &Config::default()),
+            "fn f(a: u8, b: u8) -> u8 {\n    a + b\n}"
+        );
+    }
+
+    #[test]
+    fn token_spans_count_escaped_braces_once() {
+        let file = FileParser::new().parse("arch Top { }").unwrap();
+        let spans = file
+            .to_tokens()
+            .iter()
+            .map(|tok| (tok.to_string(), tok.span()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            spans,
+            vec![
+                ("arch".to_string(), Span(0, 4)),
+                ("Top".to_string(), Span(5, 8)),
+                ("{".to_string(), Span(9, 10)),
+                ("}".to_string(), Span(11, 12)),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_error_diagnostic() {
+        let source = "entity Top { in clk: bit }\narch Top { , }";
+        let file = FileParser::new().parse(source).unwrap();
+        let diagnostic = diagnostics::recovered(&file)[0].clone();
+        assert_eq!(diagnostic.message, "unexpected `,`");
+        assert_eq!(diagnostic.span, Span(38, 39));
+        assert!(diagnostic.expected.contains(&"`}`".to_string()));
+        assert!(diagnostic.expected.contains(&"`let`".to_string()));
+        assert!(diagnostic.expected.contains(&"identifier".to_string()));
+        assert!(!diagnostic.expected.iter().any(|e| e.starts_with("Tok")));
+
+        let mut source_map = SourceMap::new();
+        let file = source_map.add("top.rhdl", source);
+        let rendered = diagnostic.in_file(file).render(&source_map);
+        assert!(rendered.starts_with("error: unexpected `,`, expected one of "));
+        assert_eq!(
+            rendered.lines().skip(1).collect::<Vec<_>>(),
+            vec![
+                " --> top.rhdl:2:12",
+                "  |",
+                "2 | arch Top { , }",
+                "  |            ^"
+            ]
+        );
+
+        let file = FileParser::new().parse("entity Top {").unwrap();
+        let diagnostic = diagnostics::recovered(&file)[0].clone();
+        assert_eq!(diagnostic.message, "unexpected end of file");
+        assert!(diagnostic.expected.contains(&"`in`".to_string()));
+    }
+
+    #[test]
+    fn file_parser_recovers_from_errors() {
+        let source = "arch Top { , }\nentity Next { in clk: bit }\nfn f() { let x = ; let y = 1; }";
+        let file = FileParser::new().parse(source).unwrap();
+        assert!(matches!(file.items[0], Item::Error(_)));
+        assert!(matches!(file.items[1], Item::Entity(_)));
+        assert!(matches!(file.items[2], Item::Fn(_)));
+
+        let errors = diagnostics::recovered(&file);
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].message, "unexpected `,`");
+        assert_eq!(errors[0].span, Span(11, 12));
+        assert_eq!(errors[1].message, "unexpected `;`");
+        assert_eq!(errors[1].span, Span(60, 61));
+    }
+
+    #[test]
+    fn source_map_line_col() {
+        let mut source_map = SourceMap::new();
+        let top = source_map.add("top.rhdl", "entity Top {\r\n    in clk: bit\n}");
+        let unicode = source_map.add("größe.rhdl", "const X: u8 = 0;\nconst GRÖẞE: u8 = X;\n");
+        assert_ne!(top, unicode);
+
+        let file = source_map.get(top);
+        assert_eq!(file.line_col(0), LineCol { line: 1, column: 1 });
+        assert_eq!(file.line_col(18), LineCol { line: 2, column: 5 });
+        assert_eq!(file.line(1), "entity Top {");
+        assert_eq!(file.line(3), "}");
+        assert_eq!(file.line_col(100), LineCol { line: 3, column: 2 });
+
+        let file = source_map.get(unicode);
+        assert_eq!(file.name(), std::path::Path::new("größe.rhdl"));
+        let eq = file.source().rfind('=').unwrap();
+        assert_eq!(
+            source_map.resolve(&FileSpan {
+                file: unicode,
+                span: Span(eq, eq + 1)
+            }),
+            (
+                LineCol {
+                    line: 2,
+                    column: 17
+                },
+                LineCol {
+                    line: 2,
+                    column: 18
+                }
+            )
+        );
+        assert_eq!(
+            file.line_col(eq + 2),
+            LineCol {
+                line: 2,
+                column: 19
+            }
+        );
+        assert_eq!(
+            file.line_col(file.source().len()),
+            LineCol { line: 3, column: 1 }
+        );
+    }
+
+    /// A directory for a test to write files to, which is removed when the test ends even if it
+    /// panics
+    struct TempDir(std::path::PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("rhdl-{}-{}", name, std::process::id()));
+            let _ = std::fs::remove_dir_all(&path);
+            std::fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+    }
+
+    impl std::ops::Deref for TempDir {
+        type Target = std::path::Path;
+
+        fn deref(&self) -> &Self::Target {
+            &self.0
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn loader_follows_module_files() {
+        use super::loader::Crate;
+        use std::fs;
+
+        let root = TempDir::new("loader");
+        for (path, source) in [
+            (
+                "top.rhdl",
+                "mod alu;\nmod regs;\nmod inline { mod deep; }\nmod missing;",
+            ),
+            ("alu.rhdl", "mod adder;\nfn alu() { }"),
+            ("alu/adder.rhdl", "fn add() { }"),
+            ("regs/mod.rhdl", "struct Regs { x: u8 }"),
+            ("inline/deep.rhdl", "const X: u8 = 0;"),
+        ] {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, source).unwrap();
+        }
+
+        let err = Crate::load(root.join("top.rhdl")).unwrap_err();
+        assert_eq!(err.diagnostics.len(), 1);
+        assert!(err.diagnostics[0]
+            .message
+            .starts_with("file not found for module `missing`"));
+        assert!(err.to_string().contains("top.rhdl:4:5"));
+
+        fs::write(root.join("missing.rhdl"), "").unwrap();
+        fs::create_dir_all(root.join("missing")).unwrap();
+        fs::write(root.join("missing/mod.rhdl"), "").unwrap();
+        let err = Crate::load(root.join("top.rhdl")).unwrap_err();
+        assert!(err.diagnostics[0]
+            .message
+            .starts_with("file for module `missing` found at both"));
+
+        fs::remove_file(root.join("missing/mod.rhdl")).unwrap();
+        let krate = Crate::load(root.join("top.rhdl")).unwrap();
+        let names = |module: &super::loader::Module| {
+            module
+                .children
+                .iter()
+                .map(|child| child.name.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&krate.root), vec!["alu", "regs", "inline", "missing"]);
+        assert_eq!(names(krate.root.child("alu").unwrap()), vec!["adder"]);
+        assert_eq!(names(krate.root.child("inline").unwrap()), vec!["deep"]);
+        let deep = krate.root.child("inline").unwrap().child("deep").unwrap();
+        assert_eq!(
+            krate.source_map.get(deep.file).name(),
+            root.join("inline/deep.rhdl")
+        );
+        assert_eq!(krate.source_map.files().count(), 6);
+    }
+
+    /// Module files can only reach each other through symlinks, since a module's own files are
+    /// always in a directory below it
+    #[cfg(unix)]
+    #[test]
+    fn loader_rejects_cycles_and_duplicates() {
+        use super::loader::Crate;
+        use std::fs;
+        use std::os::unix::fs::symlink;
+
+        let root = TempDir::new("loader-cycle");
+        fs::create_dir_all(root.join("b")).unwrap();
+        fs::write(root.join("a.rhdl"), "mod b;").unwrap();
+        fs::write(root.join("b.rhdl"), "mod a;").unwrap();
+        symlink(root.join("a.rhdl"), root.join("b/a.rhdl")).unwrap();
+        let err = Crate::load(root.join("a.rhdl")).unwrap_err();
+        assert_eq!(err.diagnostics.len(), 1);
+        assert_eq!(
+            err.diagnostics[0].message,
+            format!(
+                "module file `{}` includes itself",
+                root.join("b/a.rhdl").display()
+            )
+        );
+        assert!(err.to_string().contains("b.rhdl:1:5"));
+
+        let root = TempDir::new("loader-twice");
+        fs::write(root.join("top.rhdl"), "mod a;\nmod b;").unwrap();
+        fs::write(root.join("a.rhdl"), "const X: u8 = 0;").unwrap();
+        symlink(root.join("a.rhdl"), root.join("b.rhdl")).unwrap();
+        let err = Crate::load(root.join("top.rhdl")).unwrap_err();
+        assert_eq!(err.diagnostics.len(), 1);
+        assert_eq!(
+            err.diagnostics[0].message,
+            format!(
+                "module file `{}` is already loaded as another module",
+                root.join("b.rhdl").display()
+            )
+        );
+        assert!(err.to_string().contains("top.rhdl:2:5"));
+    }
+
+    /// Path of the fixture at `tests/golden/{name}`
+    fn fixture(name: &str) -> std::path::PathBuf {
+        std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/golden")
+            .join(name)
+    }
+
+    /// Messages of the first stage that rejects the fixture at `tests/golden/{name}`, running
+    /// the pipeline from loading up to lowering the netlist of its `Top`
+    fn diagnostics_for(name: &str) -> Vec<String> {
+        use super::const_eval::eval;
+        use super::elaborate::elaborate;
+        use super::loader::Crate;
+        use super::netlist::lower;
+        use super::resolve::resolve;
+        use super::typeck::check;
+
+        let krate = Crate::load(fixture(name)).unwrap();
+        let diagnostics = resolve(&krate).and_then(|resolutions| {
+            super::arch_check::check(&krate, &resolutions)?;
+            let types = check(&krate, &resolutions)?;
+            eval(&krate, &resolutions, &types)?;
+            let top = elaborate(&krate, &resolutions, &types, "Top")?;
+            lower(&krate, &resolutions, &types, &top)
+        });
+        diagnostics
+            .expect_err("expected the fixture to be rejected")
+            .into_iter()
+            .map(|diagnostic| diagnostic.message)
+            .collect()
+    }
+
+    /// Netlist of the fixture at `tests/golden/{name}`, elaborated from its `Top`
+    fn netlist_for(name: &str) -> super::netlist::Netlist {
+        use super::elaborate::elaborate;
+        use super::loader::Crate;
+        use super::netlist::lower;
+        use super::resolve::resolve;
+        use super::typeck::check;
+
+        let krate = Crate::load(fixture(name)).unwrap();
+        let resolutions = resolve(&krate).unwrap();
+        let types = check(&krate, &resolutions).unwrap();
+        let top = elaborate(&krate, &resolutions, &types, "Top").unwrap();
+        lower(&krate, &resolutions, &types, &top).unwrap()
+    }
+
+    #[test]
+    fn resolver_resolves_paths() {
+        use super::loader::Crate;
+        use super::resolve::{resolve, DefKind, Res};
+
+        let root = fixture("resolve");
+
+        let krate = Crate::load(root.join("top.rhdl")).unwrap();
+        let resolutions = resolve(&krate).unwrap();
+        let source = krate.source_map.get(krate.root.file).source();
+        let resolved = |text: &str| {
+            let mut found = resolutions
+                .paths()
+                .filter(|((file, span), _)| {
+                    *file == krate.root.file && &source[span.0..span.1] == text
+                })
+                .map(|(_, resolution)| resolution.res.clone())
+                .collect::<Vec<_>>();
+            found.dedup();
+            assert_eq!(found.len(), 1, "`{}` resolved to {:?}", text, found);
+            found.remove(0)
+        };
+        let def = |res: Res| match res {
+            Res::Def(id) => {
+                let def = resolutions.def(id);
+                (
+                    def.kind,
+                    resolutions.module_path(def.module),
+                    def.name.clone(),
+                )
+            }
+            res => panic!("expected a definition, found {:?}", res),
+        };
+
+        assert_eq!(
+            def(resolved("Adder")),
+            (
+                DefKind::Entity,
+                "crate::alu".to_string(),
+                "Adder".to_string()
+            )
+        );
+        assert_eq!(
+            def(resolved("One")),
+            (
+                DefKind::Const,
+                "crate::alu::ops".to_string(),
+                "ONE".to_string()
+            )
+        );
+        assert_eq!(
+            def(resolved("alu::helper")),
+            (
+                DefKind::Fn,
+                "crate::alu::ops".to_string(),
+                "helper".to_string()
+            )
+        );
+        assert_eq!(
+            def(resolved("Top")),
+            (DefKind::Entity, "crate".to_string(), "Top".to_string())
+        );
+        assert!(matches!(resolved("clk"), Res::Port { index: 0, .. }));
+        assert!(matches!(resolved("a"), Res::Port { index: 1, .. }));
+        assert!(matches!(resolved("carry"), Res::Local(_)));
+        assert!(matches!(resolved("self"), Res::SelfValue));
+        assert!(matches!(resolved("T"), Res::GenericParam(_)));
+        assert!(matches!(resolved("x"), Res::Local(_)));
+        assert!(matches!(resolved("y"), Res::Local(_)));
+        assert_eq!(resolved("u8"), Res::Primitive("u8".to_string()));
+
+        assert_eq!(
+            diagnostics_for("resolve/bad.rhdl"),
+            vec![
+                "`Z` is private",
+                "`ops` is private",
+                "unresolved import, cannot find `missing` in `alu`",
+                "`X` is ambiguous, more than one glob imports it",
+                "cannot find `nothing` in this scope",
+            ]
+        );
+    }
+
+    #[test]
+    fn type_checker_checks_widths() {
+        use super::ast::Span;
+        use super::loader::Crate;
+        use super::resolve::{resolve, DefId};
+        use super::ty::Ty;
+        use super::typeck::{check, TypeContext};
+
+        let root = fixture("typeck");
+
+        let krate = Crate::load(root.join("good.rhdl")).unwrap();
+        let resolutions = resolve(&krate).unwrap();
+        let types = check(&krate, &resolutions).unwrap();
+        let source = krate.source_map.get(krate.root.file).source();
+        let local = |name: &str| {
+            let start = source.find(&format!("let {}", name)).unwrap() + 4;
+            let span = Span(start, start + name.len());
+            types.local(krate.root.file, &span).unwrap().to_string()
+        };
+        assert_eq!(local("x"), "u8");
+        assert_eq!(local("t"), "(u8, i4)");
+        assert_eq!(local("low"), "u4");
+        assert_eq!(local("y"), "i4");
+        assert_eq!(local("next"), "u16");
+
+        let cx = TypeContext::new(&krate, &resolutions);
+        let width = |name: &str| {
+            let id = resolutions
+                .defs
+                .iter()
+                .position(|def| def.name == name)
+                .unwrap();
+            let def = resolutions.def(DefId(id));
+            cx.width(&Ty::Adt {
+                def: DefId(id),
+                name: def.name.clone(),
+                args: vec![],
+            })
+        };
+        assert_eq!(width("Pixel"), Some(24));
+        assert_eq!(width("Op"), Some(2 + 7));
+
+        assert_eq!(
+            diagnostics_for("typeck/bad.rhdl"),
+            vec![
+                "`-1` is not a valid length or index",
+                "mismatched widths: expected `u8`, found `u12`",
+                "mismatched signedness: expected `u8`, found `i8`",
+                "literal out of range for `u4`, whose range is `0..=15`",
+                "mismatched signedness: cannot negate unsigned `u8`",
+                "literal out of range for `i4`, whose range is `-8..=7`",
+                "mismatched signedness: `<<` expects an unsigned amount, found `i8`",
+            ]
+        );
+    }
+
+    #[test]
+    fn type_checker_checks_tests() {
+        use super::loader::Crate;
+        use super::resolve::resolve;
+        use super::typeck::check;
+
+        let krate = Crate::load(fixture("typeck/bad_tests.rhdl")).unwrap();
+        let source = krate.source_map.get(krate.root.file).source();
+        let resolutions = resolve(&krate).unwrap();
+        let diagnostics = check(&krate, &resolutions).unwrap_err();
+        let errors = diagnostics
+            .iter()
+            .map(|diagnostic| {
+                (
+                    diagnostic.message.as_str(),
+                    &source[diagnostic.span.0..diagnostic.span.1],
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            errors,
+            [
+                ("entity `Top` has no port named `carry`", "carry"),
+                ("`sum` is not an input of entity `Top`", "sum"),
+                ("mismatched types: expected `bool`, found `{integer}`", "3"),
+                ("`en` is not a clock: expected `bit`, found `bool`", "en"),
+                (
+                    "mismatched types: expected an integer, found `bool`",
+                    "true"
+                ),
+                (
+                    "mismatched types: expected `bool` or `bit`, found `i8`",
+                    "x"
+                ),
+                ("mismatched types: expected `i8`, found `bool`", "true"),
+            ]
+        );
+    }
+
+    #[test]
+    fn const_eval_evaluates_consts() {
+        use super::const_eval::eval;
+        use super::loader::Crate;
+        use super::resolve::resolve;
+        use super::typeck::check;
+
+        let root = fixture("const_eval");
+
+        let krate = Crate::load(root.join("good.rhdl")).unwrap();
+        let resolutions = resolve(&krate).unwrap();
+        let types = check(&krate, &resolutions).unwrap();
+        let consts = eval(&krate, &resolutions, &types).unwrap();
+        let value = |name: &str| {
+            let id = resolutions
+                .defs
+                .iter()
+                .position(|def| def.name == name)
+                .unwrap();
+            consts.get(super::resolve::DefId(id)).unwrap().to_string()
+        };
+        assert_eq!(value("ROM_SIZE"), "67108864");
+        assert_eq!(value("WIDTH"), "26");
+        assert_eq!(value("MASK"), "240");
+        assert_eq!(value("PAIR"), "Pair { a: 3, b: 240 }");
+        assert_eq!(value("SUM"), "243");
+        assert_eq!(value("SELECT"), "10");
+        assert_eq!(value("LOW"), "11");
+        assert_eq!(value("BIT"), "1");
+        assert_eq!(value("MIN"), "-128");
+        assert_eq!(value("SCALED"), "40");
+        assert_eq!(value("LINE"), "[7, 7, 7, 7]");
+        assert_eq!(value("Sub"), "2");
+
+        assert_eq!(
+            diagnostics_for("const_eval/bad.rhdl"),
+            vec![
+                "cycle detected when evaluating `A`: `A` -> `B` -> `A`",
+                "attempt to compute `200 + 100`, which would overflow `u8`",
+                "attempt to compute `1 / 0`, which divides by zero",
+                "attempt to shift left by `4`, which would overflow `i4`",
+                "attempt to compute `200 * 2`, which would overflow `u8`",
+                "attempt to negate `-128`, which would overflow `i8`",
+            ]
+        );
+    }
+
+    #[test]
+    fn arch_check_checks_ports() {
+        use super::arch_check::check;
+        use super::loader::Crate;
+        use super::resolve::resolve;
+
+        let root = fixture("arch_check");
+
+        let krate = Crate::load(root.join("good.rhdl")).unwrap();
+        let resolutions = resolve(&krate).unwrap();
+        check(&krate, &resolutions).unwrap();
+
+        assert_eq!(
+            diagnostics_for("arch_check/bad.rhdl"),
+            vec![
+                "cannot assign to `in` port `a`",
+                "`out` port `sum` can only be written through `self.sum`",
+                "`out` port `carry` of `Adder` is never driven",
+                "entity `Top` has more than one arch",
+                "port `clk` is connected more than once",
+                "`out` port `sum` of `Adder` must be connected to a signal or port",
+                "cannot assign to `in` port `x`",
+                "missing `b` in instantiation of `Adder`",
+                "`out` port `y` of `Top` is never driven",
+                "entity `Lonely` has no arch",
+            ]
+        );
+    }
+
+    #[test]
+    fn elaborate_builds_instance_tree() {
+        use super::elaborate::elaborate;
+        use super::loader::Crate;
+        use super::resolve::resolve;
+        use super::typeck::check;
+
+        let root = fixture("elaborate");
+
+        let krate = Crate::load(root.join("good.rhdl")).unwrap();
+        let resolutions = resolve(&krate).unwrap();
+        let types = check(&krate, &resolutions).unwrap();
+        let top = elaborate(&krate, &resolutions, &types, "crate::Top").unwrap();
+        assert_eq!(
+            top.to_string(),
+            "top: Top\n  reg_0: Reg<u8, 3>\n  reg_1: Reg<u16, 1>\n"
+        );
+        let paths = top
+            .instances()
+            .iter()
+            .map(|instance| instance.path.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, vec!["top", "top.reg_0", "top.reg_1"]);
+        let reg = &top.children[1];
+        assert_eq!(reg.port("q").unwrap().width, 16);
+        let twice = reg
+            .env
+            .locals
+            .values()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(twice, vec!["2"]);
+        let messages = elaborate(&krate, &resolutions, &types, "Missing")
+            .unwrap_err()
+            .into_iter()
+            .map(|diagnostic| diagnostic.message)
+            .collect::<Vec<_>>();
+        assert_eq!(messages, vec!["cannot find an entity named `Missing`"]);
+
+        assert_eq!(
+            diagnostics_for("elaborate/bad.rhdl"),
+            vec!["entity `Loop` instantiates itself"]
+        );
+    }
+
+    #[test]
+    fn netlist_lowers_archs() {
+        let netlist = netlist_for("netlist/good.rhdl");
+        assert_eq!(
+            netlist.to_string(),
+            "module Top {\n  in clk: bit\n  in x: u8\n  out y: u8\n  out low: u4\n  wire op: Op\n  wire nibble: u4\n  wire acc_0_en: bool\n  %0: Op = const 4\n  %1: bool = const 1\n  %2: u8 = x\n  %3: u4 = slice %2[0..4]\n  %4: u4 = nibble\n  assign acc_0_en = %1\n  assign op = %0\n  assign nibble = %3\n  always {\n    low = %4\n  }\n  inst acc_0: Acc { clk: clk, en: acc_0_en, op: op, x: x, sum: y }\n}\n\nmodule Acc {\n  in clk: bit\n  in en: bool\n  in op: Op\n  in x: u8\n  out reg sum: u8 = 0\n  %0: bool = en\n  %1: Op = op\n  %2: u8 = sum\n  %3: u8 = x\n  %4: u3 = const 1\n  %5: bool = eq %1, %4\n  %6: u8 = add %2, %3\n  %7: u3 = const 4\n  %8: bool = eq %1, %7\n  %9: u8 = sub %2, %3\n  %10: u8 = mux %8, %9, %2\n  %11: u8 = mux %5, %6, %10\n  %12: u8 = mux %0, %11, %2\n  always posedge clk {\n    sum <= %12\n  }\n}\n"
+        );
+        assert_eq!(netlist.module(netlist.top).instances[0].module.0, 1);
+
+        assert_eq!(
+            diagnostics_for("netlist/bad.rhdl"),
+            vec!["`b` has more than one driver"]
+        );
+        assert_eq!(
+            diagnostics_for("netlist/tags.rhdl"),
+            vec!["`B` has the same discriminant `1` as `A`"]
+        );
+    }
+
+    #[test]
+    fn array_lengths_are_evaluated() {
+        let netlist = netlist_for("delay.rhdl");
+        let widths = |module: &super::netlist::Module| {
+            module
+                .ports
+                .iter()
+                .map(|port| (module.net(*port).name.clone(), module.net(*port).width))
+                .collect::<Vec<_>>()
+        };
+        let top = netlist.module(netlist.top);
+        assert_eq!(
+            widths(top),
+            vec![
+                ("clk".to_string(), 1),
+                ("x".to_string(), 8),
+                ("y".to_string(), 8),
+                ("taps".to_string(), 32),
+            ]
+        );
+        assert_eq!(
+            widths(netlist.module(top.instances[0].module)),
+            vec![
+                ("clk".to_string(), 1),
+                ("d".to_string(), 8),
+                ("q".to_string(), 8),
+                ("taps".to_string(), 32),
+            ]
+        );
+    }
+
+    #[test]
+    fn verilog_emits_golden_designs() {
+        let verilog = super::verilog::emit(&netlist_for("sawtooth.rhdl"));
+        assert_eq!(verilog, include_str!("../tests/golden/sawtooth.v"));
+        let verilog = super::verilog::emit(&netlist_for("gray.rhdl"));
+        assert_eq!(verilog, include_str!("../tests/golden/gray.v"));
+        let verilog = super::verilog::emit(&netlist_for("pixels.rhdl"));
+        assert_eq!(verilog, include_str!("../tests/golden/pixels.v"));
+        // Constant slices fold away in lowering, and a constant indexed by a signal gets a wire
+        let verilog = super::verilog::emit(&netlist_for("slices.rhdl"));
+        assert_eq!(verilog, include_str!("../tests/golden/slices.v"));
+    }
+
+    #[test]
+    fn vhdl_emits_golden_designs() {
+        let vhdl = super::vhdl::emit(&netlist_for("sawtooth.rhdl"));
+        assert_eq!(vhdl, include_str!("../tests/golden/sawtooth.vhd"));
+        let vhdl = super::vhdl::emit(&netlist_for("gray.rhdl"));
+        assert_eq!(vhdl, include_str!("../tests/golden/gray.vhd"));
+        let vhdl = super::vhdl::emit(&netlist_for("divider.rhdl"));
+        assert_eq!(vhdl, include_str!("../tests/golden/divider.vhd"));
+    }
+
+    #[test]
+    fn systemverilog_emits_golden_designs() {
+        let sv = super::systemverilog::emit(&netlist_for("sawtooth.rhdl"));
+        assert_eq!(sv, include_str!("../tests/golden/sawtooth.sv"));
+        let sv = super::systemverilog::emit(&netlist_for("gray.rhdl"));
+        assert_eq!(sv, include_str!("../tests/golden/gray.sv"));
+        let sv = super::systemverilog::emit(&netlist_for("pixels.rhdl"));
+        assert_eq!(sv, include_str!("../tests/golden/pixels.sv"));
+    }
+
+    #[test]
+    fn firrtl_emits_golden_designs() {
+        let fir = super::firrtl::emit(&netlist_for("sawtooth.rhdl"));
+        assert_eq!(fir, include_str!("../tests/golden/sawtooth.fir"));
+        let fir = super::firrtl::emit(&netlist_for("gray.rhdl"));
+        assert_eq!(fir, include_str!("../tests/golden/gray.fir"));
+        let fir = super::firrtl::emit(&netlist_for("pixels.rhdl"));
+        assert_eq!(fir, include_str!("../tests/golden/pixels.fir"));
+        let fir = super::firrtl::emit(&netlist_for("accumulator.rhdl"));
+        assert_eq!(fir, include_str!("../tests/golden/accumulator.fir"));
+        // Registers come after the wires and instances that their clocks are derived from
+        let fir = super::firrtl::emit(&netlist_for("divider.rhdl"));
+        assert_eq!(fir, include_str!("../tests/golden/divider.fir"));
+    }
+
+    #[test]
+    fn simulator_runs_golden_designs() {
+        use super::sim::{SimError, Simulator};
+        use rug::Integer;
+
+        let netlist = netlist_for("sawtooth.rhdl");
+        let mut sim = Simulator::new(&netlist);
+        for _ in 0..300 {
+            sim.set("clk", 1).unwrap();
+            sim.step().unwrap();
+            sim.set("clk", 0).unwrap();
+            sim.step().unwrap();
+        }
+        assert_eq!(sim.get("sawtooth_0.level").unwrap(), 300);
+        assert_eq!(sim.get("audio").unwrap(), 299 >> 8);
+        assert_eq!(
+            sim.set("audio", 0),
+            Err(SimError::NotAnInput("audio".to_string()))
+        );
+        assert_eq!(
+            sim.get("sawtooth_0.nope"),
+            Err(SimError::UnknownNet("sawtooth_0.nope".to_string()))
+        );
+
+        let netlist = netlist_for("gray.rhdl");
+        let mut sim = Simulator::new(&netlist);
+        let mut states = vec![];
+        for cycle in 0..6 {
+            sim.set("reset", (cycle == 4) as u8).unwrap();
+            sim.set("clk", 1).unwrap();
+            sim.step().unwrap();
+            states.push(sim.get("state").unwrap());
+            sim.set("clk", 0).unwrap();
+            sim.step().unwrap();
+        }
+        assert_eq!(states, [1, 3, 2, 0, 0, 1]);
+
+        let netlist = netlist_for("accumulator.rhdl");
+        let mut sim = Simulator::new(&netlist);
+        sim.set("x", -3).unwrap();
+        for en in [true, true, false, true] {
+            sim.set("en", en as u8).unwrap();
+            sim.set("clk", 1).unwrap();
+            sim.step().unwrap();
+            sim.set("clk", 0).unwrap();
+            sim.step().unwrap();
+        }
+        assert_eq!(sim.get("sum").unwrap(), Integer::from(-9));
+        assert_eq!(sim.get("negative").unwrap(), 1);
+    }
+
+    #[test]
+    fn vcd_dumps_golden_designs() {
+        use super::sim::Simulator;
+        use super::vcd::VcdWriter;
+
+        let netlist = netlist_for("gray.rhdl");
+        let mut sim = Simulator::new(&netlist);
+        let mut vcd = VcdWriter::new(vec![], &sim, &[]).unwrap();
+        vcd.sample(&sim).unwrap();
+        for cycle in 0..6 {
+            match cycle {
+                2 => vcd.stop(&sim).unwrap(),
+                4 => vcd.start(&sim).unwrap(),
+                _ => {}
+            }
+            sim.set("reset", (cycle == 4) as u8).unwrap();
+            sim.set("clk", 1).unwrap();
+            sim.step().unwrap();
+            vcd.sample(&sim).unwrap();
+            sim.set("clk", 0).unwrap();
+            sim.step().unwrap();
+            vcd.sample(&sim).unwrap();
+        }
+        assert_eq!(
+            String::from_utf8(vcd.into_inner()).unwrap(),
+            include_str!("../tests/golden/gray.vcd")
+        );
+
+        let netlist = netlist_for("sawtooth.rhdl");
+        let mut sim = Simulator::new(&netlist);
+        let mut vcd = VcdWriter::new(vec![], &sim, &["*.lev?l", "clk"]).unwrap();
+        for _ in 0..2 {
+            sim.set("clk", 1).unwrap();
+            sim.step().unwrap();
+            vcd.sample(&sim).unwrap();
+            sim.set("clk", 0).unwrap();
+            sim.step().unwrap();
+            vcd.sample(&sim).unwrap();
+        }
+        assert_eq!(
+            String::from_utf8(vcd.into_inner()).unwrap(),
+            include_str!("../tests/golden/sawtooth.vcd")
+        );
+    }
+
+    #[test]
+    fn testbench_runs_tests() {
+        use super::loader::Crate;
+        use super::testbench::{run, tests};
+        use std::fs;
+
+        let path = fixture("testbench/top.rhdl");
+        let source = fs::read_to_string(&path).unwrap();
+        let file = FileParser::new().parse(&source).unwrap();
+        let names = tests(&file)
+            .iter()
+            .map(|test| test.ident.inner.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["sums", "holds", "unknown", "huge"]);
+
+        let krate = Crate::load(path).unwrap();
+        let results = run(&krate).unwrap();
+        let outcomes = results
+            .iter()
+            .map(|result| {
+                let failures = result
+                    .failures
+                    .iter()
+                    .map(|failure| {
+                        (
+                            failure.message.as_str(),
+                            &source[failure.span.0..failure.span.1],
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                (result.name.as_str(), failures)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            outcomes,
+            [
+                ("crate::sums", vec![]),
+                (
+                    "crate::checks::holds",
+                    vec![(
+                        "assertion failed, left is `1` and right is `2`",
+                        "assert_eq sum + 1, 2;"
+                    )]
+                ),
+                (
+                    "crate::checks::unknown",
+                    vec![("there is no net named `carry`", "carry")]
+                ),
+                (
+                    "crate::checks::huge",
+                    vec![("the value is wider than 1048576 bits", "2 ** 4000000")]
+                ),
+            ]
+        );
+        assert!(results[0].passed() && !results[1].passed());
+    }
+
+    #[test]
+    fn fmt_wraps_lists_to_width() {
+        let file = FileParser::new()
+            .parse("entity Top { in clk: bit, out level: u24 = 0 } arch Top { when clk.posedge { self.level = f(self.level, 1) } }")
+            .unwrap();
+        let narrow = |trailing_comma| Config {
+            indent: 2,
+            width: 24,
+            trailing_comma,
+        };
+        assert_eq!(
+            format(&file, &Config::default()),
+            "entity Top { in clk: bit, out level: u24 = 0 }\n\narch Top {\n    when clk.posedge {\n        self.level = f(self.level, 1)\n    }\n}"
+        );
+        assert_eq!(
+            format(&file, &narrow(TrailingComma::Vertical)),
+            "entity Top {\n  in clk: bit,\n  out level: u24 = 0,\n}\n\narch Top {\n  when clk.posedge {\n    self.level = f(\n      self.level,\n      1\n    )\n  }\n}"
+        );
+        assert_eq!(
+            format(&file, &narrow(TrailingComma::Never)),
+            "entity Top {\n  in clk: bit,\n  out level: u24 = 0\n}\n\narch Top {\n  when clk.posedge {\n    self.level = f(\n      self.level,\n      1\n    )\n  }\n}"
+        );
+        assert_eq!(
+            format(
+                &file,
+                &Config {
+                    trailing_comma: TrailingComma::Always,
+                    ..Config::default()
+                }
+            ),
+            "entity Top { in clk: bit, out level: u24 = 0, }\n\narch Top {\n    when clk.posedge {\n        self.level = f(self.level, 1)\n    }\n}"
+        );
+    }
+
+    #[test]
+    fn fmt_fits_lines_with_code_after_groups() {
+        let file = FileParser::new()
+            .parse("fn long(first_argument: u8, second_argument: u8, third_argument: u8, fourth_argument: u8) -> u8 { call_something(first_argument, second_argument, third_argument, fourth_argument) + another(first_argument) }")
+            .unwrap();
+        for width in [100, 60, 40] {
+            let config = Config {
+                width,
+                ..Config::default()
+            };
+            let formatted = format(&file, &config);
+            assert!(formatted.lines().all(|line| line.chars().count() <= width), "{}", formatted);
+            let reparsed = FileParser::new().parse(&formatted).unwrap();
+            assert_eq!(format(&reparsed, &config), formatted);
+        }
+        assert_eq!(
+            format(&file, &Config::default()),
+            "fn long(first_argument: u8, second_argument: u8, third_argument: u8, fourth_argument: u8) -> u8 {\n    call_something(\n        first_argument,\n        second_argument,\n        third_argument,\n        fourth_argument\n    ) + another(first_argument)\n}"
+        );
+    }
+
+    #[test]
+    fn fmt_is_idempotent() {
+        let configs = [
+            Config::default(),
+            Config {
+                indent: 2,
+                width: 40,
+                trailing_comma: TrailingComma::Never,
+            },
+            Config {
+                indent: 8,
+                width: 60,
+                trailing_comma: TrailingComma::Always,
+            },
+            Config {
+                width: 0,
+                ..Config::default()
+            },
+        ];
+        for source in [
+            include_str!("../tests/golden/sawtooth.rhdl"),
+            include_str!("../tests/golden/gray.rhdl"),
+            include_str!("../tests/golden/pixels.rhdl"),
+            include_str!("../tests/golden/accumulator.rhdl"),
+            "struct Pair<T> { a: Wrap<Wrap<T> >, b: [Wrap<u8>; 4] }\nfn f<T>(x: Wrap<Wrap<T> >) -> [bool; 2] where Wrap<Wrap<T> >: Default { [N < 4, x > -1] }",
+        ] {
+            let file = FileParser::new().parse(source).unwrap();
+            assert!(diagnostics::recovered(&file).is_empty());
+            for config in &configs {
+                let formatted = format(&file, config);
+                let reparsed = FileParser::new().parse(&formatted).unwrap();
+                assert!(diagnostics::recovered(&reparsed).is_empty());
+                assert_eq!(format(&reparsed, config), formatted);
+            }
+        }
+    }
+
+    #[test]
+    fn fmt_tells_generics_from_comparisons() {
+        let file = FileParser::new()
+            .parse("fn f(x: Wrap<Wrap<T> >) -> [bool; 3] { [N < 4, x > -1, g::<Wrap<u8> >()] }")
+            .unwrap();
+        assert!(diagnostics::recovered(&file).is_empty());
+        assert_eq!(
+            format(&file, &Config::default()),
+            "fn f(x: Wrap<Wrap<T> >) -> [bool; 3] {\n    [N < 4, x > -1, g::<Wrap<u8> >()]\n}"
+        );
+    }
+
+    #[test]
+    fn fmt_keeps_comments() {
+        use super::{cst::Cst, fmt::format_cst};
+
+        let source = "entity Top { in a: bit, // first\n in b: bit, // last\n}\n// The end\n";
+        let cst = Cst::new(source, &FileParser::new().parse(source).unwrap());
+        assert_eq!(
+            format_cst(&cst, &Config::default()),
+            "entity Top {\n    in a: bit, // first\n    in b: bit, // last\n}\n// The end"
+        );
+        let never = Config {
+            trailing_comma: TrailingComma::Never,
+            ..Config::default()
+        };
+        assert_eq!(
+            format_cst(&cst, &never),
+            "entity Top {\n    in a: bit, // first\n    in b: bit // last\n}\n// The end"
+        );
+
+        let source = include_str!("../tests/golden/comments.rhdl");
+        let cst = Cst::new(source, &FileParser::new().parse(source).unwrap());
+        let formatted = format_cst(&cst, &Config::default());
+        // Statements of a block go on consecutive lines, so only the blank line goes
+        assert_eq!(
+            formatted,
+            source
+                .replace(
+                    "    }\n\n    // Combinational",
+                    "    }\n    // Combinational"
+                )
+                .trim_end()
+        );
+        for config in [Config::default(), never] {
+            let formatted = format_cst(&cst, &config);
+            let reparsed = Cst::new(&formatted, &FileParser::new().parse(&formatted).unwrap());
+            assert_eq!(
+                reparsed.comments().map(|c| &c.text).collect::<Vec<_>>(),
+                cst.comments().map(|c| &c.text).collect::<Vec<_>>()
+            );
+            assert_eq!(format_cst(&reparsed, &config), formatted);
+        }
+    }
+
+    #[test]
+    fn cst_lexes_fixtures_as_parsed() {
+        use super::ast::token::Spanned;
+        use super::cst::{lex, Lexeme};
+
+        fn rhdl_files(dir: &std::path::Path, acc: &mut Vec<std::path::PathBuf>) {
+            for entry in std::fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    rhdl_files(&path, acc);
+                } else if path.extension().is_some_and(|ext| ext == "rhdl") {
+                    acc.push(path);
+                }
+            }
+        }
+
+        let mut paths = vec![];
+        rhdl_files(&fixture(""), &mut paths);
+        assert!(!paths.is_empty());
+        for path in paths {
+            let source = std::fs::read_to_string(&path).unwrap();
+            let lexemes = lex(&source);
+            let relexed = lexemes
+                .iter()
+                .map(|lexeme| match lexeme {
+                    Lexeme::Token(span) => &source[span.0..span.1],
+                    Lexeme::Trivia(trivia) => &trivia.text,
+                })
+                .collect::<String>();
+            assert_eq!(relexed, source, "{}", path.display());
+
+            let file = FileParser::new().parse(&source).unwrap();
+            if !diagnostics::recovered(&file).is_empty() {
+                continue;
+            }
+            let mut parsed = file
+                .to_tokens()
+                .iter()
+                .map(|tok| tok.span())
+                .collect::<Vec<_>>();
+            parsed.dedup();
+            let lexed = lexemes
+                .into_iter()
+                .filter_map(|lexeme| match lexeme {
+                    Lexeme::Token(span) => Some(span),
+                    Lexeme::Trivia(_) => None,
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(lexed, parsed, "{}", path.display());
+        }
+    }
+
+    #[test]
+    fn cst_round_trips_fixtures() {
+        use super::cst::{lex, Cst, Lexeme, Trivia, TriviaKind};
+
+        let trivia = |kind, text: &str, start| {
+            Lexeme::Trivia(Trivia {
+                kind,
+                text: text.to_string(),
+                span: Span(start, start + text.len()),
+            })
+        };
+        assert_eq!(
+            lex("x // c\n/// d\n`"),
+            [
+                Lexeme::Token(Span(0, 1)),
+                trivia(TriviaKind::Whitespace, " ", 1),
+                trivia(TriviaKind::Comment, "// c", 2),
+                trivia(TriviaKind::Whitespace, "\n", 6),
+                Lexeme::Token(Span(7, 12)),
+                trivia(TriviaKind::Whitespace, "\n", 12),
+                trivia(TriviaKind::Skipped, "`", 13),
+            ]
+        );
+
+        for source in [
+            include_str!("../tests/golden/sawtooth.rhdl"),
+            include_str!("../tests/golden/gray.rhdl"),
+            include_str!("../tests/golden/pixels.rhdl"),
+            include_str!("../tests/golden/accumulator.rhdl"),
+            include_str!("../tests/golden/comments.rhdl"),
+            "",
+            "  // Nothing but a comment\n",
+            "\r\n/// Docs\r\nconst X: u8 = 1; // one\r\n\r\n\tconst Y: u8 = 2;",
+            "entity Top { in clk: bit }\narch Top { , } // recovered\nconst Z: u8 = 0;\n",
+        ] {
+            let file = FileParser::new().parse(source).unwrap();
+            assert_eq!(Cst::new(source, &file).to_string(), source);
+        }
+
+        let source = include_str!("../tests/golden/comments.rhdl");
+        let cst = Cst::new(source, &FileParser::new().parse(source).unwrap());
+        assert_eq!(
+            cst.comments()
+                .map(|comment| comment.text.as_str())
+                .collect::<Vec<_>>(),
+            [
+                "// Wraps around on overflow",
+                "// x is sampled on the same edge",
+                "// Combinational, so it follows the register",
+            ]
+        );
+        let semi = cst
+            .tokens
+            .iter()
+            .find(|tok| {
+                tok.trailing
+                    .iter()
+                    .any(|trivia| trivia.kind == TriviaKind::Comment)
+            })
+            .unwrap();
+        assert_eq!(semi.tok.to_string(), ";");
+        assert_eq!(
+            semi.trailing
+                .iter()
+                .map(ToString::to_string)
+                .collect::<String>(),
+            " // x is sampled on the same edge\n"
+        );
+        let when = cst
+            .tokens
+            .iter()
+            .filter(|tok| tok.tok.to_string() == "when")
+            .nth(1)
+            .unwrap();
+        assert_eq!(
+            when.leading
+                .iter()
+                .map(ToString::to_string)
+                .collect::<String>(),
+            "\n    // Combinational, so it follows the register\n    "
+        );
+
+        // Comments go along with the tokens they are attached to when the tree is edited
+        let mut file = FileParser::new().parse(source).unwrap();
+        let cst = Cst::new(source, &file);
+        match &mut file.items[1] {
+            Item::Arch(arch) => {
+                arch.items.remove(0);
+            }
+            other => panic!("expected an arch, got {:?}", other),
+        }
+        let edited = cst.update(&file).to_string();
+        let (entity, _) = source.split_at(source.find("arch").unwrap());
+        assert_eq!(
+            edited,
+            format!(
+                "{}{}",
+                entity,
+                concat!(
+                    "arch Top {\n",
+                    "\n",
+                    "    // Combinational, so it follows the register\n",
+                    "    when true {\n",
+                    "        self.negative = sum < 0;\n",
+                    "    }\n",
+                    "}\n",
+                )
+            )
+        );
+        assert_eq!(
+            Cst::new(&edited, &FileParser::new().parse(&edited).unwrap())
+                .comments()
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    fn json_writes_nodes() {
+        use super::json::{Json, ToJson};
+
+        let expr = ExprParser::new().parse("1 + 0x1F").unwrap();
+        assert_eq!(
+            expr.to_json().to_string(),
+            concat!(
+                r#"{"kind":"ExprBinary","#,
+                r#""left":{"kind":"LitInt","raw":"1","value":"1","suffix":null,"span":[0,1]},"#,
+                r#""op":{"kind":"Plus","span":[2,3]},"#,
+                r#""right":{"kind":"LitInt","raw":"0x1F","value":"31","suffix":null,"span":[4,8]}}"#,
+            )
+        );
+
+        let file = FileParser::new().parse("enum E { A, B = 1 }").unwrap();
+        let json = file.to_json().to_string();
+        assert!(json.contains(concat!(
+            r#"{"kind":"Variant","attrs":[],"#,
+            r#""ident":{"kind":"Ident","name":"A","span":[9,10]},"#,
+            r#""variant_type":{"kind":"VariantTypeUnit"}}"#,
+        )));
+        // An absent optional field stays `null`
+        assert!(json.starts_with(concat!(
+            r#"{"kind":"File","items":[{"kind":"ItemEnum","#,
+            r#""attrs":[],"vis":null,"#,
+        )));
+
+        let json = Json::node(
+            "DocComment",
+            vec![
+                ("text", Json::String(" \"quoted\"\\\n".to_string())),
+                ("span", Json::Array(vec![])),
+            ],
+        );
+        assert_eq!(
+            format!("{:#}", json),
+            "{\n  \"kind\": \"DocComment\",\n  \"text\": \" \\\"quoted\\\"\\\\\\n\",\n  \"span\": []\n}"
         );
     }
 }
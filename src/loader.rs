@@ -0,0 +1,242 @@
+//! Loads a whole crate by starting at its root file and following each `mod name;` to the file holding that module
+
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::ast::{Item, ModContent, Span};
+use crate::diagnostics::{self, Diagnostic};
+use crate::parser::FileParser;
+use crate::source_map::{FileId, SourceMap};
+
+/// Extension of RHDL source files
+pub const EXTENSION: &str = "rhdl";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Module {
+    /// `crate` for the root module
+    pub name: String,
+    /// File that the spans in `items` are offsets into
+    pub file: FileId,
+    pub items: Vec<Item>,
+    /// Modules declared in `items`, whether inline or in their own file, in declaration order
+    pub children: Vec<Module>,
+}
+
+impl Module {
+    pub fn child(&self, name: &str) -> Option<&Module> {
+        self.children.iter().find(|child| child.name == name)
+    }
+}
+
+#[derive(Debug)]
+pub struct Crate {
+    pub source_map: SourceMap,
+    pub root: Module,
+}
+
+impl Crate {
+    /// Every module of the crate in pre-order, starting with the root
+    pub fn modules(&self) -> Vec<&Module> {
+        fn pre_order<'a>(module: &'a Module, acc: &mut Vec<&'a Module>) {
+            acc.push(module);
+            module
+                .children
+                .iter()
+                .for_each(|child| pre_order(child, acc));
+        }
+        let mut acc = vec![];
+        pre_order(&self.root, &mut acc);
+        acc
+    }
+
+    /// Loads the crate whose root module is the file at `path`
+    ///
+    /// Loading continues past errors, so that all of them are reported at once.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, LoadError> {
+        let path = path.as_ref();
+        let mut loader = Loader::default();
+        let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        let root = match fs::read_to_string(path) {
+            Ok(source) => loader.load_file("crate".to_string(), path, source, dir),
+            Err(err) => {
+                loader.diagnostics.push(Diagnostic::new(
+                    format!("couldn't read `{}`: {}", path.display(), err),
+                    Span(0, 0),
+                ));
+                None
+            }
+        };
+        match root {
+            Some(root) if loader.diagnostics.is_empty() => Ok(Self {
+                source_map: loader.source_map,
+                root,
+            }),
+            _ => Err(LoadError {
+                source_map: loader.source_map,
+                diagnostics: loader.diagnostics,
+            }),
+        }
+    }
+}
+
+/// Everything that went wrong while loading a crate, along with the sources needed to render it
+#[derive(Debug)]
+pub struct LoadError {
+    pub source_map: SourceMap,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for diagnostic in &self.diagnostics {
+            write!(f, "{}", diagnostic.render(&self.source_map))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+#[derive(Default)]
+struct Loader {
+    source_map: SourceMap,
+    diagnostics: Vec<Diagnostic>,
+    /// Canonical paths of the files currently being loaded, innermost last
+    stack: Vec<PathBuf>,
+    /// Canonical paths of every file loaded so far
+    loaded: HashSet<PathBuf>,
+}
+
+impl Loader {
+    /// Parses `source`, read from `path`, as the module `name` whose own module files are in `dir`
+    fn load_file(
+        &mut self,
+        name: String,
+        path: &Path,
+        source: String,
+        dir: PathBuf,
+    ) -> Option<Module> {
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        self.loaded.insert(canonical.clone());
+
+        let file = self.source_map.add(path, source);
+        let parsed = match FileParser::new().parse(self.source_map.get(file).source()) {
+            Ok(parsed) => parsed,
+            Err(err) => {
+                self.diagnostics.push(Diagnostic::from(err).in_file(file));
+                return None;
+            }
+        };
+        self.diagnostics.extend(
+            diagnostics::recovered(&parsed)
+                .into_iter()
+                .map(|diagnostic| diagnostic.clone().in_file(file)),
+        );
+
+        self.stack.push(canonical);
+        let module = self.module(name, file, parsed.items, &dir);
+        self.stack.pop();
+        Some(module)
+    }
+
+    fn module(&mut self, name: String, file: FileId, items: Vec<Item>, dir: &Path) -> Module {
+        let mut children: Vec<Module> = vec![];
+        for item in &items {
+            let item_mod = match item {
+                Item::Mod(item_mod) => item_mod,
+                _ => continue,
+            };
+            let ident = &item_mod.ident;
+            if children.iter().any(|child| child.name == ident.inner) {
+                self.diagnostics.push(
+                    Diagnostic::new(
+                        format!("the module `{}` is defined multiple times", ident),
+                        ident.span.clone(),
+                    )
+                    .in_file(file),
+                );
+                continue;
+            }
+
+            let child_dir = dir.join(&ident.inner);
+            let child = match &item_mod.content {
+                ModContent::Here(here) => {
+                    Some(self.module(ident.inner.clone(), file, here.items.clone(), &child_dir))
+                }
+                ModContent::File(_) => {
+                    let read = self
+                        .find_module_file(&ident.inner, dir)
+                        .map_err(|message| {
+                            Diagnostic::new(message, ident.span.clone()).in_file(file)
+                        })
+                        .and_then(|path| self.read_module_file(&path, ident.span.clone(), file));
+                    match read {
+                        Ok((path, source)) => {
+                            self.load_file(ident.inner.clone(), &path, source, child_dir)
+                        }
+                        Err(diagnostic) => {
+                            self.diagnostics.push(diagnostic);
+                            None
+                        }
+                    }
+                }
+            };
+            children.extend(child);
+        }
+
+        Module {
+            name,
+            file,
+            items,
+            children,
+        }
+    }
+
+    /// Finds `dir/name.rhdl` or `dir/name/mod.rhdl`, following Rust's rules for module files
+    fn find_module_file(&self, name: &str, dir: &Path) -> Result<PathBuf, String> {
+        let flat = dir.join(format!("{}.{}", name, EXTENSION));
+        let nested = dir.join(name).join(format!("mod.{}", EXTENSION));
+        match (flat.is_file(), nested.is_file()) {
+            (true, false) => Ok(flat),
+            (false, true) => Ok(nested),
+            (true, true) => Err(format!(
+                "file for module `{}` found at both `{}` and `{}`",
+                name,
+                flat.display(),
+                nested.display()
+            )),
+            (false, false) => Err(format!(
+                "file not found for module `{}`, expected `{}` or `{}`",
+                name,
+                flat.display(),
+                nested.display()
+            )),
+        }
+    }
+
+    /// Reads a module file declared at `span` in `file`, unless loading it again would be a cycle or duplicate
+    fn read_module_file(
+        &self,
+        path: &Path,
+        span: Span,
+        file: FileId,
+    ) -> Result<(PathBuf, String), Diagnostic> {
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        let message = if self.stack.contains(&canonical) {
+            format!("module file `{}` includes itself", path.display())
+        } else if self.loaded.contains(&canonical) {
+            format!(
+                "module file `{}` is already loaded as another module",
+                path.display()
+            )
+        } else {
+            match fs::read_to_string(path) {
+                Ok(source) => return Ok((path.to_path_buf(), source)),
+                Err(err) => format!("couldn't read `{}`: {}", path.display(), err),
+            }
+        };
+        Err(Diagnostic::new(message, span).in_file(file))
+    }
+}
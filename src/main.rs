@@ -0,0 +1,574 @@
+//! The `rhdl` command-line driver
+//!
+//! ```text
+//! rhdl check [--top <entity>] <file>
+//! rhdl fmt [--check] <file>...
+//! rhdl ast [--json] <file>
+//! rhdl emit --target verilog|systemverilog|vhdl|firrtl [--top <entity>] <file>
+//! rhdl sim --cycles <n> [--top <entity>] [--clock <port>] [--set <port>=<value>]... [--vcd <path>] <file>
+//! ```
+//!
+//! `check`, `emit` and `sim` load the whole crate whose root module is `<file>` and check it
+//! before doing anything else, while `fmt` and `ast` only parse the files they are given. `check`
+//! goes as far as lowering the design under `--top`, which is `Top` unless given, so it rejects
+//! whatever `emit` would. `ast` prints the syntax tree of its file, as JSON with `--json`, and
+//! `emit` writes the design under `--top` to stdout. `sim` drives the inputs given with `--set`,
+//! toggles `--clock`, which is `clk` unless given, for `--cycles` clock cycles, optionally dumping
+//! every net to a VCD file, and prints the values that the outputs of the top are left with.
+//!
+//! Errors are rendered to stderr. The exit code is 0 on success, 1 if the source has errors or
+//! `fmt --check` finds a file that is not formatted, and 2 if the command line is malformed.
+
+use std::env;
+use std::fs;
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use rug::Integer;
+
+use rhdl::ast::File;
+use rhdl::cst::Cst;
+use rhdl::diagnostics::{self, Diagnostic};
+use rhdl::elaborate::elaborate;
+use rhdl::fmt::{format_cst, Config};
+use rhdl::json::ToJson;
+use rhdl::loader::Crate;
+use rhdl::netlist::{lower, Direction, Netlist};
+use rhdl::parser::FileParser;
+use rhdl::resolve::{resolve, Resolutions};
+use rhdl::sim::Simulator;
+use rhdl::source_map::SourceMap;
+use rhdl::typeck::Types;
+use rhdl::vcd::VcdWriter;
+use rhdl::{arch_check, const_eval, firrtl, systemverilog, typeck, verilog, vhdl};
+
+const USAGE: &str = "\
+usage: rhdl check [--top <entity>] <file>
+       rhdl fmt [--check] <file>...
+       rhdl ast [--json] <file>
+       rhdl emit --target verilog|systemverilog|vhdl|firrtl [--top <entity>] <file>
+       rhdl sim --cycles <n> [--top <entity>] [--clock <port>] [--set <port>=<value>]... [--vcd <path>] <file>";
+
+/// Why a command did not succeed
+#[derive(Debug, PartialEq)]
+enum Failure {
+    /// The command line was malformed
+    Usage(String),
+    /// The command ran into errors, already rendered
+    Errors(String),
+}
+
+impl From<io::Error> for Failure {
+    fn from(err: io::Error) -> Self {
+        Self::Errors(format!("error: {}\n", err))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Target {
+    Verilog,
+    SystemVerilog,
+    Vhdl,
+    Firrtl,
+}
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    Check {
+        top: String,
+        path: PathBuf,
+    },
+    Fmt {
+        check: bool,
+        paths: Vec<PathBuf>,
+    },
+    Ast {
+        json: bool,
+        path: PathBuf,
+    },
+    Emit {
+        target: Target,
+        top: String,
+        path: PathBuf,
+    },
+    Sim {
+        top: String,
+        cycles: u64,
+        clock: String,
+        inputs: Vec<(String, Integer)>,
+        vcd: Option<PathBuf>,
+        path: PathBuf,
+    },
+}
+
+impl Command {
+    /// Parses the arguments that follow the name of the binary
+    fn parse(args: Vec<String>) -> Result<Self, Failure> {
+        let mut args = args.into_iter();
+        let name = args
+            .next()
+            .ok_or_else(|| Failure::Usage("no command given".to_string()))?;
+        let mut args = Args(args.collect());
+        match name.as_str() {
+            "check" => Ok(Self::Check {
+                top: args.option("--top")?.unwrap_or_else(|| "Top".to_string()),
+                path: args.path()?,
+            }),
+            "fmt" => Ok(Self::Fmt {
+                check: args.flag("--check"),
+                paths: args.paths()?,
+            }),
+            "ast" => Ok(Self::Ast {
+                json: args.flag("--json"),
+                path: args.path()?,
+            }),
+            "emit" => {
+                let target = match args.option("--target")?.as_deref() {
+                    Some("verilog") => Target::Verilog,
+                    Some("systemverilog") => Target::SystemVerilog,
+                    Some("vhdl") => Target::Vhdl,
+                    Some("firrtl") => Target::Firrtl,
+                    Some(other) => {
+                        return Err(Failure::Usage(format!("unknown target `{}`", other)))
+                    }
+                    None => return Err(Failure::Usage("`--target` is required".to_string())),
+                };
+                Ok(Self::Emit {
+                    target,
+                    top: args.option("--top")?.unwrap_or_else(|| "Top".to_string()),
+                    path: args.path()?,
+                })
+            }
+            "sim" => {
+                let cycles = args
+                    .option("--cycles")?
+                    .ok_or_else(|| Failure::Usage("`--cycles` is required".to_string()))?;
+                let cycles = cycles.parse().map_err(|_| {
+                    Failure::Usage(format!("`{}` is not a number of cycles", cycles))
+                })?;
+                let inputs = args
+                    .options("--set")?
+                    .into_iter()
+                    .map(|input| {
+                        let value = input
+                            .split_once('=')
+                            .and_then(|(port, value)| Some((port, value.parse::<Integer>().ok()?)));
+                        let (port, value) = value.ok_or_else(|| {
+                            Failure::Usage(format!("`{}` is not of the form <port>=<value>", input))
+                        })?;
+                        Ok((port.to_string(), value))
+                    })
+                    .collect::<Result<_, Failure>>()?;
+                Ok(Self::Sim {
+                    top: args.option("--top")?.unwrap_or_else(|| "Top".to_string()),
+                    cycles,
+                    clock: args.option("--clock")?.unwrap_or_else(|| "clk".to_string()),
+                    inputs,
+                    vcd: args.option("--vcd")?.map(PathBuf::from),
+                    path: args.path()?,
+                })
+            }
+            other => Err(Failure::Usage(format!("unknown command `{}`", other))),
+        }
+    }
+
+    fn run(&self) -> Result<(), Failure> {
+        match self {
+            Self::Check { top, path } => {
+                let krate = load(path)?;
+                let (resolutions, types) = analyze(&krate)?;
+                const_eval::eval(&krate, &resolutions, &types)
+                    .map_err(|diagnostics| render(&krate.source_map, &diagnostics))?;
+                lower_top(&krate, &resolutions, &types, top)?;
+                Ok(())
+            }
+            Self::Fmt { check, paths } => {
+                let mut unformatted = vec![];
+                for path in paths {
+                    let (source, file) = parse(path)?;
+                    let mut formatted = format_cst(&Cst::new(&source, &file), &Config::default());
+                    if !formatted.is_empty() {
+                        formatted.push('\n');
+                    }
+                    if formatted == source {
+                        continue;
+                    }
+                    if *check {
+                        unformatted.push(format!("`{}` is not formatted", path.display()));
+                    } else {
+                        fs::write(path, formatted)?;
+                    }
+                }
+                if unformatted.is_empty() {
+                    Ok(())
+                } else {
+                    Err(Failure::Errors(
+                        unformatted
+                            .iter()
+                            .map(|message| format!("error: {}\n", message))
+                            .collect(),
+                    ))
+                }
+            }
+            Self::Ast { json, path } => {
+                let (_, file) = parse(path)?;
+                if *json {
+                    println!("{:#}", file.to_json());
+                } else {
+                    println!("{:#?}", file);
+                }
+                Ok(())
+            }
+            Self::Emit { target, top, path } => {
+                let krate = load(path)?;
+                let netlist = netlist(&krate, top)?;
+                print!(
+                    "{}",
+                    match target {
+                        Target::Verilog => verilog::emit(&netlist),
+                        Target::SystemVerilog => systemverilog::emit(&netlist),
+                        Target::Vhdl => vhdl::emit(&netlist),
+                        Target::Firrtl => firrtl::emit(&netlist),
+                    }
+                );
+                Ok(())
+            }
+            Self::Sim {
+                top,
+                cycles,
+                clock,
+                inputs,
+                vcd,
+                path,
+            } => {
+                let krate = load(path)?;
+                let netlist = netlist(&krate, top)?;
+                simulate(&netlist, *cycles, clock, inputs, vcd.as_deref())
+            }
+        }
+    }
+}
+
+/// Arguments of a command, which its options are taken out of one by one
+struct Args(Vec<String>);
+
+impl Args {
+    /// Whether the flag `name` was given
+    fn flag(&mut self, name: &str) -> bool {
+        let len = self.0.len();
+        self.0.retain(|arg| arg != name);
+        self.0.len() != len
+    }
+
+    /// Values of every `name value` or `name=value` option, in order
+    fn options(&mut self, name: &str) -> Result<Vec<String>, Failure> {
+        let mut values = vec![];
+        let mut rest = vec![];
+        let mut args = std::mem::take(&mut self.0).into_iter();
+        while let Some(arg) = args.next() {
+            if arg == name {
+                let value = args
+                    .next()
+                    .ok_or_else(|| Failure::Usage(format!("`{}` needs a value", name)))?;
+                values.push(value);
+            } else if let Some(value) = arg.strip_prefix(name).and_then(|s| s.strip_prefix('=')) {
+                values.push(value.to_string());
+            } else {
+                rest.push(arg);
+            }
+        }
+        self.0 = rest;
+        Ok(values)
+    }
+
+    /// Value of the option `name`, which may be given at most once
+    fn option(&mut self, name: &str) -> Result<Option<String>, Failure> {
+        let mut values = self.options(name)?;
+        if values.len() > 1 {
+            return Err(Failure::Usage(format!(
+                "`{}` was given more than once",
+                name
+            )));
+        }
+        Ok(values.pop())
+    }
+
+    /// The one or more paths left once every option has been taken
+    fn paths(self) -> Result<Vec<PathBuf>, Failure> {
+        if let Some(arg) = self.0.iter().find(|arg| arg.starts_with("--")) {
+            return Err(Failure::Usage(format!("unknown option `{}`", arg)));
+        }
+        if self.0.is_empty() {
+            return Err(Failure::Usage("no file given".to_string()));
+        }
+        Ok(self.0.into_iter().map(PathBuf::from).collect())
+    }
+
+    /// The one path left once every option has been taken
+    fn path(self) -> Result<PathBuf, Failure> {
+        let mut paths = self.paths()?;
+        if paths.len() > 1 {
+            return Err(Failure::Usage("more than one file given".to_string()));
+        }
+        Ok(paths.remove(0))
+    }
+}
+
+fn render(source_map: &SourceMap, diagnostics: &[Diagnostic]) -> Failure {
+    Failure::Errors(
+        diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.render(source_map))
+            .collect(),
+    )
+}
+
+/// Parses the file at `path` alone, failing on any syntax error, even one the parser recovered from
+fn parse(path: &Path) -> Result<(String, File), Failure> {
+    let source = fs::read_to_string(path).map_err(|err| {
+        Failure::Errors(format!(
+            "error: couldn't read `{}`: {}\n",
+            path.display(),
+            err
+        ))
+    })?;
+    let mut source_map = SourceMap::new();
+    let id = source_map.add(path, source.clone());
+    let file = FileParser::new()
+        .parse(&source)
+        .map_err(|err| render(&source_map, &[Diagnostic::from(err).in_file(id)]))?;
+    let recovered = diagnostics::recovered(&file)
+        .into_iter()
+        .map(|diagnostic| diagnostic.clone().in_file(id))
+        .collect::<Vec<_>>();
+    if !recovered.is_empty() {
+        return Err(render(&source_map, &recovered));
+    }
+    Ok((source, file))
+}
+
+fn load(path: &Path) -> Result<Crate, Failure> {
+    Crate::load(path).map_err(|err| Failure::Errors(err.to_string()))
+}
+
+/// Resolves and checks `krate`, reporting what the type and arch checks find together
+fn analyze(krate: &Crate) -> Result<(Resolutions, Types), Failure> {
+    let resolutions =
+        resolve(krate).map_err(|diagnostics| render(&krate.source_map, &diagnostics))?;
+    let mut diagnostics = arch_check::check(krate, &resolutions)
+        .err()
+        .unwrap_or_default();
+    match typeck::check(krate, &resolutions) {
+        Ok(types) if diagnostics.is_empty() => Ok((resolutions, types)),
+        Ok(_) => Err(render(&krate.source_map, &diagnostics)),
+        Err(errors) => {
+            diagnostics.extend(errors);
+            Err(render(&krate.source_map, &diagnostics))
+        }
+    }
+}
+
+/// The netlist of the design under the entity named `top`
+fn netlist(krate: &Crate, top: &str) -> Result<Netlist, Failure> {
+    let (resolutions, types) = analyze(krate)?;
+    lower_top(krate, &resolutions, &types, top)
+}
+
+/// Elaborates the design under the entity named `top` of an analyzed `krate` and lowers it
+fn lower_top(
+    krate: &Crate,
+    resolutions: &Resolutions,
+    types: &Types,
+    top: &str,
+) -> Result<Netlist, Failure> {
+    elaborate(krate, resolutions, types, top)
+        .and_then(|top| lower(krate, resolutions, types, &top))
+        .map_err(|diagnostics| render(&krate.source_map, &diagnostics))
+}
+
+/// Simulates `cycles` cycles of `clock` with `inputs` driven, then prints the outputs of the top
+fn simulate(
+    netlist: &Netlist,
+    cycles: u64,
+    clock: &str,
+    inputs: &[(String, Integer)],
+    vcd: Option<&Path>,
+) -> Result<(), Failure> {
+    let sim_error = |err: rhdl::sim::SimError| Failure::Errors(format!("error: {}\n", err));
+    let mut sim = Simulator::new(netlist);
+    for (port, value) in inputs {
+        sim.set(port, value.clone()).map_err(sim_error)?;
+    }
+    let mut vcd = match vcd {
+        Some(path) => Some(VcdWriter::new(
+            BufWriter::new(fs::File::create(path)?),
+            &sim,
+            &[],
+        )?),
+        None => None,
+    };
+    if let Some(vcd) = &mut vcd {
+        vcd.sample(&sim)?;
+    }
+    for _ in 0..cycles {
+        for level in [1, 0] {
+            sim.set(clock, level).map_err(sim_error)?;
+            sim.step().map_err(sim_error)?;
+            if let Some(vcd) = &mut vcd {
+                vcd.sample(&sim)?;
+            }
+        }
+    }
+    if let Some(vcd) = vcd {
+        vcd.into_inner()
+            .into_inner()
+            .map_err(|err| err.into_error())?;
+    }
+
+    let top = netlist.module(netlist.top);
+    for id in &top.ports {
+        let net = top.net(*id);
+        if net.port == Some(Direction::Out) || net.port == Some(Direction::InOut) {
+            println!("{} = {}", net.name, sim.get(&net.name).map_err(sim_error)?);
+        }
+    }
+    Ok(())
+}
+
+fn main() {
+    let result = Command::parse(env::args().skip(1).collect()).and_then(|command| command.run());
+    match result {
+        Ok(()) => {}
+        Err(Failure::Usage(message)) => {
+            eprintln!("error: {}\n\n{}", message, USAGE);
+            process::exit(2);
+        }
+        Err(Failure::Errors(rendered)) => {
+            eprint!("{}", rendered);
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(
+            Command::parse(args("fmt --check a.rhdl b.rhdl")),
+            Ok(Command::Fmt {
+                check: true,
+                paths: vec![PathBuf::from("a.rhdl"), PathBuf::from("b.rhdl")],
+            })
+        );
+        assert_eq!(
+            Command::parse(args("emit top.rhdl --target=vhdl")),
+            Ok(Command::Emit {
+                target: Target::Vhdl,
+                top: "Top".to_string(),
+                path: PathBuf::from("top.rhdl"),
+            })
+        );
+        assert_eq!(
+            Command::parse(args(
+                "sim --top Acc --cycles 3 --set en=1 --set x=-3 --vcd out.vcd acc.rhdl"
+            )),
+            Ok(Command::Sim {
+                top: "Acc".to_string(),
+                cycles: 3,
+                clock: "clk".to_string(),
+                inputs: vec![
+                    ("en".to_string(), Integer::from(1)),
+                    ("x".to_string(), Integer::from(-3)),
+                ],
+                vcd: Some(PathBuf::from("out.vcd")),
+                path: PathBuf::from("acc.rhdl"),
+            })
+        );
+
+        for (line, message) in [
+            ("", "no command given"),
+            ("build top.rhdl", "unknown command `build`"),
+            ("check", "no file given"),
+            ("check a.rhdl b.rhdl", "more than one file given"),
+            ("ast --yaml top.rhdl", "unknown option `--yaml`"),
+            ("emit top.rhdl", "`--target` is required"),
+            ("emit --target spice top.rhdl", "unknown target `spice`"),
+            (
+                "sim --cycles many top.rhdl",
+                "`many` is not a number of cycles",
+            ),
+            (
+                "sim --cycles 1 --set en top.rhdl",
+                "`en` is not of the form <port>=<value>",
+            ),
+            (
+                "sim --cycles 1 --top A --top B top.rhdl",
+                "`--top` was given more than once",
+            ),
+            ("sim top.rhdl --cycles", "`--cycles` needs a value"),
+        ] {
+            assert_eq!(
+                Command::parse(args(line)),
+                Err(Failure::Usage(message.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn runs_golden_designs() {
+        let golden = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+
+        Command::Check {
+            top: "Top".to_string(),
+            path: golden.join("gray.rhdl"),
+        }
+        .run()
+        .unwrap();
+
+        // Checking rejects what only elaborating and lowering find
+        for (name, message) in [
+            ("elaborate/bad.rhdl", "entity `Loop` instantiates itself"),
+            ("netlist/bad.rhdl", "`b` has more than one driver"),
+        ] {
+            let check = Command::parse(args(&format!("check {}", golden.join(name).display())));
+            match check.unwrap().run() {
+                Err(Failure::Errors(rendered)) => {
+                    assert!(rendered.starts_with(&format!("error: {}", message)))
+                }
+                other => panic!("expected errors, got {:?}", other),
+            }
+        }
+
+        let krate = load(&golden.join("sawtooth.rhdl")).unwrap();
+        assert_eq!(
+            verilog::emit(&netlist(&krate, "Top").unwrap()),
+            include_str!("../tests/golden/sawtooth.v")
+        );
+        match netlist(&krate, "Bottom") {
+            Err(Failure::Errors(rendered)) => assert!(rendered.starts_with("error: ")),
+            other => panic!("expected errors, got {:?}", other.map(|_| ())),
+        }
+
+        // Comments are formatted along with the rest, so checking looks at them too
+        let comments = golden.join("comments.rhdl");
+        assert_eq!(
+            Command::Fmt {
+                check: true,
+                paths: vec![comments.clone()],
+            }
+            .run(),
+            Err(Failure::Errors(format!(
+                "error: `{}` is not formatted\n",
+                comments.display()
+            )))
+        );
+    }
+}
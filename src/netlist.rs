@@ -0,0 +1,2650 @@
+//! A netlist of the elaborated design, which is the form that every HDL backend is emitted from
+//!
+//! Each distinct pair of an entity and its generic arguments becomes a [`Module`] of nets, wires
+//! driven by combinational [`Node`]s, [`Process`]es that update nets on a clock edge or whenever
+//! their inputs change, and instances of other modules. Values are plain bit vectors: the fields
+//! of a struct, the elements of a tuple or array and the payload of an enum follow one another
+//! from the least significant bit up, and an enum starts with a tag that holds the discriminant
+//! of its variant.
+//!
+//! Within a `when` block, reads of a signal or port give its value from before the block ran, so
+//! a process computes every update from the values its nets held when it was triggered. Calls to
+//! functions and methods are inlined, and `for` loops over constant ranges are unrolled.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+
+use rug::Integer;
+
+use crate::ast::*;
+use crate::const_eval::{ConstEval, Env, Value};
+use crate::diagnostics::Diagnostic;
+use crate::elaborate::{GenericValue, Instance};
+use crate::loader::Crate;
+use crate::resolve::{DefId, DefKind, PathResolution, Res, Resolutions};
+use crate::source_map::FileId;
+use crate::ty::Ty;
+use crate::typeck::{self, Method, Subst, Types};
+use crate::visit::Visit;
+
+/// How deeply calls may be inlined into one another
+const MAX_INLINE_DEPTH: usize = 64;
+
+/// How many iterations of a `for` loop may be unrolled
+const MAX_UNROLL: u64 = 1 << 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ModuleId(pub usize);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NetId(pub usize);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(pub usize);
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Netlist {
+    /// Modules in the order they were first instantiated, starting with the top
+    pub modules: Vec<Module>,
+    /// Structs and enums that nets, nodes and consts are of, each after the types of its fields
+    pub types: Vec<TypeDecl>,
+    /// The `const` items of the crate that have a fixed width
+    pub consts: Vec<ConstDecl>,
+    pub top: ModuleId,
+}
+
+impl Netlist {
+    pub fn module(&self, id: ModuleId) -> &Module {
+        &self.modules[id.0]
+    }
+
+    /// Modules in an order where each comes after every module that it instantiates, which ends
+    /// with the top
+    pub fn dependency_order(&self) -> Vec<ModuleId> {
+        fn visit(netlist: &Netlist, id: ModuleId, seen: &mut [bool], acc: &mut Vec<ModuleId>) {
+            if std::mem::replace(&mut seen[id.0], true) {
+                return;
+            }
+            for instance in &netlist.module(id).instances {
+                visit(netlist, instance.module, seen, acc);
+            }
+            acc.push(id);
+        }
+
+        let mut seen = vec![false; self.modules.len()];
+        let mut acc = vec![];
+        visit(self, self.top, &mut seen, &mut acc);
+        acc
+    }
+
+    /// Declaration of a struct or enum type
+    pub fn type_decl(&self, ty: &Ty) -> Option<&TypeDecl> {
+        self.types.iter().find(|decl| decl.ty == *ty)
+    }
+
+    /// Width in bits of a value of type `ty`, which is zero for a type that has none
+    pub fn width(&self, ty: &Ty) -> u64 {
+        match ty {
+            Ty::Bit | Ty::Bool => 1,
+            Ty::Unsigned(Some(width)) | Ty::Signed(Some(width)) => u64::from(*width),
+            Ty::Array(elem, len) => self.width(elem) * len,
+            Ty::Tuple(elems) => elems.iter().map(|elem| self.width(elem)).sum(),
+            Ty::Adt { .. } => match self.type_decl(ty).map(|decl| &decl.kind) {
+                Some(TypeDeclKind::Struct(fields)) => {
+                    fields.iter().map(|(_, ty)| self.width(ty)).sum()
+                }
+                Some(TypeDeclKind::Enum {
+                    tag_width,
+                    variants,
+                }) => {
+                    let payload = variants
+                        .iter()
+                        .map(|variant| variant.fields.iter().map(|(_, ty)| self.width(ty)).sum())
+                        .max()
+                        .unwrap_or(0);
+                    tag_width + payload
+                }
+                None => 0,
+            },
+            _ => 0,
+        }
+    }
+
+    /// Fields of a struct or tuple, from the least significant up
+    pub fn fields(&self, ty: &Ty) -> Option<Vec<(String, Ty)>> {
+        match ty {
+            Ty::Tuple(elems) => Some(
+                elems
+                    .iter()
+                    .enumerate()
+                    .map(|(i, elem)| (i.to_string(), elem.clone()))
+                    .collect(),
+            ),
+            Ty::Adt { .. } => match &self.type_decl(ty)?.kind {
+                TypeDeclKind::Struct(fields) => Some(fields.clone()),
+                TypeDeclKind::Enum { .. } => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+/// A struct or enum along with its type arguments, and how its values are laid out
+#[derive(Clone, Debug, PartialEq)]
+pub struct TypeDecl {
+    pub ty: Ty,
+    /// Name of the type that is unique within the netlist, with its type arguments mangled in
+    pub name: String,
+    /// Path of the module that the type is defined in, such as `crate::alu`
+    pub module: String,
+    pub kind: TypeDeclKind,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConstDecl {
+    pub name: String,
+    /// Path of the module that the const is defined in
+    pub module: String,
+    pub ty: Ty,
+    pub width: u64,
+    pub bits: Integer,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TypeDeclKind {
+    /// Fields from the least significant bits up
+    Struct(Vec<(String, Ty)>),
+    /// Variants whose tags take the low `tag_width` bits, followed by their fields
+    Enum {
+        tag_width: u64,
+        variants: Vec<VariantDecl>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct VariantDecl {
+    pub name: String,
+    /// The value given by `= expr`, or one more than that of the variant before
+    pub discriminant: Integer,
+    /// Whether the discriminant was given by `= expr`
+    pub explicit: bool,
+    pub fields: Vec<(String, Ty)>,
+}
+
+impl TypeDeclKind {
+    /// Whether this is an enum whose variants all have no fields
+    pub fn is_fieldless_enum(&self) -> bool {
+        match self {
+            Self::Enum { variants, .. } => variants.iter().all(|variant| variant.fields.is_empty()),
+            Self::Struct(_) => false,
+        }
+    }
+
+    /// The variant of an enum whose tag is `tag`
+    pub fn variant(&self, tag: &Integer) -> Option<&VariantDecl> {
+        match self {
+            Self::Enum { variants, .. } => {
+                variants.iter().find(|variant| variant.discriminant == *tag)
+            }
+            Self::Struct(_) => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Direction {
+    In,
+    Out,
+    InOut,
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::In => write!(f, "in"),
+            Self::Out => write!(f, "out"),
+            Self::InOut => write!(f, "inout"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum NetKind {
+    /// Driven by an assignment, an instance or a combinational process
+    Wire,
+    /// Driven by a clocked process
+    Reg,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Net {
+    pub name: String,
+    pub ty: Ty,
+    pub width: u64,
+    pub kind: NetKind,
+    /// Direction of the port that the net is, if it is one
+    pub port: Option<Direction>,
+    /// Value of a register before its first clock edge
+    pub init: Option<Integer>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Node {
+    pub kind: NodeKind,
+    pub ty: Ty,
+    pub width: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum NodeKind {
+    /// The bits of a constant, which are never negative
+    Const(Integer),
+    /// The value of a net
+    Net(NetId),
+    Unary(UnaryOp, NodeId),
+    /// An operation on two operands, which are signed if the first operand's type is
+    Binary(BinaryOp, NodeId, NodeId),
+    /// Converts the operand to the node's type, truncating it or extending it by its signedness
+    Cast(NodeId),
+    /// The node's width in bits of the operand, starting at the given bit, which is how fields
+    /// and constant indices are read
+    Slice(NodeId, u64),
+    /// The element of the node's width at the index given by the second operand
+    Index(NodeId, NodeId),
+    /// Operands from the least significant bits up
+    Concat(Vec<NodeId>),
+    /// The second operand if the first is set, and the third otherwise
+    Mux(NodeId, NodeId, NodeId),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum UnaryOp {
+    Not,
+    Neg,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Pow,
+    Xor,
+    And,
+    Or,
+    Shl,
+    Shr,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl BinaryOp {
+    fn of(op: &BinOp) -> Self {
+        match op {
+            BinOp::Plus(_) => Self::Add,
+            BinOp::Minus(_) => Self::Sub,
+            BinOp::Star(_) => Self::Mul,
+            BinOp::Slash(_) => Self::Div,
+            BinOp::Percent(_) => Self::Rem,
+            BinOp::StarStar(_) => Self::Pow,
+            BinOp::Caret(_) => Self::Xor,
+            BinOp::And(_) => Self::And,
+            BinOp::Or(_) => Self::Or,
+            BinOp::Shl(_) => Self::Shl,
+            BinOp::Shr(_) => Self::Shr,
+            BinOp::EqEq(_) => Self::Eq,
+            BinOp::Ne(_) => Self::Ne,
+            BinOp::Lt(_) => Self::Lt,
+            BinOp::Le(_) => Self::Le,
+            BinOp::Gt(_) => Self::Gt,
+            BinOp::Ge(_) => Self::Ge,
+        }
+    }
+
+    /// Operation of a compound assignment such as `+=`
+    fn of_assign(op: &AssOp) -> Option<Self> {
+        match op {
+            AssOp::Eq(_) => None,
+            AssOp::PlusEq(_) => Some(Self::Add),
+            AssOp::MinusEq(_) => Some(Self::Sub),
+            AssOp::StarEq(_) => Some(Self::Mul),
+            AssOp::SlashEq(_) => Some(Self::Div),
+            AssOp::PercentEq(_) => Some(Self::Rem),
+            AssOp::StarStarEq(_) => Some(Self::Pow),
+            AssOp::CaretEq(_) => Some(Self::Xor),
+            AssOp::AndEq(_) => Some(Self::And),
+            AssOp::OrEq(_) => Some(Self::Or),
+            AssOp::ShlEq(_) => Some(Self::Shl),
+            AssOp::ShrEq(_) => Some(Self::Shr),
+        }
+    }
+
+    pub fn is_comparison(self) -> bool {
+        matches!(
+            self,
+            Self::Eq | Self::Ne | Self::Lt | Self::Le | Self::Gt | Self::Ge
+        )
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Add => "add",
+            Self::Sub => "sub",
+            Self::Mul => "mul",
+            Self::Div => "div",
+            Self::Rem => "rem",
+            Self::Pow => "pow",
+            Self::Xor => "xor",
+            Self::And => "and",
+            Self::Or => "or",
+            Self::Shl => "shl",
+            Self::Shr => "shr",
+            Self::Eq => "eq",
+            Self::Ne => "ne",
+            Self::Lt => "lt",
+            Self::Le => "le",
+            Self::Gt => "gt",
+            Self::Ge => "ge",
+        }
+    }
+}
+
+/// A continuous assignment of a node to a net
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Assign {
+    pub net: NetId,
+    pub value: NodeId,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Trigger {
+    Posedge(NetId),
+    Negedge(NetId),
+    /// Whenever any of the nets that the process reads changes
+    Always,
+}
+
+/// Nets updated together, from a `when` block
+#[derive(Clone, Debug, PartialEq)]
+pub struct Process {
+    pub trigger: Trigger,
+    /// Values that each net takes once the process runs, ordered by net
+    pub updates: Vec<Assign>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModuleInstance {
+    pub name: String,
+    pub module: ModuleId,
+    /// Net connected to each port of the module, in the order the ports were declared
+    pub connections: Vec<(String, NetId)>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Module {
+    pub name: String,
+    pub entity: DefId,
+    pub generics: Vec<GenericValue>,
+    /// Nets that are ports, in the order they were declared
+    pub ports: Vec<NetId>,
+    pub nets: Vec<Net>,
+    /// Nodes in an order where operands come before the nodes that use them
+    pub nodes: Vec<Node>,
+    pub assigns: Vec<Assign>,
+    pub processes: Vec<Process>,
+    pub instances: Vec<ModuleInstance>,
+}
+
+impl Module {
+    pub fn net(&self, id: NetId) -> &Net {
+        &self.nets[id.0]
+    }
+
+    pub fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id.0]
+    }
+}
+
+impl fmt::Display for Netlist {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, module) in self.modules.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            module.fmt_in(f, self)?;
+        }
+        Ok(())
+    }
+}
+
+impl Module {
+    fn fmt_in(&self, f: &mut fmt::Formatter<'_>, netlist: &Netlist) -> fmt::Result {
+        writeln!(f, "module {} {{", self.name)?;
+        for net in &self.nets {
+            write!(f, "  ")?;
+            if let Some(direction) = net.port {
+                write!(f, "{} ", direction)?;
+            }
+            match (net.kind, net.port) {
+                (NetKind::Reg, _) => write!(f, "reg ")?,
+                (NetKind::Wire, None) => write!(f, "wire ")?,
+                (NetKind::Wire, Some(_)) => {}
+            }
+            write!(f, "{}: {}", net.name, net.ty)?;
+            if let Some(init) = &net.init {
+                write!(f, " = {}", init)?;
+            }
+            writeln!(f)?;
+        }
+        for (i, node) in self.nodes.iter().enumerate() {
+            write!(f, "  %{}: {} = ", i, node.ty)?;
+            match &node.kind {
+                NodeKind::Const(bits) => write!(f, "const {}", bits)?,
+                NodeKind::Net(net) => write!(f, "{}", self.net(*net).name)?,
+                NodeKind::Unary(UnaryOp::Not, arg) => write!(f, "not %{}", arg.0)?,
+                NodeKind::Unary(UnaryOp::Neg, arg) => write!(f, "neg %{}", arg.0)?,
+                NodeKind::Binary(op, lhs, rhs) => {
+                    write!(f, "{} %{}, %{}", op.name(), lhs.0, rhs.0)?
+                }
+                NodeKind::Cast(arg) => write!(f, "cast %{}", arg.0)?,
+                NodeKind::Slice(arg, lo) => {
+                    write!(f, "slice %{}[{}..{}]", arg.0, lo, lo + node.width)?
+                }
+                NodeKind::Index(arg, index) => write!(f, "index %{}[%{}]", arg.0, index.0)?,
+                NodeKind::Concat(args) => {
+                    let args = args
+                        .iter()
+                        .map(|arg| format!("%{}", arg.0))
+                        .collect::<Vec<_>>();
+                    write!(f, "concat {}", args.join(", "))?
+                }
+                NodeKind::Mux(cond, then, otherwise) => {
+                    write!(f, "mux %{}, %{}, %{}", cond.0, then.0, otherwise.0)?
+                }
+            }
+            writeln!(f)?;
+        }
+        for assign in &self.assigns {
+            writeln!(
+                f,
+                "  assign {} = %{}",
+                self.net(assign.net).name,
+                assign.value.0
+            )?;
+        }
+        for process in &self.processes {
+            let arrow = match process.trigger {
+                Trigger::Posedge(clock) => {
+                    writeln!(f, "  always posedge {} {{", self.net(clock).name)?;
+                    "<="
+                }
+                Trigger::Negedge(clock) => {
+                    writeln!(f, "  always negedge {} {{", self.net(clock).name)?;
+                    "<="
+                }
+                Trigger::Always => {
+                    writeln!(f, "  always {{")?;
+                    "="
+                }
+            };
+            for update in &process.updates {
+                writeln!(
+                    f,
+                    "    {} {} %{}",
+                    self.net(update.net).name,
+                    arrow,
+                    update.value.0
+                )?;
+            }
+            writeln!(f, "  }}")?;
+        }
+        for instance in &self.instances {
+            let connections = instance
+                .connections
+                .iter()
+                .map(|(port, net)| format!("{}: {}", port, self.net(*net).name))
+                .collect::<Vec<_>>();
+            writeln!(
+                f,
+                "  inst {}: {} {{ {} }}",
+                instance.name,
+                netlist.module(instance.module).name,
+                connections.join(", ")
+            )?;
+        }
+        writeln!(f, "}}")
+    }
+}
+
+/// Lowers the design elaborated from `top` into a netlist
+pub fn lower<'a>(
+    krate: &'a Crate,
+    resolutions: &'a Resolutions,
+    types: &'a Types,
+    top: &Instance<'a>,
+) -> Result<Netlist, Vec<Diagnostic>> {
+    let mut lowerer = Lowerer {
+        eval: ConstEval::new(krate, resolutions, types),
+        types,
+        methods: HashMap::new(),
+        modules: vec![],
+        keys: HashMap::new(),
+        names: HashSet::new(),
+        diagnostics: vec![],
+    };
+    lowerer.methods = typeck::methods(lowerer.eval.cx());
+    lowerer.module(top);
+    let tys = lowerer
+        .modules
+        .iter()
+        .flat_map(|module| {
+            let nets = module.nets.iter().map(|net| net.ty.clone());
+            nets.chain(module.nodes.iter().map(|node| node.ty.clone()))
+        })
+        .collect::<Vec<_>>();
+    let mut decls = vec![];
+    for ty in &tys {
+        lowerer.declare(ty, &mut decls);
+    }
+    let consts = (0..resolutions.defs.len())
+        .filter(|id| resolutions.defs[*id].kind == DefKind::Const)
+        .filter_map(|id| lowerer.const_decl(DefId(id), &mut decls))
+        .collect();
+    let mut diagnostics = lowerer.diagnostics;
+    diagnostics.extend(lowerer.eval.take_diagnostics());
+    if diagnostics.is_empty() {
+        Ok(Netlist {
+            modules: lowerer.modules,
+            types: decls,
+            consts,
+            top: ModuleId(0),
+        })
+    } else {
+        Err(diagnostics)
+    }
+}
+
+struct Lowerer<'a> {
+    eval: ConstEval<'a>,
+    types: &'a Types,
+    methods: HashMap<DefId, Vec<Method<'a>>>,
+    modules: Vec<Module>,
+    /// Module of each entity and its generic arguments, written as they are printed
+    keys: HashMap<String, ModuleId>,
+    /// Names taken by modules
+    names: HashSet<String>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Lowerer<'a> {
+    /// Bits of a constant laid out as a value of type `ty`
+    fn encode(&mut self, value: &Value, ty: &Ty) -> Option<Integer> {
+        let cx = self.eval.cx();
+        let bits = match (value, ty) {
+            (Value::Int { val, .. }, ty) => Integer::from(val.keep_bits_ref(cx.width(ty)? as u32)),
+            (Value::Bool(value), _) => Integer::from(u8::from(*value)),
+            (Value::Array(values), Ty::Array(element, _)) => {
+                let width = cx.width(element)?;
+                let mut bits = Integer::new();
+                for (i, value) in values.iter().enumerate() {
+                    bits |= self.encode(value, element)? << (i as u64 * width) as u32;
+                }
+                bits
+            }
+            (Value::Tuple(values), Ty::Tuple(tys)) => {
+                let mut bits = Integer::new();
+                let mut lo = 0;
+                for (value, ty) in values.iter().zip(tys) {
+                    bits |= self.encode(value, ty)? << lo as u32;
+                    lo += self.eval.cx().width(ty)?;
+                }
+                bits
+            }
+            (
+                Value::Adt {
+                    variant, fields, ..
+                },
+                Ty::Adt { def, args, .. },
+            ) => {
+                let (def, mut lo, tag) = match variant {
+                    Some(variant) => (*variant, self.tag_width(def), self.tag(*variant)),
+                    None => (*def, 0, Integer::new()),
+                };
+                let (_, declared) = self.eval.cx().fields(def, args);
+                let mut bits = tag;
+                for ((_, value), (_, ty)) in fields.iter().zip(&declared) {
+                    bits |= self.encode(value, ty)? << lo as u32;
+                    lo += self.eval.cx().width(ty)?;
+                }
+                bits
+            }
+            _ => return None,
+        };
+        Some(bits)
+    }
+
+    /// Width of the tag of an enum, which holds its largest discriminant
+    fn tag_width(&self, def: &DefId) -> u64 {
+        self.eval.cx().tag_width(*def)
+    }
+
+    /// Tag of an enum variant, which is its discriminant
+    fn tag(&self, variant: DefId) -> Integer {
+        let owner = self.eval.cx().parent_enum(variant);
+        let index = self
+            .eval
+            .cx()
+            .resolutions()
+            .def(variant)
+            .variant
+            .unwrap_or(0);
+        self.eval
+            .tags(owner)
+            .get(index)
+            .map_or_else(Integer::new, |(tag, _)| tag.clone())
+    }
+
+    /// Adds the declarations of the structs and enums within `ty` that are not yet declared
+    fn declare(&mut self, ty: &Ty, decls: &mut Vec<TypeDecl>) {
+        let (def, args) = match ty {
+            Ty::Array(element, _) => return self.declare(element, decls),
+            Ty::Tuple(tys) => {
+                for ty in tys {
+                    self.declare(ty, decls);
+                }
+                return;
+            }
+            Ty::Adt { def, args, .. } => (*def, args),
+            _ => return,
+        };
+        if decls.iter().any(|decl| decl.ty == *ty) {
+            return;
+        }
+        let cx = self.eval.cx();
+        let kind = match cx.resolutions().def(def).kind {
+            DefKind::Enum => {
+                let ids = cx
+                    .resolutions()
+                    .variants
+                    .get(&def)
+                    .cloned()
+                    .unwrap_or_default();
+                let variants = ids
+                    .iter()
+                    .zip(self.eval.tags(def))
+                    .map(|(id, (discriminant, explicit))| VariantDecl {
+                        name: cx.resolutions().def(*id).name.clone(),
+                        discriminant: discriminant.clone(),
+                        explicit: *explicit,
+                        fields: cx.fields(*id, args).1,
+                    })
+                    .collect();
+                TypeDeclKind::Enum {
+                    tag_width: cx.tag_width(def),
+                    variants,
+                }
+            }
+            _ => TypeDeclKind::Struct(cx.fields(def, args).1),
+        };
+        let fields = match &kind {
+            TypeDeclKind::Struct(fields) => fields.clone(),
+            TypeDeclKind::Enum { variants, .. } => variants
+                .iter()
+                .flat_map(|variant| variant.fields.clone())
+                .collect(),
+        };
+        for (_, ty) in &fields {
+            self.declare(ty, decls);
+        }
+
+        let base = ty
+            .to_string()
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .map(|c| if c.is_alphanumeric() { c } else { '_' })
+            .collect::<String>()
+            .trim_end_matches('_')
+            .to_string();
+        let mut name = base.clone();
+        let mut suffix = 1;
+        while decls.iter().any(|decl| decl.name == name) {
+            name = format!("{}_{}", base, suffix);
+            suffix += 1;
+        }
+        let resolutions = self.eval.cx().resolutions();
+        decls.push(TypeDecl {
+            ty: ty.clone(),
+            name,
+            module: resolutions.module_path(resolutions.def(def).module),
+            kind,
+        });
+    }
+
+    /// Declaration of a `const` item, unless its type has no fixed width
+    fn const_decl(&mut self, id: DefId, decls: &mut Vec<TypeDecl>) -> Option<ConstDecl> {
+        let cx = self.eval.cx();
+        let item = match cx.item(id) {
+            Item::Const(item) => item,
+            _ => return None,
+        };
+        let ty = cx.lower(cx.file(id), &item.ty, &Subst::default());
+        let width = cx.width(&ty)?;
+        let value = self.eval.eval_const(id)?;
+        let bits = self.encode(&value, &ty)?;
+        self.declare(&ty, decls);
+        let resolutions = self.eval.cx().resolutions();
+        let def = resolutions.def(id);
+        Some(ConstDecl {
+            name: def.name.clone(),
+            module: resolutions.module_path(def.module),
+            ty,
+            width,
+            bits,
+        })
+    }
+
+    /// Module for an instance, which is lowered unless one for the same entity and generics exists
+    fn module(&mut self, instance: &Instance<'a>) -> ModuleId {
+        let generics = instance
+            .generics
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        let key = format!("{}<{}>", instance.entity.0, generics.join(", "));
+        if let Some(id) = self.keys.get(&key) {
+            return *id;
+        }
+
+        let mut name = instance.entity_name.clone();
+        for generic in &generics {
+            name.push('_');
+            name.extend(
+                generic
+                    .chars()
+                    .map(|c| if c.is_alphanumeric() { c } else { '_' }),
+            );
+        }
+        let base = name.clone();
+        let mut suffix = 1;
+        while !self.names.insert(name.clone()) {
+            name = format!("{}_{}", base, suffix);
+            suffix += 1;
+        }
+
+        let id = ModuleId(self.modules.len());
+        self.keys.insert(key, id);
+        self.modules.push(Module {
+            name,
+            entity: instance.entity,
+            generics: instance.generics.clone(),
+            ports: vec![],
+            nets: vec![],
+            nodes: vec![],
+            assigns: vec![],
+            processes: vec![],
+            instances: vec![],
+        });
+        let mut builder = Builder {
+            lowerer: self,
+            module: Module {
+                name: String::new(),
+                entity: instance.entity,
+                generics: vec![],
+                ports: vec![],
+                nets: vec![],
+                nodes: vec![],
+                assigns: vec![],
+                processes: vec![],
+                instances: vec![],
+            },
+            cache: HashMap::new(),
+            signals: HashMap::new(),
+            holds: HashMap::new(),
+            depth: 0,
+        };
+        builder.arch(instance);
+        let mut module = builder.module;
+        prune(&mut module);
+        let slot = &mut self.modules[id.0];
+        module.name = std::mem::take(&mut slot.name);
+        module.generics = std::mem::take(&mut slot.generics);
+        *slot = module;
+        id
+    }
+}
+
+/// What is known while lowering the body of an arch or of an inlined function
+struct Frame {
+    /// Values of constants and const generic params, and the file the body is in
+    env: Env,
+    subst: Subst,
+    /// `self` of an inlined method
+    self_node: Option<NodeId>,
+    scope: Scope,
+    /// Whether the body is in a `when` block, which makes the signals of the arch writable
+    in_when: bool,
+}
+
+impl Frame {
+    fn file(&self) -> FileId {
+        self.env.file
+    }
+}
+
+/// What assignments change, which is split and merged again around branches
+#[derive(Clone, Default)]
+struct Scope {
+    /// Values of the bindings made by `let`s, parameters and patterns
+    locals: HashMap<Span, NodeId>,
+    /// Values that the signals written so far in the `when` block will take
+    pending: BTreeMap<NetId, NodeId>,
+    /// Whether a `return` was reached
+    returned: bool,
+}
+
+struct Builder<'l, 'a> {
+    lowerer: &'l mut Lowerer<'a>,
+    module: Module,
+    cache: HashMap<(NodeKind, Ty), NodeId>,
+    /// Nets of the arch's ports and signals, by the span of their name
+    signals: HashMap<Span, NetId>,
+    /// Values that signals keep in the `when` block being lowered when it does not write them
+    holds: HashMap<NetId, NodeId>,
+    depth: usize,
+}
+
+impl<'l, 'a> Builder<'l, 'a> {
+    fn error(&mut self, message: impl Into<String>, span: Span, file: FileId) {
+        self.lowerer
+            .diagnostics
+            .push(Diagnostic::new(message, span).in_file(file));
+    }
+
+    fn unsupported(&mut self, what: &str, span: Span, file: FileId) {
+        let message = format!("{} is not supported in hardware", what);
+        self.error(message, span, file);
+    }
+
+    fn eval(&mut self) -> &mut ConstEval<'a> {
+        &mut self.lowerer.eval
+    }
+
+    fn resolutions(&self) -> &'a Resolutions {
+        self.lowerer.eval.cx().resolutions()
+    }
+
+    fn width(&mut self, ty: &Ty, span: Span, file: FileId) -> Option<u64> {
+        match self.lowerer.eval.cx().width(ty) {
+            Some(width) => Some(width),
+            None => {
+                let message = format!("a value of type `{}` has no fixed width", ty);
+                self.error(message, span, file);
+                None
+            }
+        }
+    }
+
+    fn add_net(&mut self, name: &str, ty: Ty, width: u64, port: Option<Direction>) -> NetId {
+        let mut unique = name.to_string();
+        let mut suffix = 1;
+        while self.module.nets.iter().any(|net| net.name == unique) {
+            unique = format!("{}_{}", name, suffix);
+            suffix += 1;
+        }
+        self.module.nets.push(Net {
+            name: unique,
+            ty,
+            width,
+            kind: NetKind::Wire,
+            port,
+            init: None,
+        });
+        NetId(self.module.nets.len() - 1)
+    }
+
+    fn node(&mut self, kind: NodeKind, ty: Ty, width: u64) -> NodeId {
+        let key = (kind, ty);
+        if let Some(id) = self.cache.get(&key) {
+            return *id;
+        }
+        let (kind, ty) = key.clone();
+        self.module.nodes.push(Node { kind, ty, width });
+        let id = NodeId(self.module.nodes.len() - 1);
+        self.cache.insert(key, id);
+        id
+    }
+
+    fn ty(&self, node: NodeId) -> Ty {
+        self.module.node(node).ty.clone()
+    }
+
+    fn node_width(&self, node: NodeId) -> u64 {
+        self.module.node(node).width
+    }
+
+    fn constant(&mut self, bits: Integer, ty: Ty, width: u64) -> NodeId {
+        self.node(NodeKind::Const(bits), ty, width)
+    }
+
+    fn boolean(&mut self, value: bool) -> NodeId {
+        self.constant(Integer::from(u8::from(value)), Ty::Bool, 1)
+    }
+
+    fn unit(&mut self) -> NodeId {
+        self.constant(Integer::new(), Ty::unit(), 0)
+    }
+
+    fn as_const(&self, node: NodeId) -> Option<&Integer> {
+        match &self.module.node(node).kind {
+            NodeKind::Const(bits) => Some(bits),
+            _ => None,
+        }
+    }
+
+    fn read(&mut self, net: NetId) -> NodeId {
+        let (ty, width) = {
+            let net = self.module.net(net);
+            (net.ty.clone(), net.width)
+        };
+        self.node(NodeKind::Net(net), ty, width)
+    }
+
+    fn slice(&mut self, on: NodeId, lo: u64, ty: Ty, width: u64) -> NodeId {
+        if lo == 0 && width == self.node_width(on) {
+            return on;
+        }
+        if let Some(bits) = self.as_const(on) {
+            let bits = Integer::from(bits >> lo as u32).keep_bits(width as u32);
+            return self.constant(bits, ty, width);
+        }
+        self.node(NodeKind::Slice(on, lo), ty, width)
+    }
+
+    fn concat(&mut self, parts: Vec<NodeId>, ty: Ty, width: u64) -> NodeId {
+        let parts = parts
+            .into_iter()
+            .filter(|part| self.node_width(*part) > 0)
+            .collect::<Vec<_>>();
+        if parts.iter().all(|part| self.as_const(*part).is_some()) {
+            let mut bits = Integer::new();
+            let mut lo = 0;
+            for part in &parts {
+                bits |= Integer::from(self.as_const(*part).unwrap() << lo as u32);
+                lo += self.node_width(*part);
+            }
+            return self.constant(bits, ty, width);
+        }
+        match parts.as_slice() {
+            [part] if self.ty(*part) == ty => *part,
+            _ => self.node(NodeKind::Concat(parts), ty, width),
+        }
+    }
+
+    fn binary(&mut self, op: BinaryOp, lhs: NodeId, rhs: NodeId, ty: Ty, width: u64) -> NodeId {
+        if matches!(op, BinaryOp::And | BinaryOp::Or) && width == 1 {
+            let (known, other) = match (self.as_const(lhs), self.as_const(rhs)) {
+                (Some(bits), _) => (Some(*bits != 0), rhs),
+                (None, Some(bits)) => (Some(*bits != 0), lhs),
+                (None, None) => (None, lhs),
+            };
+            match (op, known) {
+                (BinaryOp::And, Some(true)) | (BinaryOp::Or, Some(false)) => return other,
+                (BinaryOp::And, Some(false)) => return self.boolean(false),
+                (BinaryOp::Or, Some(true)) => return self.boolean(true),
+                _ => {}
+            }
+        }
+        self.node(NodeKind::Binary(op, lhs, rhs), ty, width)
+    }
+
+    fn and(&mut self, lhs: NodeId, rhs: NodeId) -> NodeId {
+        self.binary(BinaryOp::And, lhs, rhs, Ty::Bool, 1)
+    }
+
+    fn eq(&mut self, lhs: NodeId, rhs: NodeId) -> NodeId {
+        self.binary(BinaryOp::Eq, lhs, rhs, Ty::Bool, 1)
+    }
+
+    fn mux(&mut self, cond: NodeId, then: NodeId, otherwise: NodeId) -> NodeId {
+        if then == otherwise {
+            return then;
+        }
+        if let Some(bits) = self.as_const(cond) {
+            return if *bits != 0 { then } else { otherwise };
+        }
+        let ty = self.ty(then);
+        let width = self.node_width(then);
+        self.node(NodeKind::Mux(cond, then, otherwise), ty, width)
+    }
+
+    /// Node for a constant value of type `ty`, where integers of unbounded width get the fewest bits that hold them
+    fn value(&mut self, value: &Value, ty: Ty, span: Span, file: FileId) -> Option<NodeId> {
+        let ty = match (&ty, value) {
+            (Ty::Integer | Ty::Unsigned(None) | Ty::Signed(None), Value::Int { val, .. }) => {
+                let bits = val.significant_bits().max(1);
+                if *val < 0 {
+                    Ty::Signed(Some(bits + 1))
+                } else {
+                    Ty::Unsigned(Some(bits))
+                }
+            }
+            _ => ty,
+        };
+        let width = self.width(&ty, span.clone(), file)?;
+        match self.lowerer.encode(value, &ty) {
+            Some(bits) => Some(self.constant(bits, ty, width)),
+            None => {
+                let message = format!("cannot lay out `{}` as `{}`", value, ty);
+                self.error(message, span, file);
+                None
+            }
+        }
+    }
+
+    /// Type of `expr` as the type checker inferred it, with the generic params of the frame substituted
+    fn expr_ty(&mut self, expr: &Expr, frame: &Frame) -> Option<Ty> {
+        match self.lowerer.types.expr(frame.file(), expr) {
+            Some(ty) => Some(substitute(ty, &frame.subst)),
+            None => {
+                let message = "the type of this expression is not known";
+                self.error(message, expr.span(), frame.file());
+                None
+            }
+        }
+    }
+
+    /// Lowers the arch of an instance into the module being built
+    fn arch(&mut self, instance: &Instance<'a>) {
+        let file = instance.file();
+        let mut frame = Frame {
+            env: instance.env.clone(),
+            subst: instance.subst.clone(),
+            self_node: None,
+            scope: Scope::default(),
+            in_when: false,
+        };
+
+        for port in &instance.ports {
+            let net = self.add_net(
+                port.name(),
+                port.ty.clone(),
+                port.width,
+                Some(direction(port.port)),
+            );
+            self.module.ports.push(net);
+        }
+
+        let mut inits = BTreeMap::new();
+        for (port, net) in instance.ports.iter().zip(self.module.ports.clone()) {
+            if let (Some(value), false) = (
+                &port.default,
+                matches!(port.port.port_type, PortType::In(_)),
+            ) {
+                let span = port.port.ident.span.clone();
+                if let Some(node) = self.value(
+                    value,
+                    port.ty.clone(),
+                    span,
+                    self.lowerer.eval.cx().file(instance.entity),
+                ) {
+                    inits.insert(net, node);
+                }
+            }
+        }
+
+        let mut signals = vec![];
+        for arch_item in &instance.arch.items {
+            if let ArchItem::Let(local) = arch_item {
+                for ident in pat_idents(&local.pat) {
+                    let ty = match self.lowerer.types.local(file, &ident.span) {
+                        Some(ty) => substitute(ty, &frame.subst),
+                        None => continue,
+                    };
+                    let width = match self.width(&ty, ident.span.clone(), file) {
+                        Some(width) => width,
+                        None => continue,
+                    };
+                    let net = self.add_net(&ident.inner, ty, width, None);
+                    self.signals.insert(ident.span.clone(), net);
+                }
+                signals.push(local);
+            }
+        }
+        for local in signals {
+            let init = match &local.init {
+                Some((_, init)) => init,
+                None => continue,
+            };
+            let value = match self.expr(init, &mut frame) {
+                Some(value) => value,
+                None => continue,
+            };
+            let mut bound = Scope::default();
+            std::mem::swap(&mut bound, &mut frame.scope);
+            self.bind(&local.pat, value, &mut frame);
+            std::mem::swap(&mut bound, &mut frame.scope);
+            for ident in pat_idents(&local.pat) {
+                if let (Some(net), Some(node)) =
+                    (self.signals.get(&ident.span), bound.locals.get(&ident.span))
+                {
+                    inits.insert(*net, *node);
+                }
+            }
+        }
+
+        // Nets written by each `when` block and by instances, to find those driven more than once
+        let mut drivers: HashMap<NetId, usize> = HashMap::new();
+        for arch_item in &instance.arch.items {
+            if let ArchItem::When(when) = arch_item {
+                if let Some(process) = self.when(when, &inits, &mut frame) {
+                    for update in &process.updates {
+                        *drivers.entry(update.net).or_default() += 1;
+                        if let Trigger::Posedge(_) | Trigger::Negedge(_) = process.trigger {
+                            self.module.nets[update.net.0].kind = NetKind::Reg;
+                        }
+                    }
+                    self.module.processes.push(process);
+                }
+            }
+        }
+
+        for child in &instance.children {
+            let module = self.lowerer.module(child);
+            if let Some(connections) = self.connect(child, &mut frame) {
+                for (port, (_, net)) in child.ports.iter().zip(&connections) {
+                    if !matches!(port.port.port_type, PortType::In(_)) {
+                        *drivers.entry(*net).or_default() += 1;
+                    }
+                }
+                self.module.instances.push(ModuleInstance {
+                    name: child.name.clone(),
+                    module,
+                    connections,
+                });
+            }
+        }
+
+        for (net, value) in inits {
+            match (
+                drivers.get(&net).copied().unwrap_or(0),
+                self.module.net(net).kind,
+            ) {
+                (0, _) => {
+                    *drivers.entry(net).or_default() += 1;
+                    self.module.assigns.push(Assign { net, value })
+                }
+                (_, NetKind::Reg) => match self.as_const(value).cloned() {
+                    Some(bits) => self.module.nets[net.0].init = Some(bits),
+                    None => {
+                        let message = format!(
+                            "the initial value of register `{}` must be a constant",
+                            self.module.net(net).name
+                        );
+                        self.error(message, instance.arch.entity.segments.span(), file);
+                    }
+                },
+                // The default of a signal that a combinational `when` writes is what it holds otherwise
+                (_, NetKind::Wire) => {}
+            }
+        }
+
+        let mut driven = drivers
+            .into_iter()
+            .filter(|(_, count)| *count > 1)
+            .collect::<Vec<_>>();
+        driven.sort();
+        for (net, _) in driven {
+            let message = format!("`{}` has more than one driver", self.module.net(net).name);
+            self.error(message, instance.arch.entity.segments.span(), file);
+        }
+    }
+
+    /// Lowers a `when` block into a process
+    fn when(
+        &mut self,
+        when: &ArchItemWhen,
+        inits: &BTreeMap<NetId, NodeId>,
+        frame: &mut Frame,
+    ) -> Option<Process> {
+        let (edge, guard) = self.trigger(&when.expr, frame)?;
+        self.holds = match edge {
+            Some(_) => HashMap::new(),
+            None => inits.iter().map(|(net, node)| (*net, *node)).collect(),
+        };
+        frame.scope = Scope::default();
+        frame.in_when = true;
+        let result = self.block(&when.block, frame);
+        frame.in_when = false;
+        let scope = std::mem::take(&mut frame.scope);
+        result?;
+
+        let mut updates = vec![];
+        for (net, value) in scope.pending {
+            let value = match guard {
+                Some(guard) => {
+                    let hold = self.hold(net);
+                    self.mux(guard, value, hold)
+                }
+                None => value,
+            };
+            updates.push(Assign { net, value });
+        }
+        let trigger = edge.unwrap_or(Trigger::Always);
+        Some(Process { trigger, updates })
+    }
+
+    /// The clock edge that a `when` waits for, if any, and the condition under which it runs
+    fn trigger(
+        &mut self,
+        cond: &Expr,
+        frame: &mut Frame,
+    ) -> Option<(Option<Trigger>, Option<NodeId>)> {
+        match cond {
+            Expr::Field(field) => {
+                if let Member::Named(ident) = &field.member {
+                    if ident.inner == "posedge" || ident.inner == "negedge" {
+                        let clock = self.expr(&field.on, frame)?;
+                        let net = match self.module.node(clock).kind {
+                            NodeKind::Net(net) => net,
+                            _ => {
+                                let message = "clock edges can only be taken of signals and ports";
+                                self.error(message, field.on.span(), frame.file());
+                                return None;
+                            }
+                        };
+                        let edge = match ident.inner.as_str() {
+                            "posedge" => Trigger::Posedge(net),
+                            _ => Trigger::Negedge(net),
+                        };
+                        return Some((Some(edge), None));
+                    }
+                }
+            }
+            Expr::Binary(binary) if matches!(binary.op, BinOp::And(_)) => {
+                let (left_edge, left_guard) = self.trigger(&binary.left, frame)?;
+                let (right_edge, right_guard) = self.trigger(&binary.right, frame)?;
+                let edge = match (left_edge, right_edge) {
+                    (Some(_), Some(_)) => {
+                        let message = "a `when` can only wait for one clock edge";
+                        self.error(message, cond.span(), frame.file());
+                        return None;
+                    }
+                    (left, right) => left.or(right),
+                };
+                let guard = match (left_guard, right_guard) {
+                    (Some(left), Some(right)) => Some(self.and(left, right)),
+                    (left, right) => left.or(right),
+                };
+                return Some((edge, guard));
+            }
+            Expr::Grouped(grouped) => return self.trigger(&grouped.expr, frame),
+            _ => {}
+        }
+        let guard = self.expr(cond, frame)?;
+        Some((None, Some(guard)))
+    }
+
+    /// Value that a signal keeps when the `when` block being lowered does not write it
+    fn hold(&mut self, net: NetId) -> NodeId {
+        match self.holds.get(&net) {
+            Some(node) => *node,
+            None => self.read(net),
+        }
+    }
+
+    /// Nets connected to the ports of a child instance, adding wires for values that are not nets
+    fn connect(&mut self, child: &Instance<'a>, frame: &mut Frame) -> Option<Vec<(String, NetId)>> {
+        let expr = child.instantiation?;
+        let file = frame.file();
+        let mut connections = vec![];
+        for port in &child.ports {
+            let field = expr
+                .fields
+                .iter()
+                .find(|field| field.ident.inner == port.name());
+            let value = match field {
+                Some(field) => match &field.expr {
+                    Some((_, value)) => self.expr(value, frame)?,
+                    None => self.shorthand(&field.ident, frame)?,
+                },
+                None => match &port.default {
+                    Some(value) => {
+                        let entity_file = self.lowerer.eval.cx().file(child.entity);
+                        self.value(
+                            value,
+                            port.ty.clone(),
+                            port.port.ident.span.clone(),
+                            entity_file,
+                        )?
+                    }
+                    None => {
+                        let name = format!("{}_{}", child.name, port.name());
+                        let net = self.add_net(&name, port.ty.clone(), port.width, None);
+                        connections.push((port.name().to_string(), net));
+                        continue;
+                    }
+                },
+            };
+            let net = match (&self.module.node(value).kind, &port.port.port_type) {
+                (NodeKind::Net(net), _) => *net,
+                (_, PortType::In(_)) => {
+                    let name = format!("{}_{}", child.name, port.name());
+                    let net = self.add_net(&name, port.ty.clone(), port.width, None);
+                    self.module.assigns.push(Assign { net, value });
+                    net
+                }
+                (_, _) => {
+                    let message = format!(
+                        "`{}` port `{}` of `{}` can only be connected to a whole signal or port",
+                        direction(port.port),
+                        port.name(),
+                        child.entity_name
+                    );
+                    let span =
+                        field.map_or(expr.path.segments.span(), |field| field.ident.span.clone());
+                    self.error(message, span, file);
+                    return None;
+                }
+            };
+            connections.push((port.name().to_string(), net));
+        }
+        Some(connections)
+    }
+
+    /// Value of a field written as a bare name, as in `Adder { clk, a }`
+    fn shorthand(&mut self, ident: &Ident, frame: &mut Frame) -> Option<NodeId> {
+        let resolution = self.resolutions().ident(frame.file(), ident).cloned();
+        match resolution {
+            Some(resolution) => self.res(&resolution, ident.span.clone(), frame),
+            None => {
+                let message = format!("cannot find `{}`", ident);
+                self.error(message, ident.span.clone(), frame.file());
+                None
+            }
+        }
+    }
+
+    fn block(&mut self, block: &Block, frame: &mut Frame) -> Option<NodeId> {
+        let mut value = self.unit();
+        let count = block.statements.len();
+        for (i, stmt) in block.statements.iter().enumerate() {
+            if frame.scope.returned {
+                self.unsupported("code after a `return`", stmt.span(), frame.file());
+                return None;
+            }
+            value = self.unit();
+            match stmt {
+                Stmt::Local(local) => {
+                    let init = match &local.init {
+                        Some((_, init)) => self.expr(init, frame)?,
+                        None => {
+                            // A `let` without a value holds nothing until it is assigned
+                            let idents = pat_idents(&local.pat);
+                            for ident in idents {
+                                let ty =
+                                    self.lowerer.types.local(frame.file(), &ident.span).cloned();
+                                let ty = substitute(&ty.unwrap_or(Ty::Error), &frame.subst);
+                                let width = self.width(&ty, ident.span.clone(), frame.file())?;
+                                let zero = self.constant(Integer::new(), ty, width);
+                                frame.scope.locals.insert(ident.span.clone(), zero);
+                            }
+                            continue;
+                        }
+                    };
+                    self.bind(&local.pat, init, frame);
+                }
+                Stmt::Item(_) => {}
+                Stmt::Expr(stmt) => {
+                    let result = self.expr(&stmt.expr, frame)?;
+                    if stmt.semi.is_none() || frame.scope.returned {
+                        value = result;
+                    }
+                }
+            }
+            if frame.scope.returned && i + 1 < count {
+                self.unsupported(
+                    "code after a `return`",
+                    block.statements[i + 1].span(),
+                    frame.file(),
+                );
+                return None;
+            }
+        }
+        Some(value)
+    }
+
+    fn expr(&mut self, expr: &Expr, frame: &mut Frame) -> Option<NodeId> {
+        let file = frame.file();
+        if is_const(self.resolutions(), expr, &frame.env) {
+            let value = self.eval().eval_expr(expr, &mut frame.env.clone())?;
+            let ty = self.expr_ty(expr, frame)?;
+            return self.value(&value, ty, expr.span(), file);
+        }
+        match expr {
+            Expr::Path(path) => {
+                let resolution = self.resolutions().expr_path(file, path).cloned();
+                match resolution {
+                    Some(resolution) if resolution.unresolved_segments == 0 => {
+                        self.res(&resolution, path.span(), frame)
+                    }
+                    _ => {
+                        self.unsupported("this path", path.span(), file);
+                        None
+                    }
+                }
+            }
+            Expr::Grouped(grouped) => self.expr(&grouped.expr, frame),
+            Expr::Block(block) => self.block(block, frame),
+            Expr::Unary(unary) => {
+                let arg = self.expr(&unary.expr, frame)?;
+                let ty = self.expr_ty(expr, frame)?;
+                let width = self.width(&ty, expr.span(), file)?;
+                let op = match unary.op {
+                    UnOp::Not(_) => UnaryOp::Not,
+                    UnOp::Minus(_) => UnaryOp::Neg,
+                };
+                Some(self.node(NodeKind::Unary(op, arg), ty, width))
+            }
+            Expr::Binary(binary) => {
+                let lhs = self.expr(&binary.left, frame)?;
+                let rhs = self.expr(&binary.right, frame)?;
+                let ty = self.expr_ty(expr, frame)?;
+                let width = self.width(&ty, expr.span(), file)?;
+                Some(self.binary(BinaryOp::of(&binary.op), lhs, rhs, ty, width))
+            }
+            Expr::Cast(cast) => {
+                let arg = self.expr(&cast.expr, frame)?;
+                let ty = self.expr_ty(expr, frame)?;
+                let width = self.width(&ty, expr.span(), file)?;
+                if self.ty(arg) == ty {
+                    return Some(arg);
+                }
+                Some(self.node(NodeKind::Cast(arg), ty, width))
+            }
+            Expr::Field(field) => self.field(field, frame),
+            Expr::Index(index) => {
+                let on = self.expr(&index.on, frame)?;
+                let ty = self.expr_ty(expr, frame)?;
+                let width = self.width(&ty, expr.span(), file)?;
+                match self.position(&index.on, &index.index, frame)? {
+                    Position::Bits(lo) => Some(self.slice(on, lo, ty, width)),
+                    Position::Node(by) => Some(self.node(NodeKind::Index(on, by), ty, width)),
+                }
+            }
+            Expr::Tuple(tuple) => {
+                let elements = tuple
+                    .elements
+                    .iter()
+                    .map(|element| self.expr(element, frame))
+                    .collect::<Option<Vec<_>>>()?;
+                let ty = self.expr_ty(expr, frame)?;
+                let width = self.width(&ty, expr.span(), file)?;
+                Some(self.concat(elements, ty, width))
+            }
+            Expr::Array(array) => {
+                let elements = array
+                    .elements
+                    .iter()
+                    .map(|element| self.expr(element, frame))
+                    .collect::<Option<Vec<_>>>()?;
+                let ty = self.expr_ty(expr, frame)?;
+                let width = self.width(&ty, expr.span(), file)?;
+                Some(self.concat(elements, ty, width))
+            }
+            Expr::Repeat(repeat) => {
+                let element = self.expr(&repeat.init, frame)?;
+                // The count can name a const generic param, which is only known for this instance
+                let cx = self.lowerer.eval.cx();
+                let count = match cx.len(file, &repeat.repeat, &frame.subst) {
+                    Some(count) => count,
+                    None => {
+                        let message = "the length of this array is not known";
+                        self.error(message, repeat.repeat.span(), file);
+                        return None;
+                    }
+                };
+                let ty = Ty::Array(Box::new(self.ty(element)), count);
+                let width = self.width(&ty, expr.span(), file)?;
+                Some(self.concat(vec![element; count as usize], ty, width))
+            }
+            Expr::Struct(expr_struct) => self.construct_struct(expr, expr_struct, frame),
+            Expr::Call(call) => self.call(expr, call, frame),
+            Expr::MethodCall(call) => self.method_call(call, frame),
+            Expr::If(expr_if) => self.expr_if(expr_if, frame),
+            Expr::Match(expr_match) => self.expr_match(expr_match, frame),
+            Expr::For(expr_for) => self.expr_for(expr_for, frame),
+            Expr::Assign(assign) => {
+                self.assign(assign, frame)?;
+                Some(self.unit())
+            }
+            Expr::Return(expr_return) => {
+                let value = match &expr_return.expr {
+                    Some(value) => self.expr(value, frame)?,
+                    None => self.unit(),
+                };
+                frame.scope.returned = true;
+                Some(value)
+            }
+            Expr::Lit(_) | Expr::Range(_) | Expr::QPath(_) => {
+                self.unsupported("this expression", expr.span(), file);
+                None
+            }
+        }
+    }
+
+    /// Value of what a path resolves to
+    fn res(
+        &mut self,
+        resolution: &PathResolution,
+        span: Span,
+        frame: &mut Frame,
+    ) -> Option<NodeId> {
+        let file = frame.file();
+        match &resolution.res {
+            Res::Local(local) => {
+                if let Some(node) = frame.scope.locals.get(local) {
+                    return Some(*node);
+                }
+                if let Some(net) = self.signals.get(local).copied() {
+                    return Some(self.read(net));
+                }
+                let message = "this binding has no value here";
+                self.error(message, span, file);
+                None
+            }
+            Res::Port { index, .. } if frame.self_node.is_none() => {
+                let net = *self.module.ports.get(*index)?;
+                Some(self.read(net))
+            }
+            Res::SelfValue => match frame.self_node {
+                Some(node) => Some(node),
+                None => {
+                    self.unsupported("`self` as a value", span, file);
+                    None
+                }
+            },
+            _ => {
+                self.unsupported("this path", span, file);
+                None
+            }
+        }
+    }
+
+    fn field(&mut self, field: &ExprField, frame: &mut Frame) -> Option<NodeId> {
+        let file = frame.file();
+        let name = member_name(&field.member);
+        if frame.self_node.is_none() && is_self(self.resolutions(), file, &field.on) {
+            let port = self
+                .module
+                .ports
+                .iter()
+                .copied()
+                .find(|port| self.module.net(*port).name == name);
+            return match port {
+                Some(port) => Some(self.read(port)),
+                None => {
+                    self.unsupported(&format!("`self.{}`", name), field.member.span(), file);
+                    None
+                }
+            };
+        }
+        let on = self.expr(&field.on, frame)?;
+        let on_ty = self.ty(on);
+        let (lo, ty, width) = match self.member_layout(&on_ty, &name) {
+            Some(layout) => layout,
+            None => {
+                self.unsupported(
+                    &format!("the field `{}` of `{}`", name, on_ty),
+                    field.member.span(),
+                    file,
+                );
+                return None;
+            }
+        };
+        Some(self.slice(on, lo, ty, width))
+    }
+
+    /// Offset, type and width of a field of a struct or tuple
+    fn member_layout(&self, ty: &Ty, name: &str) -> Option<(u64, Ty, u64)> {
+        let cx = self.lowerer.eval.cx();
+        let fields = match ty {
+            Ty::Tuple(tys) => tys
+                .iter()
+                .enumerate()
+                .map(|(i, ty)| (i.to_string(), ty.clone()))
+                .collect(),
+            Ty::Adt { def, args, .. } if self.resolutions().def(*def).kind == DefKind::Struct => {
+                cx.fields(*def, args).1
+            }
+            _ => return None,
+        };
+        let mut lo = 0;
+        for (field, ty) in fields {
+            let width = cx.width(&ty)?;
+            if field == name {
+                return Some((lo, ty, width));
+            }
+            lo += width;
+        }
+        None
+    }
+
+    /// Where the element that `index` selects from `on` starts, in bits when it is a constant
+    fn position(&mut self, on: &Expr, index: &Expr, frame: &mut Frame) -> Option<Position> {
+        let on_ty = self.expr_ty(on, frame)?;
+        let element = match &on_ty {
+            Ty::Array(element, _) => self.width(element, on.span(), frame.file())?,
+            _ => 1,
+        };
+        let start = match index {
+            Expr::Range(range) => match &range.left {
+                Some(left) => left.as_ref(),
+                None => return Some(Position::Bits(0)),
+            },
+            index => index,
+        };
+        if is_const(self.resolutions(), start, &frame.env) {
+            let value = self.eval().eval_expr(start, &mut frame.env.clone())?;
+            let lo = value.as_int().and_then(Integer::to_u64);
+            return match lo {
+                Some(lo) => Some(Position::Bits(lo * element)),
+                None => {
+                    let message = format!("index `{}` is out of range", value);
+                    self.error(message, start.span(), frame.file());
+                    None
+                }
+            };
+        }
+        if let Expr::Range(_) = index {
+            self.unsupported(
+                "a slice with bounds that are not constant",
+                index.span(),
+                frame.file(),
+            );
+            return None;
+        }
+        self.expr(index, frame).map(Position::Node)
+    }
+
+    fn construct_struct(
+        &mut self,
+        expr: &Expr,
+        expr_struct: &ExprStruct,
+        frame: &mut Frame,
+    ) -> Option<NodeId> {
+        let file = frame.file();
+        let ty = self.expr_ty(expr, frame)?;
+        let id = match self.resolutions().expr_path(file, &expr_struct.path) {
+            Some(PathResolution {
+                res: Res::Def(id),
+                unresolved_segments: 0,
+            }) => *id,
+            _ => {
+                self.unsupported("this struct", expr_struct.path.span(), file);
+                return None;
+            }
+        };
+        let base = match &expr_struct.base {
+            Some((_, base)) => Some(self.expr(base, frame)?),
+            None => None,
+        };
+        let args = match &ty {
+            Ty::Adt { args, .. } => args.clone(),
+            _ => vec![],
+        };
+        let (_, declared) = self.lowerer.eval.cx().fields(id, &args);
+        let mut values = vec![];
+        for (name, _) in declared {
+            let given = expr_struct
+                .fields
+                .iter()
+                .find(|field| member_name(&field.member) == name);
+            let value = match given {
+                Some(field) => match (&field.expr, &field.member) {
+                    (Some((_, value)), _) => self.expr(value, frame)?,
+                    (None, Member::Named(ident)) => self.shorthand(ident, frame)?,
+                    (None, Member::Unnamed(_)) => return None,
+                },
+                None => {
+                    let base = base?;
+                    let (lo, ty, width) = self.member_layout(&ty, &name)?;
+                    self.slice(base, lo, ty, width)
+                }
+            };
+            values.push(value);
+        }
+        self.construct(id, values, ty, expr.span(), file)
+    }
+
+    /// A struct or enum variant made of the values of its fields
+    fn construct(
+        &mut self,
+        id: DefId,
+        fields: Vec<NodeId>,
+        ty: Ty,
+        span: Span,
+        file: FileId,
+    ) -> Option<NodeId> {
+        let width = self.width(&ty, span, file)?;
+        let def = self.resolutions().def(id);
+        let mut parts = vec![];
+        if def.kind == DefKind::Variant {
+            let owner = self.lowerer.eval.cx().parent_enum(id);
+            let tag_width = self.lowerer.tag_width(&owner);
+            let tag_ty = Ty::Unsigned(Some(tag_width as u32));
+            let tag = self.lowerer.tag(id);
+            parts.push(self.constant(tag, tag_ty, tag_width));
+        }
+        parts.extend(fields);
+        let used = parts.iter().map(|part| self.node_width(*part)).sum::<u64>();
+        if used < width {
+            let padding = width - used;
+            parts.push(self.constant(Integer::new(), Ty::Unsigned(Some(padding as u32)), padding));
+        }
+        Some(self.concat(parts, ty, width))
+    }
+
+    fn call(&mut self, expr: &Expr, call: &ExprCall, frame: &mut Frame) -> Option<NodeId> {
+        let file = frame.file();
+        let path = match call.on.as_ref() {
+            Expr::Path(path) => path,
+            on => {
+                self.unsupported("calling this expression", on.span(), file);
+                return None;
+            }
+        };
+        let id = match self.resolutions().expr_path(file, path) {
+            Some(PathResolution {
+                res: Res::Def(id),
+                unresolved_segments: 0,
+            }) => *id,
+            _ => {
+                self.unsupported("calling this expression", path.span(), file);
+                return None;
+            }
+        };
+        let args = call
+            .args
+            .iter()
+            .map(|arg| self.expr(arg, frame))
+            .collect::<Option<Vec<_>>>()?;
+        match (
+            self.resolutions().def(id).kind,
+            self.lowerer.eval.cx().item(id),
+        ) {
+            (DefKind::Fn, Item::Fn(item)) => {
+                let mut env = frame.env.clone();
+                let params = self
+                    .eval()
+                    .const_params(id, path.segments.last(), &mut env)?;
+                let mut subst = Subst::default();
+                for (span, value) in &params {
+                    if let Some(len) = value.as_int().and_then(Integer::to_u64) {
+                        subst.consts.insert(span.clone(), len);
+                    }
+                }
+                let mut callee = Env::new(self.lowerer.eval.cx().file(id));
+                callee.params = params;
+                if let (Some(generics), Some(segment)) = (&item.sig.generics, path.segments.last())
+                {
+                    let given = segment
+                        .generic_args
+                        .iter()
+                        .flat_map(|args| args.args.iter())
+                        .zip(generics.params.iter());
+                    for (arg, param) in given {
+                        if let (GenericArg::Type(ty), GenericParam::Type(param)) = (arg, param) {
+                            let ty = self.lowerer.eval.cx().lower(file, ty, &frame.subst);
+                            subst.params.insert(param.ident.span.clone(), ty);
+                        }
+                    }
+                }
+                self.inline(item, args, None, callee, subst, call.on.span(), file)
+            }
+            (DefKind::Struct, _) | (DefKind::Variant, _) => {
+                let ty = self.expr_ty(expr, frame)?;
+                self.construct(id, args, ty, expr.span(), file)
+            }
+            _ => {
+                self.unsupported("calling this expression", path.span(), file);
+                None
+            }
+        }
+    }
+
+    fn method_call(&mut self, call: &ExprMethodCall, frame: &mut Frame) -> Option<NodeId> {
+        let file = frame.file();
+        let on = self.expr(&call.on, frame)?;
+        let on_ty = self.ty(on);
+        let method = match &on_ty {
+            Ty::Adt { def, .. } => self
+                .lowerer
+                .methods
+                .get(def)
+                .and_then(|methods| {
+                    methods
+                        .iter()
+                        .find(|method| method.item.sig.ident == call.method)
+                })
+                .map(|method| (method.file, method.item, method.self_ty.clone())),
+            _ => None,
+        };
+        let (method_file, item, self_ty) = match method {
+            Some(method) => method,
+            None => {
+                self.unsupported(
+                    &format!("the method `{}` of `{}`", call.method, on_ty),
+                    call.method.span.clone(),
+                    file,
+                );
+                return None;
+            }
+        };
+        let args = call
+            .args
+            .iter()
+            .map(|arg| self.expr(arg, frame))
+            .collect::<Option<Vec<_>>>()?;
+        let mut subst = Subst::default();
+        bind_params(&self_ty, &on_ty, &mut subst);
+        subst.self_ty = Some(on_ty);
+        let callee = Env::new(method_file);
+        self.inline(
+            item,
+            args,
+            Some(on),
+            callee,
+            subst,
+            call.method.span.clone(),
+            file,
+        )
+    }
+
+    /// Lowers the body of a function in place of a call to it
+    #[allow(clippy::too_many_arguments)]
+    fn inline(
+        &mut self,
+        item: &'a ItemFn,
+        args: Vec<NodeId>,
+        self_node: Option<NodeId>,
+        env: Env,
+        mut subst: Subst,
+        span: Span,
+        file: FileId,
+    ) -> Option<NodeId> {
+        if self.depth >= MAX_INLINE_DEPTH {
+            let message = format!(
+                "reached the recursion limit of {} while inlining `{}`",
+                MAX_INLINE_DEPTH, item.sig.ident
+            );
+            self.error(message, span, file);
+            return None;
+        }
+        let inputs = item
+            .sig
+            .inputs
+            .iter()
+            .filter_map(|input| match input {
+                FnArg::Typed(typed) => Some(typed),
+                FnArg::Receiver(_) => None,
+            })
+            .collect::<Vec<_>>();
+        for (input, arg) in inputs.iter().zip(&args) {
+            let declared = self.lowerer.eval.cx().lower(env.file, &input.ty, &subst);
+            bind_params(&declared, &self.ty(*arg), &mut subst);
+        }
+        let mut callee = Frame {
+            env,
+            subst,
+            self_node,
+            scope: Scope::default(),
+            in_when: false,
+        };
+        for (input, arg) in inputs.into_iter().zip(args) {
+            self.bind(&input.pat, arg, &mut callee);
+        }
+        self.depth += 1;
+        let result = self.block(&item.block, &mut callee);
+        self.depth -= 1;
+        result
+    }
+
+    fn expr_if(&mut self, expr_if: &ExprIf, frame: &mut Frame) -> Option<NodeId> {
+        let cond = self.expr(&expr_if.expr, frame)?;
+        let before = frame.scope.clone();
+        let then = self.block(&expr_if.block, frame)?;
+        let then_scope = std::mem::replace(&mut frame.scope, before.clone());
+        let otherwise = match &expr_if.else_token {
+            Some((_, otherwise)) => self.expr(otherwise, frame)?,
+            None => self.unit(),
+        };
+        let else_scope = std::mem::replace(&mut frame.scope, before);
+        frame.scope = self.merge(cond, then_scope, else_scope, &frame.scope.clone());
+        Some(self.mux(cond, then, otherwise))
+    }
+
+    /// Scope after a branch on `cond`, taking the values of `then` where it is set and of `otherwise` elsewhere
+    fn merge(&mut self, cond: NodeId, then: Scope, otherwise: Scope, before: &Scope) -> Scope {
+        let mut merged = before.clone();
+        for (span, value) in &before.locals {
+            let then_value = then.locals.get(span).copied().unwrap_or(*value);
+            let else_value = otherwise.locals.get(span).copied().unwrap_or(*value);
+            let value = self.mux(cond, then_value, else_value);
+            merged.locals.insert(span.clone(), value);
+        }
+        let nets = then
+            .pending
+            .keys()
+            .chain(otherwise.pending.keys())
+            .copied()
+            .collect::<Vec<_>>();
+        for net in nets {
+            let then_value = match then.pending.get(&net) {
+                Some(value) => *value,
+                None => self.hold(net),
+            };
+            let else_value = match otherwise.pending.get(&net) {
+                Some(value) => *value,
+                None => self.hold(net),
+            };
+            let value = self.mux(cond, then_value, else_value);
+            merged.pending.insert(net, value);
+        }
+        merged.returned = then.returned || otherwise.returned;
+        merged
+    }
+
+    fn expr_match(&mut self, expr_match: &ExprMatch, frame: &mut Frame) -> Option<NodeId> {
+        let scrutinee = self.expr(&expr_match.expr, frame)?;
+        let before = frame.scope.clone();
+        let mut arms = vec![];
+        for arm in expr_match.arms.iter() {
+            frame.scope = before.clone();
+            let mut cond = self.pat(&arm.pat, scrutinee, frame)?;
+            if let Some((_, guard)) = &arm.guard {
+                let guard = self.expr(guard, frame)?;
+                cond = self.and(cond, guard);
+            }
+            let value = self.expr(&arm.body, frame)?;
+            arms.push((cond, value, std::mem::take(&mut frame.scope)));
+        }
+        frame.scope = before;
+        // The last arm is taken whenever no other one is, as the type checker ensures that arms cover every value
+        let (_, mut value, mut scope) = match arms.pop() {
+            Some(arm) => arm,
+            None => return Some(self.unit()),
+        };
+        while let Some((cond, arm_value, arm_scope)) = arms.pop() {
+            value = self.mux(cond, arm_value, value);
+            scope = self.merge(cond, arm_scope, scope, &frame.scope.clone());
+        }
+        frame.scope = scope;
+        Some(value)
+    }
+
+    fn expr_for(&mut self, expr_for: &ExprFor, frame: &mut Frame) -> Option<NodeId> {
+        let file = frame.file();
+        let range = if is_const(self.resolutions(), &expr_for.expr, &frame.env) {
+            self.eval()
+                .eval_expr(&expr_for.expr, &mut frame.env.clone())?
+        } else {
+            self.unsupported(
+                "a `for` loop over a range that is not constant",
+                expr_for.expr.span(),
+                file,
+            );
+            return None;
+        };
+        let (start, end) = match range {
+            Value::Range(start, end) => (start, end),
+            value => {
+                self.unsupported(
+                    &format!("a `for` loop over `{}`", value),
+                    expr_for.expr.span(),
+                    file,
+                );
+                return None;
+            }
+        };
+        if Integer::from(&end - &start) > MAX_UNROLL {
+            let message = format!(
+                "cannot unroll a loop of more than {} iterations",
+                MAX_UNROLL
+            );
+            self.error(message, expr_for.expr.span(), file);
+            return None;
+        }
+        let ident = match expr_for.pat.as_ref() {
+            Pat::Ident(ident) => Some(ident),
+            Pat::Wildcard(_) => None,
+            pat => {
+                self.unsupported("this pattern in a `for` loop", pat.span(), file);
+                return None;
+            }
+        };
+        let ty = match ident.and_then(|ident| self.lowerer.types.local(file, &ident.span)) {
+            Some(ty) => substitute(ty, &frame.subst),
+            None => Ty::Integer,
+        };
+        let mut i = start;
+        while i < end {
+            if let Some(ident) = ident {
+                let value = Value::Int {
+                    val: i.clone(),
+                    ty: ty.clone(),
+                };
+                frame.env.locals.insert(ident.span.clone(), value.clone());
+                let node = self.value(&value, ty.clone(), ident.span.clone(), file)?;
+                frame.scope.locals.insert(ident.span.clone(), node);
+            }
+            self.block(&expr_for.block, frame)?;
+            i += 1;
+        }
+        if let Some(ident) = ident {
+            frame.env.locals.remove(&ident.span);
+        }
+        Some(self.unit())
+    }
+
+    fn assign(&mut self, assign: &ExprAssign, frame: &mut Frame) -> Option<()> {
+        let rhs = self.expr(&assign.rhs, frame)?;
+        let value = match BinaryOp::of_assign(&assign.op) {
+            Some(op) => {
+                let lhs = self.expr(&assign.lhs, frame)?;
+                let ty = self.ty(lhs);
+                let width = self.node_width(lhs);
+                self.binary(op, lhs, rhs, ty, width)
+            }
+            None => rhs,
+        };
+        self.store(&assign.lhs, value, frame)
+    }
+
+    /// Writes `value` to the place `lhs`
+    fn store(&mut self, lhs: &Expr, value: NodeId, frame: &mut Frame) -> Option<()> {
+        let file = frame.file();
+        match lhs {
+            Expr::Grouped(grouped) => self.store(&grouped.expr, value, frame),
+            Expr::Tuple(tuple) => {
+                let mut lo = 0;
+                for element in tuple.elements.iter() {
+                    let ty = self.expr_ty(element, frame)?;
+                    let width = self.width(&ty, element.span(), file)?;
+                    let part = self.slice(value, lo, ty, width);
+                    self.store(element, part, frame)?;
+                    lo += width;
+                }
+                Some(())
+            }
+            Expr::Path(path) => {
+                let resolution = self.resolutions().expr_path(file, path).cloned();
+                match resolution.map(|resolution| resolution.res) {
+                    Some(Res::Local(span)) if frame.scope.locals.contains_key(&span) => {
+                        frame.scope.locals.insert(span, value);
+                        Some(())
+                    }
+                    Some(Res::Local(span)) if frame.in_when && self.signals.contains_key(&span) => {
+                        let net = self.signals[&span];
+                        frame.scope.pending.insert(net, value);
+                        Some(())
+                    }
+                    Some(Res::Port { index, .. }) if frame.in_when && frame.self_node.is_none() => {
+                        let net = *self.module.ports.get(index)?;
+                        frame.scope.pending.insert(net, value);
+                        Some(())
+                    }
+                    _ => {
+                        self.unsupported("assigning to this", lhs.span(), file);
+                        None
+                    }
+                }
+            }
+            Expr::Field(field)
+                if frame.in_when
+                    && frame.self_node.is_none()
+                    && is_self(self.resolutions(), file, &field.on) =>
+            {
+                let name = member_name(&field.member);
+                let port = self
+                    .module
+                    .ports
+                    .iter()
+                    .copied()
+                    .find(|port| self.module.net(*port).name == name)?;
+                frame.scope.pending.insert(port, value);
+                Some(())
+            }
+            Expr::Field(field) => {
+                let on = self.current(&field.on, frame)?;
+                let on_ty = self.ty(on);
+                let name = member_name(&field.member);
+                let (lo, _, _) = match self.member_layout(&on_ty, &name) {
+                    Some(layout) => layout,
+                    None => {
+                        self.unsupported("assigning to this field", field.member.span(), file);
+                        return None;
+                    }
+                };
+                let updated = self.splice(on, lo, value);
+                self.store(&field.on, updated, frame)
+            }
+            Expr::Index(index) => {
+                let on = self.current(&index.on, frame)?;
+                let width = self.node_width(value);
+                let updated = match self.position(&index.on, &index.index, frame)? {
+                    Position::Bits(lo) => self.splice(on, lo, value),
+                    Position::Node(by) => {
+                        // Each element takes the value where the index selects it
+                        let count = self.node_width(on) / width.max(1);
+                        let by_ty = self.ty(by);
+                        let by_width = self.node_width(by);
+                        let element_ty = self.ty(value);
+                        let mut elements = vec![];
+                        for i in 0..count {
+                            let old = self.slice(on, i * width, element_ty.clone(), width);
+                            let position = self.constant(Integer::from(i), by_ty.clone(), by_width);
+                            let selected = self.eq(by, position);
+                            elements.push(self.mux(selected, value, old));
+                        }
+                        let ty = self.ty(on);
+                        let on_width = self.node_width(on);
+                        self.concat(elements, ty, on_width)
+                    }
+                };
+                self.store(&index.on, updated, frame)
+            }
+            _ => {
+                self.unsupported("assigning to this", lhs.span(), file);
+                None
+            }
+        }
+    }
+
+    /// Value that a place holds at this point of the block, which includes the writes made to it so far
+    fn current(&mut self, place: &Expr, frame: &mut Frame) -> Option<NodeId> {
+        let file = frame.file();
+        let net = match place {
+            Expr::Path(path) => match self
+                .resolutions()
+                .expr_path(file, path)
+                .map(|resolution| &resolution.res)
+            {
+                Some(Res::Local(span)) if !frame.scope.locals.contains_key(span) => {
+                    self.signals.get(span).copied()
+                }
+                Some(Res::Port { index, .. }) => self.module.ports.get(*index).copied(),
+                _ => None,
+            },
+            Expr::Field(field)
+                if frame.self_node.is_none() && is_self(self.resolutions(), file, &field.on) =>
+            {
+                let name = member_name(&field.member);
+                self.module
+                    .ports
+                    .iter()
+                    .copied()
+                    .find(|port| self.module.net(*port).name == name)
+            }
+            Expr::Grouped(grouped) => return self.current(&grouped.expr, frame),
+            Expr::Field(field) => {
+                let on = self.current(&field.on, frame)?;
+                let on_ty = self.ty(on);
+                let (lo, ty, width) = self.member_layout(&on_ty, &member_name(&field.member))?;
+                return Some(self.slice(on, lo, ty, width));
+            }
+            _ => None,
+        };
+        match net {
+            Some(net) if frame.in_when => match frame.scope.pending.get(&net) {
+                Some(value) => Some(*value),
+                None => Some(self.hold(net)),
+            },
+            _ => self.expr(place, frame),
+        }
+    }
+
+    /// `on` with the bits from `lo` replaced by `value`
+    fn splice(&mut self, on: NodeId, lo: u64, value: NodeId) -> NodeId {
+        let ty = self.ty(on);
+        let width = self.node_width(on);
+        let value_width = self.node_width(value);
+        let low = self.slice(on, 0, Ty::Unsigned(Some(lo as u32)), lo);
+        let hi = lo + value_width;
+        let high = self.slice(on, hi, Ty::Unsigned(Some((width - hi) as u32)), width - hi);
+        self.concat(vec![low, value, high], ty, width)
+    }
+
+    /// Binds the names in an irrefutable pattern
+    fn bind(&mut self, pat: &Pat, value: NodeId, frame: &mut Frame) {
+        let _ = self.pat(pat, value, frame);
+    }
+
+    /// Condition under which `value` matches `pat`, binding the names within it
+    fn pat(&mut self, pat: &Pat, value: NodeId, frame: &mut Frame) -> Option<NodeId> {
+        let file = frame.file();
+        let ty = self.ty(value);
+        match pat {
+            Pat::Ident(ident) => {
+                frame.scope.locals.insert(ident.span.clone(), value);
+                Some(self.boolean(true))
+            }
+            Pat::Wildcard(_) => Some(self.boolean(true)),
+            Pat::Lit(lit) => {
+                let lit = self
+                    .eval()
+                    .eval_expr(&Expr::Lit(lit.clone()), &mut frame.env.clone())?;
+                let expected = self.value(&lit, ty, pat.span(), file)?;
+                Some(self.eq(value, expected))
+            }
+            Pat::Range(range) => {
+                let low = self.eval().eval_expr(&range.left, &mut frame.env.clone())?;
+                let high = self
+                    .eval()
+                    .eval_expr(&range.right, &mut frame.env.clone())?;
+                let low = self.value(&low, ty.clone(), range.left.span(), file)?;
+                let high = self.value(&high, ty, range.right.span(), file)?;
+                let above = self.binary(BinaryOp::Ge, value, low, Ty::Bool, 1);
+                let op = match range.range_type {
+                    RangeType::Closed(_) => BinaryOp::Le,
+                    RangeType::HalfOpen(_) => BinaryOp::Lt,
+                };
+                let below = self.binary(op, value, high, Ty::Bool, 1);
+                Some(self.and(above, below))
+            }
+            Pat::Path(path) => {
+                let resolution = self.resolutions().expr_path(file, path).cloned();
+                match resolution.map(|resolution| resolution.res) {
+                    Some(Res::Def(id)) if self.resolutions().def(id).kind == DefKind::Variant => {
+                        Some(self.is_variant(value, id))
+                    }
+                    _ => {
+                        let expected = self
+                            .eval()
+                            .eval_expr(&Expr::Path(path.clone()), &mut frame.env.clone())?;
+                        let expected = self.value(&expected, ty, path.span(), file)?;
+                        Some(self.eq(value, expected))
+                    }
+                }
+            }
+            Pat::Tuple(tuple) => {
+                let tys = match &ty {
+                    Ty::Tuple(tys) => tys.clone(),
+                    _ => return None,
+                };
+                let elements = self.elements(value, &tys)?;
+                self.subpats(
+                    &tuple.subpats,
+                    tuple.rest_subpats.as_ref().map(|rest| &rest.2),
+                    &elements,
+                    frame,
+                )
+            }
+            Pat::Slice(slice) => {
+                let (element, len) = match &ty {
+                    Ty::Array(element, len) => (element.as_ref().clone(), *len),
+                    _ => return None,
+                };
+                let elements = self.elements(value, &vec![element; len as usize])?;
+                self.subpats(&slice.subpats, None, &elements, frame)
+            }
+            Pat::TupleStruct(tuple_struct) => {
+                let (cond, fields) = self.adt_fields(&tuple_struct.path, value, frame)?;
+                let fields = fields.into_iter().map(|(_, node)| node).collect::<Vec<_>>();
+                let rest = tuple_struct.rest_subpats.as_ref().map(|rest| &rest.2);
+                let subpats = self.subpats(&tuple_struct.subpats, rest, &fields, frame)?;
+                Some(self.and(cond, subpats))
+            }
+            Pat::Struct(pat_struct) => {
+                let (mut cond, fields) = self.adt_fields(&pat_struct.path, value, frame)?;
+                for field in pat_struct.fields.iter() {
+                    let (name, subpat) = match field {
+                        StructPatternField::TuplePat(field) => {
+                            (field.index.val.to_string(), Some(field.pat.as_ref()))
+                        }
+                        StructPatternField::IdentPat(field) => {
+                            (field.ident.inner.clone(), Some(field.pat.as_ref()))
+                        }
+                        StructPatternField::Ident(ident) => (ident.inner.clone(), None),
+                    };
+                    let node = fields.iter().find(|(field, _)| *field == name)?.1;
+                    let matched = match (subpat, field) {
+                        (Some(subpat), _) => self.pat(subpat, node, frame)?,
+                        (None, StructPatternField::Ident(ident)) => {
+                            frame.scope.locals.insert(ident.span.clone(), node);
+                            self.boolean(true)
+                        }
+                        (None, _) => self.boolean(true),
+                    };
+                    cond = self.and(cond, matched);
+                }
+                Some(cond)
+            }
+        }
+    }
+
+    /// Slices of `value` for each of a sequence of types laid out one after another
+    fn elements(&mut self, value: NodeId, tys: &[Ty]) -> Option<Vec<NodeId>> {
+        let mut lo = 0;
+        let mut elements = vec![];
+        for ty in tys {
+            let width = self.lowerer.eval.cx().width(ty)?;
+            elements.push(self.slice(value, lo, ty.clone(), width));
+            lo += width;
+        }
+        Some(elements)
+    }
+
+    /// Matches leading subpatterns to the first values and those after `..` to the last ones
+    fn subpats(
+        &mut self,
+        subpats: &Punctuated<Pat, token::Comma>,
+        rest: Option<&Punctuated<Pat, token::Comma>>,
+        values: &[NodeId],
+        frame: &mut Frame,
+    ) -> Option<NodeId> {
+        let mut cond = self.boolean(true);
+        for (subpat, value) in subpats.iter().zip(values) {
+            let matched = self.pat(subpat, *value, frame)?;
+            cond = self.and(cond, matched);
+        }
+        if let Some(rest) = rest {
+            let skip = values.len().saturating_sub(rest.len());
+            for (subpat, value) in rest.iter().zip(&values[skip..]) {
+                let matched = self.pat(subpat, *value, frame)?;
+                cond = self.and(cond, matched);
+            }
+        }
+        Some(cond)
+    }
+
+    /// Condition that `value` is of the struct or variant named by `path`, along with its fields
+    fn adt_fields(
+        &mut self,
+        path: &ExprPath,
+        value: NodeId,
+        frame: &mut Frame,
+    ) -> Option<(NodeId, Vec<(String, NodeId)>)> {
+        let file = frame.file();
+        let id = match self.resolutions().expr_path(file, path) {
+            Some(PathResolution {
+                res: Res::Def(id),
+                unresolved_segments: 0,
+            }) => *id,
+            _ => {
+                self.unsupported("this pattern", path.span(), file);
+                return None;
+            }
+        };
+        let args = match self.ty(value) {
+            Ty::Adt { args, .. } => args,
+            _ => vec![],
+        };
+        let (cond, lo) = match self.resolutions().def(id).kind {
+            DefKind::Variant => {
+                let owner = self.lowerer.eval.cx().parent_enum(id);
+                (self.is_variant(value, id), self.lowerer.tag_width(&owner))
+            }
+            _ => (self.boolean(true), 0),
+        };
+        let (_, declared) = self.lowerer.eval.cx().fields(id, &args);
+        let tys = declared
+            .iter()
+            .map(|(_, ty)| ty.clone())
+            .collect::<Vec<_>>();
+        let payload_width = tys
+            .iter()
+            .map(|ty| self.lowerer.eval.cx().width(ty))
+            .sum::<Option<u64>>()?;
+        let payload = self.slice(
+            value,
+            lo,
+            Ty::Unsigned(Some(payload_width as u32)),
+            payload_width,
+        );
+        let nodes = self.elements(payload, &tys)?;
+        let fields = declared
+            .into_iter()
+            .map(|(name, _)| name)
+            .zip(nodes)
+            .collect();
+        Some((cond, fields))
+    }
+
+    /// Condition that an enum value is of the variant `id`
+    fn is_variant(&mut self, value: NodeId, id: DefId) -> NodeId {
+        let owner = self.lowerer.eval.cx().parent_enum(id);
+        let width = self.lowerer.tag_width(&owner);
+        if width == 0 {
+            return self.boolean(true);
+        }
+        let ty = Ty::Unsigned(Some(width as u32));
+        let tag = self.slice(value, 0, ty.clone(), width);
+        let discriminant = self.lowerer.tag(id);
+        let expected = self.constant(discriminant, ty, width);
+        self.eq(tag, expected)
+    }
+}
+
+/// Where an index selects from, which is a bit offset when it is known at compile time
+enum Position {
+    Bits(u64),
+    Node(NodeId),
+}
+
+fn direction(port: &Port) -> Direction {
+    match port.port_type {
+        PortType::In(_) => Direction::In,
+        PortType::Out(_) => Direction::Out,
+        PortType::InOut(_) => Direction::InOut,
+    }
+}
+
+/// Removes the nodes that no net depends on, renumbering the rest
+fn prune(module: &mut Module) {
+    let mut used = vec![false; module.nodes.len()];
+    let mut stack = module
+        .assigns
+        .iter()
+        .chain(module.processes.iter().flat_map(|process| &process.updates))
+        .map(|assign| assign.value)
+        .collect::<Vec<_>>();
+    while let Some(node) = stack.pop() {
+        if std::mem::replace(&mut used[node.0], true) {
+            continue;
+        }
+        stack.extend(operands(&module.nodes[node.0].kind));
+    }
+    let mut renumbered = vec![NodeId(0); module.nodes.len()];
+    let mut nodes = vec![];
+    for (i, node) in std::mem::take(&mut module.nodes).into_iter().enumerate() {
+        if !used[i] {
+            continue;
+        }
+        renumbered[i] = NodeId(nodes.len());
+        let map = |id: NodeId| renumbered[id.0];
+        let kind = match node.kind {
+            NodeKind::Unary(op, arg) => NodeKind::Unary(op, map(arg)),
+            NodeKind::Binary(op, lhs, rhs) => NodeKind::Binary(op, map(lhs), map(rhs)),
+            NodeKind::Cast(arg) => NodeKind::Cast(map(arg)),
+            NodeKind::Slice(arg, lo) => NodeKind::Slice(map(arg), lo),
+            NodeKind::Index(arg, index) => NodeKind::Index(map(arg), map(index)),
+            NodeKind::Concat(args) => NodeKind::Concat(args.into_iter().map(map).collect()),
+            NodeKind::Mux(cond, then, otherwise) => {
+                NodeKind::Mux(map(cond), map(then), map(otherwise))
+            }
+            kind => kind,
+        };
+        nodes.push(Node { kind, ..node });
+    }
+    module.nodes = nodes;
+    for assign in module.assigns.iter_mut().chain(
+        module
+            .processes
+            .iter_mut()
+            .flat_map(|process| &mut process.updates),
+    ) {
+        assign.value = renumbered[assign.value.0];
+    }
+}
+
+/// Nodes that a node reads
+pub fn operands(kind: &NodeKind) -> Vec<NodeId> {
+    match kind {
+        NodeKind::Const(_) | NodeKind::Net(_) => vec![],
+        NodeKind::Unary(_, arg) | NodeKind::Cast(arg) | NodeKind::Slice(arg, _) => vec![*arg],
+        NodeKind::Binary(_, lhs, rhs) | NodeKind::Index(lhs, rhs) => vec![*lhs, *rhs],
+        NodeKind::Concat(args) => args.clone(),
+        NodeKind::Mux(cond, then, otherwise) => vec![*cond, *then, *otherwise],
+    }
+}
+
+/// Replaces the generic params in `ty` by what `subst` gives for them
+fn substitute(ty: &Ty, subst: &Subst) -> Ty {
+    match ty {
+        Ty::Param { span, .. } => subst
+            .params
+            .get(span)
+            .cloned()
+            .unwrap_or_else(|| ty.clone()),
+        Ty::Array(element, len) => Ty::Array(Box::new(substitute(element, subst)), *len),
+        Ty::Tuple(tys) => Ty::Tuple(tys.iter().map(|ty| substitute(ty, subst)).collect()),
+        Ty::Adt { def, name, args } => Ty::Adt {
+            def: *def,
+            name: name.clone(),
+            args: args.iter().map(|ty| substitute(ty, subst)).collect(),
+        },
+        Ty::Entity { def, name, args } => Ty::Entity {
+            def: *def,
+            name: name.clone(),
+            args: args.iter().map(|ty| substitute(ty, subst)).collect(),
+        },
+        Ty::Range(ty) => Ty::Range(Box::new(substitute(ty, subst))),
+        ty => ty.clone(),
+    }
+}
+
+/// Binds the generic params in `declared` to the parts of `actual` in the same place
+fn bind_params(declared: &Ty, actual: &Ty, subst: &mut Subst) {
+    match (declared, actual) {
+        (Ty::Param { span, .. }, actual) => {
+            subst
+                .params
+                .entry(span.clone())
+                .or_insert_with(|| actual.clone());
+        }
+        (Ty::Array(declared, _), Ty::Array(actual, _)) => bind_params(declared, actual, subst),
+        (Ty::Tuple(declared), Ty::Tuple(actual)) => {
+            for (declared, actual) in declared.iter().zip(actual) {
+                bind_params(declared, actual, subst);
+            }
+        }
+        (Ty::Adt { args: declared, .. }, Ty::Adt { args: actual, .. }) => {
+            for (declared, actual) in declared.iter().zip(actual) {
+                bind_params(declared, actual, subst);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Names bound by the pattern of a `let`
+fn pat_idents(pat: &Pat) -> Vec<&Ident> {
+    match pat {
+        Pat::Ident(ident) => vec![ident],
+        Pat::Tuple(tuple) => tuple
+            .subpats
+            .iter()
+            .chain(tuple.rest_subpats.iter().flat_map(|rest| rest.2.iter()))
+            .flat_map(pat_idents)
+            .collect(),
+        Pat::Slice(slice) => slice.subpats.iter().flat_map(pat_idents).collect(),
+        _ => vec![],
+    }
+}
+
+fn member_name(member: &Member) -> String {
+    match member {
+        Member::Named(ident) => ident.inner.clone(),
+        Member::Unnamed(index) => index.val.to_string(),
+    }
+}
+
+fn is_self(resolutions: &Resolutions, file: FileId, expr: &Expr) -> bool {
+    match expr {
+        Expr::Path(path) => matches!(
+            resolutions.expr_path(file, path),
+            Some(PathResolution {
+                res: Res::SelfValue,
+                ..
+            })
+        ),
+        Expr::Grouped(grouped) => is_self(resolutions, file, &grouped.expr),
+        _ => false,
+    }
+}
+
+/// Whether an expression can be evaluated at compile time, which is when it reads no signal, port or
+/// binding whose value is only known in hardware
+fn is_const(resolutions: &Resolutions, expr: &Expr, env: &Env) -> bool {
+    let mut checker = ConstChecker {
+        resolutions,
+        env,
+        is_const: true,
+    };
+    checker.visit_expr(expr);
+    checker.is_const
+}
+
+struct ConstChecker<'r> {
+    resolutions: &'r Resolutions,
+    env: &'r Env,
+    is_const: bool,
+}
+
+impl ConstChecker<'_> {
+    fn check(&mut self, resolution: Option<&PathResolution>) {
+        let known = match resolution.map(|resolution| &resolution.res) {
+            Some(Res::Def(_))
+            | Some(Res::GenericParam(_))
+            | Some(Res::Primitive(_))
+            | Some(Res::SelfType) => true,
+            Some(Res::Local(span)) => self.env.locals.contains_key(span),
+            _ => false,
+        };
+        self.is_const &= known;
+    }
+}
+
+impl<'ast> Visit<'ast> for ConstChecker<'_> {
+    fn visit_expr_path(&mut self, path: &'ast ExprPath) {
+        let resolution = self.resolutions.expr_path(self.env.file, path);
+        self.check(resolution);
+    }
+
+    fn visit_field_value(&mut self, field: &'ast FieldValue) {
+        if let (None, Member::Named(ident)) = (&field.expr, &field.member) {
+            let resolution = self.resolutions.ident(self.env.file, ident);
+            self.check(resolution);
+        }
+        visit_field_value(self, field);
+    }
+
+    fn visit_expr_assign(&mut self, _: &'ast ExprAssign) {
+        self.is_const = false;
+    }
+
+    fn visit_expr_return(&mut self, _: &'ast ExprReturn) {
+        self.is_const = false;
+    }
+
+    fn visit_pat(&mut self, _: &'ast Pat) {
+        // Bindings made by patterns are only known when the whole expression is evaluated
+        self.is_const = false;
+    }
+}
@@ -0,0 +1,1211 @@
+//! Resolves `use` trees and the paths in expressions, patterns and types to what they refer to
+
+use std::collections::HashMap;
+
+use crate::ast::*;
+use crate::diagnostics::Diagnostic;
+use crate::loader::{Crate, Module};
+use crate::source_map::FileId;
+use crate::visit::Visit;
+
+/// Index of a module in [`Crate::modules`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ModuleId(pub usize);
+
+/// Index of a definition in [`Resolutions::defs`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct DefId(pub usize);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Namespace {
+    /// Modules, types, traits and entities
+    Type,
+    /// Constants, functions, ports and locals
+    Value,
+}
+
+impl Namespace {
+    fn other(self) -> Self {
+        match self {
+            Self::Type => Self::Value,
+            Self::Value => Self::Type,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DefKind {
+    Mod,
+    Const,
+    Fn,
+    Type,
+    Struct,
+    Enum,
+    Variant,
+    Trait,
+    TraitAlias,
+    Entity,
+}
+
+impl DefKind {
+    pub fn namespace(&self) -> Namespace {
+        match self {
+            Self::Const | Self::Fn => Namespace::Value,
+            _ => Namespace::Type,
+        }
+    }
+}
+
+/// Where a name may be used from
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Visibility {
+    Public,
+    /// Visible in the module and its descendants
+    Restricted(ModuleId),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Def {
+    pub name: String,
+    pub kind: DefKind,
+    pub vis: Visibility,
+    /// Module that the definition is an item of
+    pub module: ModuleId,
+    /// Index of the item in its module's items, which is the enum for a variant
+    pub item: usize,
+    /// Index of a variant within its enum
+    pub variant: Option<usize>,
+    /// The module itself for a [`DefKind::Mod`]
+    pub defines: Option<ModuleId>,
+    pub ident: Ident,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModuleData {
+    pub name: String,
+    pub parent: Option<ModuleId>,
+    pub file: FileId,
+}
+
+/// What a path refers to
+#[derive(Clone, Debug, PartialEq)]
+pub enum Res {
+    Def(DefId),
+    /// A `let`, parameter or pattern binding introduced at the span
+    Local(Span),
+    /// A generic parameter declared at the span
+    GenericParam(Span),
+    /// A port, by index, of the entity that the enclosing arch implements
+    Port {
+        entity: DefId,
+        index: usize,
+    },
+    /// A built-in type such as `bit`, `bool` or `u12`
+    Primitive(String),
+    /// `Self` in a trait or impl
+    SelfType,
+    /// `self` in a method or arch
+    SelfValue,
+}
+
+/// What a path refers to, where trailing segments naming associated items are left to type checking
+#[derive(Clone, Debug, PartialEq)]
+pub struct PathResolution {
+    pub res: Res,
+    pub unresolved_segments: usize,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Resolutions {
+    pub modules: Vec<ModuleData>,
+    pub defs: Vec<Def>,
+    /// Variants of each enum, in declaration order
+    pub variants: HashMap<DefId, Vec<DefId>>,
+    paths: HashMap<(FileId, Span), PathResolution>,
+}
+
+impl Resolutions {
+    pub fn def(&self, id: DefId) -> &Def {
+        &self.defs[id.0]
+    }
+
+    pub fn module(&self, id: ModuleId) -> &ModuleData {
+        &self.modules[id.0]
+    }
+
+    /// Path of a module from the crate root, i.e. `crate::alu::adder`
+    pub fn module_path(&self, id: ModuleId) -> String {
+        let module = self.module(id);
+        match module.parent {
+            Some(parent) => format!("{}::{}", self.module_path(parent), module.name),
+            None => module.name.clone(),
+        }
+    }
+
+    pub fn expr_path(&self, file: FileId, path: &ExprPath) -> Option<&PathResolution> {
+        self.paths.get(&(file, segments_span(&path.segments)))
+    }
+
+    pub fn type_path(&self, file: FileId, path: &TypePath) -> Option<&PathResolution> {
+        self.paths.get(&(file, segments_span(&path.segments)))
+    }
+
+    /// Resolution of a shorthand field such as `level` in `Sawtooth { clk, level }`
+    pub fn ident(&self, file: FileId, ident: &Ident) -> Option<&PathResolution> {
+        self.paths.get(&(file, ident.span.clone()))
+    }
+
+    /// Every resolved path along with the file and span it was found at
+    pub fn paths(&self) -> impl Iterator<Item = (&(FileId, Span), &PathResolution)> {
+        self.paths.iter()
+    }
+}
+
+fn segments_span(segments: &Punctuated<PathSegment, PathSep>) -> Span {
+    match (segments.first(), segments.last()) {
+        (Some(first), Some(last)) => first.ident.span.clone() + last.ident.span.clone(),
+        _ => Span(0, 0),
+    }
+}
+
+/// Resolves every `use` and path in `krate`, reporting each name that cannot be found or accessed
+pub fn resolve(krate: &Crate) -> Result<Resolutions, Vec<Diagnostic>> {
+    let modules = krate.modules();
+    let mut resolver = Resolver {
+        modules: &modules,
+        resolutions: Resolutions::default(),
+        scopes: vec![],
+        diagnostics: vec![],
+    };
+    resolver.collect_modules(&krate.root, None);
+    resolver.collect_defs();
+    resolver.resolve_imports();
+    for (i, module) in modules.iter().enumerate() {
+        let mut walker = Walker {
+            resolver: &mut resolver,
+            module: ModuleId(i),
+            file: module.file,
+            locals: vec![],
+        };
+        for item in &module.items {
+            walker.visit_item(item);
+        }
+    }
+    if resolver.diagnostics.is_empty() {
+        Ok(resolver.resolutions)
+    } else {
+        Err(resolver.diagnostics)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Binding {
+    res: Res,
+    vis: Visibility,
+}
+
+/// Something that a path can name items within
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Container {
+    Module(ModuleId),
+    Enum(DefId),
+}
+
+#[derive(Default)]
+struct Scope {
+    names: HashMap<(Namespace, String), Binding>,
+    /// Containers whose names are brought in by `use path::*`
+    globs: Vec<(Container, Visibility)>,
+}
+
+enum Lookup {
+    Found(Binding),
+    Private,
+    Ambiguous,
+    NotFound,
+}
+
+/// A path that failed to name a container at `segment`
+struct PathError {
+    segment: usize,
+    message: String,
+    /// Set when the segment named an item that may have associated items
+    res: Option<Res>,
+}
+
+/// One leaf of a `use` tree
+struct Import<'a> {
+    module: ModuleId,
+    file: FileId,
+    vis: Visibility,
+    leading_sep: bool,
+    prefix: Vec<&'a Ident>,
+    /// Name and rename of the import, or `None` for a glob
+    name: Option<(&'a Ident, &'a Ident)>,
+}
+
+struct Resolver<'a> {
+    modules: &'a [&'a Module],
+    resolutions: Resolutions,
+    scopes: Vec<Scope>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Resolver<'a> {
+    /// Numbers modules in the same pre-order as [`Crate::modules`]
+    fn collect_modules(&mut self, module: &Module, parent: Option<ModuleId>) {
+        let id = ModuleId(self.resolutions.modules.len());
+        self.resolutions.modules.push(ModuleData {
+            name: module.name.clone(),
+            parent,
+            file: module.file,
+        });
+        self.scopes.push(Scope::default());
+        for child in &module.children {
+            self.collect_modules(child, Some(id));
+        }
+    }
+
+    fn error(&mut self, message: impl Into<String>, span: Span, file: FileId) {
+        self.diagnostics
+            .push(Diagnostic::new(message, span).in_file(file));
+    }
+
+    fn child(&self, module: ModuleId, name: &str) -> Option<ModuleId> {
+        self.resolutions
+            .modules
+            .iter()
+            .position(|data| data.parent == Some(module) && data.name == name)
+            .map(ModuleId)
+    }
+
+    fn is_ancestor(&self, ancestor: ModuleId, mut module: ModuleId) -> bool {
+        loop {
+            if module == ancestor {
+                return true;
+            }
+            match self.resolutions.module(module).parent {
+                Some(parent) => module = parent,
+                None => return false,
+            }
+        }
+    }
+
+    fn is_accessible(&self, vis: Visibility, from: ModuleId) -> bool {
+        match vis {
+            Visibility::Public => true,
+            Visibility::Restricted(module) => self.is_ancestor(module, from),
+        }
+    }
+
+    fn visibility(&mut self, vis: &Option<Vis>, module: ModuleId) -> Visibility {
+        let file = self.resolutions.module(module).file;
+        match vis {
+            None | Some(Vis::Priv(_)) | Some(Vis::LowerSelf(_)) => Visibility::Restricted(module),
+            Some(Vis::Pub(_)) => Visibility::Public,
+            Some(Vis::Crate(_)) => Visibility::Restricted(ModuleId(0)),
+            Some(Vis::Super(vis)) => match self.resolutions.module(module).parent {
+                Some(parent) => Visibility::Restricted(parent),
+                None => {
+                    let left = vis.super_token.left;
+                    self.error(
+                        "there are too many leading `super` keywords",
+                        Span(left, left + "super".len()),
+                        file,
+                    );
+                    Visibility::Restricted(module)
+                }
+            },
+            Some(Vis::Restricted(vis)) => {
+                // Follows the module tree alone, since these paths may not go through imports
+                let mut target = Some(module).filter(|_| vis.path.leading_sep.is_none());
+                for (i, segment) in vis.path.segments.iter().enumerate() {
+                    target = match (segment.inner.as_str(), target) {
+                        (_, None) => None,
+                        ("crate", _) if i == 0 => Some(ModuleId(0)),
+                        ("self", current) if i == 0 => current,
+                        ("super", Some(current)) => self.resolutions.module(current).parent,
+                        (name, Some(current)) => self.child(current, name),
+                    };
+                }
+                let segments = &vis.path.segments;
+                let span =
+                    segments.first().unwrap().span.clone() + segments.last().unwrap().span.clone();
+                match target {
+                    Some(target) if self.is_ancestor(target, module) => {
+                        Visibility::Restricted(target)
+                    }
+                    Some(_) => {
+                        self.error(
+                            "visibilities can only be restricted to ancestor modules",
+                            span,
+                            file,
+                        );
+                        Visibility::Restricted(module)
+                    }
+                    None => {
+                        self.error("cannot find this module", span, file);
+                        Visibility::Restricted(module)
+                    }
+                }
+            }
+        }
+    }
+
+    fn define(&mut self, module: ModuleId, ns: Namespace, ident: &Ident, binding: Binding) {
+        let key = (ns, ident.inner.clone());
+        match self.scopes[module.0].names.get(&key) {
+            Some(existing) if existing.res == binding.res => {}
+            Some(_) => {
+                let file = self.resolutions.module(module).file;
+                self.error(
+                    format!("the name `{}` is defined multiple times", ident),
+                    ident.span.clone(),
+                    file,
+                );
+            }
+            None => {
+                self.scopes[module.0].names.insert(key, binding);
+            }
+        }
+    }
+
+    fn add_def(&mut self, def: Def) -> DefId {
+        let id = DefId(self.resolutions.defs.len());
+        self.resolutions.defs.push(def);
+        id
+    }
+
+    fn collect_defs(&mut self) {
+        let modules = self.modules;
+        for (i, module) in modules.iter().enumerate() {
+            let module_id = ModuleId(i);
+            for (index, item) in module.items.iter().enumerate() {
+                let (kind, vis, ident) = match item {
+                    Item::Mod(item) => (DefKind::Mod, &item.vis, &item.ident),
+                    Item::Const(item) => (DefKind::Const, &item.vis, &item.ident),
+                    Item::Fn(item) => (DefKind::Fn, &item.vis, &item.sig.ident),
+                    Item::Type(item) => (DefKind::Type, &item.vis, &item.ident),
+                    Item::Struct(item) => (DefKind::Struct, &item.vis, &item.ident),
+                    Item::Enum(item) => (DefKind::Enum, &item.vis, &item.ident),
+                    Item::Trait(item) => (DefKind::Trait, &item.vis, &item.ident),
+                    Item::TraitAlias(item) => (DefKind::TraitAlias, &item.vis, &item.ident),
+                    Item::Entity(item) => (DefKind::Entity, &item.vis, &item.ident),
+                    Item::Use(_)
+                    | Item::Impl(_)
+                    | Item::Arch(_)
+                    | Item::Test(_)
+                    | Item::Error(_) => continue,
+                };
+                let defines = match kind {
+                    // The loader has already reported modules that are missing
+                    DefKind::Mod => match self.child(module_id, &ident.inner) {
+                        Some(child) => Some(child),
+                        None => continue,
+                    },
+                    _ => None,
+                };
+                let vis = self.visibility(vis, module_id);
+                let id = self.add_def(Def {
+                    name: ident.inner.clone(),
+                    kind,
+                    vis,
+                    module: module_id,
+                    item: index,
+                    variant: None,
+                    defines,
+                    ident: ident.clone(),
+                });
+                let binding = Binding {
+                    res: Res::Def(id),
+                    vis,
+                };
+                self.define(module_id, kind.namespace(), ident, binding.clone());
+
+                match item {
+                    // Tuple structs are also their own constructor
+                    Item::Struct(item) if item.semi.is_some() => {
+                        self.define(module_id, Namespace::Value, ident, binding)
+                    }
+                    Item::Enum(item) => {
+                        let variants = item
+                            .variants
+                            .iter()
+                            .enumerate()
+                            .map(|(variant, v)| {
+                                self.add_def(Def {
+                                    name: v.ident.inner.clone(),
+                                    kind: DefKind::Variant,
+                                    vis,
+                                    module: module_id,
+                                    item: index,
+                                    variant: Some(variant),
+                                    defines: None,
+                                    ident: v.ident.clone(),
+                                })
+                            })
+                            .collect();
+                        self.resolutions.variants.insert(id, variants);
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// Looks up `name` among the names defined in or imported into `container`, as seen from `from`
+    fn lookup(&self, container: Container, ns: Namespace, name: &str, from: ModuleId) -> Lookup {
+        self.lookup_in(container, ns, name, from, &mut vec![])
+    }
+
+    fn lookup_in(
+        &self,
+        container: Container,
+        ns: Namespace,
+        name: &str,
+        from: ModuleId,
+        visited: &mut Vec<ModuleId>,
+    ) -> Lookup {
+        let module = match container {
+            Container::Module(module) => module,
+            Container::Enum(id) => {
+                return self.resolutions.variants[&id]
+                    .iter()
+                    .find(|variant| self.resolutions.def(**variant).name == name)
+                    .map(|variant| {
+                        Lookup::Found(Binding {
+                            res: Res::Def(*variant),
+                            vis: self.resolutions.def(id).vis,
+                        })
+                    })
+                    .unwrap_or(Lookup::NotFound)
+            }
+        };
+        if let Some(binding) = self.scopes[module.0].names.get(&(ns, name.to_string())) {
+            return if self.is_accessible(binding.vis, from) {
+                Lookup::Found(binding.clone())
+            } else {
+                Lookup::Private
+            };
+        }
+        // Globs may import each other in a cycle
+        if visited.contains(&module) {
+            return Lookup::NotFound;
+        }
+        visited.push(module);
+
+        let mut found: Option<Binding> = None;
+        let mut private = false;
+        for (source, vis) in &self.scopes[module.0].globs {
+            if !self.is_accessible(*vis, from) {
+                continue;
+            }
+            match self.lookup_in(*source, ns, name, module, visited) {
+                Lookup::Found(binding) => match &found {
+                    Some(existing) if existing.res != binding.res => return Lookup::Ambiguous,
+                    _ => {
+                        found = Some(Binding {
+                            res: binding.res,
+                            vis: *vis,
+                        })
+                    }
+                },
+                Lookup::Ambiguous => return Lookup::Ambiguous,
+                Lookup::Private => private = true,
+                Lookup::NotFound => {}
+            }
+        }
+        match found {
+            Some(binding) => Lookup::Found(binding),
+            None if private => Lookup::Private,
+            None => Lookup::NotFound,
+        }
+    }
+
+    /// Follows `segments` from `module` through modules and enums
+    fn resolve_container(
+        &self,
+        module: ModuleId,
+        leading_sep: bool,
+        segments: &[&Ident],
+    ) -> Result<Container, PathError> {
+        let mut container = Container::Module(if leading_sep { ModuleId(0) } else { module });
+        for (i, segment) in segments.iter().enumerate() {
+            let error = |message: String, res: Option<Res>| PathError {
+                segment: i,
+                message,
+                res,
+            };
+            let current = match container {
+                Container::Module(current) => current,
+                Container::Enum(_) => {
+                    let message = format!("`{}` is a variant, not a module", segment);
+                    return Err(error(message, None));
+                }
+            };
+            let leading = !leading_sep && segments[..i].iter().all(|s| s.inner == "super");
+            container = match segment.inner.as_str() {
+                "crate" if i == 0 && !leading_sep => Container::Module(ModuleId(0)),
+                "self" if i == 0 && !leading_sep => Container::Module(module),
+                "super" if leading => match self.resolutions.module(current).parent {
+                    Some(parent) => Container::Module(parent),
+                    None => {
+                        let message = "there are too many leading `super` keywords".to_string();
+                        return Err(error(message, None));
+                    }
+                },
+                name => match self.lookup(container, Namespace::Type, name, module) {
+                    Lookup::Found(binding) => {
+                        let def = match binding.res {
+                            Res::Def(id) => Some((id, self.resolutions.def(id))),
+                            _ => None,
+                        };
+                        match def {
+                            Some((
+                                _,
+                                Def {
+                                    kind: DefKind::Mod,
+                                    defines: Some(child),
+                                    ..
+                                },
+                            )) => Container::Module(*child),
+                            Some((
+                                id,
+                                Def {
+                                    kind: DefKind::Enum,
+                                    ..
+                                },
+                            )) => Container::Enum(id),
+                            _ => {
+                                let message =
+                                    format!("expected a module or enum, found `{}`", segment);
+                                return Err(error(message, Some(binding.res)));
+                            }
+                        }
+                    }
+                    lookup => {
+                        let message = lookup_message(lookup, segment, &segments[..i]);
+                        return Err(error(message, None));
+                    }
+                },
+            };
+        }
+        Ok(container)
+    }
+
+    fn collect_imports(&mut self) -> Vec<Import<'a>> {
+        fn flatten<'a>(
+            tree: &'a UseTree,
+            prefix: &mut Vec<&'a Ident>,
+            leading_sep: bool,
+            base: &Import<'a>,
+            acc: &mut Vec<Import<'a>>,
+        ) {
+            let name = match tree {
+                UseTree::Path(path) => {
+                    let len = prefix.len();
+                    prefix.extend(path.path.segments.iter());
+                    let leading_sep = leading_sep || path.path.leading_sep.is_some();
+                    flatten(&path.tree, prefix, leading_sep, base, acc);
+                    prefix.truncate(len);
+                    return;
+                }
+                UseTree::Group(group) => {
+                    for tree in group.trees.iter() {
+                        flatten(tree, prefix, leading_sep, base, acc);
+                    }
+                    return;
+                }
+                // `use a::{self}` imports `a` itself
+                UseTree::Name(name) if name.inner == "self" => {
+                    Some((name, prefix.last().copied().unwrap_or(name)))
+                }
+                UseTree::Name(name) => Some((name, name)),
+                UseTree::Rename(rename) => Some((&rename.name, &rename.rename)),
+                UseTree::Glob(_) => None,
+            };
+            acc.push(Import {
+                leading_sep,
+                prefix: prefix.clone(),
+                name,
+                ..*base
+            });
+        }
+
+        let modules = self.modules;
+        let mut imports = vec![];
+        for (i, module) in modules.iter().enumerate() {
+            for item in &module.items {
+                if let Item::Use(item_use) = item {
+                    let base = Import {
+                        module: ModuleId(i),
+                        file: module.file,
+                        vis: self.visibility(&item_use.vis, ModuleId(i)),
+                        leading_sep: false,
+                        prefix: vec![],
+                        name: None,
+                    };
+                    flatten(&item_use.tree, &mut vec![], false, &base, &mut imports);
+                }
+            }
+        }
+        imports
+    }
+
+    /// Resolves imports until no more make progress, since each may depend on names brought in by others
+    fn resolve_imports(&mut self) {
+        let mut pending = self.collect_imports();
+        loop {
+            let count = pending.len();
+            let mut failed = vec![];
+            for import in pending {
+                if let Err(err) = self.resolve_import(&import) {
+                    failed.push((import, err));
+                }
+            }
+            if failed.len() == count {
+                for (import, (span, message)) in failed {
+                    self.error(message, span, import.file);
+                }
+                return;
+            }
+            pending = failed.into_iter().map(|(import, _)| import).collect();
+        }
+    }
+
+    fn resolve_import(&mut self, import: &Import<'a>) -> Result<(), (Span, String)> {
+        let (name, rename) = match import.name {
+            Some(name) => name,
+            None => {
+                let container = self
+                    .resolve_container(import.module, import.leading_sep, &import.prefix)
+                    .map_err(|err| (import.prefix[err.segment].span.clone(), err.message))?;
+                self.scopes[import.module.0]
+                    .globs
+                    .push((container, import.vis));
+                return Ok(());
+            }
+        };
+        let (prefix, name) = if name.inner == "self" {
+            match import.prefix.split_last() {
+                Some((last, prefix)) => (prefix, *last),
+                None => {
+                    let message = "`self` imports are only allowed within a { } list";
+                    return Err((name.span.clone(), message.to_string()));
+                }
+            }
+        } else {
+            (&import.prefix[..], name)
+        };
+        let container = self
+            .resolve_container(import.module, import.leading_sep, prefix)
+            .map_err(|err| (prefix[err.segment].span.clone(), err.message))?;
+
+        // A name is imported from each namespace that it is defined in
+        let mut found = false;
+        let mut failure = Lookup::NotFound;
+        for ns in [Namespace::Type, Namespace::Value] {
+            match self.lookup(container, ns, &name.inner, import.module) {
+                Lookup::Found(binding) => {
+                    found = true;
+                    let binding = Binding {
+                        res: binding.res,
+                        vis: import.vis,
+                    };
+                    self.define(import.module, ns, rename, binding);
+                }
+                Lookup::NotFound => {}
+                lookup => failure = lookup,
+            }
+        }
+        if found {
+            Ok(())
+        } else if let Lookup::NotFound = failure {
+            let message = format!(
+                "unresolved import, {}",
+                lookup_message(failure, name, prefix)
+            );
+            Err((name.span.clone(), message))
+        } else {
+            Err((name.span.clone(), lookup_message(failure, name, prefix)))
+        }
+    }
+}
+
+fn lookup_message(lookup: Lookup, ident: &Ident, prefix: &[&Ident]) -> String {
+    match lookup {
+        Lookup::Private => format!("`{}` is private", ident),
+        Lookup::Ambiguous => format!("`{}` is ambiguous, more than one glob imports it", ident),
+        Lookup::Found(_) | Lookup::NotFound if prefix.is_empty() => {
+            format!("cannot find `{}` in this scope", ident)
+        }
+        Lookup::Found(_) | Lookup::NotFound => format!(
+            "cannot find `{}` in `{}`",
+            ident,
+            prefix
+                .iter()
+                .map(|segment| segment.inner.as_str())
+                .collect::<Vec<_>>()
+                .join("::")
+        ),
+    }
+}
+
+/// Built-in types: `bit`, `bool`, and unsigned or signed integers of any width such as `u12` and `i9`
+fn is_primitive(name: &str) -> bool {
+    match name {
+        "bit" | "bool" | "uint" | "int" => true,
+        _ => match name.strip_prefix('u').or_else(|| name.strip_prefix('i')) {
+            Some(width) => {
+                !width.is_empty()
+                    && !width.starts_with('0')
+                    && width.chars().all(|c| c.is_ascii_digit())
+            }
+            None => false,
+        },
+    }
+}
+
+/// Names bound by a pattern
+fn pat_bindings<'ast>(pat: &'ast Pat, acc: &mut Vec<&'ast Ident>) {
+    match pat {
+        Pat::Ident(ident) => acc.push(ident),
+        Pat::Struct(pat) => {
+            for field in pat.fields.iter() {
+                match field {
+                    StructPatternField::TuplePat(field) => pat_bindings(&field.pat, acc),
+                    StructPatternField::IdentPat(field) => pat_bindings(&field.pat, acc),
+                    StructPatternField::Ident(ident) => acc.push(ident),
+                }
+            }
+        }
+        Pat::TupleStruct(PatTupleStruct {
+            subpats,
+            rest_subpats,
+            ..
+        })
+        | Pat::Tuple(PatTuple {
+            subpats,
+            rest_subpats,
+            ..
+        }) => {
+            subpats.iter().for_each(|pat| pat_bindings(pat, acc));
+            if let Some((_, _, rest)) = rest_subpats {
+                rest.iter().for_each(|pat| pat_bindings(pat, acc));
+            }
+        }
+        Pat::Slice(pat) => pat.subpats.iter().for_each(|pat| pat_bindings(pat, acc)),
+        Pat::Lit(_) | Pat::Path(_) | Pat::Wildcard(_) | Pat::Range(_) => {}
+    }
+}
+
+/// Walks the items of a module, tracking the locals and generic parameters in scope
+struct Walker<'r, 'a> {
+    resolver: &'r mut Resolver<'a>,
+    module: ModuleId,
+    file: FileId,
+    locals: Vec<HashMap<(Namespace, String), Res>>,
+}
+
+impl<'r, 'a> Walker<'r, 'a> {
+    fn scoped(&mut self, f: impl FnOnce(&mut Self)) {
+        self.locals.push(HashMap::new());
+        f(self);
+        self.locals.pop();
+    }
+
+    fn bind(&mut self, ns: Namespace, name: &str, res: Res) {
+        self.locals
+            .last_mut()
+            .expect("bindings are always made in a scope")
+            .insert((ns, name.to_string()), res);
+    }
+
+    fn bind_pat(&mut self, pat: &Pat) {
+        let mut idents = vec![];
+        pat_bindings(pat, &mut idents);
+        for ident in idents {
+            self.bind(
+                Namespace::Value,
+                &ident.inner,
+                Res::Local(ident.span.clone()),
+            );
+        }
+    }
+
+    fn bind_generics(&mut self, generics: &Option<Generics>) {
+        for param in generics.iter().flat_map(|generics| generics.params.iter()) {
+            let ns = match param {
+                GenericParam::Type(_) => Namespace::Type,
+                GenericParam::Const(_) => Namespace::Value,
+            };
+            let ident = param.ident();
+            self.bind(ns, &ident.inner, Res::GenericParam(ident.span.clone()));
+        }
+    }
+
+    fn bind_sig(&mut self, sig: &Sig) {
+        self.bind_generics(&sig.generics);
+        for input in sig.inputs.iter() {
+            match input {
+                FnArg::Receiver(_) => self.bind(Namespace::Value, "self", Res::SelfValue),
+                FnArg::Typed(typed) => self.bind_pat(&typed.pat),
+            }
+        }
+    }
+
+    fn local(&self, ns: Namespace, name: &str) -> Option<Res> {
+        self.locals
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(&(ns, name.to_string())).cloned())
+    }
+
+    /// Resolves a path whose last segment is looked for in `ns` first
+    fn resolve_path(
+        &mut self,
+        leading_sep: bool,
+        segments: &[&Ident],
+        ns: Namespace,
+    ) -> Option<PathResolution> {
+        let (last, prefix) = segments.split_last()?;
+        let first = &segments[0].inner;
+        let unresolved_after = |segment: usize| segments.len() - segment - 1;
+
+        if !leading_sep {
+            // Locals can only start a path, which goes on to name their associated items
+            let local = if prefix.is_empty() {
+                self.local(ns, first)
+                    .or_else(|| self.local(ns.other(), first))
+            } else {
+                self.local(Namespace::Type, first)
+            };
+            if let Some(res) = local {
+                return Some(PathResolution {
+                    res,
+                    unresolved_segments: unresolved_after(0),
+                });
+            }
+            // Items may shadow primitives
+            let module = Container::Module(self.module);
+            let shadowed = !matches!(
+                self.resolver
+                    .lookup(module, Namespace::Type, first, self.module),
+                Lookup::NotFound
+            );
+            if is_primitive(first) && !shadowed {
+                return Some(PathResolution {
+                    res: Res::Primitive(first.clone()),
+                    unresolved_segments: unresolved_after(0),
+                });
+            }
+        }
+
+        let container = match self
+            .resolver
+            .resolve_container(self.module, leading_sep, prefix)
+        {
+            Ok(container) => container,
+            Err(PathError {
+                segment,
+                res: Some(res),
+                ..
+            }) => {
+                return Some(PathResolution {
+                    res,
+                    unresolved_segments: unresolved_after(segment),
+                })
+            }
+            Err(err) => {
+                let span = prefix[err.segment].span.clone();
+                self.resolver.error(err.message, span, self.file);
+                return None;
+            }
+        };
+        let lookup = match self
+            .resolver
+            .lookup(container, ns, &last.inner, self.module)
+        {
+            Lookup::NotFound => {
+                self.resolver
+                    .lookup(container, ns.other(), &last.inner, self.module)
+            }
+            lookup => lookup,
+        };
+        match lookup {
+            Lookup::Found(binding) => Some(PathResolution {
+                res: binding.res,
+                unresolved_segments: 0,
+            }),
+            lookup => {
+                let message = lookup_message(lookup, last, prefix);
+                self.resolver.error(message, last.span.clone(), self.file);
+                None
+            }
+        }
+    }
+
+    fn resolve_segments(
+        &mut self,
+        leading_sep: &Option<PathSep>,
+        segments: &Punctuated<PathSegment, PathSep>,
+        ns: Namespace,
+    ) {
+        let idents: Vec<&Ident> = segments.iter().map(|segment| &segment.ident).collect();
+        if let Some(resolution) = self.resolve_path(leading_sep.is_some(), &idents, ns) {
+            self.resolver
+                .resolutions
+                .paths
+                .insert((self.file, segments_span(segments)), resolution);
+        }
+        for segment in segments.iter() {
+            if let Some(generic_args) = &segment.generic_args {
+                self.visit_generic_args(generic_args);
+            }
+        }
+    }
+
+    /// Entity that an arch or test names by an already resolved path, reporting paths that resolve
+    /// to anything else
+    fn entity(&mut self, path: &TypePath, item: &str) -> Option<DefId> {
+        let resolutions = &self.resolver.resolutions;
+        match resolutions.type_path(self.file, path) {
+            Some(PathResolution {
+                res: Res::Def(id),
+                unresolved_segments: 0,
+            }) if resolutions.def(*id).kind == DefKind::Entity => Some(*id),
+            Some(_) => {
+                let span = segments_span(&path.segments);
+                let message = format!("expected an entity for this {}", item);
+                self.resolver.error(message, span, self.file);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Resolves a field shorthand, such as `level` in `Sawtooth { level }`, as the value it stands for
+    fn resolve_shorthand(&mut self, ident: &Ident) {
+        if let Some(resolution) = self.resolve_path(false, &[ident], Namespace::Value) {
+            self.resolver
+                .resolutions
+                .paths
+                .insert((self.file, ident.span.clone()), resolution);
+        }
+    }
+}
+
+impl<'ast, 'r, 'a> Visit<'ast> for Walker<'r, 'a> {
+    // Names that are declared rather than referred to are not resolved
+    fn visit_ident(&mut self, _ident: &'ast Ident) {}
+
+    fn visit_attribute(&mut self, _attribute: &'ast Attribute) {}
+
+    fn visit_vis(&mut self, _vis: &'ast Vis) {}
+
+    fn visit_item_use(&mut self, _item: &'ast ItemUse) {}
+
+    // Inline modules are walked as modules of their own
+    fn visit_item_mod(&mut self, _item: &'ast ItemMod) {}
+
+    fn visit_item_fn(&mut self, item: &'ast ItemFn) {
+        self.scoped(|walker| {
+            walker.bind_sig(&item.sig);
+            visit_item_fn(walker, item);
+        });
+    }
+
+    fn visit_trait_item_fn(&mut self, item: &'ast TraitItemFn) {
+        self.scoped(|walker| {
+            walker.bind_sig(&item.sig);
+            visit_trait_item_fn(walker, item);
+        });
+    }
+
+    fn visit_item_type(&mut self, item: &'ast ItemType) {
+        self.scoped(|walker| {
+            walker.bind_generics(&item.generics);
+            visit_item_type(walker, item);
+        });
+    }
+
+    fn visit_item_struct(&mut self, item: &'ast ItemStruct) {
+        self.scoped(|walker| {
+            walker.bind_generics(&item.generics);
+            visit_item_struct(walker, item);
+        });
+    }
+
+    fn visit_item_enum(&mut self, item: &'ast ItemEnum) {
+        self.scoped(|walker| {
+            walker.bind_generics(&item.generics);
+            visit_item_enum(walker, item);
+        });
+    }
+
+    fn visit_item_trait(&mut self, item: &'ast ItemTrait) {
+        self.scoped(|walker| {
+            walker.bind_generics(&item.generics);
+            walker.bind(Namespace::Type, "Self", Res::SelfType);
+            visit_item_trait(walker, item);
+        });
+    }
+
+    fn visit_item_trait_alias(&mut self, item: &'ast ItemTraitAlias) {
+        self.scoped(|walker| {
+            walker.bind_generics(&item.generics);
+            visit_item_trait_alias(walker, item);
+        });
+    }
+
+    fn visit_item_impl(&mut self, item: &'ast ItemImpl) {
+        self.scoped(|walker| {
+            walker.bind_generics(&item.generics);
+            walker.bind(Namespace::Type, "Self", Res::SelfType);
+            visit_item_impl(walker, item);
+        });
+    }
+
+    fn visit_item_entity(&mut self, item: &'ast ItemEntity) {
+        self.scoped(|walker| {
+            walker.bind_generics(&item.generics);
+            visit_item_entity(walker, item);
+        });
+    }
+
+    fn visit_item_arch(&mut self, item: &'ast ItemArch) {
+        self.scoped(|walker| {
+            walker.bind_generics(&item.generics);
+            walker.visit_type_path(&item.entity);
+            walker.bind(Namespace::Value, "self", Res::SelfValue);
+
+            if let Some(entity) = walker.entity(&item.entity, "arch") {
+                let def = walker.resolver.resolutions.def(entity);
+                let modules = walker.resolver.modules;
+                if let Item::Entity(item_entity) = &modules[def.module.0].items[def.item] {
+                    for (index, port) in item_entity.ports.iter().enumerate() {
+                        let res = Res::Port { entity, index };
+                        walker.bind(Namespace::Value, &port.ident.inner, res);
+                    }
+                }
+            }
+            // Signals and constants declared anywhere in an arch are visible throughout it
+            for arch_item in &item.items {
+                match arch_item {
+                    ArchItem::Let(local) => walker.bind_pat(&local.pat),
+                    ArchItem::Const(item_const) => {
+                        let ident = &item_const.ident;
+                        walker.bind(
+                            Namespace::Value,
+                            &ident.inner,
+                            Res::Local(ident.span.clone()),
+                        )
+                    }
+                    ArchItem::When(_) | ArchItem::EntityExpression(_) => {}
+                }
+            }
+
+            if let Some(generics) = &item.generics {
+                walker.visit_generics(generics);
+            }
+            if let Some(where_clause) = &item.where_clause {
+                walker.visit_where_clause(where_clause);
+            }
+            for arch_item in &item.items {
+                walker.visit_arch_item(arch_item);
+            }
+        });
+    }
+
+    /// Only the entity of a test is resolved, as its statements name the nets of a simulation
+    fn visit_item_test(&mut self, item: &'ast ItemTest) {
+        self.visit_type_path(&item.entity);
+        self.entity(&item.entity, "test");
+    }
+
+    fn visit_block(&mut self, block: &'ast Block) {
+        self.scoped(|walker| visit_block(walker, block));
+    }
+
+    fn visit_stmt_local(&mut self, local: &'ast StmtLocal) {
+        if let Some((_, ty)) = &local.ty {
+            self.visit_type(ty);
+        }
+        if let Some((_, init)) = &local.init {
+            self.visit_expr(init);
+        }
+        self.visit_pat(&local.pat);
+        self.bind_pat(&local.pat);
+    }
+
+    fn visit_expr_for(&mut self, expr: &'ast ExprFor) {
+        self.visit_expr(&expr.expr);
+        self.scoped(|walker| {
+            walker.visit_pat(&expr.pat);
+            walker.bind_pat(&expr.pat);
+            walker.visit_block(&expr.block);
+        });
+    }
+
+    fn visit_arm(&mut self, arm: &'ast Arm) {
+        self.scoped(|walker| {
+            walker.bind_pat(&arm.pat);
+            visit_arm(walker, arm);
+        });
+    }
+
+    fn visit_expr_path(&mut self, path: &'ast ExprPath) {
+        self.resolve_segments(&path.leading_sep, &path.segments, Namespace::Value);
+    }
+
+    fn visit_type_path(&mut self, path: &'ast TypePath) {
+        self.resolve_segments(&path.leading_sep, &path.segments, Namespace::Type);
+    }
+
+    fn visit_expr_struct(&mut self, expr: &'ast ExprStruct) {
+        let path = &expr.path;
+        self.resolve_segments(&path.leading_sep, &path.segments, Namespace::Type);
+        for field in expr.fields.iter() {
+            self.visit_field_value(field);
+        }
+        if let Some((_, base)) = &expr.base {
+            self.visit_expr(base);
+        }
+    }
+
+    fn visit_pat_struct(&mut self, pat: &'ast PatStruct) {
+        let path = &pat.path;
+        self.resolve_segments(&path.leading_sep, &path.segments, Namespace::Type);
+        for field in pat.fields.iter() {
+            self.visit_struct_pattern_field(field);
+        }
+    }
+
+    // The segments after a qualifier name associated items, which are left to type checking
+    fn visit_expr_q_path(&mut self, path: &'ast ExprQPath) {
+        self.visit_qualifier(&path.qualifier);
+    }
+
+    fn visit_type_q_path(&mut self, path: &'ast TypeQPath) {
+        self.visit_qualifier(&path.qualifier);
+    }
+
+    fn visit_field_value(&mut self, field: &'ast FieldValue) {
+        match (&field.member, &field.expr) {
+            (_, Some((_, expr))) => self.visit_expr(expr),
+            (Member::Named(ident), None) => self.resolve_shorthand(ident),
+            (Member::Unnamed(_), None) => {}
+        }
+    }
+
+    fn visit_arch_item_entity_expression(&mut self, expr: &'ast ArchItemEntityExpression) {
+        let path = &expr.path;
+        self.resolve_segments(&path.leading_sep, &path.segments, Namespace::Type);
+        for field in expr.fields.iter() {
+            match &field.expr {
+                Some((_, expr)) => self.visit_expr(expr),
+                None => self.resolve_shorthand(&field.ident),
+            }
+        }
+    }
+}
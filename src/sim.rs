@@ -0,0 +1,401 @@
+//! A cycle-accurate simulator that executes a [`Netlist`]
+//!
+//! Every instance of the elaborated design becomes a [`Scope`] whose nets hold values, where the
+//! ports of an instance share their values with the nets of its parent that they are connected
+//! to. Each [`Simulator::step`] first lets the combinational nodes, assignments and processes
+//! settle, then runs the processes whose clocks rose or fell since the step before, all of which
+//! compute their updates from the values their nets held before any of them ran, and repeats this
+//! until no clock changes. Values are kept as their bits, which [`Simulator::get`] reads back as
+//! the integers that their types give them.
+
+use std::error::Error;
+use std::fmt;
+
+use rug::Integer;
+
+use crate::netlist::{
+    BinaryOp, Direction, ModuleId, NetId, Netlist, Node, NodeId, NodeKind, Trigger, UnaryOp,
+};
+use crate::ty::Ty;
+
+/// How many times the combinational logic may be evaluated before it is taken to never settle
+const MAX_PASSES: usize = 1 << 12;
+
+/// How many rounds of clock edges may follow one another within a step
+const MAX_EDGES: usize = 1 << 8;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SimError {
+    /// No net has the given hierarchical name
+    UnknownNet(String),
+    /// The net with the given name is not an input port of the top
+    NotAnInput(String),
+    /// The combinational logic kept changing, because it loops
+    Unsettled,
+    /// Clock edges kept causing further clock edges
+    Runaway,
+}
+
+impl fmt::Display for SimError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownNet(name) => write!(f, "there is no net named `{}`", name),
+            Self::NotAnInput(name) => write!(f, "`{}` is not an input port of the top", name),
+            Self::Unsettled => write!(f, "the combinational logic does not settle"),
+            Self::Runaway => write!(f, "clock edges keep causing further clock edges"),
+        }
+    }
+}
+
+impl Error for SimError {}
+
+/// An instance of a module within the design
+#[derive(Clone, Debug)]
+pub struct Scope {
+    /// Name of the instance, which is empty for the top
+    pub name: String,
+    /// Names of the instances from the top down to this one, joined by `.`
+    pub path: String,
+    pub module: ModuleId,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+    /// Index into the simulator's values of each net of the module
+    slots: Vec<usize>,
+    /// Bits of each node of the module, as of the last evaluation
+    nodes: Vec<Integer>,
+}
+
+/// Executes a netlist one step at a time
+pub struct Simulator<'n> {
+    netlist: &'n Netlist,
+    /// Instances of the design, starting with the top, each after its parent
+    scopes: Vec<Scope>,
+    /// Bits of every distinct net of the design
+    values: Vec<Integer>,
+    /// Bits of every net as of when clock edges were last looked for
+    seen: Vec<Integer>,
+    /// How many steps have been taken
+    time: u64,
+}
+
+impl<'n> Simulator<'n> {
+    /// A simulator of `netlist` in which registers hold their initial values and every other net
+    /// holds zero, until the first step
+    pub fn new(netlist: &'n Netlist) -> Self {
+        let mut sim = Self {
+            netlist,
+            scopes: vec![],
+            values: vec![],
+            seen: vec![],
+            time: 0,
+        };
+        sim.instantiate(netlist.top, String::new(), None, &[]);
+        for scope in &sim.scopes {
+            let module = netlist.module(scope.module);
+            for (net, slot) in module.nets.iter().zip(&scope.slots) {
+                if let Some(init) = &net.init {
+                    sim.values[*slot] = init.clone();
+                }
+            }
+        }
+        sim.seen = sim.values.clone();
+        sim
+    }
+
+    /// Adds a scope for an instance of `module` whose ports take the given slots
+    fn instantiate(
+        &mut self,
+        module: ModuleId,
+        name: String,
+        parent: Option<usize>,
+        ports: &[usize],
+    ) -> usize {
+        let netlist = self.netlist;
+        let def = netlist.module(module);
+        let mut slots = vec![usize::MAX; def.nets.len()];
+        for (port, slot) in def.ports.iter().zip(ports) {
+            slots[port.0] = *slot;
+        }
+        for slot in &mut slots {
+            if *slot == usize::MAX {
+                *slot = self.values.len();
+                self.values.push(Integer::new());
+            }
+        }
+        let path = match parent {
+            Some(parent) if !self.scopes[parent].path.is_empty() => {
+                format!("{}.{}", self.scopes[parent].path, name)
+            }
+            _ => name.clone(),
+        };
+        let id = self.scopes.len();
+        self.scopes.push(Scope {
+            name,
+            path,
+            module,
+            parent,
+            children: vec![],
+            slots,
+            nodes: vec![Integer::new(); def.nodes.len()],
+        });
+        for instance in &def.instances {
+            let ports = instance
+                .connections
+                .iter()
+                .map(|(_, net)| self.scopes[id].slots[net.0])
+                .collect::<Vec<_>>();
+            let child = self.instantiate(instance.module, instance.name.clone(), Some(id), &ports);
+            self.scopes[id].children.push(child);
+        }
+        id
+    }
+
+    pub fn netlist(&self) -> &'n Netlist {
+        self.netlist
+    }
+
+    /// Instances of the design, starting with the top, each after its parent
+    pub fn scopes(&self) -> &[Scope] {
+        &self.scopes
+    }
+
+    /// How many steps have been taken
+    pub fn time(&self) -> u64 {
+        self.time
+    }
+
+    /// Bits that a net of a scope holds
+    pub fn bits(&self, scope: usize, net: NetId) -> &Integer {
+        &self.values[self.scopes[scope].slots[net.0]]
+    }
+
+    /// Drives an input port of the top, whose value is truncated to the port's width and takes
+    /// effect at the next step
+    pub fn set(&mut self, port: &str, value: impl Into<Integer>) -> Result<(), SimError> {
+        let module = self.netlist.module(self.netlist.top);
+        let id = module
+            .ports
+            .iter()
+            .copied()
+            .find(|id| module.net(*id).name == port)
+            .ok_or_else(|| match self.find(port) {
+                Some(_) => SimError::NotAnInput(port.to_string()),
+                None => SimError::UnknownNet(port.to_string()),
+            })?;
+        let net = module.net(id);
+        if net.port != Some(Direction::In) && net.port != Some(Direction::InOut) {
+            return Err(SimError::NotAnInput(port.to_string()));
+        }
+        let slot = self.scopes[0].slots[id.0];
+        self.values[slot] = value.into().keep_bits(net.width as u32);
+        Ok(())
+    }
+
+    /// Value of a net as of the last step, by its name prefixed by the names of the instances
+    /// that it is in, such as `audio` or `sawtooth_0.level`, which is negative for a net of a
+    /// signed type whose top bit is set
+    pub fn get(&self, name: &str) -> Result<Integer, SimError> {
+        let (scope, id) = self
+            .find(name)
+            .ok_or_else(|| SimError::UnknownNet(name.to_string()))?;
+        let net = self.netlist.module(self.scopes[scope].module).net(id);
+        Ok(decode(self.bits(scope, id), &net.ty, net.width))
+    }
+
+    /// Scope and net of a hierarchical name
+    fn find(&self, name: &str) -> Option<(usize, NetId)> {
+        let mut scope = 0;
+        let mut parts = name.split('.').peekable();
+        while let Some(part) = parts.next() {
+            if parts.peek().is_none() {
+                let module = self.netlist.module(self.scopes[scope].module);
+                let id = module.nets.iter().position(|net| net.name == part)?;
+                return Some((scope, NetId(id)));
+            }
+            scope = *self.scopes[scope]
+                .children
+                .iter()
+                .find(|child| self.scopes[**child].name == part)?;
+        }
+        None
+    }
+
+    /// Lets the design settle and runs the processes of every clock edge since the last step
+    pub fn step(&mut self) -> Result<(), SimError> {
+        self.time += 1;
+        for _ in 0..MAX_EDGES {
+            self.settle()?;
+            let updates = self.clocked();
+            self.seen.clone_from(&self.values);
+            if updates.is_empty() {
+                return Ok(());
+            }
+            for (slot, bits) in updates {
+                self.values[slot] = bits;
+            }
+        }
+        Err(SimError::Runaway)
+    }
+
+    /// Evaluates the combinational logic until no net changes
+    fn settle(&mut self) -> Result<(), SimError> {
+        for _ in 0..MAX_PASSES {
+            let mut changed = false;
+            for scope in 0..self.scopes.len() {
+                self.evaluate(scope);
+                let module = self.netlist.module(self.scopes[scope].module);
+                let assigns = module.assigns.iter().chain(
+                    module
+                        .processes
+                        .iter()
+                        .filter(|process| process.trigger == Trigger::Always)
+                        .flat_map(|process| &process.updates),
+                );
+                for assign in assigns {
+                    let slot = self.scopes[scope].slots[assign.net.0];
+                    let bits = &self.scopes[scope].nodes[assign.value.0];
+                    if self.values[slot] != *bits {
+                        self.values[slot] = bits.clone();
+                        changed = true;
+                    }
+                }
+            }
+            if !changed {
+                return Ok(());
+            }
+        }
+        Err(SimError::Unsettled)
+    }
+
+    /// Updates of the processes whose clocks changed since edges were last looked for
+    fn clocked(&self) -> Vec<(usize, Integer)> {
+        let mut updates = vec![];
+        for scope in &self.scopes {
+            let module = self.netlist.module(scope.module);
+            for process in &module.processes {
+                let (clock, rising) = match process.trigger {
+                    Trigger::Posedge(clock) => (clock, true),
+                    Trigger::Negedge(clock) => (clock, false),
+                    Trigger::Always => continue,
+                };
+                let slot = scope.slots[clock.0];
+                let (before, now) = (self.seen[slot] != 0, self.values[slot] != 0);
+                if before != now && now == rising {
+                    for update in &process.updates {
+                        let bits = scope.nodes[update.value.0].clone();
+                        updates.push((scope.slots[update.net.0], bits));
+                    }
+                }
+            }
+        }
+        updates
+    }
+
+    /// Evaluates every node of a scope from the values its nets hold
+    fn evaluate(&mut self, scope: usize) {
+        let module = self.netlist.module(self.scopes[scope].module);
+        let mut nodes = std::mem::take(&mut self.scopes[scope].nodes);
+        for (i, node) in module.nodes.iter().enumerate() {
+            let bits = match &node.kind {
+                NodeKind::Net(net) => self.values[self.scopes[scope].slots[net.0]].clone(),
+                kind => eval(kind, node, &module.nodes, &nodes),
+            };
+            nodes[i] = bits.keep_bits(node.width as u32);
+        }
+        self.scopes[scope].nodes = nodes;
+    }
+}
+
+/// Value of a node other than a read of a net, from the bits of the nodes before it, which may
+/// need to be truncated to the node's width
+fn eval(kind: &NodeKind, node: &Node, defs: &[Node], nodes: &[Integer]) -> Integer {
+    // The value of an operand, which is signed if `signed` is
+    let value = |id: NodeId, signed: bool| {
+        let def = &defs[id.0];
+        if signed {
+            decode(&nodes[id.0], &Ty::Signed(None), def.width)
+        } else {
+            nodes[id.0].clone()
+        }
+    };
+    let is_signed = |id: NodeId| matches!(defs[id.0].ty, Ty::Signed(_));
+    // Shift amounts beyond the width of the node all have the same effect
+    let amount = |id: NodeId| {
+        nodes[id.0]
+            .to_u32()
+            .unwrap_or(u32::MAX)
+            .min(node.width as u32 + 1)
+    };
+    match kind {
+        NodeKind::Const(bits) => bits.clone(),
+        NodeKind::Net(_) => unreachable!("reads of nets are evaluated by the scope"),
+        NodeKind::Unary(UnaryOp::Not, arg) => !nodes[arg.0].clone(),
+        NodeKind::Unary(UnaryOp::Neg, arg) => -nodes[arg.0].clone(),
+        NodeKind::Binary(op, lhs, rhs) => {
+            let signed = is_signed(*lhs);
+            let (a, b) = (value(*lhs, signed), value(*rhs, signed));
+            let truth = |b: bool| Integer::from(b as u8);
+            match op {
+                BinaryOp::Add => a + b,
+                BinaryOp::Sub => a - b,
+                BinaryOp::Mul => a * b,
+                // Division by zero gives zero, where hardware would give whatever its divider does
+                BinaryOp::Div if b == 0 => Integer::new(),
+                BinaryOp::Div => a / b,
+                BinaryOp::Rem if b == 0 => Integer::new(),
+                BinaryOp::Rem => a % b,
+                BinaryOp::Pow => match b.to_u32() {
+                    Some(exponent) => {
+                        let modulus = Integer::from(1) << node.width as u32;
+                        a.pow_mod(&Integer::from(exponent), &modulus)
+                            .unwrap_or_default()
+                    }
+                    None => Integer::new(),
+                },
+                BinaryOp::Xor => a ^ b,
+                BinaryOp::And => a & b,
+                BinaryOp::Or => a | b,
+                BinaryOp::Shl => a << amount(*rhs),
+                BinaryOp::Shr => a >> amount(*rhs),
+                BinaryOp::Eq => truth(a == b),
+                BinaryOp::Ne => truth(a != b),
+                BinaryOp::Lt => truth(a < b),
+                BinaryOp::Le => truth(a <= b),
+                BinaryOp::Gt => truth(a > b),
+                BinaryOp::Ge => truth(a >= b),
+            }
+        }
+        NodeKind::Cast(arg) => value(*arg, is_signed(*arg)),
+        NodeKind::Slice(arg, lo) => nodes[arg.0].clone() >> *lo as u32,
+        NodeKind::Index(arg, index) => {
+            let index = nodes[index.0].to_u32().unwrap_or(u32::MAX);
+            nodes[arg.0].clone() >> index.saturating_mul(node.width as u32)
+        }
+        NodeKind::Concat(args) => {
+            let mut bits = Integer::new();
+            let mut offset = 0;
+            for arg in args {
+                bits |= nodes[arg.0].clone() << offset;
+                offset += defs[arg.0].width as u32;
+            }
+            bits
+        }
+        NodeKind::Mux(cond, then, otherwise) => {
+            if nodes[cond.0] != 0 {
+                nodes[then.0].clone()
+            } else {
+                nodes[otherwise.0].clone()
+            }
+        }
+    }
+}
+
+/// The integer that the bits of a value of type `ty` stand for, which is negative for a signed
+/// type whose top bit is set
+pub fn decode(bits: &Integer, ty: &Ty, width: u64) -> Integer {
+    if matches!(ty, Ty::Signed(_)) && width > 0 && bits.get_bit(width as u32 - 1) {
+        bits - (Integer::from(1) << width as u32)
+    } else {
+        bits.clone()
+    }
+}
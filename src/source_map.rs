@@ -0,0 +1,143 @@
+//! Owns the sources that have been loaded and maps byte offsets in them to lines and columns
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::ast::Span;
+
+/// Identifies a [`SourceFile`] within the [`SourceMap`] that loaded it
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FileId(usize);
+
+/// A [`Span`] along with the file it is an offset into
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct FileSpan {
+    pub file: FileId,
+    pub span: Span,
+}
+
+/// A 1-based position, where the column counts characters rather than bytes
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct LineCol {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for LineCol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SourceFile {
+    id: FileId,
+    name: PathBuf,
+    source: String,
+    /// Byte offset at which each line starts
+    line_starts: Vec<usize>,
+}
+
+impl SourceFile {
+    fn new(id: FileId, name: PathBuf, source: String) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self {
+            id,
+            name,
+            source,
+            line_starts,
+        }
+    }
+
+    pub fn id(&self) -> FileId {
+        self.id
+    }
+
+    pub fn name(&self) -> &Path {
+        &self.name
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Position of the character at `offset`, which is clamped to the end of the source
+    /// and moved back to the start of a character if it falls inside one
+    pub fn line_col(&self, offset: usize) -> LineCol {
+        let mut offset = offset.min(self.source.len());
+        while !self.source.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        let line = self.line_starts.partition_point(|&start| start <= offset);
+        let column = self.source[self.line_starts[line - 1]..offset]
+            .chars()
+            .count()
+            + 1;
+        LineCol { line, column }
+    }
+
+    /// Start and end positions of `span`
+    pub fn resolve(&self, span: &Span) -> (LineCol, LineCol) {
+        (self.line_col(span.0), self.line_col(span.1))
+    }
+
+    /// Text of the 1-based `line`, without its line ending
+    pub fn line(&self, line: usize) -> &str {
+        let start = self.line_starts[line - 1];
+        let end = self
+            .line_starts
+            .get(line)
+            .copied()
+            .unwrap_or(self.source.len());
+        self.source[start..end].trim_end_matches(&['\n', '\r'][..])
+    }
+
+    /// Byte range of the 1-based `line`, without its line ending
+    pub fn line_span(&self, line: usize) -> Span {
+        let start = self.line_starts[line - 1];
+        Span(start, start + self.line(line).len())
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Takes ownership of `source`, which is known by `name` in diagnostics
+    pub fn add(&mut self, name: impl Into<PathBuf>, source: impl Into<String>) -> FileId {
+        let id = FileId(self.files.len());
+        self.files
+            .push(SourceFile::new(id, name.into(), source.into()));
+        id
+    }
+
+    /// Reads the file at `path` and adds it to the map
+    pub fn load(&mut self, path: impl AsRef<Path>) -> io::Result<FileId> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path)?;
+        Ok(self.add(path, source))
+    }
+
+    pub fn get(&self, id: FileId) -> &SourceFile {
+        &self.files[id.0]
+    }
+
+    pub fn files(&self) -> impl Iterator<Item = &SourceFile> {
+        self.files.iter()
+    }
+
+    /// Start and end positions of `span` in the file it belongs to
+    pub fn resolve(&self, span: &FileSpan) -> (LineCol, LineCol) {
+        self.get(span.file).resolve(&span.span)
+    }
+}
@@ -0,0 +1,715 @@
+//! Emits a [`Netlist`] as synthesizable SystemVerilog
+//!
+//! Unlike the Verilog backend, values keep the shape of their types: structs and tuples are packed
+//! structs, arrays are packed arrays and an enum whose variants have no fields is a
+//! `typedef enum` whose values are its discriminants, while an enum with fields is a plain vector.
+//! The structs, enums and consts of each module of the crate are declared in a package named
+//! after it, such as `crate_pkg`, and referred to by their qualified names. Every module of the
+//! netlist becomes a module whose nodes are variables named after their index, clocked processes
+//! become `always_ff` blocks and the other processes become `always_comb` blocks.
+
+use std::collections::HashMap;
+use std::fmt::{self, Write};
+
+use rug::Integer;
+
+use crate::netlist::{
+    BinaryOp, Direction, Module, NetId, Netlist, NodeId, NodeKind, Trigger, TypeDecl, TypeDeclKind,
+    UnaryOp,
+};
+use crate::ty::Ty;
+use crate::verilog;
+
+/// Words that SystemVerilog reserves on top of those of Verilog, which names are escaped to avoid
+#[rustfmt::skip]
+const KEYWORDS: &[&str] = &[
+    "accept_on", "alias", "always_comb", "always_ff", "always_latch", "assert", "assume", "before",
+    "bind", "bins", "binsof", "bit", "break", "byte", "chandle", "checker", "class", "clocking",
+    "const", "constraint", "context", "continue", "cover", "covergroup", "coverpoint", "cross",
+    "dist", "do", "endchecker", "endclass", "endclocking", "endgroup", "endinterface",
+    "endpackage", "endprogram", "endproperty", "endsequence", "enum", "eventually", "expect",
+    "export", "extends", "extern", "final", "first_match", "foreach", "forkjoin", "global", "iff",
+    "ignore_bins", "illegal_bins", "implements", "implies", "import", "inside", "int",
+    "interconnect", "interface", "intersect", "join_any", "join_none", "let", "local", "logic",
+    "longint", "matches", "modport", "nettype", "new", "nexttime", "null", "package", "packed",
+    "priority", "program", "property", "protected", "pure", "rand", "randc", "randcase",
+    "randsequence", "ref", "reject_on", "restrict", "return", "s_always", "s_eventually",
+    "s_nexttime", "s_until", "s_until_with", "sequence", "shortint", "shortreal", "soft", "solve",
+    "static", "string", "strong", "struct", "super", "sync_accept_on", "sync_reject_on", "tagged",
+    "this", "throughout", "timeprecision", "timeunit", "type", "typedef", "union", "unique",
+    "unique0", "until", "until_with", "untyped", "var", "virtual", "void", "wait_order", "weak",
+    "wildcard", "with", "within",
+];
+
+/// SystemVerilog source for a package per module of the crate that declares types or consts,
+/// followed by every module of `netlist`, starting with the top
+pub fn emit(netlist: &Netlist) -> String {
+    let types = Types::new(netlist);
+    let mut out = String::new();
+    for package in &types.packages {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        types.package(package, &mut out);
+    }
+    for module in &netlist.modules {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        Emitter::new(&types, module).emit(&mut out);
+    }
+    out
+}
+
+/// A packed type, as a base type and the ranges of the dimensions after it from the outermost in,
+/// such as `logic [3:0][7:0]`
+struct DataType {
+    base: String,
+    dims: Vec<u64>,
+}
+
+impl fmt::Display for DataType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.base)?;
+        if !self.dims.is_empty() {
+            write!(f, " ")?;
+        }
+        for dim in &self.dims {
+            write!(f, "[{}:0]", dim - 1)?;
+        }
+        Ok(())
+    }
+}
+
+/// How the structs, enums and consts of a netlist are declared and referred to
+struct Types<'n> {
+    netlist: &'n Netlist,
+    /// Paths of the modules of the crate that declare types or consts, in the order in which
+    /// their declarations first appear
+    packages: Vec<String>,
+    /// Name of each variant of each enum that is declared as a `typedef enum`
+    literals: HashMap<&'n Ty, Vec<String>>,
+}
+
+impl<'n> Types<'n> {
+    fn new(netlist: &'n Netlist) -> Self {
+        let mut types = Self {
+            netlist,
+            packages: vec![],
+            literals: HashMap::new(),
+        };
+        let modules = netlist
+            .types
+            .iter()
+            .filter(|decl| types.netlist.width(&decl.ty) > 0)
+            .map(|decl| &decl.module)
+            .chain(
+                netlist
+                    .consts
+                    .iter()
+                    .filter(|decl| decl.width > 0)
+                    .map(|decl| &decl.module),
+            )
+            .collect::<Vec<_>>();
+        for module in modules {
+            if !types.packages.contains(module) {
+                types.packages.push(module.clone());
+            }
+        }
+
+        // Variants are named after their enum where that is needed to tell them apart from
+        // anything else that the package declares
+        let mut counts = HashMap::<(&str, &str), usize>::new();
+        for decl in &netlist.types {
+            *counts.entry((&decl.module, &decl.name)).or_default() += 1;
+            if let TypeDeclKind::Enum { variants, .. } = &decl.kind {
+                for variant in variants {
+                    *counts.entry((&decl.module, &variant.name)).or_default() += 1;
+                }
+            }
+        }
+        for decl in &netlist.consts {
+            *counts.entry((&decl.module, &decl.name)).or_default() += 1;
+        }
+        for decl in &netlist.types {
+            if !types.is_enumeration_decl(decl) {
+                continue;
+            }
+            if let TypeDeclKind::Enum { variants, .. } = &decl.kind {
+                let names = variants
+                    .iter()
+                    .map(|variant| {
+                        if counts[&(decl.module.as_str(), variant.name.as_str())] > 1 {
+                            ident(&format!("{}_{}", decl.name, variant.name))
+                        } else {
+                            ident(&variant.name)
+                        }
+                    })
+                    .collect();
+                types.literals.insert(&decl.ty, names);
+            }
+        }
+        types
+    }
+
+    /// Writes the package of the module of the crate at `path`
+    fn package(&self, path: &str, out: &mut String) {
+        let name = package_name(path);
+        writeln!(out, "package {};", name).unwrap();
+        let within = Some(path);
+        for decl in &self.netlist.types {
+            if decl.module != path || self.netlist.width(&decl.ty) == 0 {
+                continue;
+            }
+            match &decl.kind {
+                TypeDeclKind::Struct(fields) => {
+                    writeln!(out, "  typedef struct packed {{").unwrap();
+                    for member in self.members(fields, within) {
+                        writeln!(out, "    {}", member).unwrap();
+                    }
+                    writeln!(out, "  }} {};", ident(&decl.name)).unwrap();
+                }
+                TypeDeclKind::Enum {
+                    tag_width,
+                    variants,
+                } if self.is_enumeration_decl(decl) => {
+                    let base = vector(&Ty::Unsigned(None), *tag_width);
+                    writeln!(out, "  typedef enum {} {{", base).unwrap();
+                    let values = variants
+                        .iter()
+                        .zip(&self.literals[&decl.ty])
+                        .map(|(variant, name)| {
+                            let value = verilog::literal(
+                                &variant.discriminant,
+                                &Ty::Unsigned(None),
+                                *tag_width,
+                            );
+                            format!("    {} = {}", name, value)
+                        })
+                        .collect::<Vec<_>>();
+                    writeln!(out, "{}", values.join(",\n")).unwrap();
+                    writeln!(out, "  }} {};", ident(&decl.name)).unwrap();
+                }
+                // An enum with fields is a plain vector, which needs no declaration
+                TypeDeclKind::Enum { .. } => {}
+            }
+        }
+        for decl in &self.netlist.consts {
+            if decl.module != path || decl.width == 0 {
+                continue;
+            }
+            writeln!(
+                out,
+                "  localparam {} {} = {};",
+                self.data_type(&decl.ty, decl.width, within),
+                ident(&decl.name),
+                self.literal(&decl.bits, &decl.ty, decl.width, within)
+            )
+            .unwrap();
+        }
+        writeln!(out, "endpackage").unwrap();
+    }
+
+    /// Whether an enum is declared as a `typedef enum`, which it is if its variants have no fields
+    fn is_enumeration_decl(&self, decl: &TypeDecl) -> bool {
+        decl.kind.is_fieldless_enum() && self.netlist.width(&decl.ty) > 0
+    }
+
+    fn is_enumeration(&self, ty: &Ty) -> bool {
+        self.literals.contains_key(ty)
+    }
+
+    /// The type that a value of type `ty` is declared as, referred to from within the package of
+    /// `within` if it is given, or a plain vector if `ty` is not `width` bits wide
+    fn data_type(&self, ty: &Ty, width: u64, within: Option<&str>) -> DataType {
+        if self.netlist.width(ty) != width {
+            return vector(ty, width);
+        }
+        match ty {
+            Ty::Array(elem, len) => {
+                let mut data_type = self.data_type(elem, self.netlist.width(elem), within);
+                data_type.dims.insert(0, *len);
+                data_type
+            }
+            Ty::Tuple(_) => {
+                let fields = self.netlist.fields(ty).unwrap_or_default();
+                DataType {
+                    base: format!(
+                        "struct packed {{ {} }}",
+                        self.members(&fields, within).join(" ")
+                    ),
+                    dims: vec![],
+                }
+            }
+            Ty::Adt { .. } => match self.netlist.type_decl(ty) {
+                Some(decl)
+                    if !matches!(decl.kind, TypeDeclKind::Enum { .. })
+                        || self.is_enumeration(ty) =>
+                {
+                    DataType {
+                        base: qualified(&decl.module, &ident(&decl.name), within),
+                        dims: vec![],
+                    }
+                }
+                _ => vector(ty, width),
+            },
+            _ => vector(ty, width),
+        }
+    }
+
+    /// Members of a packed struct with the given fields, from the most significant down
+    fn members(&self, fields: &[(String, Ty)], within: Option<&str>) -> Vec<String> {
+        fields
+            .iter()
+            .rev()
+            .filter(|(_, ty)| self.netlist.width(ty) > 0)
+            .map(|(name, ty)| {
+                format!(
+                    "{} {};",
+                    self.data_type(ty, self.netlist.width(ty), within),
+                    member(name)
+                )
+            })
+            .collect()
+    }
+
+    /// Expression for the `width` bits from bit `lo` of `base`, a value of type `ty`, along with
+    /// their type if they are a whole field, element or value, or `None` if they can only be
+    /// read as bits of a vector
+    fn select(&self, base: String, ty: &Ty, lo: u64, width: u64) -> Option<(String, Option<Ty>)> {
+        if lo == 0 && width == self.netlist.width(ty) {
+            return Some((base, Some(ty.clone())));
+        }
+        if self.is_enumeration(ty) {
+            return None;
+        }
+        if let Ty::Array(elem, _) = ty {
+            let elem_width = self.netlist.width(elem);
+            let index = lo / elem_width.max(1);
+            let start = index * elem_width;
+            if elem_width == 0 || lo + width > start + elem_width {
+                return None;
+            }
+            return self.select(format!("{}[{}]", base, index), elem, lo - start, width);
+        }
+        if let Some(fields) = self.netlist.fields(ty) {
+            let mut start = 0;
+            for (name, ty) in &fields {
+                let field_width = self.netlist.width(ty);
+                if lo >= start && lo + width <= start + field_width {
+                    let base = format!("{}.{}", base, member(name));
+                    return self.select(base, ty, lo - start, width);
+                }
+                start += field_width;
+            }
+            return None;
+        }
+        let bits = if width == 1 {
+            format!("{}[{}]", base, lo)
+        } else {
+            format!("{}[{}:{}]", base, lo + width - 1, lo)
+        };
+        Some((bits, None))
+    }
+
+    /// Constant with the bits of a value of type `ty`, which is the name of its variant if `ty`
+    /// is a `typedef enum`
+    fn literal(&self, bits: &Integer, ty: &Ty, width: u64, within: Option<&str>) -> String {
+        match self.variant(bits, ty) {
+            Some((decl, name)) => qualified(&decl.module, name, within),
+            None => verilog::literal(bits, ty, width),
+        }
+    }
+
+    /// The enum that `ty` is along with the name of its variant whose discriminant is `bits`
+    fn variant(&self, bits: &Integer, ty: &Ty) -> Option<(&'n TypeDecl, &str)> {
+        let names = self.literals.get(ty)?;
+        let decl = self.netlist.type_decl(ty)?;
+        match &decl.kind {
+            TypeDeclKind::Enum { variants, .. } => {
+                let index = variants
+                    .iter()
+                    .position(|variant| variant.discriminant == *bits)?;
+                Some((decl, &names[index]))
+            }
+            TypeDeclKind::Struct(_) => None,
+        }
+    }
+}
+
+struct Emitter<'t, 'n> {
+    types: &'t Types<'n>,
+    module: &'n Module,
+    /// Name of the variable that holds each node
+    wires: Vec<String>,
+    /// Name of the vector that holds the bits of each node that is read in a way that its type
+    /// does not allow, such as the bits that straddle two elements of an array
+    flat: Vec<Option<String>>,
+}
+
+impl<'t, 'n> Emitter<'t, 'n> {
+    fn new(types: &'t Types<'n>, module: &'n Module) -> Self {
+        let taken = module
+            .nets
+            .iter()
+            .map(|net| net.name.as_str())
+            .collect::<Vec<_>>();
+        let fresh = |mut name: String| {
+            while taken.contains(&name.as_str()) {
+                name.push('_');
+            }
+            name
+        };
+        let wires = (0..module.nodes.len())
+            .map(|i| fresh(format!("_n{}", i)))
+            .collect::<Vec<_>>();
+        let mut emitter = Self {
+            types,
+            module,
+            wires,
+            flat: vec![None; module.nodes.len()],
+        };
+        for node in &module.nodes {
+            let arg = match node.kind {
+                NodeKind::Slice(arg, lo) if emitter.slice(arg, lo, node.width).is_none() => arg,
+                NodeKind::Index(arg, index) if emitter.index(arg, index, node.width).is_none() => {
+                    arg
+                }
+                _ => continue,
+            };
+            if node.width > 0 && emitter.flat[arg.0].is_none() {
+                emitter.flat[arg.0] = Some(fresh(format!("{}_bits", emitter.wires[arg.0])));
+            }
+        }
+        emitter
+    }
+
+    fn emit(&self, out: &mut String) {
+        let ports = self
+            .module
+            .ports
+            .iter()
+            .map(|port| {
+                let direction = match self.module.net(*port).port {
+                    Some(Direction::In) | None => "input",
+                    Some(Direction::Out) => "output",
+                    Some(Direction::InOut) => "inout",
+                };
+                format!("  {} {}", direction, self.declaration(*port))
+            })
+            .collect::<Vec<_>>();
+        if ports.is_empty() {
+            writeln!(out, "module {} ();", ident(&self.module.name)).unwrap();
+        } else {
+            writeln!(out, "module {} (", ident(&self.module.name)).unwrap();
+            writeln!(out, "{}", ports.join(",\n")).unwrap();
+            writeln!(out, ");").unwrap();
+        }
+
+        for (i, net) in self.module.nets.iter().enumerate() {
+            if net.port.is_none() && net.width > 0 {
+                writeln!(out, "  {};", self.declaration(NetId(i))).unwrap();
+            }
+        }
+        let mut assigns = vec![];
+        for (i, node) in self.module.nodes.iter().enumerate() {
+            if let Some(expr) = self.expr(NodeId(i)) {
+                writeln!(out, "  {} {};", self.node_type(NodeId(i)), self.wires[i]).unwrap();
+                assigns.push((self.wires[i].clone(), expr));
+            }
+            if let Some(flat) = &self.flat[i] {
+                writeln!(
+                    out,
+                    "  {} {};",
+                    vector(&Ty::Unsigned(None), node.width),
+                    flat
+                )
+                .unwrap();
+                assigns.push((flat.clone(), self.operand(NodeId(i))));
+            }
+        }
+        for (wire, expr) in assigns {
+            writeln!(out, "  assign {} = {};", wire, expr).unwrap();
+        }
+        for assign in &self.module.assigns {
+            writeln!(
+                out,
+                "  assign {} = {};",
+                ident(&self.module.net(assign.net).name),
+                self.value(assign.net, assign.value)
+            )
+            .unwrap();
+        }
+        for process in &self.module.processes {
+            let (block, arrow) = match process.trigger {
+                Trigger::Posedge(clock) => (
+                    format!(
+                        "always_ff @(posedge {})",
+                        ident(&self.module.net(clock).name)
+                    ),
+                    "<=",
+                ),
+                Trigger::Negedge(clock) => (
+                    format!(
+                        "always_ff @(negedge {})",
+                        ident(&self.module.net(clock).name)
+                    ),
+                    "<=",
+                ),
+                Trigger::Always => ("always_comb".to_string(), "="),
+            };
+            writeln!(out, "  {} begin", block).unwrap();
+            for update in &process.updates {
+                writeln!(
+                    out,
+                    "    {} {} {};",
+                    ident(&self.module.net(update.net).name),
+                    arrow,
+                    self.value(update.net, update.value)
+                )
+                .unwrap();
+            }
+            writeln!(out, "  end").unwrap();
+        }
+        for instance in &self.module.instances {
+            let module = self.types.netlist.module(instance.module);
+            writeln!(out, "  {} {} (", ident(&module.name), ident(&instance.name)).unwrap();
+            let connections = instance
+                .connections
+                .iter()
+                .map(|(port, net)| {
+                    format!(
+                        "    .{}({})",
+                        ident(port),
+                        ident(&self.module.net(*net).name)
+                    )
+                })
+                .collect::<Vec<_>>();
+            writeln!(out, "{}", connections.join(",\n")).unwrap();
+            writeln!(out, "  );").unwrap();
+        }
+        writeln!(out, "endmodule").unwrap();
+    }
+
+    /// Declaration of a net without its direction, such as `logic [7:0] q = 8'd0`
+    fn declaration(&self, id: NetId) -> String {
+        let net = self.module.net(id);
+        let mut declaration = format!(
+            "{} {}",
+            self.types.data_type(&net.ty, net.width, None),
+            ident(&net.name)
+        );
+        if let Some(init) = &net.init {
+            let init = self.types.literal(init, &net.ty, net.width, None);
+            write!(declaration, " = {}", init).unwrap();
+        }
+        declaration
+    }
+
+    fn node_type(&self, id: NodeId) -> DataType {
+        let node = self.module.node(id);
+        self.types.data_type(&node.ty, node.width, None)
+    }
+
+    /// The type of a node's value, or that of a vector of its bits if it is not declared as its
+    /// own type
+    fn declared_ty(&self, id: NodeId) -> Ty {
+        let node = self.module.node(id);
+        if self.types.netlist.width(&node.ty) == node.width {
+            node.ty.clone()
+        } else {
+            Ty::Unsigned(None)
+        }
+    }
+
+    /// Expression that a node's variable is driven by, or `None` if the node is used in place
+    fn expr(&self, id: NodeId) -> Option<String> {
+        let node = self.module.node(id);
+        let (expr, typed) = match &node.kind {
+            NodeKind::Const(_) | NodeKind::Net(_) => return None,
+            _ if node.width == 0 => return None,
+            NodeKind::Unary(UnaryOp::Not, arg) => (format!("~{}", self.operand(*arg)), false),
+            NodeKind::Unary(UnaryOp::Neg, arg) => (format!("-{}", self.operand(*arg)), false),
+            NodeKind::Binary(op, lhs, rhs) => {
+                let signed = matches!(self.module.node(*lhs).ty, Ty::Signed(_));
+                let op = match op {
+                    BinaryOp::Add => "+",
+                    BinaryOp::Sub => "-",
+                    BinaryOp::Mul => "*",
+                    BinaryOp::Div => "/",
+                    BinaryOp::Rem => "%",
+                    BinaryOp::Pow => "**",
+                    BinaryOp::Xor => "^",
+                    BinaryOp::And => "&",
+                    BinaryOp::Or => "|",
+                    BinaryOp::Shl => "<<",
+                    BinaryOp::Shr if signed => ">>>",
+                    BinaryOp::Shr => ">>",
+                    BinaryOp::Eq => "==",
+                    BinaryOp::Ne => "!=",
+                    BinaryOp::Lt => "<",
+                    BinaryOp::Le => "<=",
+                    BinaryOp::Gt => ">",
+                    BinaryOp::Ge => ">=",
+                };
+                let expr = format!("{} {} {}", self.operand(*lhs), op, self.operand(*rhs));
+                (expr, false)
+            }
+            // The width of the variable truncates the operand or extends it by its own signedness
+            NodeKind::Cast(arg) => (self.operand(*arg), self.typed(*arg, &node.ty)),
+            NodeKind::Slice(arg, lo) => match self.slice(*arg, *lo, node.width) {
+                Some((expr, ty)) => (expr, ty.as_ref() == Some(&node.ty)),
+                None => (self.bits(*arg, *lo, node.width), false),
+            },
+            NodeKind::Index(arg, index) => match self.index(*arg, *index, node.width) {
+                Some((expr, ty)) => (expr, ty.as_ref() == Some(&node.ty)),
+                None => {
+                    let flat = self.flat[arg.0].clone().unwrap_or_default();
+                    (self.indexed(flat, *index, node.width), false)
+                }
+            },
+            NodeKind::Concat(args) => {
+                let args = args
+                    .iter()
+                    .rev()
+                    .map(|arg| self.operand(*arg))
+                    .collect::<Vec<_>>();
+                (format!("{{{}}}", args.join(", ")), false)
+            }
+            NodeKind::Mux(cond, then, otherwise) => {
+                let expr = format!(
+                    "{} ? {} : {}",
+                    self.operand(*cond),
+                    self.operand(*then),
+                    self.operand(*otherwise)
+                );
+                let typed = self.typed(*then, &node.ty) && self.typed(*otherwise, &node.ty);
+                (expr, typed)
+            }
+        };
+        if self.types.is_enumeration(&node.ty) && !typed {
+            Some(format!("{}'({})", self.node_type(id), expr))
+        } else {
+            Some(expr)
+        }
+    }
+
+    /// Bits of a node read as a field or element of its type, if they are one or lie within one
+    fn slice(&self, arg: NodeId, lo: u64, width: u64) -> Option<(String, Option<Ty>)> {
+        self.types
+            .select(self.operand(arg), &self.declared_ty(arg), lo, width)
+    }
+
+    /// Element of a node at a variable index, if its type allows it to be read in place
+    fn index(&self, arg: NodeId, index: NodeId, width: u64) -> Option<(String, Option<Ty>)> {
+        match self.declared_ty(arg) {
+            Ty::Array(elem, _) if self.types.netlist.width(&elem) == width => Some((
+                format!("{}[{}]", self.operand(arg), self.operand(index)),
+                Some(*elem),
+            )),
+            Ty::Array(..) => None,
+            ty if self.types.is_enumeration(&ty) || self.types.netlist.fields(&ty).is_some() => {
+                None
+            }
+            _ => Some((self.indexed(self.operand(arg), index, width), None)),
+        }
+    }
+
+    /// Bits of the vector `base` at the index of an element of the given width
+    fn indexed(&self, base: String, index: NodeId, width: u64) -> String {
+        if width == 1 {
+            format!("{}[{}]", base, self.operand(index))
+        } else {
+            format!("{}[{} * {} +: {}]", base, self.operand(index), width, width)
+        }
+    }
+
+    /// Bits of a node that are read from the vector of its bits
+    fn bits(&self, arg: NodeId, lo: u64, width: u64) -> String {
+        let flat = self.flat[arg.0].clone().unwrap_or_default();
+        if width == 1 {
+            format!("{}[{}]", flat, lo)
+        } else {
+            format!("{}[{}:{}]", flat, lo + width - 1, lo)
+        }
+    }
+
+    /// Whether a node is read as a value of type `ty`, rather than as bits that must be cast to it
+    fn typed(&self, id: NodeId, ty: &Ty) -> bool {
+        let node = self.module.node(id);
+        node.ty == *ty
+            && self.types.netlist.width(ty) == node.width
+            && match &node.kind {
+                NodeKind::Const(bits) => self.types.variant(bits, ty).is_some(),
+                _ => true,
+            }
+    }
+
+    /// A node as the value of a net, which is cast if the net is an enum that the node is not
+    fn value(&self, net: NetId, value: NodeId) -> String {
+        let net = self.module.net(net);
+        if self.types.is_enumeration(&net.ty) && !self.typed(value, &net.ty) {
+            let ty = self.types.data_type(&net.ty, net.width, None);
+            format!("{}'({})", ty, self.operand(value))
+        } else {
+            self.operand(value)
+        }
+    }
+
+    /// How a node is referred to where it is used
+    fn operand(&self, id: NodeId) -> String {
+        let node = self.module.node(id);
+        match &node.kind {
+            NodeKind::Const(bits) => self.types.literal(bits, &node.ty, node.width, None),
+            NodeKind::Net(net) => ident(&self.module.net(*net).name),
+            _ => self.wires[id.0].clone(),
+        }
+    }
+}
+
+/// The type of a plain vector of bits, which is signed if `ty` is
+fn vector(ty: &Ty, width: u64) -> DataType {
+    let signed = matches!(ty, Ty::Signed(_));
+    DataType {
+        base: if signed { "logic signed" } else { "logic" }.to_string(),
+        dims: if width == 1 {
+            vec![]
+        } else {
+            vec![width.max(1)]
+        },
+    }
+}
+
+/// Name of the package of the module of the crate at `path`, such as `crate_alu_pkg`
+fn package_name(path: &str) -> String {
+    ident(&format!("{}_pkg", path.replace("::", "_")))
+}
+
+/// A name declared in the package of the module at `path`, qualified unless it is referred to
+/// from within that package
+fn qualified(path: &str, name: &str, within: Option<&str>) -> String {
+    if within == Some(path) {
+        name.to_string()
+    } else {
+        format!("{}::{}", package_name(path), name)
+    }
+}
+
+/// Name of the member of a packed struct for a field, where those of tuples are `_0`, `_1` and so
+/// on
+fn member(name: &str) -> String {
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("_{}", name)
+    } else {
+        ident(name)
+    }
+}
+
+/// A name, escaped if SystemVerilog would not read it as an identifier
+fn ident(name: &str) -> String {
+    if KEYWORDS.contains(&name) {
+        format!("\\{} ", name)
+    } else {
+        verilog::ident(name)
+    }
+}
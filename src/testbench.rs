@@ -0,0 +1,283 @@
+//! Runs the `test` items of a crate, each of which simulates the entity it is for
+//!
+//! ```text
+//! test sums_samples for Top {
+//!     en = true;
+//!     x = -3;
+//!     tick clk * 3;
+//!     assert_eq sum, -9;
+//!     assert negative;
+//! }
+//! ```
+//!
+//! The statements of a test run in order against a [`Simulator`] of its entity, as the top of the
+//! design: `port = value;` drives an input port of the top, `tick clk;` or `tick clk * n;` raises
+//! and lowers a clock once or `n` times, and `assert` and `assert_eq` check values once the
+//! inputs driven since the last step have taken effect. Their expressions are evaluated on
+//! unbounded integers, where a name such as `sum` or `sawtooth_0.level` stands for the value of
+//! that net, booleans are `0` and `1` and `!` negates a condition. A test stops at its first
+//! failure.
+
+use rug::ops::Pow;
+use rug::Integer;
+
+use crate::ast::*;
+use crate::diagnostics::Diagnostic;
+use crate::elaborate::elaborate;
+use crate::loader::Crate;
+use crate::netlist::lower;
+use crate::resolve::{resolve, DefKind, ModuleId, PathResolution, Res};
+use crate::sim::Simulator;
+use crate::source_map::FileId;
+use crate::typeck::check;
+use crate::visit::Visit;
+
+/// Largest number of bits a value in a test may grow to
+const MAX_BITS: u64 = 1 << 20;
+
+/// Outcome of running a test
+#[derive(Clone, Debug, PartialEq)]
+pub struct TestResult {
+    /// Path of the test, such as `crate::alu::adds`
+    pub name: String,
+    /// Why the test failed, which is empty if it passed
+    pub failures: Vec<Diagnostic>,
+}
+
+impl TestResult {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Every test in `file`, including those in inline modules, in source order
+pub fn tests(file: &File) -> Vec<&ItemTest> {
+    struct Tests<'ast>(Vec<&'ast ItemTest>);
+
+    impl<'ast> Visit<'ast> for Tests<'ast> {
+        fn visit_item_test(&mut self, item: &'ast ItemTest) {
+            self.0.push(item);
+        }
+    }
+
+    let mut tests = Tests(vec![]);
+    tests.visit_file(file);
+    tests.0
+}
+
+/// Runs every test of `krate`, module by module, once the crate is known to be well formed
+pub fn run(krate: &Crate) -> Result<Vec<TestResult>, Vec<Diagnostic>> {
+    let resolutions = resolve(krate)?;
+    let types = check(krate, &resolutions)?;
+    let mut results = vec![];
+    // Modules are numbered in the order that the crate lists them
+    for (id, module) in krate.modules().into_iter().enumerate() {
+        for item in &module.items {
+            let test = match item {
+                Item::Test(test) => test,
+                _ => continue,
+            };
+            let entity = match resolutions.type_path(module.file, &test.entity) {
+                Some(PathResolution {
+                    res: Res::Def(def),
+                    unresolved_segments: 0,
+                }) if resolutions.def(*def).kind == DefKind::Entity => resolutions.def(*def),
+                _ => {
+                    let diagnostic =
+                        Diagnostic::new("expected an entity for this test", test.entity.span());
+                    return Err(vec![diagnostic.in_file(module.file)]);
+                }
+            };
+            let top = format!(
+                "{}::{}",
+                resolutions.module_path(entity.module),
+                entity.name
+            );
+            let failures = elaborate(krate, &resolutions, &types, &top)
+                .and_then(|top| lower(krate, &resolutions, &types, &top))
+                .map(|netlist| {
+                    let mut bench = Bench {
+                        sim: Simulator::new(&netlist),
+                        file: module.file,
+                        pending: true,
+                    };
+                    test.stmts
+                        .iter()
+                        .try_for_each(|stmt| bench.run(stmt))
+                        .err()
+                        .into_iter()
+                        .collect()
+                })
+                .unwrap_or_else(|diagnostics| diagnostics);
+            results.push(TestResult {
+                name: format!("{}::{}", resolutions.module_path(ModuleId(id)), test.ident),
+                failures,
+            });
+        }
+    }
+    Ok(results)
+}
+
+/// Simulation of the entity that a test is for
+struct Bench<'n> {
+    sim: Simulator<'n>,
+    /// File that the spans of the test are offsets into
+    file: FileId,
+    /// Whether inputs were driven since the last step
+    pending: bool,
+}
+
+impl Bench<'_> {
+    fn error(&self, message: impl Into<String>, span: Span) -> Diagnostic {
+        Diagnostic::new(message, span).in_file(self.file)
+    }
+
+    fn run(&mut self, stmt: &TestStmt) -> Result<(), Diagnostic> {
+        match stmt {
+            TestStmt::Drive(drive) => {
+                let value = self.eval(&drive.value)?;
+                self.sim
+                    .set(&drive.port.inner, value)
+                    .map_err(|err| self.error(err.to_string(), drive.port.span.clone()))?;
+                self.pending = true;
+            }
+            TestStmt::Tick(tick) => {
+                let count = match &tick.count {
+                    Some((_, count)) => self
+                        .eval(count)?
+                        .to_u64()
+                        .ok_or_else(|| self.error("expected a count of ticks", count.span()))?,
+                    None => 1,
+                };
+                for _ in 0..count {
+                    for level in [1, 0] {
+                        self.sim
+                            .set(&tick.clock.inner, level)
+                            .and_then(|_| self.sim.step())
+                            .map_err(|err| self.error(err.to_string(), tick.clock.span.clone()))?;
+                    }
+                }
+                self.pending = false;
+            }
+            TestStmt::Assert(assert) => {
+                self.settle(stmt)?;
+                if self.eval(&assert.cond)? == 0 {
+                    return Err(self.error("assertion failed", stmt.span()));
+                }
+            }
+            TestStmt::AssertEq(assert_eq) => {
+                self.settle(stmt)?;
+                let (left, right) = (self.eval(&assert_eq.left)?, self.eval(&assert_eq.right)?);
+                if left != right {
+                    let message = format!(
+                        "assertion failed, left is `{}` and right is `{}`",
+                        left, right
+                    );
+                    return Err(self.error(message, stmt.span()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Steps the simulation if inputs were driven since the last step, so that they take effect
+    fn settle(&mut self, stmt: &TestStmt) -> Result<(), Diagnostic> {
+        if self.pending {
+            self.pending = false;
+            self.sim
+                .step()
+                .map_err(|err| self.error(err.to_string(), stmt.span()))?;
+        }
+        Ok(())
+    }
+
+    fn eval(&self, expr: &Expr) -> Result<Integer, Diagnostic> {
+        match expr {
+            Expr::Lit(Lit::Int(lit)) => Ok(lit.val.clone()),
+            Expr::Lit(Lit::Bool(lit)) => Ok(Integer::from(lit.inner as u8)),
+            Expr::Path(_) | Expr::Field(_) => {
+                let name = net_name(expr)
+                    .ok_or_else(|| self.error("expected the name of a net", expr.span()))?;
+                self.sim
+                    .get(&name)
+                    .map_err(|err| self.error(err.to_string(), expr.span()))
+            }
+            Expr::Grouped(grouped) => self.eval(&grouped.expr),
+            Expr::Unary(unary) => {
+                let value = self.eval(&unary.expr)?;
+                Ok(match unary.op {
+                    UnOp::Minus(_) => -value,
+                    UnOp::Not(_) => Integer::from((value == 0) as u8),
+                })
+            }
+            Expr::Binary(binary) => {
+                let (left, right) = (self.eval(&binary.left)?, self.eval(&binary.right)?);
+                let amount = || {
+                    right
+                        .to_u32()
+                        .ok_or_else(|| self.error("amount is out of range", binary.right.span()))
+                };
+                // Powers and left shifts can grow a value past what memory holds
+                let bounded = |bits: u64| {
+                    if bits > MAX_BITS {
+                        let message = format!("the value is wider than {} bits", MAX_BITS);
+                        Err(self.error(message, expr.span()))
+                    } else {
+                        Ok(())
+                    }
+                };
+                Ok(match &binary.op {
+                    BinOp::Plus(_) => left + right,
+                    BinOp::Minus(_) => left - right,
+                    BinOp::Star(_) => left * right,
+                    BinOp::Slash(_) | BinOp::Percent(_) if right == 0 => {
+                        return Err(self.error("division by zero", binary.right.span()))
+                    }
+                    BinOp::Slash(_) => left / right,
+                    BinOp::Percent(_) => left % right,
+                    BinOp::StarStar(_) => {
+                        let exponent = amount()?;
+                        bounded(u64::from(left.significant_bits()) * u64::from(exponent))?;
+                        left.pow(exponent)
+                    }
+                    BinOp::Caret(_) => left ^ right,
+                    BinOp::And(_) => left & right,
+                    BinOp::Or(_) => left | right,
+                    BinOp::Shl(_) => {
+                        let amount = amount()?;
+                        bounded(u64::from(left.significant_bits()) + u64::from(amount))?;
+                        left << amount
+                    }
+                    BinOp::Shr(_) => left >> amount()?,
+                    BinOp::EqEq(_) => Integer::from((left == right) as u8),
+                    BinOp::Ne(_) => Integer::from((left != right) as u8),
+                    BinOp::Lt(_) => Integer::from((left < right) as u8),
+                    BinOp::Le(_) => Integer::from((left <= right) as u8),
+                    BinOp::Gt(_) => Integer::from((left > right) as u8),
+                    BinOp::Ge(_) => Integer::from((left >= right) as u8),
+                })
+            }
+            _ => Err(self.error("expected a literal, net or operator in a test", expr.span())),
+        }
+    }
+}
+
+/// Hierarchical name of the net that a path such as `sum` or field chain such as
+/// `sawtooth_0.level` names
+fn net_name(expr: &Expr) -> Option<String> {
+    match expr {
+        Expr::Path(path) if path.leading_sep.is_none() && path.segments.len() == 1 => {
+            let segment = path.segments.first()?;
+            match segment.generic_args {
+                None => Some(segment.ident.inner.clone()),
+                Some(_) => None,
+            }
+        }
+        Expr::Field(ExprField {
+            on,
+            member: Member::Named(member),
+            ..
+        }) => Some(format!("{}.{}", net_name(on)?, member)),
+        _ => None,
+    }
+}
@@ -0,0 +1,137 @@
+//! Semantic types, which unlike the [`Type`](crate::ast::Type)s written in source know their exact width in bits
+
+use std::fmt;
+
+use crate::ast::Span;
+use crate::resolve::DefId;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Ty {
+    Bit,
+    Bool,
+    /// `uN`, or `uint` for an unsigned integer of unbounded width that only exists at compile time
+    Unsigned(Option<u32>),
+    /// `iN`, or `int`
+    Signed(Option<u32>),
+    /// An integer literal without a suffix, which takes the integer type that its context expects
+    Integer,
+    Array(Box<Ty>, u64),
+    Tuple(Vec<Ty>),
+    /// A struct or enum along with its type arguments
+    Adt {
+        def: DefId,
+        name: String,
+        args: Vec<Ty>,
+    },
+    /// The `self` of an arch, whose fields are the ports of its entity
+    Entity {
+        def: DefId,
+        name: String,
+        args: Vec<Ty>,
+    },
+    /// A range such as `0..8`, which can be iterated over or used to slice bits
+    Range(Box<Ty>),
+    /// A generic type parameter declared at the span
+    Param {
+        name: String,
+        span: Span,
+    },
+    /// A type that is still being inferred, by index into the checker's inference variables
+    Var(usize),
+    /// The type of `return`, which never produces a value
+    Never,
+    /// The type of an expression whose error has already been reported
+    Error,
+}
+
+impl Ty {
+    pub fn unit() -> Self {
+        Self::Tuple(vec![])
+    }
+
+    /// Integer type of the given signedness and width
+    pub fn int(signed: bool, width: Option<u32>) -> Self {
+        if signed {
+            Self::Signed(width)
+        } else {
+            Self::Unsigned(width)
+        }
+    }
+
+    pub fn is_integer(&self) -> bool {
+        matches!(self, Self::Unsigned(_) | Self::Signed(_) | Self::Integer)
+    }
+
+    /// Whether an integer type is signed, or `None` for other types and unsuffixed literals
+    pub fn is_signed(&self) -> Option<bool> {
+        match self {
+            Self::Unsigned(_) => Some(false),
+            Self::Signed(_) => Some(true),
+            _ => None,
+        }
+    }
+
+    /// Whether errors involving this type would only repeat one reported already
+    pub fn is_error(&self) -> bool {
+        matches!(self, Self::Error | Self::Never)
+    }
+
+    /// Width in bits of a type built from primitives alone
+    ///
+    /// Structs and enums are laid out by [`TypeContext::width`](crate::typeck::TypeContext::width),
+    /// while integers of unbounded width and types still being inferred have none.
+    pub fn width(&self) -> Option<u64> {
+        match self {
+            Self::Bit | Self::Bool => Some(1),
+            Self::Unsigned(width) | Self::Signed(width) => width.map(u64::from),
+            Self::Array(ty, len) => ty.width().map(|width| width * len),
+            Self::Tuple(tys) => tys.iter().map(Ty::width).sum(),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Ty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn list(f: &mut fmt::Formatter<'_>, tys: &[Ty]) -> fmt::Result {
+            for (i, ty) in tys.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{}", ty)?;
+            }
+            Ok(())
+        }
+
+        match self {
+            Self::Bit => write!(f, "bit"),
+            Self::Bool => write!(f, "bool"),
+            Self::Unsigned(Some(width)) => write!(f, "u{}", width),
+            Self::Unsigned(None) => write!(f, "uint"),
+            Self::Signed(Some(width)) => write!(f, "i{}", width),
+            Self::Signed(None) => write!(f, "int"),
+            Self::Integer => write!(f, "{{integer}}"),
+            Self::Array(ty, len) => write!(f, "[{}; {}]", ty, len),
+            Self::Tuple(tys) if tys.len() == 1 => write!(f, "({},)", tys[0]),
+            Self::Tuple(tys) => {
+                write!(f, "(")?;
+                list(f, tys)?;
+                write!(f, ")")
+            }
+            Self::Adt { name, args, .. } | Self::Entity { name, args, .. } => {
+                write!(f, "{}", name)?;
+                if !args.is_empty() {
+                    write!(f, "<")?;
+                    list(f, args)?;
+                    write!(f, ">")?;
+                }
+                Ok(())
+            }
+            Self::Range(ty) => write!(f, "Range<{}>", ty),
+            Self::Param { name, .. } => write!(f, "{}", name),
+            Self::Var(_) => write!(f, "_"),
+            Self::Never => write!(f, "!"),
+            Self::Error => write!(f, "{{unknown}}"),
+        }
+    }
+}
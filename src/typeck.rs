@@ -0,0 +1,2378 @@
+//! Infers and checks the type of every expression, down to the width and signedness of each integer
+
+use std::collections::HashMap;
+
+use rug::Integer;
+
+use crate::ast::token::{Comma, DotDot};
+use crate::ast::*;
+use crate::const_eval;
+use crate::diagnostics::Diagnostic;
+use crate::loader::{Crate, Module};
+use crate::resolve::{DefId, DefKind, PathResolution, Res, Resolutions};
+use crate::source_map::FileId;
+use crate::ty::Ty;
+
+/// How deeply type aliases may refer to one another before they are taken to be cyclic
+const MAX_ALIAS_DEPTH: usize = 64;
+
+/// What the generic parameters of an item and `Self` stand for while lowering its types
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Subst {
+    /// Types of the generic parameters declared at each span
+    pub params: HashMap<Span, Ty>,
+    /// Values of the const generic parameters declared at each span, which array lengths can name
+    pub consts: HashMap<Span, u64>,
+    pub self_ty: Option<Ty>,
+}
+
+/// Whether a struct or variant has named fields, positional fields or none
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shape {
+    Named,
+    Tuple,
+    Unit,
+}
+
+/// Looks up definitions and lowers the types written in source to [`Ty`]s
+pub struct TypeContext<'a> {
+    modules: Vec<&'a Module>,
+    resolutions: &'a Resolutions,
+    /// Widths of the tags of enums whose discriminants have been evaluated
+    tag_widths: HashMap<DefId, u64>,
+    /// Lengths of array types and repeat expressions that have been evaluated, by the span of the
+    /// length in its file
+    lens: HashMap<(FileId, Span), u64>,
+}
+
+impl<'a> TypeContext<'a> {
+    pub fn new(krate: &'a Crate, resolutions: &'a Resolutions) -> Self {
+        Self {
+            modules: krate.modules(),
+            resolutions,
+            tag_widths: HashMap::new(),
+            lens: HashMap::new(),
+        }
+    }
+
+    pub fn resolutions(&self) -> &'a Resolutions {
+        self.resolutions
+    }
+
+    /// The item that defines `def`, which is its enum for a variant
+    pub fn item(&self, def: DefId) -> &'a Item {
+        let def = self.resolutions.def(def);
+        &self.modules[def.module.0].items[def.item]
+    }
+
+    /// File that the spans of `def` are offsets into
+    pub fn file(&self, def: DefId) -> FileId {
+        self.modules[self.resolutions.def(def).module.0].file
+    }
+
+    /// The enum that a variant belongs to
+    pub fn parent_enum(&self, variant: DefId) -> DefId {
+        self.resolutions
+            .variants
+            .iter()
+            .find(|(_, variants)| variants.contains(&variant))
+            .map(|(id, _)| *id)
+            .expect("every variant belongs to an enum")
+    }
+
+    /// Generics declared by `def`
+    pub fn generics(&self, def: DefId) -> Option<&'a Generics> {
+        match self.item(def) {
+            Item::Fn(item) => item.sig.generics.as_ref(),
+            Item::Type(item) => item.generics.as_ref(),
+            Item::Struct(item) => item.generics.as_ref(),
+            Item::Enum(item) => item.generics.as_ref(),
+            Item::Trait(item) => item.generics.as_ref(),
+            Item::TraitAlias(item) => item.generics.as_ref(),
+            Item::Entity(item) => item.generics.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Type parameters of `def`, which take the args of a [`Ty::Adt`] or [`Ty::Entity`] in order
+    pub fn type_params(&self, def: DefId) -> Vec<&'a Ident> {
+        self.generics(def)
+            .iter()
+            .flat_map(|generics| generics.params.iter())
+            .filter_map(|param| match param {
+                GenericParam::Type(param) => Some(&param.ident),
+                GenericParam::Const(_) => None,
+            })
+            .collect()
+    }
+
+    /// Substitutes `args` for the type parameters of `def`
+    pub fn subst(&self, def: DefId, args: &[Ty]) -> Subst {
+        Subst {
+            params: self
+                .type_params(def)
+                .into_iter()
+                .zip(args)
+                .map(|(param, arg)| (param.span.clone(), arg.clone()))
+                .collect(),
+            consts: HashMap::new(),
+            self_ty: None,
+        }
+    }
+
+    /// Lowers a type written in `file`
+    ///
+    /// Types that are invalid or still to be inferred, like `_`, lower to [`Ty::Error`].
+    pub fn lower(&self, file: FileId, ty: &Type, subst: &Subst) -> Ty {
+        self.lower_in(file, ty, subst, 0)
+    }
+
+    fn lower_in(&self, file: FileId, ty: &Type, subst: &Subst, depth: usize) -> Ty {
+        match ty {
+            Type::Parenthesized(ty) => self.lower_in(file, &ty.inner, subst, depth),
+            Type::Path(path) => match self.resolutions.type_path(file, path) {
+                Some(resolution) => {
+                    self.lower_res(file, resolution, path.segments.last(), subst, depth)
+                }
+                None => Ty::Error,
+            },
+            Type::Tuple(tuple) => Ty::Tuple(
+                tuple
+                    .tys
+                    .iter()
+                    .map(|ty| self.lower_in(file, ty, subst, depth))
+                    .collect(),
+            ),
+            Type::Array(array) => match self.len(file, &array.len, subst) {
+                Some(len) => {
+                    let ty = self.lower_in(file, &array.ty, subst, depth);
+                    Ty::Array(Box::new(ty), len)
+                }
+                None => Ty::Error,
+            },
+            Type::QPath(_) | Type::Slice(_) | Type::Infer(_) | Type::Fn(_) => Ty::Error,
+        }
+    }
+
+    fn lower_res(
+        &self,
+        file: FileId,
+        resolution: &PathResolution,
+        segment: Option<&PathSegment>,
+        subst: &Subst,
+        depth: usize,
+    ) -> Ty {
+        if resolution.unresolved_segments > 0 {
+            return Ty::Error;
+        }
+        let args = self.type_args(file, segment, subst, depth);
+        match &resolution.res {
+            Res::Primitive(name) => primitive(name).unwrap_or(Ty::Error),
+            Res::GenericParam(span) => match subst.params.get(span) {
+                Some(ty) => ty.clone(),
+                None => Ty::Param {
+                    name: segment.map(|s| s.ident.inner.clone()).unwrap_or_default(),
+                    span: span.clone(),
+                },
+            },
+            Res::SelfType => subst.self_ty.clone().unwrap_or(Ty::Error),
+            Res::Def(id) => {
+                let def = self.resolutions.def(*id);
+                match (def.kind, self.item(*id)) {
+                    (DefKind::Struct, _) | (DefKind::Enum, _) => Ty::Adt {
+                        def: *id,
+                        name: def.name.clone(),
+                        args,
+                    },
+                    (DefKind::Type, Item::Type(alias)) if depth < MAX_ALIAS_DEPTH => {
+                        let subst = self.subst(*id, &args);
+                        self.lower_in(self.file(*id), &alias.ty, &subst, depth + 1)
+                    }
+                    _ => Ty::Error,
+                }
+            }
+            _ => Ty::Error,
+        }
+    }
+
+    fn type_args(
+        &self,
+        file: FileId,
+        segment: Option<&PathSegment>,
+        subst: &Subst,
+        depth: usize,
+    ) -> Vec<Ty> {
+        segment
+            .and_then(|segment| segment.generic_args.as_ref())
+            .iter()
+            .flat_map(|generic_args| generic_args.args.iter())
+            .filter_map(|arg| match arg {
+                GenericArg::Type(ty) => Some(self.lower_in(file, ty, subst, depth)),
+                GenericArg::Expr(_) | GenericArg::Binding(_) => None,
+            })
+            .collect()
+    }
+
+    /// Fields of a struct or variant, where positional fields are named `0`, `1` and so on
+    pub fn fields(&self, def: DefId, args: &[Ty]) -> (Shape, Vec<(String, Ty)>) {
+        let (owner, fields) = match (self.resolutions.def(def).kind, self.item(def)) {
+            (DefKind::Struct, Item::Struct(item)) => (def, Some(&item.fields)),
+            (DefKind::Variant, Item::Enum(item)) => {
+                let index = self.resolutions.def(def).variant.unwrap();
+                let variant = item.variants.iter().nth(index).unwrap();
+                let fields = match &variant.variant_type {
+                    VariantType::Fields(fields) => Some(fields),
+                    VariantType::Unit(_) | VariantType::Discrim(_) => None,
+                };
+                (self.parent_enum(def), fields)
+            }
+            _ => return (Shape::Unit, vec![]),
+        };
+        let file = self.file(def);
+        let subst = self.subst(owner, args);
+        match fields {
+            Some(Fields::Named(fields)) => (
+                Shape::Named,
+                fields
+                    .inner
+                    .iter()
+                    .map(|field| {
+                        let ty = self.lower(file, &field.ty, &subst);
+                        (field.ident.inner.clone(), ty)
+                    })
+                    .collect(),
+            ),
+            Some(Fields::Unnamed(fields)) => (
+                Shape::Tuple,
+                fields
+                    .inner
+                    .iter()
+                    .enumerate()
+                    .map(|(i, field)| (i.to_string(), self.lower(file, &field.ty, &subst)))
+                    .collect(),
+            ),
+            None => (Shape::Unit, vec![]),
+        }
+    }
+
+    /// Ports of an entity along with their types
+    pub fn ports(&self, entity: DefId, args: &[Ty]) -> Vec<(&'a Port, Ty)> {
+        self.ports_with(entity, &self.subst(entity, args))
+    }
+
+    /// Ports of an entity along with their types, where `subst` can also give its const params
+    pub fn ports_with(&self, entity: DefId, subst: &Subst) -> Vec<(&'a Port, Ty)> {
+        let file = self.file(entity);
+        match self.item(entity) {
+            Item::Entity(item) => item
+                .ports
+                .iter()
+                .map(|port| (port, self.lower(file, &port.ty, subst)))
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    /// Width of the tag of an enum, which holds its largest discriminant once that is known and
+    /// otherwise counts its variants
+    pub fn tag_width(&self, def: DefId) -> u64 {
+        match self.tag_widths.get(&def) {
+            Some(width) => *width,
+            None => {
+                let variants = self.resolutions.variants.get(&def).map_or(0, Vec::len);
+                u64::from(usize::BITS - variants.saturating_sub(1).leading_zeros())
+            }
+        }
+    }
+
+    pub(crate) fn set_tag_width(&mut self, def: DefId, width: u64) {
+        self.tag_widths.insert(def, width);
+    }
+
+    /// Length of an array type or repeat expression, which is known once it has been evaluated
+    /// unless it is a literal or names a const generic parameter of `subst`
+    pub fn len(&self, file: FileId, expr: &Expr, subst: &Subst) -> Option<u64> {
+        match expr {
+            Expr::Lit(Lit::Int(lit)) => lit.val.to_u64(),
+            Expr::Grouped(grouped) => self.len(file, &grouped.expr, subst),
+            Expr::Path(path) => match self.resolutions.expr_path(file, path) {
+                Some(PathResolution {
+                    res: Res::GenericParam(span),
+                    unresolved_segments: 0,
+                }) => subst.consts.get(span).copied(),
+                _ => self.lens.get(&(file, expr.span())).copied(),
+            },
+            _ => self.lens.get(&(file, expr.span())).copied(),
+        }
+    }
+
+    pub(crate) fn set_len(&mut self, file: FileId, span: Span, len: u64) {
+        self.lens.insert((file, span), len);
+    }
+
+    pub(crate) fn lens(&self) -> &HashMap<(FileId, Span), u64> {
+        &self.lens
+    }
+
+    /// Width in bits of a type, where an enum is its tag followed by the widest variant
+    pub fn width(&self, ty: &Ty) -> Option<u64> {
+        self.width_in(ty, &mut vec![])
+    }
+
+    fn width_in(&self, ty: &Ty, visiting: &mut Vec<DefId>) -> Option<u64> {
+        match ty {
+            Ty::Array(ty, len) => self.width_in(ty, visiting).map(|width| width * len),
+            Ty::Tuple(tys) => tys.iter().map(|ty| self.width_in(ty, visiting)).sum(),
+            Ty::Adt { def, args, .. } => {
+                // Types that contain themselves have no finite width
+                if visiting.contains(def) {
+                    return None;
+                }
+                visiting.push(*def);
+                let width = match self.resolutions.def(*def).kind {
+                    DefKind::Enum => {
+                        let tag = self.tag_width(*def);
+                        self.resolutions.variants[def]
+                            .iter()
+                            .map(|variant| {
+                                self.fields(*variant, args)
+                                    .1
+                                    .iter()
+                                    .map(|(_, ty)| self.width_in(ty, visiting))
+                                    .sum::<Option<u64>>()
+                            })
+                            .try_fold(0, |widest, width| width.map(|width| widest.max(width)))
+                            .map(|payload| tag + payload)
+                    }
+                    _ => self
+                        .fields(*def, args)
+                        .1
+                        .iter()
+                        .map(|(_, ty)| self.width_in(ty, visiting))
+                        .sum(),
+                };
+                visiting.pop();
+                width
+            }
+            ty => ty.width(),
+        }
+    }
+}
+
+/// Built-in type that `name` refers to
+pub fn primitive(name: &str) -> Option<Ty> {
+    match name {
+        "bit" => Some(Ty::Bit),
+        "bool" => Some(Ty::Bool),
+        "uint" => Some(Ty::Unsigned(None)),
+        "int" => Some(Ty::Signed(None)),
+        _ => {
+            let signed = name.starts_with('i');
+            let width = name.strip_prefix('u').or_else(|| name.strip_prefix('i'))?;
+            if width.starts_with('0') {
+                return None;
+            }
+            let width = width.parse::<u32>().ok()?;
+            Some(Ty::int(signed, Some(width)))
+        }
+    }
+}
+
+/// Types of the expressions and bindings of a crate
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Types {
+    exprs: HashMap<(FileId, Span), Ty>,
+    locals: HashMap<(FileId, Span), Ty>,
+}
+
+impl Types {
+    pub fn expr(&self, file: FileId, expr: &Expr) -> Option<&Ty> {
+        self.exprs.get(&(file, expr.span()))
+    }
+
+    /// Type of the `let`, parameter or pattern binding declared at `span`
+    pub fn local(&self, file: FileId, span: &Span) -> Option<&Ty> {
+        self.locals.get(&(file, span.clone()))
+    }
+}
+
+/// Checks the types of every item in `krate`
+pub fn check(krate: &Crate, resolutions: &Resolutions) -> Result<Types, Vec<Diagnostic>> {
+    let (cx, diagnostics) = const_eval::lens(krate, resolutions);
+    let mut checker = Checker {
+        methods: methods(&cx),
+        cx,
+        types: Types::default(),
+        diagnostics,
+        file: krate.root.file,
+        subst: Subst::default(),
+        locals: HashMap::new(),
+        vars: vec![],
+        exprs: vec![],
+        ret: None,
+        self_value: None,
+        ports: vec![],
+    };
+    let modules = checker.cx.modules.clone();
+    for module in modules {
+        checker.file = module.file;
+        for item in &module.items {
+            checker.check_item(item);
+        }
+    }
+    if checker.diagnostics.is_empty() {
+        Ok(checker.types)
+    } else {
+        Err(checker.diagnostics)
+    }
+}
+
+/// Methods of each struct and enum, found in the impls of every module
+pub(crate) fn methods<'a>(cx: &TypeContext<'a>) -> HashMap<DefId, Vec<Method<'a>>> {
+    let mut methods: HashMap<DefId, Vec<Method<'a>>> = HashMap::new();
+    for module in &cx.modules {
+        for item in &module.items {
+            let item_impl = match item {
+                Item::Impl(item_impl) => item_impl,
+                _ => continue,
+            };
+            let self_ty = cx.lower(module.file, &item_impl.ty, &Subst::default());
+            let def = match self_ty {
+                Ty::Adt { def, .. } => def,
+                _ => continue,
+            };
+            for impl_item in &item_impl.items {
+                if let ImplItem::Fn(item_fn) = impl_item {
+                    methods.entry(def).or_default().push(Method {
+                        file: module.file,
+                        self_ty: self_ty.clone(),
+                        item: item_fn,
+                    });
+                }
+            }
+        }
+    }
+    methods
+}
+
+pub(crate) struct Method<'a> {
+    pub(crate) file: FileId,
+    /// The type of the impl, whose type parameters are replaced by those of the receiver
+    pub(crate) self_ty: Ty,
+    pub(crate) item: &'a ItemFn,
+}
+
+/// How two types failed to unify
+enum Mismatch {
+    Width,
+    Signedness,
+    Type,
+}
+
+struct Checker<'a> {
+    cx: TypeContext<'a>,
+    methods: HashMap<DefId, Vec<Method<'a>>>,
+    types: Types,
+    diagnostics: Vec<Diagnostic>,
+    file: FileId,
+    /// What `Self` and the generic parameters of the enclosing items stand for
+    subst: Subst,
+    /// Bindings of the item being checked, by the span of their name
+    locals: HashMap<Span, (String, Ty)>,
+    /// Inference variables of the item being checked, which are `None` until unified with a type
+    vars: Vec<Option<Ty>>,
+    /// Types of the expressions of the item being checked, to be resolved once it is done
+    exprs: Vec<(Span, Ty)>,
+    /// Return type of the enclosing fn
+    ret: Option<Ty>,
+    /// Type of `self` in a method or arch
+    self_value: Option<Ty>,
+    /// Ports of the entity that the arch or test being checked is for
+    ports: Vec<(&'a Port, Ty)>,
+}
+
+impl<'a> Checker<'a> {
+    fn error(&mut self, message: impl Into<String>, span: Span) {
+        self.diagnostics
+            .push(Diagnostic::new(message, span).in_file(self.file));
+    }
+
+    fn fresh(&mut self) -> Ty {
+        self.vars.push(None);
+        Ty::Var(self.vars.len() - 1)
+    }
+
+    /// Follows inference variables that have been unified with a type
+    fn shallow(&self, ty: &Ty) -> Ty {
+        match ty {
+            Ty::Var(var) => match &self.vars[*var] {
+                Some(ty) => self.shallow(ty),
+                None => ty.clone(),
+            },
+            ty => ty.clone(),
+        }
+    }
+
+    /// Replaces every inference variable within `ty` that has been unified with a type
+    fn resolve(&self, ty: &Ty) -> Ty {
+        match self.shallow(ty) {
+            Ty::Array(ty, len) => Ty::Array(Box::new(self.resolve(&ty)), len),
+            Ty::Tuple(tys) => Ty::Tuple(tys.iter().map(|ty| self.resolve(ty)).collect()),
+            Ty::Adt { def, name, args } => Ty::Adt {
+                def,
+                name,
+                args: args.iter().map(|ty| self.resolve(ty)).collect(),
+            },
+            Ty::Entity { def, name, args } => Ty::Entity {
+                def,
+                name,
+                args: args.iter().map(|ty| self.resolve(ty)).collect(),
+            },
+            Ty::Range(ty) => Ty::Range(Box::new(self.resolve(&ty))),
+            ty => ty,
+        }
+    }
+
+    fn unify(&mut self, expected: &Ty, found: &Ty) -> Result<Ty, Mismatch> {
+        let (expected, found) = (self.shallow(expected), self.shallow(found));
+        match (&expected, &found) {
+            (Ty::Error, _) | (_, Ty::Error) => Ok(Ty::Error),
+            (Ty::Never, ty) | (ty, Ty::Never) => Ok(ty.clone()),
+            (Ty::Var(a), Ty::Var(b)) if a == b => Ok(expected),
+            (Ty::Var(var), ty) | (ty, Ty::Var(var)) => {
+                self.vars[*var] = Some(ty.clone());
+                Ok(ty.clone())
+            }
+            (Ty::Integer, ty) | (ty, Ty::Integer) if ty.is_integer() || *ty == Ty::Bit => {
+                Ok(ty.clone())
+            }
+            (Ty::Unsigned(a), Ty::Unsigned(b)) | (Ty::Signed(a), Ty::Signed(b)) => {
+                match (a, b) {
+                    (Some(a), Some(b)) if a != b => Err(Mismatch::Width),
+                    // Integers of unbounded width take on the width of the other side
+                    (None, _) => Ok(found),
+                    _ => Ok(expected),
+                }
+            }
+            (Ty::Unsigned(_), Ty::Signed(_)) | (Ty::Signed(_), Ty::Unsigned(_)) => {
+                Err(Mismatch::Signedness)
+            }
+            (Ty::Array(a, n), Ty::Array(b, m)) => {
+                let ty = self.unify(a, b)?;
+                if n == m {
+                    Ok(Ty::Array(Box::new(ty), *n))
+                } else {
+                    Err(Mismatch::Width)
+                }
+            }
+            (Ty::Tuple(a), Ty::Tuple(b)) if a.len() == b.len() => {
+                let tys = a
+                    .iter()
+                    .zip(b)
+                    .map(|(a, b)| self.unify(a, b))
+                    .collect::<Result<_, _>>()?;
+                Ok(Ty::Tuple(tys))
+            }
+            (
+                Ty::Adt {
+                    def: a,
+                    name,
+                    args: a_args,
+                },
+                Ty::Adt {
+                    def: b,
+                    args: b_args,
+                    ..
+                },
+            ) if a == b && a_args.len() == b_args.len() => {
+                let args = a_args
+                    .iter()
+                    .zip(b_args)
+                    .map(|(a, b)| self.unify(a, b))
+                    .collect::<Result<_, _>>()?;
+                Ok(Ty::Adt {
+                    def: *a,
+                    name: name.clone(),
+                    args,
+                })
+            }
+            (Ty::Range(a), Ty::Range(b)) => Ok(Ty::Range(Box::new(self.unify(a, b)?))),
+            (a, b) if a == b => Ok(expected),
+            _ => Err(Mismatch::Type),
+        }
+    }
+
+    /// Unifies the type that an expression at `span` was found to have with the type expected of it
+    fn coerce(&mut self, expected: &Ty, found: &Ty, span: Span) -> Ty {
+        match self.unify(expected, found) {
+            Ok(ty) => ty,
+            Err(mismatch) => {
+                let (expected, found) = (self.resolve(expected), self.resolve(found));
+                let kind = match mismatch {
+                    Mismatch::Width => "widths",
+                    Mismatch::Signedness => "signedness",
+                    Mismatch::Type => "types",
+                };
+                self.error(
+                    format!(
+                        "mismatched {}: expected `{}`, found `{}`",
+                        kind, expected, found
+                    ),
+                    span,
+                );
+                Ty::Error
+            }
+        }
+    }
+
+    /// Lowers a type written in the item being checked, reporting types that have no width
+    fn declared(&mut self, ty: &Type) -> Ty {
+        if let Type::Infer(_) = ty {
+            return self.fresh();
+        }
+        self.validate(ty);
+        self.cx.lower(self.file, ty, &self.subst)
+    }
+
+    fn validate(&mut self, ty: &Type) {
+        match ty {
+            Type::Parenthesized(ty) => self.validate(&ty.inner),
+            Type::Path(path) => {
+                let resolution = match self.cx.resolutions.type_path(self.file, path) {
+                    Some(resolution) => resolution,
+                    None => return,
+                };
+                let span = path.segments.span();
+                if let Res::Def(id) = resolution.res {
+                    let def = self.cx.resolutions.def(id);
+                    match def.kind {
+                        DefKind::Struct | DefKind::Enum | DefKind::Type => {
+                            let expected = self.cx.type_params(id).len();
+                            let found = path
+                                .segments
+                                .last()
+                                .and_then(|segment| segment.generic_args.as_ref())
+                                .map(|args| {
+                                    args.args
+                                        .iter()
+                                        .filter(|arg| matches!(arg, GenericArg::Type(_)))
+                                        .count()
+                                })
+                                .unwrap_or(0);
+                            if expected != found {
+                                self.error(
+                                    format!(
+                                        "`{}` takes {} type arguments but {} were given",
+                                        def.name, expected, found
+                                    ),
+                                    span,
+                                );
+                            }
+                        }
+                        kind => {
+                            let message =
+                                format!("expected a type, found {} `{}`", describe(kind), def.name);
+                            self.error(message, span);
+                        }
+                    }
+                }
+                for segment in path.segments.iter() {
+                    for arg in segment
+                        .generic_args
+                        .iter()
+                        .flat_map(|args| args.args.iter())
+                    {
+                        if let GenericArg::Type(ty) = arg {
+                            self.validate(ty);
+                        }
+                    }
+                }
+            }
+            Type::Tuple(tuple) => tuple.tys.iter().for_each(|ty| self.validate(ty)),
+            Type::Array(array) => self.validate(&array.ty),
+            Type::Slice(slice) => self.error(
+                "slices have no fixed width, use an array such as `[T; N]`",
+                slice.span(),
+            ),
+            Type::Fn(ty_fn) => self.error("function types have no width", ty_fn.span()),
+            Type::QPath(_) | Type::Infer(_) => {}
+        }
+    }
+
+    fn bind(&mut self, ident: &Ident, ty: Ty) {
+        self.locals
+            .insert(ident.span.clone(), (ident.inner.clone(), ty));
+    }
+
+    fn record(&mut self, expr: &Expr, ty: &Ty) {
+        self.exprs.push((expr.span(), ty.clone()));
+    }
+
+    fn begin_item(&mut self) {
+        self.locals.clear();
+        self.vars.clear();
+        self.exprs.clear();
+        self.ret = None;
+        self.self_value = None;
+        self.ports.clear();
+    }
+
+    /// Stores the types found for the item just checked, reporting bindings whose type could not be inferred
+    fn end_item(&mut self) {
+        let mut locals = std::mem::take(&mut self.locals)
+            .into_iter()
+            .collect::<Vec<_>>();
+        locals.sort_by_key(|(span, _)| span.0);
+        for (span, (name, ty)) in locals {
+            let ty = self.resolve(&ty);
+            if let Ty::Var(_) = ty {
+                self.error(
+                    format!("type annotations needed for `{}`", name),
+                    span.clone(),
+                );
+            }
+            self.types.locals.insert((self.file, span), ty);
+        }
+        for (span, ty) in std::mem::take(&mut self.exprs) {
+            let ty = self.resolve(&ty);
+            self.types.exprs.insert((self.file, span), ty);
+        }
+    }
+
+    /// Binds the const generic parameters of an item, and notes its type parameters in `self.subst`
+    fn generics(&mut self, generics: &Option<Generics>) {
+        for param in generics.iter().flat_map(|generics| generics.params.iter()) {
+            match param {
+                GenericParam::Type(_) => {}
+                GenericParam::Const(param) => {
+                    let ty = self.declared(&param.ty);
+                    self.bind(&param.ident, ty);
+                }
+            }
+        }
+    }
+
+    fn check_item(&mut self, item: &'a Item) {
+        self.subst = Subst::default();
+        match item {
+            Item::Const(item) => self.check_const(item),
+            Item::Fn(item) => self.check_fn(&item.sig, Some(&item.block)),
+            Item::Type(item) => {
+                self.begin_item();
+                self.validate(&item.ty);
+                self.end_item();
+            }
+            Item::Struct(item) => {
+                self.begin_item();
+                self.check_fields(&item.fields);
+                self.end_item();
+            }
+            Item::Enum(item) => {
+                self.begin_item();
+                for variant in item.variants.iter() {
+                    match &variant.variant_type {
+                        VariantType::Fields(fields) => self.check_fields(fields),
+                        VariantType::Discrim(discrim) => {
+                            let ty = self.check_expr(&discrim.expr, None);
+                            if !ty.is_integer() && !ty.is_error() {
+                                let message = format!(
+                                    "mismatched types: expected an integer, found `{}`",
+                                    self.resolve(&ty)
+                                );
+                                self.error(message, discrim.expr.span());
+                            }
+                        }
+                        VariantType::Unit(_) => {}
+                    }
+                }
+                self.end_item();
+            }
+            Item::Trait(item) => {
+                self.subst.self_ty = Some(Ty::Param {
+                    name: "Self".to_string(),
+                    span: item.ident.span.clone(),
+                });
+                for trait_item in &item.items {
+                    match trait_item {
+                        TraitItem::Const(item) => {
+                            self.begin_item();
+                            let ty = self.declared(&item.ty);
+                            if let Some((_, expr)) = &item.default {
+                                self.check_expr_coerce(expr, &ty);
+                            }
+                            self.end_item();
+                        }
+                        TraitItem::Fn(item) => self.check_fn(&item.sig, item.block.as_ref()),
+                        TraitItem::Type(_) => {}
+                    }
+                }
+            }
+            Item::Impl(item) => {
+                self.begin_item();
+                let self_ty = self.declared(&item.ty);
+                self.end_item();
+                self.subst.self_ty = Some(self_ty);
+                for impl_item in &item.items {
+                    match impl_item {
+                        ImplItem::Const(item) => self.check_const(item),
+                        ImplItem::Fn(item) => self.check_fn(&item.sig, Some(&item.block)),
+                        ImplItem::Type(item) => {
+                            self.begin_item();
+                            self.validate(&item.ty);
+                            self.end_item();
+                        }
+                    }
+                }
+            }
+            Item::Entity(item) => {
+                self.begin_item();
+                self.generics(&item.generics);
+                for port in item.ports.iter() {
+                    let ty = self.declared(&port.ty);
+                    if let Some((_, expr)) = &port.expr {
+                        self.check_expr_coerce(expr, &ty);
+                    }
+                }
+                self.end_item();
+            }
+            Item::Arch(item) => self.check_arch(item),
+            Item::Test(item) => self.check_test(item),
+            Item::Mod(_) | Item::Use(_) | Item::TraitAlias(_) | Item::Error(_) => {}
+        }
+    }
+
+    fn check_fields(&mut self, fields: &Fields) {
+        match fields {
+            Fields::Named(fields) => fields
+                .inner
+                .iter()
+                .for_each(|field| self.validate(&field.ty)),
+            Fields::Unnamed(fields) => fields
+                .inner
+                .iter()
+                .for_each(|field| self.validate(&field.ty)),
+        }
+    }
+
+    fn check_const(&mut self, item: &ItemConst) {
+        self.begin_item();
+        let ty = self.declared(&item.ty);
+        self.check_expr_coerce(&item.expr, &ty);
+        self.end_item();
+    }
+
+    fn check_fn(&mut self, sig: &Sig, block: Option<&Block>) {
+        self.begin_item();
+        self.generics(&sig.generics);
+        for input in sig.inputs.iter() {
+            match input {
+                FnArg::Receiver(_) => self.self_value = self.subst.self_ty.clone(),
+                FnArg::Typed(typed) => {
+                    let ty = self.declared(&typed.ty);
+                    self.check_pat(&typed.pat, &ty);
+                }
+            }
+        }
+        let ret = match &sig.output {
+            Some((_, ty)) => self.declared(ty),
+            None => Ty::unit(),
+        };
+        self.ret = Some(ret.clone());
+        if let Some(block) = block {
+            let found = self.check_block(block, Some(&ret));
+            let span = block_tail_span(block);
+            self.coerce(&ret, &found, span);
+        }
+        self.end_item();
+    }
+
+    fn check_arch(&mut self, item: &ItemArch) {
+        self.begin_item();
+        self.generics(&item.generics);
+        self.validate_type_path(&item.entity);
+        let entity = match self.cx.resolutions.type_path(self.file, &item.entity) {
+            Some(PathResolution {
+                res: Res::Def(id),
+                unresolved_segments: 0,
+            }) if self.cx.resolutions.def(*id).kind == DefKind::Entity => Some(*id),
+            _ => None,
+        };
+        if let Some(entity) = entity {
+            let args = self
+                .cx
+                .type_args(self.file, item.entity.segments.last(), &self.subst, 0);
+            self.ports = self.cx.ports(entity, &args);
+            self.self_value = Some(Ty::Entity {
+                def: entity,
+                name: self.cx.resolutions.def(entity).name.clone(),
+                args,
+            });
+        }
+
+        // Signals and constants are visible throughout the arch, so their types are needed up front
+        for arch_item in &item.items {
+            match arch_item {
+                ArchItem::Let(local) => {
+                    let ty = match &local.ty {
+                        Some((_, ty)) => self.declared(ty),
+                        None => self.fresh(),
+                    };
+                    self.check_pat(&local.pat, &ty);
+                }
+                ArchItem::Const(item_const) => {
+                    let ty = self.declared(&item_const.ty);
+                    self.bind(&item_const.ident, ty);
+                }
+                ArchItem::When(_) | ArchItem::EntityExpression(_) => {}
+            }
+        }
+        for arch_item in &item.items {
+            match arch_item {
+                ArchItem::Let(local) => {
+                    if let Some((_, init)) = &local.init {
+                        let ty = self.pat_ty(&local.pat);
+                        self.check_expr_coerce(init, &ty);
+                    }
+                }
+                ArchItem::Const(item_const) => {
+                    let ty = self.locals[&item_const.ident.span].1.clone();
+                    self.check_expr_coerce(&item_const.expr, &ty);
+                }
+                ArchItem::When(when) => {
+                    self.check_condition(&when.expr);
+                    let found = self.check_block(&when.block, Some(&Ty::unit()));
+                    self.coerce(&Ty::unit(), &found, block_tail_span(&when.block));
+                }
+                ArchItem::EntityExpression(expr) => self.check_entity_expression(expr),
+            }
+        }
+        self.end_item();
+    }
+
+    /// Checks the statements of a test against the ports of the entity it is for
+    ///
+    /// Other names in a test stand for nets inside the design, which only exist once it is lowered,
+    /// so the expressions that name them are left to the test bench.
+    fn check_test(&mut self, item: &ItemTest) {
+        self.begin_item();
+        self.validate_type_path(&item.entity);
+        let entity = match self.cx.resolutions.type_path(self.file, &item.entity) {
+            Some(PathResolution {
+                res: Res::Def(id),
+                unresolved_segments: 0,
+            }) if self.cx.resolutions.def(*id).kind == DefKind::Entity => *id,
+            _ => return self.end_item(),
+        };
+        let name = self.cx.resolutions.def(entity).name.clone();
+        let args = self.args_or_fresh(entity, item.entity.segments.last());
+        self.ports = self.cx.ports(entity, &args);
+        for stmt in &item.stmts {
+            match stmt {
+                TestStmt::Drive(drive) => {
+                    if let Some(ty) = self.test_input(&name, &drive.port) {
+                        self.test_expr(&drive.value, Some(&ty));
+                    }
+                }
+                TestStmt::Tick(tick) => {
+                    if let Some(ty) = self.test_input(&name, &tick.clock) {
+                        let ty = self.shallow(&ty);
+                        if ty != Ty::Bit && !ty.is_error() {
+                            let message = format!(
+                                "`{}` is not a clock: expected `bit`, found `{}`",
+                                tick.clock,
+                                self.resolve(&ty)
+                            );
+                            self.error(message, tick.clock.span.clone());
+                        }
+                    }
+                    if let Some((_, count)) = &tick.count {
+                        match self.test_expr(count, None).map(|ty| self.shallow(&ty)) {
+                            Some(ty) if !ty.is_integer() && !ty.is_error() => {
+                                let message = format!(
+                                    "mismatched types: expected an integer, found `{}`",
+                                    self.resolve(&ty)
+                                );
+                                self.error(message, count.span());
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                TestStmt::Assert(assert) => {
+                    if let Some(ty) = self.test_expr(&assert.cond, None) {
+                        self.condition(&ty, assert.cond.span());
+                    }
+                }
+                TestStmt::AssertEq(assert_eq) => {
+                    let left = self.test_expr(&assert_eq.left, None);
+                    let right = self.test_expr(&assert_eq.right, left.as_ref());
+                    if let (None, Some(right)) = (left, right) {
+                        // A net on the left has no type here, but a port on the right does
+                        self.test_expr(&assert_eq.left, Some(&right));
+                    }
+                }
+            }
+        }
+        self.end_item();
+    }
+
+    /// Type of the input port that a test drives or ticks, reporting any other name
+    fn test_input(&mut self, entity: &str, ident: &Ident) -> Option<Ty> {
+        let (port, ty) = match self.ports.iter().find(|(port, _)| port.ident == *ident) {
+            Some((port, ty)) => (*port, ty.clone()),
+            None => {
+                let message = format!("entity `{}` has no port named `{}`", entity, ident);
+                self.error(message, ident.span.clone());
+                return None;
+            }
+        };
+        match port.port_type {
+            PortType::In(_) => Some(ty),
+            _ => {
+                let message = format!("`{}` is not an input of entity `{}`", ident, entity);
+                self.error(message, ident.span.clone());
+                None
+            }
+        }
+    }
+
+    /// Type of an expression in a test, if it is a port or a constant, which is checked against
+    /// what is expected of it
+    fn test_expr(&mut self, expr: &Expr, expected: Option<&Ty>) -> Option<Ty> {
+        let port = match expr {
+            Expr::Path(path) if path.leading_sep.is_none() && path.segments.len() == 1 => {
+                let ident = &path.segments.first()?.ident;
+                self.ports
+                    .iter()
+                    .find(|(port, _)| port.ident == *ident)
+                    .map(|(_, ty)| ty.clone())
+            }
+            _ => None,
+        };
+        match (port, expected) {
+            (Some(ty), Some(expected)) => Some(self.coerce(expected, &ty, expr.span())),
+            (Some(ty), None) => Some(ty),
+            (None, _) if !is_constant(expr) => None,
+            (None, Some(expected)) => Some(self.check_expr_coerce(expr, expected)),
+            (None, None) => Some(self.check_expr(expr, None)),
+        }
+    }
+
+    /// Type that a pattern was bound with, for a `let` whose bindings are already known
+    fn pat_ty(&mut self, pat: &Pat) -> Ty {
+        match pat {
+            Pat::Ident(ident) => self.locals[&ident.span].1.clone(),
+            Pat::Tuple(tuple) if tuple.rest_subpats.is_none() => {
+                Ty::Tuple(tuple.subpats.iter().map(|pat| self.pat_ty(pat)).collect())
+            }
+            _ => self.fresh(),
+        }
+    }
+
+    fn check_entity_expression(&mut self, expr: &ArchItemEntityExpression) {
+        let path = &expr.path;
+        let entity = match self.cx.resolutions.expr_path(self.file, path) {
+            Some(PathResolution {
+                res: Res::Def(id),
+                unresolved_segments: 0,
+            }) => *id,
+            _ => return,
+        };
+        let def = self.cx.resolutions.def(entity);
+        if def.kind != DefKind::Entity {
+            let message = format!(
+                "expected an entity, found {} `{}`",
+                describe(def.kind),
+                def.name
+            );
+            self.error(message, path.segments.span());
+            return;
+        }
+        let name = def.name.clone();
+        let args = self.args_or_fresh(entity, path.segments.last());
+        let ports = self.cx.ports(entity, &args);
+        for field in expr.fields.iter() {
+            let expected = match ports.iter().find(|(port, _)| port.ident == field.ident) {
+                Some((_, ty)) => ty.clone(),
+                None => {
+                    let message = format!("entity `{}` has no port named `{}`", name, field.ident);
+                    self.error(message, field.ident.span.clone());
+                    Ty::Error
+                }
+            };
+            match &field.expr {
+                Some((_, value)) => {
+                    self.check_expr_coerce(value, &expected);
+                }
+                None => {
+                    let found = self.shorthand_ty(&field.ident);
+                    self.coerce(&expected, &found, field.ident.span.clone());
+                }
+            }
+        }
+    }
+
+    fn shorthand_ty(&mut self, ident: &Ident) -> Ty {
+        match self.cx.resolutions.ident(self.file, ident).cloned() {
+            Some(resolution) => self.res_ty(&resolution, None, ident.span.clone()),
+            None => Ty::Error,
+        }
+    }
+
+    /// Type args of `def` given by `segment`, or fresh inference variables where none are given
+    fn args_or_fresh(&mut self, def: DefId, segment: Option<&PathSegment>) -> Vec<Ty> {
+        let args = self.cx.type_args(self.file, segment, &self.subst, 0);
+        if args.is_empty() {
+            self.cx
+                .type_params(def)
+                .iter()
+                .map(|_| self.fresh())
+                .collect()
+        } else {
+            args
+        }
+    }
+
+    fn validate_type_path(&mut self, path: &TypePath) {
+        for segment in path.segments.iter() {
+            for arg in segment
+                .generic_args
+                .iter()
+                .flat_map(|args| args.args.iter())
+            {
+                if let GenericArg::Type(ty) = arg {
+                    self.validate(ty);
+                }
+            }
+        }
+    }
+
+    fn check_condition(&mut self, expr: &Expr) {
+        let ty = self.check_expr(expr, Some(&Ty::Bool));
+        self.condition(&ty, expr.span());
+    }
+
+    /// Reports a condition at `span` whose type is neither `bool` nor `bit`
+    fn condition(&mut self, ty: &Ty, span: Span) {
+        match self.shallow(ty) {
+            Ty::Bool | Ty::Bit => {}
+            ty if ty.is_error() => {}
+            Ty::Var(_) => {
+                self.coerce(&Ty::Bool, ty, span);
+            }
+            ty => {
+                let message = format!(
+                    "mismatched types: expected `bool` or `bit`, found `{}`",
+                    self.resolve(&ty)
+                );
+                self.error(message, span);
+            }
+        }
+    }
+
+    fn check_expr_coerce(&mut self, expr: &Expr, expected: &Ty) -> Ty {
+        let found = self.check_expr(expr, Some(expected));
+        self.coerce(expected, &found, expr.span())
+    }
+
+    fn check_expr(&mut self, expr: &Expr, expected: Option<&Ty>) -> Ty {
+        let ty = self.infer_expr(expr, expected);
+        self.record(expr, &ty);
+        ty
+    }
+
+    fn infer_expr(&mut self, expr: &Expr, expected: Option<&Ty>) -> Ty {
+        match expr {
+            Expr::Lit(lit) => self.check_lit(lit, expected, false),
+            Expr::Unary(unary) => self.check_unary(unary, expected),
+            Expr::Binary(binary) => self.check_binary(binary, expected),
+            Expr::Assign(assign) => self.check_assign(assign),
+            Expr::Repeat(repeat) => {
+                let element = match expected.map(|ty| self.shallow(ty)) {
+                    Some(Ty::Array(element, _)) => Some(*element),
+                    _ => None,
+                };
+                let element = self.check_expr(&repeat.init, element.as_ref());
+                self.check_expr(&repeat.repeat, Some(&Ty::Unsigned(None)));
+                match self.cx.len(self.file, &repeat.repeat, &self.subst) {
+                    Some(len) => Ty::Array(Box::new(element), len),
+                    None => Ty::Error,
+                }
+            }
+            Expr::Range(range) => {
+                let mut ty = expected
+                    .map(|ty| self.shallow(ty))
+                    .and_then(|ty| match ty {
+                        Ty::Range(ty) => Some(*ty),
+                        _ => None,
+                    })
+                    .unwrap_or(Ty::Integer);
+                for bound in range.left.iter().chain(range.right.iter()) {
+                    let found = self.check_expr(bound, Some(&ty));
+                    ty = self.coerce(&ty, &found, bound.span());
+                    if !ty.is_integer() && !ty.is_error() {
+                        let message = format!(
+                            "mismatched types: expected an integer, found `{}`",
+                            self.resolve(&ty)
+                        );
+                        self.error(message, bound.span());
+                        ty = Ty::Error;
+                    }
+                }
+                Ty::Range(Box::new(ty))
+            }
+            Expr::Path(path) => match self.cx.resolutions.expr_path(self.file, path).cloned() {
+                Some(resolution) => {
+                    self.res_ty(&resolution, path.segments.last(), path.segments.span())
+                }
+                None => Ty::Error,
+            },
+            // Associated items are not yet looked up
+            Expr::QPath(_) => Ty::Error,
+            Expr::Field(field) => self.check_field(field),
+            Expr::Call(call) => self.check_call(call),
+            Expr::MethodCall(call) => self.check_method_call(call),
+            Expr::Index(index) => self.check_index(index),
+            Expr::Array(array) => {
+                let mut element = match expected.map(|ty| self.shallow(ty)) {
+                    Some(Ty::Array(element, _)) => *element,
+                    _ => self.fresh(),
+                };
+                for expr in array.elements.iter() {
+                    let found = self.check_expr(expr, Some(&element));
+                    element = self.coerce(&element, &found, expr.span());
+                }
+                Ty::Array(Box::new(element), array.elements.len() as u64)
+            }
+            Expr::Tuple(tuple) => {
+                let expected = match expected.map(|ty| self.shallow(ty)) {
+                    Some(Ty::Tuple(tys)) if tys.len() == tuple.elements.len() => tys,
+                    _ => vec![],
+                };
+                Ty::Tuple(
+                    tuple
+                        .elements
+                        .iter()
+                        .enumerate()
+                        .map(|(i, expr)| self.check_expr(expr, expected.get(i)))
+                        .collect(),
+                )
+            }
+            Expr::Cast(cast) => {
+                let from = self.check_expr(&cast.expr, None);
+                let to = self.declared(&cast.ty);
+                let castable =
+                    |ty: &Ty| ty.is_integer() || ty.is_error() || matches!(ty, Ty::Bit | Ty::Bool);
+                let from = self.shallow(&from);
+                if !castable(&from) || !castable(&to) {
+                    let message =
+                        format!("non-primitive cast: `{}` as `{}`", self.resolve(&from), to);
+                    self.error(message, expr.span());
+                }
+                to
+            }
+            Expr::For(expr_for) => {
+                let iter = self.check_expr(&expr_for.expr, None);
+                let element = match self.shallow(&iter) {
+                    Ty::Range(element) => *element,
+                    Ty::Array(element, _) => *element,
+                    ty if ty.is_error() => Ty::Error,
+                    ty => {
+                        let message = format!("`{}` is not a range or array", self.resolve(&ty));
+                        self.error(message, expr_for.expr.span());
+                        Ty::Error
+                    }
+                };
+                self.check_pat(&expr_for.pat, &element);
+                let found = self.check_block(&expr_for.block, Some(&Ty::unit()));
+                self.coerce(&Ty::unit(), &found, block_tail_span(&expr_for.block));
+                Ty::unit()
+            }
+            Expr::If(expr_if) => {
+                self.check_condition(&expr_if.expr);
+                match &expr_if.else_token {
+                    Some((_, else_expr)) => {
+                        let then = self.check_block(&expr_if.block, expected);
+                        let otherwise = self.check_expr(else_expr, Some(&then));
+                        self.coerce(&then, &otherwise, else_expr.span())
+                    }
+                    None => {
+                        let then = self.check_block(&expr_if.block, Some(&Ty::unit()));
+                        self.coerce(&Ty::unit(), &then, block_tail_span(&expr_if.block))
+                    }
+                }
+            }
+            Expr::Match(expr_match) => {
+                let scrutinee = self.check_expr(&expr_match.expr, None);
+                let mut ty = match expected {
+                    Some(expected) => expected.clone(),
+                    None => self.fresh(),
+                };
+                let mut found_any = false;
+                for arm in expr_match.arms.iter() {
+                    self.check_pat(&arm.pat, &scrutinee);
+                    if let Some((_, guard)) = &arm.guard {
+                        self.check_condition(guard);
+                    }
+                    let found = self.check_expr(&arm.body, Some(&ty));
+                    ty = self.coerce(&ty, &found, arm.body.span());
+                    found_any = true;
+                }
+                if found_any {
+                    ty
+                } else {
+                    Ty::Never
+                }
+            }
+            Expr::Block(block) => self.check_block(block, expected),
+            Expr::Return(expr_return) => {
+                let ret = self.ret.clone().unwrap_or(Ty::Error);
+                match &expr_return.expr {
+                    Some(value) => {
+                        self.check_expr_coerce(value, &ret);
+                    }
+                    None => {
+                        self.coerce(&ret, &Ty::unit(), expr.span());
+                    }
+                }
+                Ty::Never
+            }
+            Expr::Struct(expr_struct) => self.check_struct(expr_struct),
+            Expr::Grouped(grouped) => self.check_expr(&grouped.expr, expected),
+        }
+    }
+
+    fn check_lit(&mut self, lit: &Lit, expected: Option<&Ty>, negated: bool) -> Ty {
+        let lit = match lit {
+            Lit::Int(lit) => lit,
+            Lit::Bool(_) => return Ty::Bool,
+            Lit::Float(lit) => {
+                self.error(
+                    "floating point numbers have no hardware representation",
+                    lit.span.clone(),
+                );
+                return Ty::Error;
+            }
+        };
+        let ty = match &lit.suffix {
+            Some(suffix) => match primitive(&suffix.inner) {
+                Some(ty) if ty.is_integer() => ty,
+                _ => {
+                    let message = format!("invalid suffix `{}` for an integer literal", suffix);
+                    self.error(message, lit.span.clone());
+                    return Ty::Error;
+                }
+            },
+            None => match expected.map(|ty| self.shallow(ty)) {
+                Some(ty) if ty.is_integer() || ty == Ty::Bit => ty,
+                _ => Ty::Integer,
+            },
+        };
+        let value = if negated {
+            -Integer::from(&lit.val)
+        } else {
+            Integer::from(&lit.val)
+        };
+        let (min, max) = match &ty {
+            Ty::Bit => (Integer::new(), Integer::from(1)),
+            Ty::Unsigned(Some(width)) => (Integer::new(), (Integer::from(1) << *width) - 1u32),
+            Ty::Signed(Some(width)) => {
+                let half = Integer::from(1) << (*width - 1);
+                (-half.clone(), half - 1u32)
+            }
+            _ => return ty,
+        };
+        if value < min || value > max {
+            let message = format!(
+                "literal out of range for `{}`, whose range is `{}..={}`",
+                ty, min, max
+            );
+            self.error(message, lit.span.clone());
+        }
+        ty
+    }
+
+    fn check_unary(&mut self, unary: &ExprUnary, expected: Option<&Ty>) -> Ty {
+        let negated_literal = match (&unary.op, unary.expr.as_ref()) {
+            (UnOp::Minus(_), Expr::Lit(lit)) => Some(lit),
+            _ => None,
+        };
+        let ty = match negated_literal {
+            Some(lit) => {
+                let ty = self.check_lit(lit, expected, true);
+                self.record(&unary.expr, &ty);
+                ty
+            }
+            None => self.check_expr(&unary.expr, expected),
+        };
+        let ty = self.shallow(&ty);
+        match (&unary.op, &ty) {
+            (_, ty) if ty.is_error() => ty.clone(),
+            (UnOp::Not(_), Ty::Bit | Ty::Bool | Ty::Unsigned(_) | Ty::Signed(_) | Ty::Integer) => {
+                ty
+            }
+            (UnOp::Minus(_), Ty::Signed(_) | Ty::Integer) => ty,
+            (UnOp::Minus(_), Ty::Unsigned(_)) => {
+                let message = format!("mismatched signedness: cannot negate unsigned `{}`", ty);
+                self.error(message, unary.op.span());
+                Ty::Error
+            }
+            (UnOp::Not(_), Ty::Param { .. }) | (UnOp::Minus(_), Ty::Param { .. }) => ty,
+            (op, ty) => {
+                let message = format!(
+                    "cannot apply unary `{}` to `{}`",
+                    op.to_tokens()[0],
+                    self.resolve(ty)
+                );
+                self.error(message, unary.op.span());
+                Ty::Error
+            }
+        }
+    }
+
+    fn check_binary(&mut self, binary: &ExprBinary, expected: Option<&Ty>) -> Ty {
+        let op = Operator::of_bin(&binary.op);
+        let operand = match op {
+            Operator::Arithmetic | Operator::Bitwise | Operator::Shift | Operator::Power => {
+                expected
+            }
+            _ => None,
+        };
+        self.check_operator(
+            op,
+            &binary.op.to_tokens()[0],
+            &binary.left,
+            &binary.right,
+            operand,
+        )
+    }
+
+    /// Checks `left op right`, for a binary expression or a compound assignment
+    fn check_operator(
+        &mut self,
+        op: Operator,
+        token: &Tok,
+        left: &Expr,
+        right: &Expr,
+        expected: Option<&Ty>,
+    ) -> Ty {
+        let span = token.span();
+        let mut left_ty = self.check_expr(left, expected);
+        let right_ty = match op {
+            // Shift amounts and exponents need not match the value they apply to
+            Operator::Shift | Operator::Power => {
+                let ty = self.check_expr(right, None);
+                match self.shallow(&ty) {
+                    ty if ty.is_error() => {}
+                    Ty::Unsigned(_) | Ty::Integer => {}
+                    Ty::Signed(_) => {
+                        let message = format!(
+                            "mismatched signedness: `{}` expects an unsigned amount, found `{}`",
+                            token, ty
+                        );
+                        self.error(message, right.span());
+                    }
+                    ty => {
+                        let message = format!(
+                            "mismatched types: expected an unsigned integer, found `{}`",
+                            self.resolve(&ty)
+                        );
+                        self.error(message, right.span());
+                    }
+                }
+                left_ty.clone()
+            }
+            _ => {
+                let hint = match self.shallow(&left_ty) {
+                    Ty::Integer | Ty::Var(_) => expected.cloned(),
+                    ty => Some(ty),
+                };
+                let right_ty = self.check_expr(right, hint.as_ref());
+                // A literal on the left takes its type from the right, and is checked to fit in it
+                if self.shallow(&left_ty) == Ty::Integer && is_literal(left) {
+                    left_ty = self.check_expr(left, Some(&right_ty));
+                }
+                right_ty
+            }
+        };
+
+        let ty = match op {
+            Operator::Shift | Operator::Power => self.shallow(&left_ty),
+            _ => {
+                let ty = self.coerce(&left_ty, &right_ty, right.span());
+                self.shallow(&ty)
+            }
+        };
+        let allowed = match op {
+            Operator::Arithmetic | Operator::Power | Operator::Shift | Operator::Ordering => {
+                ty.is_integer()
+            }
+            Operator::Bitwise => ty.is_integer() || matches!(ty, Ty::Bit | Ty::Bool),
+            Operator::Equality => true,
+        };
+        if !allowed && !ty.is_error() && !matches!(ty, Ty::Param { .. } | Ty::Var(_)) {
+            let message = format!("cannot apply `{}` to `{}`", token, self.resolve(&ty));
+            self.error(message, span);
+            return Ty::Error;
+        }
+        match op {
+            Operator::Equality | Operator::Ordering => Ty::Bool,
+            _ => ty,
+        }
+    }
+
+    fn check_assign(&mut self, assign: &ExprAssign) -> Ty {
+        if !is_place(&assign.lhs) {
+            self.error("invalid left-hand side of assignment", assign.lhs.span());
+        }
+        match Operator::of_ass(&assign.op) {
+            None => {
+                let lhs = self.check_expr(&assign.lhs, None);
+                self.check_expr_coerce(&assign.rhs, &lhs);
+            }
+            Some(op) => {
+                let token = assign.op.to_tokens()[0].clone();
+                let ty = self.check_operator(op, &token, &assign.lhs, &assign.rhs, None);
+                let lhs = self.resolve(&self.shallow(&self.types_of(&assign.lhs)));
+                self.coerce(&lhs, &ty, assign.rhs.span());
+            }
+        }
+        Ty::unit()
+    }
+
+    /// Type most recently recorded for an expression of the item being checked
+    fn types_of(&self, expr: &Expr) -> Ty {
+        let span = expr.span();
+        self.exprs
+            .iter()
+            .rev()
+            .find(|(recorded, _)| *recorded == span)
+            .map(|(_, ty)| ty.clone())
+            .unwrap_or(Ty::Error)
+    }
+
+    fn res_ty(
+        &mut self,
+        resolution: &PathResolution,
+        segment: Option<&PathSegment>,
+        span: Span,
+    ) -> Ty {
+        // Associated items are not yet looked up
+        if resolution.unresolved_segments > 0 {
+            return Ty::Error;
+        }
+        match &resolution.res {
+            Res::Local(local) | Res::GenericParam(local) => match self.locals.get(local) {
+                Some((_, ty)) => ty.clone(),
+                None => {
+                    self.error("expected a value, found a type parameter", span);
+                    Ty::Error
+                }
+            },
+            Res::Port { index, .. } => match self.ports.get(*index) {
+                Some((_, ty)) => ty.clone(),
+                None => Ty::Error,
+            },
+            Res::SelfValue => self.self_value.clone().unwrap_or(Ty::Error),
+            Res::Def(id) => {
+                let def = self.cx.resolutions.def(*id);
+                match (def.kind, self.cx.item(*id)) {
+                    (DefKind::Const, Item::Const(item)) => {
+                        self.cx
+                            .lower(self.cx.file(*id), &item.ty, &Subst::default())
+                    }
+                    (DefKind::Variant, _) if self.cx.fields(*id, &[]).0 == Shape::Unit => {
+                        let parent = self.cx.parent_enum(*id);
+                        let args = self.args_or_fresh(parent, segment);
+                        Ty::Adt {
+                            def: parent,
+                            name: self.cx.resolutions.def(parent).name.clone(),
+                            args,
+                        }
+                    }
+                    (kind, _) => {
+                        let message =
+                            format!("expected a value, found {} `{}`", describe(kind), def.name);
+                        self.error(message, span);
+                        Ty::Error
+                    }
+                }
+            }
+            Res::Primitive(name) => {
+                self.error(
+                    format!("expected a value, found builtin type `{}`", name),
+                    span,
+                );
+                Ty::Error
+            }
+            Res::SelfType => {
+                self.error("expected a value, found `Self`", span);
+                Ty::Error
+            }
+        }
+    }
+
+    fn check_field(&mut self, field: &ExprField) -> Ty {
+        let on = self.check_expr(&field.on, None);
+        let on = self.resolve(&on);
+        let name = match &field.member {
+            Member::Named(ident) => ident.inner.clone(),
+            Member::Unnamed(index) => index.val.to_string(),
+        };
+        let found = match &on {
+            ty if ty.is_error() => return Ty::Error,
+            Ty::Adt { def, args, .. } => {
+                let (_, fields) = self.cx.fields(*def, args);
+                fields
+                    .into_iter()
+                    .find(|(field, _)| *field == name)
+                    .map(|(_, ty)| ty)
+            }
+            Ty::Tuple(tys) => name.parse::<usize>().ok().and_then(|i| tys.get(i).cloned()),
+            Ty::Entity { def, args, .. } => self
+                .cx
+                .ports(*def, args)
+                .into_iter()
+                .find(|(port, _)| port.ident.inner == name)
+                .map(|(_, ty)| ty),
+            // Clock edges are read as fields of the clock
+            Ty::Bit if name == "posedge" || name == "negedge" => Some(Ty::Bool),
+            _ => None,
+        };
+        match found {
+            Some(ty) => ty,
+            None => {
+                let message = format!("no field `{}` on type `{}`", name, on);
+                self.error(message, field.member.span());
+                Ty::Error
+            }
+        }
+    }
+
+    fn check_args(&mut self, args: &Punctuated<Expr, Comma>, params: &[Ty], span: Span) {
+        if args.len() != params.len() {
+            let message = format!(
+                "this takes {} arguments but {} were supplied",
+                params.len(),
+                args.len()
+            );
+            self.error(message, span);
+        }
+        for (i, arg) in args.iter().enumerate() {
+            match params.get(i) {
+                Some(param) => {
+                    self.check_expr_coerce(arg, param);
+                }
+                None => {
+                    self.check_expr(arg, None);
+                }
+            }
+        }
+    }
+
+    fn check_call(&mut self, call: &ExprCall) -> Ty {
+        let path = match call.on.as_ref() {
+            Expr::Path(path) => path,
+            on => {
+                let ty = self.check_expr(on, None);
+                if !ty.is_error() {
+                    let message = format!("expected a function, found `{}`", self.resolve(&ty));
+                    self.error(message, on.span());
+                }
+                for arg in call.args.iter() {
+                    self.check_expr(arg, None);
+                }
+                return Ty::Error;
+            }
+        };
+        let resolution = self.cx.resolutions.expr_path(self.file, path).cloned();
+        let id = match resolution {
+            Some(PathResolution {
+                res: Res::Def(id),
+                unresolved_segments: 0,
+            }) => id,
+            _ => {
+                for arg in call.args.iter() {
+                    self.check_expr(arg, None);
+                }
+                return Ty::Error;
+            }
+        };
+        let segment = path.segments.last();
+        let span = call.on.span();
+        let def = self.cx.resolutions.def(id);
+        match (def.kind, self.cx.item(id)) {
+            (DefKind::Fn, Item::Fn(item)) => {
+                let file = self.cx.file(id);
+                let mut subst = Subst::default();
+                let args = self.args_or_fresh(id, segment);
+                for (param, arg) in self.cx.type_params(id).into_iter().zip(args) {
+                    subst.params.insert(param.span.clone(), arg);
+                }
+                let params = item
+                    .sig
+                    .inputs
+                    .iter()
+                    .filter_map(|input| match input {
+                        FnArg::Typed(typed) => Some(self.cx.lower(file, &typed.ty, &subst)),
+                        FnArg::Receiver(_) => None,
+                    })
+                    .collect::<Vec<_>>();
+                self.check_args(&call.args, &params, span);
+                match &item.sig.output {
+                    Some((_, ty)) => self.cx.lower(file, ty, &subst),
+                    None => Ty::unit(),
+                }
+            }
+            (DefKind::Struct, _) | (DefKind::Variant, _) => {
+                let owner = match def.kind {
+                    DefKind::Variant => self.cx.parent_enum(id),
+                    _ => id,
+                };
+                let args = self.args_or_fresh(owner, segment);
+                let (shape, fields) = self.cx.fields(id, &args);
+                if shape != Shape::Tuple {
+                    let message =
+                        format!("`{}` has no positional fields to call it with", def.name);
+                    self.error(message, span.clone());
+                }
+                let params = fields.into_iter().map(|(_, ty)| ty).collect::<Vec<_>>();
+                self.check_args(&call.args, &params, span);
+                Ty::Adt {
+                    def: owner,
+                    name: self.cx.resolutions.def(owner).name.clone(),
+                    args,
+                }
+            }
+            (kind, _) => {
+                let message = format!(
+                    "expected a function, found {} `{}`",
+                    describe(kind),
+                    def.name
+                );
+                self.error(message, span);
+                for arg in call.args.iter() {
+                    self.check_expr(arg, None);
+                }
+                Ty::Error
+            }
+        }
+    }
+
+    fn check_method_call(&mut self, call: &ExprMethodCall) -> Ty {
+        let on = self.check_expr(&call.on, None);
+        let on = self.resolve(&on);
+        let (def, args) = match &on {
+            ty if ty.is_error() => {
+                for arg in call.args.iter() {
+                    self.check_expr(arg, None);
+                }
+                return Ty::Error;
+            }
+            Ty::Adt { def, args, .. } => (*def, args.clone()),
+            _ => (DefId(usize::MAX), vec![]),
+        };
+        let method = self
+            .methods
+            .get(&def)
+            .and_then(|methods| {
+                methods
+                    .iter()
+                    .find(|method| method.item.sig.ident == call.method)
+            })
+            .map(|method| (method.file, method.self_ty.clone(), method.item));
+        let (file, self_ty, item) = match method {
+            Some(method) => method,
+            None => {
+                let message = format!("no method named `{}` found for `{}`", call.method, on);
+                self.error(message, call.method.span.clone());
+                for arg in call.args.iter() {
+                    self.check_expr(arg, None);
+                }
+                return Ty::Error;
+            }
+        };
+
+        // The impl's type parameters are those of the receiver, and the method's own are inferred
+        let mut subst = Subst {
+            params: HashMap::new(),
+            consts: HashMap::new(),
+            self_ty: Some(on.clone()),
+        };
+        if let Ty::Adt {
+            args: impl_args, ..
+        } = &self_ty
+        {
+            for (impl_arg, arg) in impl_args.iter().zip(args) {
+                if let Ty::Param { span, .. } = impl_arg {
+                    subst.params.insert(span.clone(), arg);
+                }
+            }
+        }
+        for param in item
+            .sig
+            .generics
+            .iter()
+            .flat_map(|generics| generics.params.iter())
+        {
+            if let GenericParam::Type(param) = param {
+                let var = self.fresh();
+                subst.params.insert(param.ident.span.clone(), var);
+            }
+        }
+        let mut params = vec![];
+        let mut has_receiver = false;
+        for input in item.sig.inputs.iter() {
+            match input {
+                FnArg::Receiver(_) => has_receiver = true,
+                FnArg::Typed(typed) => params.push(self.cx.lower(file, &typed.ty, &subst)),
+            }
+        }
+        if !has_receiver {
+            let message = format!("`{}` is an associated function, not a method", call.method);
+            self.error(message, call.method.span.clone());
+        }
+        self.check_args(&call.args, &params, call.method.span.clone());
+        match &item.sig.output {
+            Some((_, ty)) => self.cx.lower(file, ty, &subst),
+            None => Ty::unit(),
+        }
+    }
+
+    fn check_index(&mut self, index: &ExprIndex) -> Ty {
+        let on = self.check_expr(&index.on, None);
+        let on = self.resolve(&on);
+        let by = self.check_expr(&index.index, None);
+        let by = self.resolve(&by);
+        if on.is_error() || by.is_error() {
+            return Ty::Error;
+        }
+        let len = match &on {
+            Ty::Array(_, len) => Some(*len),
+            Ty::Unsigned(Some(width)) | Ty::Signed(Some(width)) => Some(u64::from(*width)),
+            _ => {
+                let message = format!("cannot index into a value of type `{}`", on);
+                self.error(message, index.on.span());
+                return Ty::Error;
+            }
+        };
+        match &by {
+            Ty::Range(_) => {
+                let bounds = match index.index.as_ref() {
+                    Expr::Range(range) => range_bounds(range),
+                    _ => None,
+                };
+                let (low, high) = match bounds {
+                    Some(bounds) => bounds,
+                    None => {
+                        self.error("slice bounds must be integer literals", index.index.span());
+                        return Ty::Error;
+                    }
+                };
+                let high = high.or(len).unwrap_or(0);
+                if low >= high || Some(high) > len {
+                    let message = format!("slice `{}..{}` is out of range for `{}`", low, high, on);
+                    self.error(message, index.index.span());
+                    return Ty::Error;
+                }
+                let count = high - low;
+                match on {
+                    Ty::Array(element, _) => Ty::Array(element, count),
+                    _ => Ty::Unsigned(Some(count as u32)),
+                }
+            }
+            Ty::Unsigned(_) | Ty::Integer => {
+                if let (Expr::Lit(Lit::Int(lit)), Some(len)) = (index.index.as_ref(), len) {
+                    if lit.val >= len {
+                        let message = format!(
+                            "index out of bounds: the length is {} but the index is {}",
+                            len, lit.val
+                        );
+                        self.error(message, index.index.span());
+                    }
+                }
+                match on {
+                    Ty::Array(element, _) => *element,
+                    _ => Ty::Bit,
+                }
+            }
+            Ty::Signed(_) => {
+                let message = format!(
+                    "mismatched signedness: indices must be unsigned, found `{}`",
+                    by
+                );
+                self.error(message, index.index.span());
+                Ty::Error
+            }
+            _ => {
+                let message = format!(
+                    "mismatched types: expected an unsigned integer or range, found `{}`",
+                    by
+                );
+                self.error(message, index.index.span());
+                Ty::Error
+            }
+        }
+    }
+
+    fn check_struct(&mut self, expr: &ExprStruct) -> Ty {
+        let path = &expr.path;
+        let id = match self.cx.resolutions.expr_path(self.file, path) {
+            Some(PathResolution {
+                res: Res::Def(id),
+                unresolved_segments: 0,
+            }) => *id,
+            Some(PathResolution {
+                res: Res::SelfType, ..
+            }) => match &self.subst.self_ty {
+                Some(Ty::Adt { def, .. }) => *def,
+                _ => return self.check_fields_unknown(expr),
+            },
+            _ => return self.check_fields_unknown(expr),
+        };
+        let def = self.cx.resolutions.def(id);
+        let owner = match def.kind {
+            DefKind::Struct => id,
+            DefKind::Variant => self.cx.parent_enum(id),
+            kind => {
+                let message = format!(
+                    "expected a struct or variant, found {} `{}`",
+                    describe(kind),
+                    def.name
+                );
+                self.error(message, path.segments.span());
+                return self.check_fields_unknown(expr);
+            }
+        };
+        let name = def.name.clone();
+        let args = self.args_or_fresh(owner, path.segments.last());
+        let (_, fields) = self.cx.fields(id, &args);
+        let mut missing = fields
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        for field in expr.fields.iter() {
+            let member = match &field.member {
+                Member::Named(ident) => ident.inner.clone(),
+                Member::Unnamed(index) => index.val.to_string(),
+            };
+            missing.retain(|name| *name != member);
+            let expected = match fields.iter().find(|(name, _)| *name == member) {
+                Some((_, ty)) => ty.clone(),
+                None => {
+                    let message = format!("no field `{}` on `{}`", member, name);
+                    self.error(message, field.member.span());
+                    Ty::Error
+                }
+            };
+            match (&field.member, &field.expr) {
+                (_, Some((_, value))) => {
+                    self.check_expr_coerce(value, &expected);
+                }
+                (Member::Named(ident), None) => {
+                    let found = self.shorthand_ty(ident);
+                    self.coerce(&expected, &found, ident.span.clone());
+                }
+                (Member::Unnamed(_), None) => {}
+            }
+        }
+        let ty = Ty::Adt {
+            def: owner,
+            name: self.cx.resolutions.def(owner).name.clone(),
+            args,
+        };
+        match &expr.base {
+            Some((_, base)) => {
+                self.check_expr_coerce(base, &ty);
+            }
+            None if !missing.is_empty() => {
+                let message = format!(
+                    "missing {} in initializer of `{}`",
+                    missing
+                        .iter()
+                        .map(|name| format!("`{}`", name))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    name
+                );
+                self.error(message, path.segments.span());
+            }
+            None => {}
+        }
+        ty
+    }
+
+    fn check_fields_unknown(&mut self, expr: &ExprStruct) -> Ty {
+        for field in expr.fields.iter() {
+            if let Some((_, value)) = &field.expr {
+                self.check_expr(value, None);
+            }
+        }
+        Ty::Error
+    }
+
+    fn check_block(&mut self, block: &Block, expected: Option<&Ty>) -> Ty {
+        let mut ty = Ty::unit();
+        let mut diverges = false;
+        let count = block.statements.len();
+        for (i, stmt) in block.statements.iter().enumerate() {
+            ty = Ty::unit();
+            match stmt {
+                Stmt::Local(local) => {
+                    let declared = match &local.ty {
+                        Some((_, ty)) => self.declared(ty),
+                        None => self.fresh(),
+                    };
+                    let declared = match &local.init {
+                        Some((_, init)) => self.check_expr_coerce(init, &declared),
+                        None => declared,
+                    };
+                    self.check_pat(&local.pat, &declared);
+                }
+                // Items within blocks are not resolved
+                Stmt::Item(_) => {}
+                Stmt::Expr(stmt) => {
+                    let tail = i + 1 == count && stmt.semi.is_none();
+                    let found = self.check_expr(&stmt.expr, if tail { expected } else { None });
+                    if self.shallow(&found) == Ty::Never {
+                        diverges = true;
+                    }
+                    if tail {
+                        ty = found;
+                    }
+                }
+            }
+        }
+        if diverges && ty == Ty::unit() {
+            Ty::Never
+        } else {
+            ty
+        }
+    }
+
+    fn check_pat(&mut self, pat: &Pat, expected: &Ty) {
+        match pat {
+            Pat::Ident(ident) => self.bind(ident, expected.clone()),
+            Pat::Wildcard(_) => {}
+            Pat::Lit(lit) => {
+                let found = self.check_lit(lit, Some(expected), false);
+                self.coerce(expected, &found, pat.span());
+            }
+            Pat::Range(range) => {
+                for bound in [&range.left, &range.right] {
+                    self.check_expr_coerce(bound, expected);
+                }
+            }
+            Pat::Path(path) => {
+                let found = match self.cx.resolutions.expr_path(self.file, path).cloned() {
+                    Some(resolution) => {
+                        self.res_ty(&resolution, path.segments.last(), path.segments.span())
+                    }
+                    None => Ty::Error,
+                };
+                self.coerce(expected, &found, pat.span());
+            }
+            Pat::Struct(pat_struct) => {
+                let fields = self.pat_fields(&pat_struct.path, expected, pat.span());
+                for field in pat_struct.fields.iter() {
+                    let (ident, subpat) = match field {
+                        StructPatternField::TuplePat(field) => {
+                            let name = field.index.val.to_string();
+                            let ty = self.pat_field(&fields, &name, field.index.span.clone());
+                            self.check_pat(&field.pat, &ty);
+                            continue;
+                        }
+                        StructPatternField::IdentPat(field) => (&field.ident, Some(&field.pat)),
+                        StructPatternField::Ident(ident) => (ident, None),
+                    };
+                    let ty = self.pat_field(&fields, &ident.inner, ident.span.clone());
+                    match subpat {
+                        Some(subpat) => self.check_pat(subpat, &ty),
+                        None => self.bind(ident, ty),
+                    }
+                }
+            }
+            Pat::TupleStruct(pat_tuple) => {
+                let fields = self.pat_fields(&pat_tuple.path, expected, pat.span());
+                let tys: Vec<Ty> = fields
+                    .map(|fields| fields.into_iter().map(|(_, ty)| ty).collect())
+                    .unwrap_or_default();
+                self.check_subpats(
+                    &pat_tuple.subpats,
+                    pat_tuple.rest_subpats.as_ref(),
+                    &tys,
+                    pat.span(),
+                );
+            }
+            Pat::Tuple(tuple) => {
+                let tys = match self.shallow(expected) {
+                    Ty::Tuple(tys) => tys,
+                    ty if ty.is_error() => vec![],
+                    Ty::Var(_) if tuple.rest_subpats.is_none() => {
+                        let tys = tuple
+                            .subpats
+                            .iter()
+                            .map(|_| self.fresh())
+                            .collect::<Vec<_>>();
+                        self.coerce(expected, &Ty::Tuple(tys.clone()), pat.span());
+                        tys
+                    }
+                    ty => {
+                        let message = format!(
+                            "mismatched types: expected `{}`, found a tuple",
+                            self.resolve(&ty)
+                        );
+                        self.error(message, pat.span());
+                        vec![]
+                    }
+                };
+                self.check_subpats(
+                    &tuple.subpats,
+                    tuple.rest_subpats.as_ref(),
+                    &tys,
+                    pat.span(),
+                );
+            }
+            Pat::Slice(slice) => {
+                let element = match self.shallow(expected) {
+                    Ty::Array(element, len) => {
+                        if len != slice.subpats.len() as u64 {
+                            let message = format!(
+                                "expected an array of {} elements, found {}",
+                                len,
+                                slice.subpats.len()
+                            );
+                            self.error(message, pat.span());
+                        }
+                        *element
+                    }
+                    ty if ty.is_error() => Ty::Error,
+                    ty => {
+                        let message = format!(
+                            "mismatched types: expected `{}`, found an array",
+                            self.resolve(&ty)
+                        );
+                        self.error(message, pat.span());
+                        Ty::Error
+                    }
+                };
+                for subpat in slice.subpats.iter() {
+                    self.check_pat(subpat, &element);
+                }
+            }
+        }
+    }
+
+    /// Binds subpatterns to the types of a tuple or tuple struct, where those after `..` match the last types
+    fn check_subpats(
+        &mut self,
+        subpats: &Punctuated<Pat, Comma>,
+        rest: Option<&(DotDot, Comma, Punctuated<Pat, Comma>)>,
+        tys: &[Ty],
+        span: Span,
+    ) {
+        let rest_pats = rest
+            .map(|(_, _, pats)| pats.iter().collect::<Vec<_>>())
+            .unwrap_or_default();
+        let count = subpats.len() + rest_pats.len();
+        if (rest.is_none() && count != tys.len()) || count > tys.len() {
+            if !tys.is_empty() {
+                let message = format!(
+                    "this pattern has {} fields, but the type has {}",
+                    count,
+                    tys.len()
+                );
+                self.error(message, span);
+            }
+            for subpat in subpats.iter().chain(rest_pats) {
+                self.check_pat(subpat, &Ty::Error);
+            }
+            return;
+        }
+        for (subpat, ty) in subpats.iter().zip(tys) {
+            self.check_pat(subpat, ty);
+        }
+        let skip = tys.len() - rest_pats.len();
+        for (subpat, ty) in rest_pats.into_iter().zip(&tys[skip..]) {
+            self.check_pat(subpat, ty);
+        }
+    }
+
+    /// Fields of the struct or variant named by a pattern, which must match `expected`
+    fn pat_fields(
+        &mut self,
+        path: &ExprPath,
+        expected: &Ty,
+        span: Span,
+    ) -> Option<Vec<(String, Ty)>> {
+        let id = match self.cx.resolutions.expr_path(self.file, path) {
+            Some(PathResolution {
+                res: Res::Def(id),
+                unresolved_segments: 0,
+            }) => *id,
+            _ => return None,
+        };
+        let def = self.cx.resolutions.def(id);
+        let owner = match def.kind {
+            DefKind::Struct => id,
+            DefKind::Variant => self.cx.parent_enum(id),
+            kind => {
+                let message = format!(
+                    "expected a struct or variant, found {} `{}`",
+                    describe(kind),
+                    def.name
+                );
+                self.error(message, path.segments.span());
+                return None;
+            }
+        };
+        let args = self.args_or_fresh(owner, path.segments.last());
+        let ty = Ty::Adt {
+            def: owner,
+            name: self.cx.resolutions.def(owner).name.clone(),
+            args: args.clone(),
+        };
+        self.coerce(expected, &ty, span);
+        Some(self.cx.fields(id, &args).1)
+    }
+
+    fn pat_field(&mut self, fields: &Option<Vec<(String, Ty)>>, name: &str, span: Span) -> Ty {
+        let fields = match fields {
+            Some(fields) => fields,
+            None => return Ty::Error,
+        };
+        match fields.iter().find(|(field, _)| field == name) {
+            Some((_, ty)) => ty.clone(),
+            None => {
+                self.error(format!("no field `{}` in this pattern's type", name), span);
+                Ty::Error
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Operator {
+    /// `+`, `-`, `*`, `/` and `%`
+    Arithmetic,
+    /// `**`
+    Power,
+    /// `&`, `|` and `^`
+    Bitwise,
+    /// `<<` and `>>`
+    Shift,
+    /// `==` and `!=`
+    Equality,
+    /// `<`, `<=`, `>` and `>=`
+    Ordering,
+}
+
+impl Operator {
+    fn of_bin(op: &BinOp) -> Self {
+        match op {
+            BinOp::Plus(_)
+            | BinOp::Minus(_)
+            | BinOp::Star(_)
+            | BinOp::Slash(_)
+            | BinOp::Percent(_) => Self::Arithmetic,
+            BinOp::StarStar(_) => Self::Power,
+            BinOp::Caret(_) | BinOp::And(_) | BinOp::Or(_) => Self::Bitwise,
+            BinOp::Shl(_) | BinOp::Shr(_) => Self::Shift,
+            BinOp::EqEq(_) | BinOp::Ne(_) => Self::Equality,
+            BinOp::Lt(_) | BinOp::Le(_) | BinOp::Gt(_) | BinOp::Ge(_) => Self::Ordering,
+        }
+    }
+
+    /// The kind of operator that a compound assignment applies, or `None` for `=`
+    fn of_ass(op: &AssOp) -> Option<Self> {
+        compound(op).map(|op| Self::of_bin(&op))
+    }
+}
+
+/// The binary operator that a compound assignment applies, or `None` for `=`
+pub(crate) fn compound(op: &AssOp) -> Option<BinOp> {
+    Some(match op {
+        AssOp::Eq(_) => return None,
+        AssOp::PlusEq(op) => BinOp::Plus(token::Plus { left: op.left }),
+        AssOp::MinusEq(op) => BinOp::Minus(token::Minus { left: op.left }),
+        AssOp::StarEq(op) => BinOp::Star(token::Star { left: op.left }),
+        AssOp::SlashEq(op) => BinOp::Slash(token::Slash { left: op.left }),
+        AssOp::PercentEq(op) => BinOp::Percent(token::Percent { left: op.left }),
+        AssOp::StarStarEq(op) => BinOp::StarStar(token::StarStar { left: op.left }),
+        AssOp::CaretEq(op) => BinOp::Caret(token::Caret { left: op.left }),
+        AssOp::AndEq(op) => BinOp::And(token::And { left: op.left }),
+        AssOp::OrEq(op) => BinOp::Or(token::Or { left: op.left }),
+        AssOp::ShlEq(op) => BinOp::Shl(token::Shl { left: op.left }),
+        AssOp::ShrEq(op) => BinOp::Shr(token::Shr { left: op.left }),
+    })
+}
+
+pub(crate) fn describe(kind: DefKind) -> &'static str {
+    match kind {
+        DefKind::Mod => "module",
+        DefKind::Const => "constant",
+        DefKind::Fn => "function",
+        DefKind::Type => "type alias",
+        DefKind::Struct => "struct",
+        DefKind::Enum => "enum",
+        DefKind::Variant => "variant",
+        DefKind::Trait => "trait",
+        DefKind::TraitAlias => "trait alias",
+        DefKind::Entity => "entity",
+    }
+}
+
+fn is_literal(expr: &Expr) -> bool {
+    match expr {
+        Expr::Lit(_) => true,
+        Expr::Grouped(grouped) => is_literal(&grouped.expr),
+        Expr::Unary(unary) => is_literal(&unary.expr),
+        _ => false,
+    }
+}
+
+/// Whether an expression names something that can be assigned to
+pub(crate) fn is_place(expr: &Expr) -> bool {
+    match expr {
+        Expr::Path(_) => true,
+        Expr::Field(field) => is_place(&field.on),
+        Expr::Index(index) => is_place(&index.on),
+        Expr::Grouped(grouped) => is_place(&grouped.expr),
+        Expr::Tuple(tuple) => tuple.elements.iter().all(is_place),
+        _ => false,
+    }
+}
+
+/// Bounds of a range of integer literals, as a half open range
+fn range_bounds(range: &ExprRange) -> Option<(u64, Option<u64>)> {
+    let bound = |expr: &Option<Box<Expr>>| match expr.as_deref() {
+        Some(Expr::Lit(Lit::Int(lit))) => lit.val.to_u64().map(Some),
+        Some(_) => None,
+        None => Some(None),
+    };
+    let low = bound(&range.left)?.unwrap_or(0);
+    let high = match (&range.range_type, bound(&range.right)?) {
+        (RangeType::Closed(_), Some(high)) => Some(high + 1),
+        (_, high) => high,
+    };
+    Some((low, high))
+}
+
+/// Whether an expression is made of literals alone, so it can be typed without resolving names
+fn is_constant(expr: &Expr) -> bool {
+    match expr {
+        Expr::Lit(_) => true,
+        Expr::Grouped(grouped) => is_constant(&grouped.expr),
+        Expr::Unary(unary) => is_constant(&unary.expr),
+        Expr::Binary(binary) => is_constant(&binary.left) && is_constant(&binary.right),
+        _ => false,
+    }
+}
+
+/// Span of the expression that gives a block its value, or of the whole block
+fn block_tail_span(block: &Block) -> Span {
+    match block.statements.as_slice().last() {
+        Some(Stmt::Expr(stmt)) if stmt.semi.is_none() => stmt.expr.span(),
+        _ => block.span(),
+    }
+}
@@ -0,0 +1,236 @@
+//! Writes the nets of a [`Simulator`] as a value change dump, for waveform viewers such as GTKWave
+//!
+//! Every instance of the design becomes a `module` scope within that of its parent, holding a
+//! variable for each of its ports, `let` signals and registers as wide as its type. The nets that
+//! are dumped can be narrowed down by globs over their hierarchical names, which are those that
+//! [`Simulator::get`] takes, and dumping can be stopped and started again between steps.
+
+use std::io::{self, Write};
+
+use rug::Integer;
+
+use crate::netlist::{NetId, NetKind};
+use crate::sim::Simulator;
+
+/// A net that is dumped
+struct Var {
+    scope: usize,
+    net: NetId,
+    width: u64,
+    /// Short code that value changes refer to the net by
+    code: String,
+}
+
+/// Dumps the values of the nets of a simulation as it steps
+pub struct VcdWriter<W: Write> {
+    out: W,
+    vars: Vec<Var>,
+    /// Value of each variable as last dumped, or `None` if it has not been since dumping started
+    last: Vec<Option<Integer>>,
+    /// Whether values are being dumped
+    dumping: bool,
+    /// Time that the last values were dumped at
+    time: Option<u64>,
+}
+
+impl<W: Write> VcdWriter<W> {
+    /// A writer of the nets of `sim` whose hierarchical names match any of `filters`, or of every
+    /// net if there are none, which writes the header of the dump to `out`
+    ///
+    /// In a filter, `*` matches any characters but the `.` between names, `**` matches any at all
+    /// and `?` matches any one character.
+    pub fn new(mut out: W, sim: &Simulator, filters: &[&str]) -> io::Result<Self> {
+        let netlist = sim.netlist();
+        let mut vars = vec![];
+        for (id, scope) in sim.scopes().iter().enumerate() {
+            let module = netlist.module(scope.module);
+            for (i, net) in module.nets.iter().enumerate() {
+                let name = if scope.path.is_empty() {
+                    net.name.clone()
+                } else {
+                    format!("{}.{}", scope.path, net.name)
+                };
+                let selected =
+                    filters.is_empty() || filters.iter().any(|filter| glob(filter, &name));
+                if net.width > 0 && selected {
+                    vars.push(Var {
+                        scope: id,
+                        net: NetId(i),
+                        width: net.width,
+                        code: code(vars.len()),
+                    });
+                }
+            }
+        }
+
+        writeln!(out, "$version rhdl $end")?;
+        writeln!(out, "$timescale 1ns $end")?;
+        let top = netlist.module(netlist.top);
+        let mut writer = Self {
+            out,
+            last: vec![None; vars.len()],
+            vars,
+            dumping: true,
+            time: None,
+        };
+        writer.scope(sim, 0, &top.name)?;
+        writeln!(writer.out, "$enddefinitions $end")?;
+        Ok(writer)
+    }
+
+    /// Writes the declarations of a scope and those within it that hold any variables
+    fn scope(&mut self, sim: &Simulator, id: usize, name: &str) -> io::Result<()> {
+        let scope = &sim.scopes()[id];
+        let within = |scope: usize| {
+            let mut scope = Some(scope);
+            while let Some(inner) = scope {
+                if inner == id {
+                    return true;
+                }
+                scope = sim.scopes()[inner].parent;
+            }
+            false
+        };
+        if !self.vars.iter().any(|var| within(var.scope)) {
+            return Ok(());
+        }
+        writeln!(self.out, "$scope module {} $end", name)?;
+        let module = sim.netlist().module(scope.module);
+        for var in self.vars.iter().filter(|var| var.scope == id) {
+            let net = module.net(var.net);
+            let kind = match net.kind {
+                NetKind::Reg => "reg",
+                NetKind::Wire => "wire",
+            };
+            writeln!(
+                self.out,
+                "$var {} {} {} {} $end",
+                kind, var.width, var.code, net.name
+            )?;
+        }
+        for child in &scope.children {
+            let name = sim.scopes()[*child].name.clone();
+            self.scope(sim, *child, &name)?;
+        }
+        writeln!(self.out, "$upscope $end")
+    }
+
+    /// Writes the values that changed since they were last dumped, at the simulator's time
+    pub fn sample(&mut self, sim: &Simulator) -> io::Result<()> {
+        if !self.dumping {
+            return Ok(());
+        }
+        let first = self.time.is_none();
+        let mut changes = vec![];
+        for (i, var) in self.vars.iter().enumerate() {
+            let bits = sim.bits(var.scope, var.net);
+            if self.last[i].as_ref() != Some(bits) {
+                changes.push(value(bits, var));
+                self.last[i] = Some(bits.clone());
+            }
+        }
+        if changes.is_empty() && !first {
+            return Ok(());
+        }
+        self.timestamp(sim.time())?;
+        if first {
+            writeln!(self.out, "$dumpvars")?;
+        }
+        for change in changes {
+            writeln!(self.out, "{}", change)?;
+        }
+        if first {
+            writeln!(self.out, "$end")?;
+        }
+        Ok(())
+    }
+
+    /// Stops dumping values, which become unknown until dumping starts again
+    pub fn stop(&mut self, sim: &Simulator) -> io::Result<()> {
+        if !self.dumping {
+            return Ok(());
+        }
+        self.dumping = false;
+        self.timestamp(sim.time())?;
+        writeln!(self.out, "$dumpoff")?;
+        for (i, var) in self.vars.iter().enumerate() {
+            if var.width == 1 {
+                writeln!(self.out, "x{}", var.code)?;
+            } else {
+                writeln!(self.out, "bx {}", var.code)?;
+            }
+            self.last[i] = None;
+        }
+        writeln!(self.out, "$end")
+    }
+
+    /// Starts dumping values again, beginning with those that every net holds
+    pub fn start(&mut self, sim: &Simulator) -> io::Result<()> {
+        if self.dumping {
+            return Ok(());
+        }
+        self.dumping = true;
+        self.timestamp(sim.time())?;
+        writeln!(self.out, "$dumpon")?;
+        for (i, var) in self.vars.iter().enumerate() {
+            let bits = sim.bits(var.scope, var.net);
+            writeln!(self.out, "{}", value(bits, var))?;
+            self.last[i] = Some(bits.clone());
+        }
+        writeln!(self.out, "$end")
+    }
+
+    /// Writes the time that the values after it change at, unless it is the time already given
+    fn timestamp(&mut self, time: u64) -> io::Result<()> {
+        if self.time != Some(time) {
+            self.time = Some(time);
+            writeln!(self.out, "#{}", time)?;
+        }
+        Ok(())
+    }
+
+    /// The writer that the dump is written to
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+/// A change of a variable to the given bits, such as `1!` or `b1010 "`
+fn value(bits: &Integer, var: &Var) -> String {
+    if var.width == 1 {
+        format!("{}{}", bits, var.code)
+    } else {
+        format!("b{} {}", bits.to_string_radix(2), var.code)
+    }
+}
+
+/// Code of the variable at an index, written in the printable characters from `!` to `~`
+fn code(mut index: usize) -> String {
+    let mut code = String::new();
+    loop {
+        code.push((b'!' + (index % 94) as u8) as char);
+        index /= 94;
+        if index == 0 {
+            return code;
+        }
+        index -= 1;
+    }
+}
+
+/// Whether a hierarchical name matches a glob, where `*` matches any characters but `.`, `**`
+/// matches any at all and `?` matches any one character
+fn glob(pattern: &str, name: &str) -> bool {
+    fn matches(pattern: &[u8], name: &[u8]) -> bool {
+        match pattern {
+            [] => name.is_empty(),
+            [b'*', b'*', rest @ ..] => (0..=name.len()).any(|i| matches(rest, &name[i..])),
+            [b'*', rest @ ..] => {
+                let end = name.iter().position(|c| *c == b'.').unwrap_or(name.len());
+                (0..=end).any(|i| matches(rest, &name[i..]))
+            }
+            [b'?', rest @ ..] => !name.is_empty() && matches(rest, &name[1..]),
+            [c, rest @ ..] => name.first() == Some(c) && matches(rest, &name[1..]),
+        }
+    }
+    matches(pattern.as_bytes(), name.as_bytes())
+}
@@ -0,0 +1,327 @@
+//! Emits a [`Netlist`] as synthesizable Verilog-2005
+//!
+//! Every module of the netlist becomes a module with ANSI-style ports. Nodes other than constants
+//! and reads of nets become wires named after their index, as do the constants that are sliced or
+//! indexed since Verilog cannot select bits of a literal. Clocked processes become
+//! `always @(posedge clk)` blocks of non-blocking assignments and the other processes become
+//! `always @*` blocks.
+
+use std::collections::HashSet;
+use std::fmt::Write;
+
+use rug::Integer;
+
+use crate::netlist::{
+    BinaryOp, Direction, Module, NetId, NetKind, Netlist, NodeId, NodeKind, Trigger, UnaryOp,
+};
+use crate::ty::Ty;
+
+/// Words that Verilog reserves, which names are escaped to avoid
+#[rustfmt::skip]
+pub(crate) const KEYWORDS: &[&str] = &[
+    "always", "and", "assign", "automatic", "begin", "buf", "bufif0", "bufif1", "case", "casex",
+    "casez", "cell", "cmos", "config", "deassign", "default", "defparam", "design", "disable",
+    "edge", "else", "end", "endcase", "endconfig", "endfunction", "endgenerate", "endmodule",
+    "endprimitive", "endspecify", "endtable", "endtask", "event", "for", "force", "forever",
+    "fork", "function", "generate", "genvar", "highz0", "highz1", "if", "ifnone", "incdir",
+    "include", "initial", "inout", "input", "instance", "integer", "join", "large", "liblist",
+    "library", "localparam", "macromodule", "medium", "module", "nand", "negedge", "nmos", "nor",
+    "noshowcancelled", "not", "notif0", "notif1", "or", "output", "parameter", "pmos", "posedge",
+    "primitive", "pull0", "pull1", "pulldown", "pullup", "pulsestyle_ondetect",
+    "pulsestyle_onevent", "rcmos", "real", "realtime", "reg", "release", "repeat", "rnmos",
+    "rpmos", "rtran", "rtranif0", "rtranif1", "scalared", "showcancelled", "signed", "small",
+    "specify", "specparam", "strong0", "strong1", "supply0", "supply1", "table", "task", "time",
+    "tran", "tranif0", "tranif1", "tri", "tri0", "tri1", "triand", "trior", "trireg", "unsigned",
+    "use", "uwire", "vectored", "wait", "wand", "weak0", "weak1", "while", "wire", "wor", "xnor",
+    "xor",
+];
+
+/// Verilog source for every module of `netlist`, starting with the top
+pub fn emit(netlist: &Netlist) -> String {
+    let mut out = String::new();
+    for (i, module) in netlist.modules.iter().enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        Emitter::new(netlist, module).emit(&mut out);
+    }
+    out
+}
+
+struct Emitter<'n> {
+    netlist: &'n Netlist,
+    module: &'n Module,
+    /// Name of the wire that holds each node
+    wires: Vec<String>,
+    /// Whether each net is assigned in an `always` block, which makes it a `reg`
+    regs: Vec<bool>,
+    /// Whether each constant is sliced or indexed, which Verilog only allows of a wire
+    sliced: Vec<bool>,
+}
+
+impl<'n> Emitter<'n> {
+    fn new(netlist: &'n Netlist, module: &'n Module) -> Self {
+        let taken = module
+            .nets
+            .iter()
+            .map(|net| net.name.as_str())
+            .collect::<HashSet<_>>();
+        let wires = (0..module.nodes.len())
+            .map(|i| {
+                let mut name = format!("_n{}", i);
+                while taken.contains(name.as_str()) {
+                    name.push('_');
+                }
+                name
+            })
+            .collect();
+        let mut regs = vec![false; module.nets.len()];
+        for process in &module.processes {
+            for update in &process.updates {
+                regs[update.net.0] = true;
+            }
+        }
+        let mut sliced = vec![false; module.nodes.len()];
+        for node in &module.nodes {
+            if let NodeKind::Slice(arg, _) | NodeKind::Index(arg, _) = node.kind {
+                sliced[arg.0] = matches!(module.node(arg).kind, NodeKind::Const(_));
+            }
+        }
+        Self {
+            netlist,
+            module,
+            wires,
+            regs,
+            sliced,
+        }
+    }
+
+    fn emit(&self, out: &mut String) {
+        let ports = self
+            .module
+            .ports
+            .iter()
+            .map(|port| {
+                let direction = match self.module.net(*port).port {
+                    Some(Direction::In) | None => "input",
+                    Some(Direction::Out) => "output",
+                    Some(Direction::InOut) => "inout",
+                };
+                format!("  {} {}", direction, self.declaration(*port))
+            })
+            .collect::<Vec<_>>();
+        if ports.is_empty() {
+            writeln!(out, "module {} ();", ident(&self.module.name)).unwrap();
+        } else {
+            writeln!(out, "module {} (", ident(&self.module.name)).unwrap();
+            writeln!(out, "{}", ports.join(",\n")).unwrap();
+            writeln!(out, ");").unwrap();
+        }
+
+        for (i, net) in self.module.nets.iter().enumerate() {
+            if net.port.is_none() && net.width > 0 {
+                writeln!(out, "  {};", self.declaration(NetId(i))).unwrap();
+            }
+        }
+        for (i, node) in self.module.nodes.iter().enumerate() {
+            if let Some(expr) = self.expr(NodeId(i)) {
+                writeln!(
+                    out,
+                    "  wire {}{} = {};",
+                    signed_range(&node.ty, node.width),
+                    self.wires[i],
+                    expr
+                )
+                .unwrap();
+            }
+        }
+        for assign in &self.module.assigns {
+            writeln!(
+                out,
+                "  assign {} = {};",
+                ident(&self.module.net(assign.net).name),
+                self.operand(assign.value)
+            )
+            .unwrap();
+        }
+        for process in &self.module.processes {
+            let (event, arrow) = match process.trigger {
+                Trigger::Posedge(clock) => (
+                    format!("@(posedge {})", ident(&self.module.net(clock).name)),
+                    "<=",
+                ),
+                Trigger::Negedge(clock) => (
+                    format!("@(negedge {})", ident(&self.module.net(clock).name)),
+                    "<=",
+                ),
+                Trigger::Always => ("@*".to_string(), "="),
+            };
+            writeln!(out, "  always {} begin", event).unwrap();
+            for update in &process.updates {
+                writeln!(
+                    out,
+                    "    {} {} {};",
+                    ident(&self.module.net(update.net).name),
+                    arrow,
+                    self.operand(update.value)
+                )
+                .unwrap();
+            }
+            writeln!(out, "  end").unwrap();
+        }
+        for instance in &self.module.instances {
+            let module = self.netlist.module(instance.module);
+            writeln!(out, "  {} {} (", ident(&module.name), ident(&instance.name)).unwrap();
+            let connections = instance
+                .connections
+                .iter()
+                .map(|(port, net)| {
+                    format!(
+                        "    .{}({})",
+                        ident(port),
+                        ident(&self.module.net(*net).name)
+                    )
+                })
+                .collect::<Vec<_>>();
+            writeln!(out, "{}", connections.join(",\n")).unwrap();
+            writeln!(out, "  );").unwrap();
+        }
+        writeln!(out, "endmodule").unwrap();
+    }
+
+    /// Declaration of a net without its direction, such as `reg [7:0] q = 8'd0`
+    fn declaration(&self, id: NetId) -> String {
+        let net = self.module.net(id);
+        let kind = match (self.regs[id.0], net.kind) {
+            (true, _) | (_, NetKind::Reg) => "reg",
+            (false, NetKind::Wire) => "wire",
+        };
+        let mut declaration = format!(
+            "{} {}{}",
+            kind,
+            signed_range(&net.ty, net.width),
+            ident(&net.name)
+        );
+        if let Some(init) = &net.init {
+            write!(declaration, " = {}", literal(init, &net.ty, net.width)).unwrap();
+        }
+        declaration
+    }
+
+    /// Expression that a node's wire is driven by, or `None` if the node is used in place
+    fn expr(&self, id: NodeId) -> Option<String> {
+        let node = self.module.node(id);
+        let expr = match &node.kind {
+            _ if node.width == 0 => return None,
+            NodeKind::Const(bits) if self.sliced[id.0] => literal(bits, &node.ty, node.width),
+            NodeKind::Const(_) | NodeKind::Net(_) => return None,
+            NodeKind::Unary(UnaryOp::Not, arg) => format!("~{}", self.operand(*arg)),
+            NodeKind::Unary(UnaryOp::Neg, arg) => format!("-{}", self.operand(*arg)),
+            NodeKind::Binary(op, lhs, rhs) => {
+                let signed = is_signed(&self.module.node(*lhs).ty);
+                let op = match op {
+                    BinaryOp::Add => "+",
+                    BinaryOp::Sub => "-",
+                    BinaryOp::Mul => "*",
+                    BinaryOp::Div => "/",
+                    BinaryOp::Rem => "%",
+                    BinaryOp::Pow => "**",
+                    BinaryOp::Xor => "^",
+                    BinaryOp::And => "&",
+                    BinaryOp::Or => "|",
+                    BinaryOp::Shl => "<<",
+                    BinaryOp::Shr if signed => ">>>",
+                    BinaryOp::Shr => ">>",
+                    BinaryOp::Eq => "==",
+                    BinaryOp::Ne => "!=",
+                    BinaryOp::Lt => "<",
+                    BinaryOp::Le => "<=",
+                    BinaryOp::Gt => ">",
+                    BinaryOp::Ge => ">=",
+                };
+                format!("{} {} {}", self.operand(*lhs), op, self.operand(*rhs))
+            }
+            // The width of the wire truncates the operand or extends it by its own signedness
+            NodeKind::Cast(arg) => self.operand(*arg),
+            NodeKind::Slice(arg, lo) if node.width == 1 => {
+                format!("{}[{}]", self.operand(*arg), lo)
+            }
+            NodeKind::Slice(arg, lo) => {
+                format!("{}[{}:{}]", self.operand(*arg), lo + node.width - 1, lo)
+            }
+            NodeKind::Index(arg, index) if node.width == 1 => {
+                format!("{}[{}]", self.operand(*arg), self.operand(*index))
+            }
+            NodeKind::Index(arg, index) => format!(
+                "{}[{} * {} +: {}]",
+                self.operand(*arg),
+                self.operand(*index),
+                node.width,
+                node.width
+            ),
+            NodeKind::Concat(args) => {
+                let args = args
+                    .iter()
+                    .rev()
+                    .map(|arg| self.operand(*arg))
+                    .collect::<Vec<_>>();
+                format!("{{{}}}", args.join(", "))
+            }
+            NodeKind::Mux(cond, then, otherwise) => format!(
+                "{} ? {} : {}",
+                self.operand(*cond),
+                self.operand(*then),
+                self.operand(*otherwise)
+            ),
+        };
+        Some(expr)
+    }
+
+    /// How a node is referred to where it is used
+    fn operand(&self, id: NodeId) -> String {
+        let node = self.module.node(id);
+        match &node.kind {
+            NodeKind::Const(bits) if !self.sliced[id.0] => literal(bits, &node.ty, node.width),
+            NodeKind::Net(net) => ident(&self.module.net(*net).name),
+            _ => self.wires[id.0].clone(),
+        }
+    }
+}
+
+fn is_signed(ty: &Ty) -> bool {
+    matches!(ty, Ty::Signed(_))
+}
+
+/// `signed [7:0] ` for a value of type `i8`, or nothing for a single unsigned bit
+fn signed_range(ty: &Ty, width: u64) -> String {
+    let signed = if is_signed(ty) { "signed " } else { "" };
+    if width == 1 {
+        signed.to_string()
+    } else {
+        format!("{}[{}:0] ", signed, width.max(1) - 1)
+    }
+}
+
+/// Sized literal with the bits of a constant, such as `8'd255` or `8'sd255` for an `i8` of `-1`
+pub(crate) fn literal(bits: &Integer, ty: &Ty, width: u64) -> String {
+    let signed = if is_signed(ty) { "s" } else { "" };
+    if width == 1 {
+        format!("1'{}b{}", signed, bits)
+    } else {
+        format!("{}'{}d{}", width, signed, bits)
+    }
+}
+
+/// A name, escaped if Verilog would not read it as an identifier
+pub(crate) fn ident(name: &str) -> String {
+    let simple = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if simple && !KEYWORDS.contains(&name) {
+        name.to_string()
+    } else {
+        format!("\\{} ", name)
+    }
+}
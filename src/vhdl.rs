@@ -0,0 +1,631 @@
+//! Emits a [`Netlist`] as synthesizable VHDL-2008
+//!
+//! Every module of the netlist becomes an entity with an `rtl` architecture. Bits and booleans
+//! are `std_logic`, `iN` is `signed` and every other value is an `unsigned` vector, except that
+//! ports and signals of an enum whose variants have no fields are of an enumeration type. Those
+//! types are declared in a package along with functions that convert them to and from the bits
+//! of the netlist, and an `enum_encoding` attribute gives the discriminants the enum declares.
+
+use std::collections::HashSet;
+use std::fmt::Write;
+
+use rug::Integer;
+
+use crate::netlist::{
+    BinaryOp, Direction, Module, NetId, Netlist, NodeId, NodeKind, Trigger, TypeDeclKind, UnaryOp,
+};
+use crate::ty::Ty;
+
+/// Words that VHDL reserves, which names are escaped to avoid
+#[rustfmt::skip]
+const KEYWORDS: &[&str] = &[
+    "abs", "access", "after", "alias", "all", "and", "architecture", "array", "assert", "assume",
+    "assume_guarantee", "attribute", "begin", "block", "body", "buffer", "bus", "case",
+    "component", "configuration", "constant", "context", "cover", "default", "disconnect",
+    "downto", "else", "elsif", "end", "entity", "exit", "fairness", "file", "for", "force",
+    "function", "generate", "generic", "group", "guarded", "if", "impure", "in", "inertial",
+    "inout", "is", "label", "library", "linkage", "literal", "loop", "map", "mod", "nand", "new",
+    "next", "nor", "not", "null", "of", "on", "open", "or", "others", "out", "package",
+    "parameter", "port", "postponed", "procedure", "process", "property", "protected", "pure",
+    "range", "record", "register", "reject", "release", "rem", "report", "restrict",
+    "restrict_guarantee", "return", "rol", "ror", "select", "sequence", "severity", "shared",
+    "signal", "sla", "sll", "sra", "srl", "strong", "subtype", "then", "to", "transport", "type",
+    "unaffected", "units", "until", "use", "variable", "vmode", "vprop", "vunit", "wait", "when",
+    "while", "with", "xnor", "xor",
+];
+
+const CONTEXT: &str = "library ieee;\nuse ieee.std_logic_1164.all;\nuse ieee.numeric_std.all;\n";
+
+/// VHDL source for the package of enumeration types, if any, and every module of `netlist`, each
+/// after the modules it instantiates so that the file can be analyzed from top to bottom
+pub fn emit(netlist: &Netlist) -> String {
+    let mut out = String::new();
+    let package = package(netlist, &mut out);
+    for id in netlist.dependency_order() {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        Emitter::new(netlist, netlist.module(id)).emit(package.as_deref(), &mut out);
+    }
+    out
+}
+
+/// Writes the package that declares the enumeration types, returning its name if there is one
+fn package(netlist: &Netlist, out: &mut String) -> Option<String> {
+    let enums = netlist
+        .types
+        .iter()
+        .filter(|decl| decl.kind.is_fieldless_enum())
+        .collect::<Vec<_>>();
+    if enums.is_empty() {
+        return None;
+    }
+    let name = ident(&format!("{}_types", netlist.module(netlist.top).name));
+
+    let mut declarations = String::new();
+    let mut bodies = vec![];
+    let mut encoded = false;
+    for decl in enums {
+        let (tag_width, variants) = match &decl.kind {
+            TypeDeclKind::Enum {
+                tag_width,
+                variants,
+            } => (*tag_width, variants),
+            TypeDeclKind::Struct(_) => continue,
+        };
+        let ty = enum_type(&decl.name);
+        let literals = variants
+            .iter()
+            .map(|variant| ident(&variant.name))
+            .collect::<Vec<_>>();
+        writeln!(declarations, "  type {} is ({});", ty, literals.join(", ")).unwrap();
+        if variants.iter().any(|variant| variant.explicit) {
+            if !encoded {
+                writeln!(declarations, "  attribute enum_encoding : string;").unwrap();
+                encoded = true;
+            }
+            let codes = variants
+                .iter()
+                .map(|variant| {
+                    let code = variant.discriminant.to_string_radix(2);
+                    format!("{:0>width$}", code, width = tag_width.max(1) as usize)
+                })
+                .collect::<Vec<_>>();
+            writeln!(
+                declarations,
+                "  attribute enum_encoding of {} : type is \"{}\";",
+                ty,
+                codes.join(" ")
+            )
+            .unwrap();
+        }
+        let to_bits = format!("function to_unsigned(x : {}) return unsigned", ty);
+        let from_bits = format!("function to_{}(x : unsigned) return {}", ty, ty);
+        writeln!(declarations, "  {};", to_bits).unwrap();
+        writeln!(declarations, "  {};", from_bits).unwrap();
+
+        // Both functions map each variant to its discriminant, and bits that are the discriminant
+        // of no variant to the first one
+        let bits = Kind::Unsigned(tag_width.max(1));
+        let mut body = String::new();
+        writeln!(body, "  {} is", to_bits).unwrap();
+        writeln!(body, "  begin").unwrap();
+        writeln!(body, "    case x is").unwrap();
+        for variant in variants {
+            writeln!(
+                body,
+                "      when {} => return {};",
+                ident(&variant.name),
+                qualified(&variant.discriminant, &bits)
+            )
+            .unwrap();
+        }
+        writeln!(body, "    end case;").unwrap();
+        writeln!(body, "  end function;").unwrap();
+        writeln!(body).unwrap();
+        writeln!(body, "  {} is", from_bits).unwrap();
+        writeln!(body, "  begin").unwrap();
+        writeln!(body, "    case to_integer(x) is").unwrap();
+        for variant in variants {
+            writeln!(
+                body,
+                "      when {} => return {};",
+                variant.discriminant,
+                ident(&variant.name)
+            )
+            .unwrap();
+        }
+        writeln!(body, "      when others => return {}'left;", ty).unwrap();
+        writeln!(body, "    end case;").unwrap();
+        write!(body, "  end function;").unwrap();
+        bodies.push(body);
+    }
+
+    write!(out, "{}\npackage {} is\n{}", CONTEXT, name, declarations).unwrap();
+    writeln!(out, "end package {};\n", name).unwrap();
+    writeln!(out, "package body {} is", name).unwrap();
+    writeln!(out, "{}", bodies.join("\n\n")).unwrap();
+    writeln!(out, "end package body {};", name).unwrap();
+    Some(name)
+}
+
+/// The VHDL type of a value
+#[derive(Clone, Debug, PartialEq)]
+enum Kind {
+    Logic,
+    Unsigned(u64),
+    Signed(u64),
+    /// An enumeration type, by its name
+    Enum(String),
+}
+
+impl Kind {
+    fn name(&self) -> String {
+        match self {
+            Self::Logic => "std_logic".to_string(),
+            Self::Unsigned(width) => format!("unsigned({} downto 0)", width - 1),
+            Self::Signed(width) => format!("signed({} downto 0)", width - 1),
+            Self::Enum(name) => name.clone(),
+        }
+    }
+}
+
+fn enum_type(name: &str) -> String {
+    ident(&format!("{}_t", name))
+}
+
+/// The VHDL type of a net, which is an enumeration type for a fieldless enum
+fn net_kind(netlist: &Netlist, ty: &Ty, width: u64) -> Kind {
+    match netlist.type_decl(ty) {
+        Some(decl) if decl.kind.is_fieldless_enum() => Kind::Enum(enum_type(&decl.name)),
+        _ => node_kind(ty, width),
+    }
+}
+
+/// The VHDL type of a node, which is always made of bits
+fn node_kind(ty: &Ty, width: u64) -> Kind {
+    match ty {
+        Ty::Bit | Ty::Bool => Kind::Logic,
+        Ty::Signed(_) => Kind::Signed(width),
+        _ => Kind::Unsigned(width),
+    }
+}
+
+struct Emitter<'n> {
+    netlist: &'n Netlist,
+    module: &'n Module,
+    /// Name of each net
+    nets: Vec<String>,
+    /// Name of the signal that holds each node
+    signals: Vec<String>,
+}
+
+impl<'n> Emitter<'n> {
+    fn new(netlist: &'n Netlist, module: &'n Module) -> Self {
+        // VHDL ignores the case of basic identifiers, so names that differ only in case are escaped
+        let mut taken = HashSet::new();
+        let nets = module
+            .nets
+            .iter()
+            .map(|net| {
+                let name = ident(&net.name);
+                if taken.insert(name.to_lowercase()) {
+                    name
+                } else {
+                    extended(&net.name)
+                }
+            })
+            .collect();
+        let signals = (0..module.nodes.len())
+            .map(|i| {
+                let mut name = format!("n{}", i);
+                let mut suffix = 1;
+                while taken.contains(&name) {
+                    name = format!("n{}_{}", i, suffix);
+                    suffix += 1;
+                }
+                name
+            })
+            .collect();
+        Self {
+            netlist,
+            module,
+            nets,
+            signals,
+        }
+    }
+
+    fn emit(&self, package: Option<&str>, out: &mut String) {
+        let name = ident(&self.module.name);
+        write!(out, "{}", CONTEXT).unwrap();
+        if let Some(package) = package {
+            writeln!(out, "use work.{}.all;", package).unwrap();
+        }
+        writeln!(out, "\nentity {} is", name).unwrap();
+        let ports = self
+            .module
+            .ports
+            .iter()
+            .filter(|port| self.module.net(**port).width > 0)
+            .map(|port| {
+                let direction = match self.module.net(*port).port {
+                    Some(Direction::In) | None => "in",
+                    Some(Direction::Out) => "out",
+                    Some(Direction::InOut) => "inout",
+                };
+                format!(
+                    "    {} : {} {}",
+                    self.nets[port.0],
+                    direction,
+                    self.net_type(*port)
+                )
+            })
+            .collect::<Vec<_>>();
+        if !ports.is_empty() {
+            writeln!(out, "  port (\n{}\n  );", ports.join(";\n")).unwrap();
+        }
+        writeln!(out, "end entity {};\n", name).unwrap();
+
+        writeln!(out, "architecture rtl of {} is", name).unwrap();
+        for (i, net) in self.module.nets.iter().enumerate() {
+            if net.port.is_none() && net.width > 0 {
+                writeln!(
+                    out,
+                    "  signal {} : {};",
+                    self.nets[i],
+                    self.net_type(NetId(i))
+                )
+                .unwrap();
+            }
+        }
+        let mut assigns = vec![];
+        for (i, node) in self.module.nodes.iter().enumerate() {
+            if let Some(expr) = self.expr(NodeId(i)) {
+                let kind = node_kind(&node.ty, node.width);
+                writeln!(out, "  signal {} : {};", self.signals[i], kind.name()).unwrap();
+                assigns.push(format!("  {} <= {};", self.signals[i], expr));
+            }
+        }
+        writeln!(out, "begin").unwrap();
+        for assign in assigns {
+            writeln!(out, "{}", assign).unwrap();
+        }
+        for assign in &self.module.assigns {
+            writeln!(
+                out,
+                "  {} <= {};",
+                self.nets[assign.net.0],
+                self.to_net(assign.net, assign.value)
+            )
+            .unwrap();
+        }
+        for process in &self.module.processes {
+            let (sensitivity, edge) = match process.trigger {
+                Trigger::Posedge(clock) => (
+                    self.nets[clock.0].clone(),
+                    Some(format!("rising_edge({})", self.nets[clock.0])),
+                ),
+                Trigger::Negedge(clock) => (
+                    self.nets[clock.0].clone(),
+                    Some(format!("falling_edge({})", self.nets[clock.0])),
+                ),
+                Trigger::Always => ("all".to_string(), None),
+            };
+            writeln!(out, "  process ({})\n  begin", sensitivity).unwrap();
+            let indent = match &edge {
+                Some(edge) => {
+                    writeln!(out, "    if {} then", edge).unwrap();
+                    "      "
+                }
+                None => "    ",
+            };
+            for update in &process.updates {
+                writeln!(
+                    out,
+                    "{}{} <= {};",
+                    indent,
+                    self.nets[update.net.0],
+                    self.to_net(update.net, update.value)
+                )
+                .unwrap();
+            }
+            if edge.is_some() {
+                writeln!(out, "    end if;").unwrap();
+            }
+            writeln!(out, "  end process;").unwrap();
+        }
+        for instance in &self.module.instances {
+            let module = self.netlist.module(instance.module);
+            writeln!(
+                out,
+                "  {} : entity work.{}",
+                ident(&instance.name),
+                ident(&module.name)
+            )
+            .unwrap();
+            let connections = instance
+                .connections
+                .iter()
+                .filter(|(_, net)| self.module.net(*net).width > 0)
+                .map(|(port, net)| format!("      {} => {}", ident(port), self.nets[net.0]))
+                .collect::<Vec<_>>();
+            writeln!(out, "    port map (\n{}\n    );", connections.join(",\n")).unwrap();
+        }
+        writeln!(out, "end architecture rtl;").unwrap();
+    }
+
+    /// Type of a net along with its initial value, such as `unsigned(7 downto 0) := 8d"0"`
+    fn net_type(&self, id: NetId) -> String {
+        let net = self.module.net(id);
+        let kind = net_kind(self.netlist, &net.ty, net.width);
+        match &net.init {
+            Some(init) => {
+                let init = match (&kind, self.netlist.type_decl(&net.ty)) {
+                    (Kind::Enum(_), Some(decl)) => decl
+                        .kind
+                        .variant(init)
+                        .map_or_else(|| literal(init, &kind), |variant| ident(&variant.name)),
+                    _ => literal(init, &kind),
+                };
+                format!("{} := {}", kind.name(), init)
+            }
+            None => kind.name(),
+        }
+    }
+
+    fn net_kind(&self, id: NetId) -> Kind {
+        let net = self.module.net(id);
+        net_kind(self.netlist, &net.ty, net.width)
+    }
+
+    fn kind(&self, id: NodeId) -> Kind {
+        let node = self.module.node(id);
+        node_kind(&node.ty, node.width)
+    }
+
+    /// A node as the value of a net, converted to the net's enumeration type if it has one
+    fn to_net(&self, net: NetId, value: NodeId) -> String {
+        match self.net_kind(net) {
+            Kind::Enum(name) => match self.module.node(value).kind {
+                NodeKind::Net(from) if self.net_kind(from) == Kind::Enum(name.clone()) => {
+                    self.nets[from.0].clone()
+                }
+                _ => format!("to_{}({})", name, self.operand(value)),
+            },
+            _ => self.operand(value),
+        }
+    }
+
+    /// Expression that a node's signal is assigned, or `None` if the node is used in place
+    fn expr(&self, id: NodeId) -> Option<String> {
+        let node = self.module.node(id);
+        let kind = node_kind(&node.ty, node.width);
+        let expr = match &node.kind {
+            _ if node.width == 0 => return None,
+            NodeKind::Const(_) => return None,
+            NodeKind::Net(net) => match self.net_kind(*net) {
+                Kind::Enum(_) => format!("to_unsigned({})", self.nets[net.0]),
+                _ => return None,
+            },
+            NodeKind::Unary(UnaryOp::Not, arg) => format!("not {}", self.operand(*arg)),
+            NodeKind::Unary(UnaryOp::Neg, arg) => match kind {
+                Kind::Signed(_) => format!("-{}", self.operand(*arg)),
+                Kind::Unsigned(_) => format!("0 - {}", self.operand(*arg)),
+                _ => self.operand(*arg),
+            },
+            NodeKind::Binary(op, lhs, rhs) => self.binary(*op, *lhs, *rhs, &kind),
+            NodeKind::Cast(arg) => self.cast(*arg, &kind),
+            NodeKind::Slice(arg, lo) if node.width == 1 => {
+                let bit = format!("{}({})", self.operand(*arg), lo);
+                convert(bit, &Kind::Logic, &kind)
+            }
+            NodeKind::Slice(arg, lo) => {
+                let bits = format!(
+                    "{}({} downto {})",
+                    self.operand(*arg),
+                    lo + node.width - 1,
+                    lo
+                );
+                convert(bits, &self.kind(*arg), &kind)
+            }
+            NodeKind::Index(arg, index) => {
+                let index = self.integer(*index);
+                if node.width == 1 {
+                    let bit = format!("{}({})", self.operand(*arg), index);
+                    convert(bit, &Kind::Logic, &kind)
+                } else {
+                    let bits = format!(
+                        "{}({} * {} + {} downto {} * {})",
+                        self.operand(*arg),
+                        index,
+                        node.width,
+                        node.width - 1,
+                        index,
+                        node.width
+                    );
+                    convert(bits, &self.kind(*arg), &kind)
+                }
+            }
+            NodeKind::Concat(args) => {
+                let args = args
+                    .iter()
+                    .rev()
+                    .map(|arg| match self.kind(*arg) {
+                        Kind::Signed(_) => format!("unsigned({})", self.operand(*arg)),
+                        _ => self.operand(*arg),
+                    })
+                    .collect::<Vec<_>>();
+                match kind {
+                    Kind::Signed(_) => format!("signed(unsigned'({}))", args.join(" & ")),
+                    _ => args.join(" & "),
+                }
+            }
+            NodeKind::Mux(cond, then, otherwise) => format!(
+                "{} when {} = '1' else {}",
+                self.operand(*then),
+                self.operand(*cond),
+                self.operand(*otherwise)
+            ),
+        };
+        Some(expr)
+    }
+
+    fn binary(&self, op: BinaryOp, lhs: NodeId, rhs: NodeId, kind: &Kind) -> String {
+        let (a, b) = (self.operand(lhs), self.operand(rhs));
+        if op.is_comparison() {
+            let op = match op {
+                BinaryOp::Eq => "=",
+                BinaryOp::Ne => "/=",
+                BinaryOp::Lt => "<",
+                BinaryOp::Le => "<=",
+                BinaryOp::Gt => ">",
+                _ => ">=",
+            };
+            return format!("'1' when {} {} {} else '0'", a, op, b);
+        }
+        if *kind == Kind::Logic {
+            // Arithmetic on single bits wraps around, so sums are exclusive ors and products are ands
+            return match op {
+                BinaryOp::Add | BinaryOp::Sub | BinaryOp::Xor => format!("{} xor {}", a, b),
+                BinaryOp::Mul | BinaryOp::And => format!("{} and {}", a, b),
+                BinaryOp::Or => format!("{} or {}", a, b),
+                BinaryOp::Rem => "'0'".to_string(),
+                BinaryOp::Shl | BinaryOp::Shr => {
+                    format!("{} when {} = 0 else '0'", a, self.integer(rhs))
+                }
+                _ => a,
+            };
+        }
+        let width = match kind {
+            Kind::Unsigned(width) | Kind::Signed(width) => *width,
+            _ => 1,
+        };
+        match op {
+            BinaryOp::Add => format!("{} + {}", a, b),
+            BinaryOp::Sub => format!("{} - {}", a, b),
+            BinaryOp::Mul => format!("resize({} * {}, {})", a, b, width),
+            BinaryOp::Div => format!("{} / {}", a, b),
+            BinaryOp::Rem => format!("{} rem {}", a, b),
+            BinaryOp::Pow => {
+                let convert = match kind {
+                    Kind::Signed(_) => "to_signed",
+                    _ => "to_unsigned",
+                };
+                format!(
+                    "{}(to_integer({}) ** {}, {})",
+                    convert,
+                    a,
+                    self.integer(rhs),
+                    width
+                )
+            }
+            BinaryOp::Xor => format!("{} xor {}", a, b),
+            BinaryOp::And => format!("{} and {}", a, b),
+            BinaryOp::Or => format!("{} or {}", a, b),
+            BinaryOp::Shl => format!("shift_left({}, {})", a, self.integer(rhs)),
+            BinaryOp::Shr => format!("shift_right({}, {})", a, self.integer(rhs)),
+            _ => unreachable!("comparisons are handled above"),
+        }
+    }
+
+    /// A node converted to another type, which truncates it or extends it by its own signedness
+    fn cast(&self, arg: NodeId, to: &Kind) -> String {
+        let from = self.kind(arg);
+        let value = self.operand(arg);
+        let (from_width, to_width) = match (&from, to) {
+            (Kind::Logic, Kind::Logic) => return value,
+            (Kind::Logic, Kind::Unsigned(1) | Kind::Signed(1)) => {
+                return format!("(0 => {})", value)
+            }
+            (Kind::Logic, Kind::Unsigned(width) | Kind::Signed(width)) => {
+                return format!("({} downto 1 => '0') & {}", width - 1, value)
+            }
+            (_, Kind::Logic) => return format!("{}(0)", value),
+            (Kind::Unsigned(from) | Kind::Signed(from), Kind::Unsigned(to) | Kind::Signed(to)) => {
+                (*from, *to)
+            }
+            _ => return value,
+        };
+        let resized = if to_width < from_width {
+            format!("{}({} downto 0)", value, to_width - 1)
+        } else if to_width > from_width {
+            format!("resize({}, {})", value, to_width)
+        } else {
+            value
+        };
+        convert(resized, &from, to)
+    }
+
+    /// A node as an integer, for shift amounts and dynamic indices
+    fn integer(&self, id: NodeId) -> String {
+        let node = self.module.node(id);
+        match (&node.kind, self.kind(id)) {
+            (NodeKind::Const(bits), _) => bits.to_string(),
+            (_, Kind::Logic) => format!("to_integer(unsigned'(0 => {}))", self.operand(id)),
+            _ => format!("to_integer({})", self.operand(id)),
+        }
+    }
+
+    /// How a node is referred to where it is used
+    fn operand(&self, id: NodeId) -> String {
+        let node = self.module.node(id);
+        match &node.kind {
+            NodeKind::Const(bits) => qualified(bits, &self.kind(id)),
+            NodeKind::Net(net) if !matches!(self.net_kind(*net), Kind::Enum(_)) => {
+                self.nets[net.0].clone()
+            }
+            _ => self.signals[id.0].clone(),
+        }
+    }
+}
+
+/// An expression of one type as another of the same width
+fn convert(expr: String, from: &Kind, to: &Kind) -> String {
+    match (from, to) {
+        (Kind::Logic, Kind::Unsigned(_) | Kind::Signed(_)) => format!("(0 => {})", expr),
+        (Kind::Unsigned(_), Kind::Signed(_)) => format!("signed({})", expr),
+        (Kind::Signed(_), Kind::Unsigned(_)) => format!("unsigned({})", expr),
+        _ => expr,
+    }
+}
+
+/// Literal with the bits of a constant, such as `8d"255"`
+fn literal(bits: &Integer, kind: &Kind) -> String {
+    match kind {
+        Kind::Logic => format!("'{}'", bits),
+        Kind::Unsigned(width) | Kind::Signed(width) => format!("{}d\"{}\"", width, bits),
+        Kind::Enum(name) => format!(
+            "to_{}({}d\"{}\")",
+            name,
+            bits.significant_bits().max(1),
+            bits
+        ),
+    }
+}
+
+/// A literal qualified by its type, so that it can be an operand of an overloaded operator
+fn qualified(bits: &Integer, kind: &Kind) -> String {
+    match kind {
+        Kind::Unsigned(_) => format!("unsigned'({})", literal(bits, kind)),
+        Kind::Signed(_) => format!("signed'({})", literal(bits, kind)),
+        _ => literal(bits, kind),
+    }
+}
+
+/// A name, escaped if VHDL would not read it as a basic identifier
+fn ident(name: &str) -> String {
+    let basic = name.chars().next().is_some_and(|c| c.is_ascii_alphabetic())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !name.contains("__")
+        && !name.ends_with('_');
+    if basic && !KEYWORDS.contains(&name.to_lowercase().as_str()) {
+        name.to_string()
+    } else {
+        extended(name)
+    }
+}
+
+/// An extended identifier, which VHDL reads as written and distinguishes by case
+fn extended(name: &str) -> String {
+    format!("\\{}\\", name.replace('\\', "\\\\"))
+}
@@ -2,10 +2,21 @@ use paste::paste;
 
 use crate::ast::token::*;
 use crate::ast::*;
+use crate::diagnostics::Diagnostic;
+
+/// Where the walkers that the default methods of [`Visit`] and [`VisitMut`] call are defined
+mod walkers {
+    pub use crate::ast::token::*;
+    pub use crate::ast::*;
+    pub use crate::diagnostics::{visit_diagnostic, visit_diagnostic_mut};
+}
 
 macro_rules! visit {
     ($($token_class: ident),*) => {
         paste! {
+            // An overriding method calls these to keep visiting the children of its node
+            pub use walkers::{$( [<visit_ $token_class:snake>], [<visit_ $token_class:snake _mut>] ),*};
+
             pub trait Visit<'ast> {
                 $(
                     fn [<visit_ $token_class:snake>](&mut self, inst: &'ast $token_class)  {
@@ -13,229 +24,459 @@ macro_rules! visit {
                     }
                 )*
             }
+
+            pub trait VisitMut {
+                $(
+                    fn [<visit_ $token_class:snake _mut>](&mut self, inst: &mut $token_class)  {
+                        [<visit_ $token_class:snake _mut>](self, inst)
+                    }
+                )*
+            }
         }
     };
 }
 
-visit! {
-    Expr,
-    Type,
-    Item,
-    File,
-
-    // Generated by the following method:
-    // cargo build 2>&1 1>/dev/null | grep 'no method named `visit_' out | cut -d '`' -f 2 | sed 's|visit||' | sed -E 's/_([a-z])/\U\1/g' | sort | uniq | sed '$!s/$/,/'
-    And,
-    AndEq,
-    Arch,
-    ArchItem,
-    ArchItemConst,
-    ArchItemEntityExpression,
-    ArchItemLet,
-    ArchItemWhen,
-    Arm,
-    As,
-    AssOp,
-    BinOp,
-    Block,
-    BraceClose,
-    BraceOpen,
-    BracketClose,
-    BracketOpen,
-    Caret,
-    CaretEq,
-    Colon,
-    Comma,
-    Const,
-    Crate,
-    Dot,
-    DotDot,
-    DotDotEq,
-    Else,
-    Entity,
-    EntityFieldValue,
-    Enum,
-    Eq,
-    EqEq,
-    ExprArray,
-    ExprAssign,
-    ExprBinary,
-    ExprBlock,
-    ExprCall,
-    ExprCast,
-    ExprField,
-    ExprFor,
-    ExprGrouped,
-    ExprIf,
-    ExprIndex,
-    ExprLit,
-    ExprMatch,
-    ExprMethodCall,
-    ExprPath,
-    ExprQPath,
-    ExprRange,
-    ExprRepeat,
-    ExprReturn,
-    ExprStruct,
-    ExprTuple,
-    ExprUnary,
-    FatArrow,
-    Fields,
-    FieldsNamed,
-    FieldsUnnamed,
-    FieldValue,
-    Fn,
-    FnArg,
-    FnArgReceiver,
-    FnArgTyped,
-    For,
-    Ge,
-    GenericArgs,
-    GenericArg,
-    GenericArgType,
-    GenericArgExpr,
-    GenericArgBinding,
-    GenericParam,
-    GenericParamConst,
-    GenericParamType,
-    Generics,
-    Gt,
-    Ident,
-    If,
-    Impl,
-    ImplItem,
-    ImplItemConst,
-    ImplItemFn,
-    ImplItemType,
-    In,
-    InOut,
-    ItemArch,
-    ItemConst,
-    ItemEntity,
-    ItemEnum,
-    ItemFn,
-    ItemImpl,
-    ItemMod,
-    ItemStruct,
-    ItemTrait,
-    ItemTraitAlias,
-    ItemType,
-    ItemUse,
-    Le,
-    Let,
-    Lit,
-    LitBool,
-    LitFloat,
-    LitInt,
-    LowerSelf,
-    Lt,
-    Match,
-    Member,
-    MemberNamed,
-    MemberUnnamed,
-    Minus,
-    MinusEq,
-    Mod,
-    ModContent,
-    ModContentFile,
-    ModContentHere,
-    NamedField,
-    Ne,
-    Not,
-    Or,
-    OrEq,
-    Out,
-    ParenClose,
-    ParenOpen,
-    Pat,
-    PathSegment,
-    PathSep,
-    PatIdent,
-    PatLit,
-    PatPath,
-    PatRange,
-    PatSlice,
-    PatStruct,
-    PatTuple,
-    PatTupleStruct,
-    PatType,
-    PatWildcard,
-    Percent,
-    PercentEq,
-    Plus,
-    PlusEq,
-    Port,
-    PortType,
-    Priv,
-    Pub,
-    RangeType,
-    RangeTypeClosed,
-    RangeTypeHalfOpen,
-    RArrow,
-    Return,
-    Qualifier,
-    Semi,
-    Shl,
-    ShlEq,
-    Shr,
-    ShrEq,
-    Sig,
-    SimplePath,
-    Slash,
-    SlashEq,
-    Star,
-    StarEq,
-    StarStar,
-    StarStarEq,
-    Stmt,
-    StmtExpr,
-    StmtItem,
-    StmtLocal,
-    Struct,
-    StructPatternField,
-    StructPatternFieldIdent,
-    StructPatternFieldIdentPat,
-    StructPatternFieldTuplePat,
-    Super,
-    TokenType,
-    Trait,
-    TraitItem,
-    TraitItemConst,
-    TraitItemFn,
-    TraitItemType,
-    TypeArray,
-    TypeFn,
-    TypeInfer,
-    TypeParenthesized,
-    TypePath,
-    TypeQPath,
-    TypeSlice,
-    TypeTuple,
-    Underscore,
-    UnnamedField,
-    UnOp,
-    Use,
-    UseTree,
-    UseTreeGlob,
-    UseTreeGroup,
-    UseTreeName,
-    UseTreePath,
-    UseTreeRename,
-    Variant,
-    VariantType,
-    VariantTypeDiscrim,
-    VariantTypeFields,
-    VariantTypeUnit,
-    Vis,
-    VisPub,
-    VisRestricted,
-    VisCrate,
-    VisSuper,
-    VisLowerSelf,
-    VisPriv,
-    When,
-    Where,
-    WhereClause,
-    WhereClauseItem,
-    WhereClauseItemType
+/// Dispatches a node to its [`Visit`] method, looking through the containers that wrap AST fields
+pub trait Visitable<'ast> {
+    fn visit<V>(&'ast self, v: &mut V)
+    where
+        V: Visit<'ast> + ?Sized;
+}
+
+impl<'ast, T: Visitable<'ast>> Visitable<'ast> for Option<T> {
+    fn visit<V>(&'ast self, v: &mut V)
+    where
+        V: Visit<'ast> + ?Sized,
+    {
+        if let Some(inner) = self {
+            inner.visit(v);
+        }
+    }
 }
+
+impl<'ast, T: Visitable<'ast>> Visitable<'ast> for Box<T> {
+    fn visit<V>(&'ast self, v: &mut V)
+    where
+        V: Visit<'ast> + ?Sized,
+    {
+        self.as_ref().visit(v);
+    }
+}
+
+impl<'ast, T: Visitable<'ast>> Visitable<'ast> for Vec<T> {
+    fn visit<V>(&'ast self, v: &mut V)
+    where
+        V: Visit<'ast> + ?Sized,
+    {
+        self.iter().for_each(|inner| inner.visit(v));
+    }
+}
+
+impl<'ast, A: Visitable<'ast>, B: Visitable<'ast>> Visitable<'ast> for (A, B) {
+    fn visit<V>(&'ast self, v: &mut V)
+    where
+        V: Visit<'ast> + ?Sized,
+    {
+        self.0.visit(v);
+        self.1.visit(v);
+    }
+}
+
+impl<'ast, A: Visitable<'ast>, B: Visitable<'ast>, C: Visitable<'ast>> Visitable<'ast>
+    for (A, B, C)
+{
+    fn visit<V>(&'ast self, v: &mut V)
+    where
+        V: Visit<'ast> + ?Sized,
+    {
+        self.0.visit(v);
+        self.1.visit(v);
+        self.2.visit(v);
+    }
+}
+
+impl<'ast, T, P> Visitable<'ast> for Punctuated<T, P>
+where
+    T: Visitable<'ast> + ToTokens + Clone + std::fmt::Debug + PartialEq,
+    P: Visitable<'ast> + ToTokens + Clone + std::fmt::Debug + PartialEq,
+{
+    fn visit<V>(&'ast self, v: &mut V)
+    where
+        V: Visit<'ast> + ?Sized,
+    {
+        self.inner.iter().for_each(|(t, p)| {
+            t.visit(v);
+            p.visit(v);
+        });
+        if let Some(last) = self.last.as_ref() {
+            last.visit(v);
+        }
+    }
+}
+
+/// Dispatches a node to its [`VisitMut`] method, looking through the containers that wrap AST fields
+pub trait VisitableMut {
+    fn visit_mut<V>(&mut self, v: &mut V)
+    where
+        V: VisitMut + ?Sized;
+}
+
+impl<T: VisitableMut> VisitableMut for Option<T> {
+    fn visit_mut<V>(&mut self, v: &mut V)
+    where
+        V: VisitMut + ?Sized,
+    {
+        if let Some(inner) = self {
+            inner.visit_mut(v);
+        }
+    }
+}
+
+impl<T: VisitableMut> VisitableMut for Box<T> {
+    fn visit_mut<V>(&mut self, v: &mut V)
+    where
+        V: VisitMut + ?Sized,
+    {
+        self.as_mut().visit_mut(v);
+    }
+}
+
+impl<T: VisitableMut> VisitableMut for Vec<T> {
+    fn visit_mut<V>(&mut self, v: &mut V)
+    where
+        V: VisitMut + ?Sized,
+    {
+        self.iter_mut().for_each(|inner| inner.visit_mut(v));
+    }
+}
+
+impl<A: VisitableMut, B: VisitableMut> VisitableMut for (A, B) {
+    fn visit_mut<V>(&mut self, v: &mut V)
+    where
+        V: VisitMut + ?Sized,
+    {
+        self.0.visit_mut(v);
+        self.1.visit_mut(v);
+    }
+}
+
+impl<A: VisitableMut, B: VisitableMut, C: VisitableMut> VisitableMut for (A, B, C) {
+    fn visit_mut<V>(&mut self, v: &mut V)
+    where
+        V: VisitMut + ?Sized,
+    {
+        self.0.visit_mut(v);
+        self.1.visit_mut(v);
+        self.2.visit_mut(v);
+    }
+}
+
+impl<T, P> VisitableMut for Punctuated<T, P>
+where
+    T: VisitableMut + ToTokens + Clone + std::fmt::Debug + PartialEq,
+    P: VisitableMut + ToTokens + Clone + std::fmt::Debug + PartialEq,
+{
+    fn visit_mut<V>(&mut self, v: &mut V)
+    where
+        V: VisitMut + ?Sized,
+    {
+        self.inner.iter_mut().for_each(|(t, p)| {
+            t.visit_mut(v);
+            p.visit_mut(v);
+        });
+        if let Some(last) = self.last.as_mut() {
+            last.visit_mut(v);
+        }
+    }
+}
+
+/// Calls `$callback!` with every AST node and token, so each generated trait covers the same set
+macro_rules! nodes {
+    ($callback: ident) => {
+        $callback! {
+        Expr,
+        Type,
+        Item,
+        File,
+
+        // Generated by the following method:
+        // cargo build 2>&1 1>/dev/null | grep 'no method named `visit_' out | cut -d '`' -f 2 | sed 's|visit||' | sed -E 's/_([a-z])/\U\1/g' | sort | uniq | sed '$!s/$/,/'
+        Abstract,
+        And,
+        AndAnd,
+        AndEq,
+        Arch,
+        ArchItem,
+        ArchItemConst,
+        ArchItemEntityExpression,
+        ArchItemLet,
+        ArchItemWhen,
+        Arm,
+        As,
+        AssOp,
+        Assert,
+        AssertEq,
+        Async,
+        At,
+        Attribute,
+        Await,
+        Bag,
+        Become,
+        BinOp,
+        Block,
+        BraceClose,
+        BraceOpen,
+        BracketClose,
+        BracketOpen,
+        Break,
+        Caret,
+        CaretEq,
+        Colon,
+        Comma,
+        Const,
+        Continue,
+        Crate,
+        Diagnostic,
+        Do,
+        DocComment,
+        Dollar,
+        Dot,
+        DotDot,
+        DotDotEq,
+        Dyn,
+        Else,
+        Entity,
+        EntityFieldValue,
+        Enum,
+        Eq,
+        EqEq,
+        ExprArray,
+        ExprAssign,
+        ExprBinary,
+        ExprBlock,
+        ExprCall,
+        ExprCast,
+        ExprField,
+        ExprFor,
+        ExprGrouped,
+        ExprIf,
+        ExprIndex,
+        ExprLit,
+        ExprMatch,
+        ExprMethodCall,
+        ExprPath,
+        ExprQPath,
+        ExprRange,
+        ExprRepeat,
+        ExprReturn,
+        ExprStruct,
+        ExprTuple,
+        ExprUnary,
+        Extern,
+        FatArrow,
+        FieldValue,
+        Fields,
+        FieldsNamed,
+        FieldsUnnamed,
+        Final,
+        Fn,
+        FnArg,
+        FnArgReceiver,
+        FnArgTyped,
+        For,
+        Ge,
+        GenericArg,
+        GenericArgBinding,
+        GenericArgExpr,
+        GenericArgType,
+        GenericArgs,
+        GenericParam,
+        GenericParamConst,
+        GenericParamType,
+        Generics,
+        Gt,
+        Ident,
+        If,
+        Impl,
+        ImplItem,
+        ImplItemConst,
+        ImplItemFn,
+        ImplItemType,
+        In,
+        InOut,
+        ItemArch,
+        ItemConst,
+        ItemEntity,
+        ItemEnum,
+        ItemError,
+        ItemFn,
+        ItemImpl,
+        ItemMod,
+        ItemStruct,
+        ItemTest,
+        ItemTrait,
+        ItemTraitAlias,
+        ItemType,
+        ItemUse,
+        Le,
+        Let,
+        Lit,
+        LitBool,
+        LitFloat,
+        LitInt,
+        Loop,
+        LowerSelf,
+        Lt,
+        Macro,
+        Match,
+        Member,
+        MemberNamed,
+        MemberUnnamed,
+        Meta,
+        MetaList,
+        MetaNameValue,
+        MetaPath,
+        Minus,
+        MinusEq,
+        Mod,
+        ModContent,
+        ModContentFile,
+        ModContentHere,
+        Move,
+        Mut,
+        NamedField,
+        Ne,
+        NestedMeta,
+        NestedMetaLit,
+        NestedMetaMeta,
+        Not,
+        Or,
+        OrEq,
+        OrOr,
+        Out,
+        OuterAttr,
+        OuterAttrAttr,
+        OuterAttrDoc,
+        Override,
+        ParenClose,
+        ParenOpen,
+        Pat,
+        PatIdent,
+        PatLit,
+        PatPath,
+        PatRange,
+        PatSlice,
+        PatStruct,
+        PatTuple,
+        PatTupleStruct,
+        PatType,
+        PatWildcard,
+        PathSegment,
+        PathSep,
+        Percent,
+        PercentEq,
+        Plus,
+        PlusEq,
+        Port,
+        PortType,
+        Pound,
+        Priv,
+        Pub,
+        Qualifier,
+        Question,
+        RArrow,
+        RangeType,
+        RangeTypeClosed,
+        RangeTypeHalfOpen,
+        Ref,
+        Return,
+        Ring,
+        Semi,
+        Shl,
+        ShlEq,
+        Shr,
+        ShrEq,
+        Sig,
+        SimplePath,
+        Slash,
+        SlashEq,
+        Star,
+        StarEq,
+        StarStar,
+        StarStarEq,
+        Static,
+        Stmt,
+        StmtExpr,
+        StmtItem,
+        StmtLocal,
+        Struct,
+        StructPatternField,
+        StructPatternFieldIdent,
+        StructPatternFieldIdentPat,
+        StructPatternFieldTuplePat,
+        Super,
+        Test,
+        TestStmt,
+        TestStmtAssert,
+        TestStmtAssertEq,
+        TestStmtDrive,
+        TestStmtTick,
+        Tick,
+        TokenBox,
+        TokenType,
+        Trait,
+        TraitItem,
+        TraitItemConst,
+        TraitItemFn,
+        TraitItemType,
+        Try,
+        TypeArray,
+        TypeFn,
+        TypeInfer,
+        TypeParenthesized,
+        TypePath,
+        TypeQPath,
+        TypeSlice,
+        TypeTuple,
+        Typeof,
+        UnOp,
+        Underscore,
+        Union,
+        UnnamedField,
+        Unsafe,
+        Unsized,
+        UpperSelf,
+        Use,
+        UseTree,
+        UseTreeGlob,
+        UseTreeGroup,
+        UseTreeName,
+        UseTreePath,
+        UseTreeRename,
+        Variant,
+        VariantType,
+        VariantTypeDiscrim,
+        VariantTypeFields,
+        VariantTypeUnit,
+        Virtual,
+        Vis,
+        VisCrate,
+        VisLowerSelf,
+        VisPriv,
+        VisPub,
+        VisRestricted,
+        VisSuper,
+        When,
+        Where,
+        WhereClause,
+        WhereClauseItem,
+        WhereClauseItemType,
+        While,
+        Yield
+        }
+    };
+}
+
+pub(crate) use nodes;
+
+nodes!(visit);
@@ -0,0 +1,60 @@
+library ieee;
+use ieee.std_logic_1164.all;
+use ieee.numeric_std.all;
+
+entity Div is
+  port (
+    clk : in std_logic;
+    slow : out std_logic := '0'
+  );
+end entity Div;
+
+architecture rtl of Div is
+  signal n1 : std_logic;
+begin
+  n1 <= not slow;
+  process (clk)
+  begin
+    if rising_edge(clk) then
+      slow <= n1;
+    end if;
+  end process;
+end architecture rtl;
+
+library ieee;
+use ieee.std_logic_1164.all;
+use ieee.numeric_std.all;
+
+entity Top is
+  port (
+    clk : in std_logic;
+    count : out unsigned(3 downto 0) := 4d"0"
+  );
+end entity Top;
+
+architecture rtl of Top is
+  signal slow : std_logic;
+  signal quarter : std_logic := '0';
+  signal n1 : std_logic;
+  signal n4 : unsigned(3 downto 0);
+begin
+  n1 <= not quarter;
+  n4 <= count + unsigned'(4d"1");
+  process (slow)
+  begin
+    if rising_edge(slow) then
+      quarter <= n1;
+    end if;
+  end process;
+  process (quarter)
+  begin
+    if rising_edge(quarter) then
+      count <= n4;
+    end if;
+  end process;
+  div_0 : entity work.Div
+    port map (
+      clk => clk,
+      slow => slow
+    );
+end architecture rtl;
@@ -0,0 +1,32 @@
+package crate_pkg;
+  typedef enum logic [1:0] {
+    Zero = 2'd0,
+    One = 2'd1,
+    Two = 2'd3,
+    Three = 2'd2
+  } GrayU2;
+endpackage
+
+module Top (
+  input logic clk,
+  input logic reset,
+  output crate_pkg::GrayU2 state = crate_pkg::Zero
+);
+  logic _n4;
+  logic _n7;
+  logic _n10;
+  crate_pkg::GrayU2 _n12;
+  crate_pkg::GrayU2 _n13;
+  crate_pkg::GrayU2 _n14;
+  crate_pkg::GrayU2 _n15;
+  assign _n4 = state == 2'd0;
+  assign _n7 = state == 2'd1;
+  assign _n10 = state == 2'd3;
+  assign _n12 = _n10 ? crate_pkg::Three : crate_pkg::Zero;
+  assign _n13 = _n7 ? crate_pkg::Two : _n12;
+  assign _n14 = _n4 ? crate_pkg::One : _n13;
+  assign _n15 = reset ? crate_pkg::Zero : _n14;
+  always_ff @(posedge clk) begin
+    state <= _n15;
+  end
+endmodule
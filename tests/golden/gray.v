@@ -0,0 +1,16 @@
+module Top (
+  input wire clk,
+  input wire reset,
+  output reg [1:0] state = 2'd0
+);
+  wire _n4 = state == 2'd0;
+  wire _n7 = state == 2'd1;
+  wire _n10 = state == 2'd3;
+  wire [1:0] _n12 = _n10 ? 2'd2 : 2'd0;
+  wire [1:0] _n13 = _n7 ? 2'd3 : _n12;
+  wire [1:0] _n14 = _n4 ? 2'd1 : _n13;
+  wire [1:0] _n15 = reset ? 2'd0 : _n14;
+  always @(posedge clk) begin
+    state <= _n15;
+  end
+endmodule
@@ -0,0 +1,73 @@
+library ieee;
+use ieee.std_logic_1164.all;
+use ieee.numeric_std.all;
+
+package Top_types is
+  type GrayU2_t is (Zero, One, Two, Three);
+  attribute enum_encoding : string;
+  attribute enum_encoding of GrayU2_t : type is "00 01 11 10";
+  function to_unsigned(x : GrayU2_t) return unsigned;
+  function to_GrayU2_t(x : unsigned) return GrayU2_t;
+end package Top_types;
+
+package body Top_types is
+  function to_unsigned(x : GrayU2_t) return unsigned is
+  begin
+    case x is
+      when Zero => return unsigned'(2d"0");
+      when One => return unsigned'(2d"1");
+      when Two => return unsigned'(2d"3");
+      when Three => return unsigned'(2d"2");
+    end case;
+  end function;
+
+  function to_GrayU2_t(x : unsigned) return GrayU2_t is
+  begin
+    case to_integer(x) is
+      when 0 => return Zero;
+      when 1 => return One;
+      when 3 => return Two;
+      when 2 => return Three;
+      when others => return GrayU2_t'left;
+    end case;
+  end function;
+end package body Top_types;
+
+library ieee;
+use ieee.std_logic_1164.all;
+use ieee.numeric_std.all;
+use work.Top_types.all;
+
+entity Top is
+  port (
+    clk : in std_logic;
+    reset : in std_logic;
+    state : out GrayU2_t := Zero
+  );
+end entity Top;
+
+architecture rtl of Top is
+  signal n2 : unsigned(1 downto 0);
+  signal n4 : std_logic;
+  signal n7 : std_logic;
+  signal n10 : std_logic;
+  signal n12 : unsigned(1 downto 0);
+  signal n13 : unsigned(1 downto 0);
+  signal n14 : unsigned(1 downto 0);
+  signal n15 : unsigned(1 downto 0);
+begin
+  n2 <= to_unsigned(state);
+  n4 <= '1' when n2 = unsigned'(2d"0") else '0';
+  n7 <= '1' when n2 = unsigned'(2d"1") else '0';
+  n10 <= '1' when n2 = unsigned'(2d"3") else '0';
+  n12 <= unsigned'(2d"2") when n10 = '1' else unsigned'(2d"0");
+  n13 <= unsigned'(2d"3") when n7 = '1' else n12;
+  n14 <= unsigned'(2d"1") when n4 = '1' else n13;
+  n15 <= unsigned'(2d"0") when reset = '1' else n14;
+  process (clk)
+  begin
+    if rising_edge(clk) then
+      state <= to_GrayU2_t(n15);
+    end if;
+  end process;
+end architecture rtl;
@@ -0,0 +1,59 @@
+package crate_pkg;
+  typedef enum logic [1:0] {
+    Off = 2'd0,
+    Solid = 2'd2,
+    Fade = 2'd3
+  } Mode;
+  typedef struct packed {
+    logic [7:0] b;
+    logic [7:0] g;
+    logic [7:0] r;
+  } Pixel;
+  localparam logic [7:0] MAX = 8'd255;
+endpackage
+
+module Top (
+  input logic clk,
+  input crate_pkg::Mode mode,
+  input logic [1:0] sel,
+  input crate_pkg::Pixel [3:0] palette,
+  output crate_pkg::Pixel pixel = 24'd0,
+  output struct packed { logic _1; logic [7:0] _0; } bright = 9'd0
+);
+  crate_pkg::Pixel _n3;
+  logic _n6;
+  logic _n8;
+  logic [7:0] _n9;
+  logic [7:0] _n11;
+  logic [7:0] _n12;
+  logic [7:0] _n13;
+  logic [7:0] _n14;
+  logic [7:0] _n15;
+  crate_pkg::Pixel _n16;
+  crate_pkg::Pixel _n17;
+  crate_pkg::Pixel _n18;
+  crate_pkg::Pixel _n19;
+  logic [7:0] _n20;
+  logic _n22;
+  struct packed { logic _1; logic [7:0] _0; } _n23;
+  assign _n3 = palette[sel];
+  assign _n6 = mode == 2'd0;
+  assign _n8 = mode == 2'd2;
+  assign _n9 = _n3.r;
+  assign _n11 = _n9 >> 1'b1;
+  assign _n12 = _n3.g;
+  assign _n13 = _n12 >> 1'b1;
+  assign _n14 = _n3.b;
+  assign _n15 = _n14 >> 1'b1;
+  assign _n16 = {_n15, _n13, _n11};
+  assign _n17 = _n8 ? _n3 : _n16;
+  assign _n18 = _n6 ? 24'd0 : _n17;
+  assign _n19 = palette[0];
+  assign _n20 = _n19.g;
+  assign _n22 = _n9 == 8'd255;
+  assign _n23 = {_n22, _n20};
+  always_ff @(posedge clk) begin
+    pixel <= _n18;
+    bright <= _n23;
+  end
+endmodule
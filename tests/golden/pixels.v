@@ -0,0 +1,29 @@
+module Top (
+  input wire clk,
+  input wire [1:0] mode,
+  input wire [1:0] sel,
+  input wire [95:0] palette,
+  output reg [23:0] pixel = 24'd0,
+  output reg [8:0] bright = 9'd0
+);
+  wire [23:0] _n3 = palette[sel * 24 +: 24];
+  wire _n6 = mode == 2'd0;
+  wire _n8 = mode == 2'd2;
+  wire [7:0] _n9 = _n3[7:0];
+  wire [7:0] _n11 = _n9 >> 1'b1;
+  wire [7:0] _n12 = _n3[15:8];
+  wire [7:0] _n13 = _n12 >> 1'b1;
+  wire [7:0] _n14 = _n3[23:16];
+  wire [7:0] _n15 = _n14 >> 1'b1;
+  wire [23:0] _n16 = {_n15, _n13, _n11};
+  wire [23:0] _n17 = _n8 ? _n3 : _n16;
+  wire [23:0] _n18 = _n6 ? 24'd0 : _n17;
+  wire [23:0] _n19 = palette[23:0];
+  wire [7:0] _n20 = _n19[15:8];
+  wire _n22 = _n9 == 8'd255;
+  wire [8:0] _n23 = {_n22, _n20};
+  always @(posedge clk) begin
+    pixel <= _n18;
+    bright <= _n23;
+  end
+endmodule
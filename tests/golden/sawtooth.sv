@@ -0,0 +1,26 @@
+module Top (
+  input logic clk,
+  output logic [23:0] audio = 24'd0
+);
+  logic [23:0] level;
+  logic [23:0] _n2;
+  assign _n2 = level >> 4'd8;
+  always_ff @(posedge clk) begin
+    audio <= _n2;
+  end
+  Sawtooth sawtooth_0 (
+    .clk(clk),
+    .level(level)
+  );
+endmodule
+
+module Sawtooth (
+  input logic clk,
+  output logic [23:0] level = 24'd0
+);
+  logic [23:0] _n2;
+  assign _n2 = level + 24'd1;
+  always_ff @(posedge clk) begin
+    level <= _n2;
+  end
+endmodule
@@ -0,0 +1,24 @@
+module Top (
+  input wire clk,
+  output reg [23:0] audio = 24'd0
+);
+  wire [23:0] level;
+  wire [23:0] _n2 = level >> 4'd8;
+  always @(posedge clk) begin
+    audio <= _n2;
+  end
+  Sawtooth sawtooth_0 (
+    .clk(clk),
+    .level(level)
+  );
+endmodule
+
+module Sawtooth (
+  input wire clk,
+  output reg [23:0] level = 24'd0
+);
+  wire [23:0] _n2 = level + 24'd1;
+  always @(posedge clk) begin
+    level <= _n2;
+  end
+endmodule
@@ -0,0 +1,51 @@
+library ieee;
+use ieee.std_logic_1164.all;
+use ieee.numeric_std.all;
+
+entity Sawtooth is
+  port (
+    clk : in std_logic;
+    level : out unsigned(23 downto 0) := 24d"0"
+  );
+end entity Sawtooth;
+
+architecture rtl of Sawtooth is
+  signal n2 : unsigned(23 downto 0);
+begin
+  n2 <= level + unsigned'(24d"1");
+  process (clk)
+  begin
+    if rising_edge(clk) then
+      level <= n2;
+    end if;
+  end process;
+end architecture rtl;
+
+library ieee;
+use ieee.std_logic_1164.all;
+use ieee.numeric_std.all;
+
+entity Top is
+  port (
+    clk : in std_logic;
+    audio : out unsigned(23 downto 0) := 24d"0"
+  );
+end entity Top;
+
+architecture rtl of Top is
+  signal level : unsigned(23 downto 0);
+  signal n2 : unsigned(23 downto 0);
+begin
+  n2 <= shift_right(level, 8);
+  process (clk)
+  begin
+    if rising_edge(clk) then
+      audio <= n2;
+    end if;
+  end process;
+  sawtooth_0 : entity work.Sawtooth
+    port map (
+      clk => clk,
+      level => level
+    );
+end architecture rtl;
@@ -0,0 +1,14 @@
+module Top (
+  input wire [1:0] sel,
+  output reg [3:0] low,
+  output reg top,
+  output reg [3:0] step
+);
+  wire [15:0] _n2 = 16'd33825;
+  wire [3:0] _n4 = _n2[sel * 4 +: 4];
+  always @* begin
+    low = 4'd4;
+    top = 1'b0;
+    step = _n4;
+  end
+endmodule